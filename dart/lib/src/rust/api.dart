@@ -28,7 +28,8 @@ Future<List<NodeInfo>> getChildren(
         {required FigmaDocument doc, required String nodeId}) =>
     RustLib.instance.api.crateApiGetChildren(doc: doc, nodeId: nodeId);
 
-/// Get render commands for a node (and optionally its children)
+/// Get render commands for a node (and optionally its children), with
+/// frames scrolled as set by [`set_scroll_offset`]
 Future<List<DrawCommand>> renderNode(
         {required FigmaDocument doc,
         required String nodeId,
@@ -46,13 +47,18 @@ Future<String> exportSvgPath(
         {required FigmaDocument doc, required String nodeId}) =>
     RustLib.instance.api.crateApiExportSvgPath(doc: doc, nodeId: nodeId);
 
-/// Decode Kiwi-encoded fill paint data (matches Figma's JsKiwiSerialization_decodeFillPaintData)
-Future<List<PaintInfo>> decodeFillPaint({required List<int> data}) =>
-    RustLib.instance.api.crateApiDecodeFillPaint(data: data);
-
-/// Decode Kiwi-encoded effect data
-Future<List<EffectInfo>> decodeEffects({required List<int> data}) =>
-    RustLib.instance.api.crateApiDecodeEffects(data: data);
+/// Decode a Kiwi-encoded `Paint[]` value, such as a node's `fillPaints`,
+/// with the document's schema. Paint types that can't be drawn are skipped,
+/// and gradient geometry is computed for a 1x1 node.
+Future<List<PaintInfo>> decodeFillPaint(
+        {required FigmaDocument doc, required List<int> data}) =>
+    RustLib.instance.api.crateApiDecodeFillPaint(doc: doc, data: data);
+
+/// Decode a Kiwi-encoded `Effect[]` value, such as a node's `effects`, with
+/// the document's schema. Effect types that can't be drawn are skipped.
+Future<List<EffectInfo>> decodeEffects(
+        {required FigmaDocument doc, required List<int> data}) =>
+    RustLib.instance.api.crateApiDecodeEffects(doc: doc, data: data);
 
 /// Decode Kiwi-encoded vector data
 Future<PathData> decodeVector({required List<int> data}) =>
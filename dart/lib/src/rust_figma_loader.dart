@@ -72,16 +72,22 @@ class RustFigmaLoader {
     return await rust_api.exportSvgPath(doc: doc, nodeId: nodeId);
   }
 
-  /// Decode Kiwi-encoded fill paint data
-  Future<List<rust_api.PaintInfo>> decodeFillPaint(Uint8List data) async {
+  /// Decode Kiwi-encoded fill paint data with the document's schema
+  Future<List<rust_api.PaintInfo>> decodeFillPaint(
+    rust_api.FigmaDocument doc,
+    Uint8List data,
+  ) async {
     _ensureInitialized();
-    return await rust_api.decodeFillPaint(data: data);
+    return await rust_api.decodeFillPaint(doc: doc, data: data);
   }
 
-  /// Decode Kiwi-encoded effect data
-  Future<List<rust_api.EffectInfo>> decodeEffects(Uint8List data) async {
+  /// Decode Kiwi-encoded effect data with the document's schema
+  Future<List<rust_api.EffectInfo>> decodeEffects(
+    rust_api.FigmaDocument doc,
+    Uint8List data,
+  ) async {
     _ensureInitialized();
-    return await rust_api.decodeEffects(data: data);
+    return await rust_api.decodeEffects(doc: doc, data: data);
   }
 
   /// Decode Kiwi-encoded vector data
@@ -0,0 +1,17 @@
+//! Feed arbitrary bytes to the .fig parser. Run with:
+//!
+//!     cargo +nightly fuzz run parse_fig
+//!
+//! Any panic, overflow or runaway allocation is a bug; malformed input
+//! must come back as a `FigmaError`.
+
+#![no_main]
+
+use figma_renderer::kiwi::{FigContainer, FigFile};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if FigContainer::parse(data).is_ok() {
+        let _ = FigFile::parse(data);
+    }
+});
@@ -3,20 +3,49 @@
 //! These functions are exposed to Dart and can be called from Flutter.
 
 use crate::{FigmaError, Result};
+use crate::archive::{self, FigArchive, FigMeta};
+use crate::components;
+use crate::effects::Effect;
+use crate::fonts::FontRegistry;
+use crate::grids;
+use crate::images::{image_size, ImageStore};
+use crate::instances::expand_instance;
 use crate::kiwi::FigFile;
 use crate::nodes::FigmaNode;
-use crate::render::RenderTree;
+use crate::prototype::{self, Transition};
+use crate::render::{RenderContext, RenderTree};
+use crate::scroll::{self, ScrollBehavior, ScrollOffsets};
 use crate::spatial::SpatialIndex;
+use crate::text::collect_font_usage;
+use crate::vector::path_to_svg;
 use crate::tiles::{TileGrid, TileCoord, Viewport, TILE_SIZE};
+use crate::codegen;
+use crate::html;
+use crate::layout;
+use crate::tokens::{self, TokenFormat};
+use crate::transitions;
+use crate::variables::{VariableRef, VariableResolver, VariableValue};
 
 use flutter_rust_bridge::frb;
 use serde::Serialize;
-use std::sync::RwLock;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard};
 
 /// Opaque handle to a loaded Figma document
 #[frb(opaque)]
 pub struct FigmaDocument {
     file: FigFile,
+    /// `meta.json` from a zipped .fig file
+    meta: Option<FigMeta>,
+    /// Image bytes for image paints, filled from a zipped .fig file's
+    /// `images/` and by `register_image`
+    images: RwLock<ImageStore>,
+    /// Fonts for text layout, registered by the app
+    fonts: RwLock<FontRegistry>,
+    /// Variable modes picked by the app, by collection id
+    variable_modes: RwLock<HashMap<String, String>>,
+    /// Scroll positions of scrolling frames, used when rendering tiles
+    scroll_offsets: RwLock<ScrollOffsets>,
     render_tree: RwLock<Option<RenderTree>>,
     spatial_index: RwLock<Option<SpatialIndex>>,
     tile_grid: RwLock<TileGrid>,
@@ -37,6 +66,229 @@ pub struct NodeInfo {
     pub opacity: f64,
     pub visible: bool,
     pub children: Vec<String>,
+    /// For instances, the component their content is copied from
+    pub component_id: Option<String>,
+}
+
+/// A property of a component or component set
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentPropertyInfo {
+    /// Id the component's instances assign the property by
+    pub id: String,
+    pub name: String,
+    /// "BOOL", "TEXT", "INSTANCE_SWAP", "NUMBER", ...
+    pub property_type: String,
+    /// Value for instances that don't assign one: "true"/"false" for BOOL,
+    /// a component id for INSTANCE_SWAP
+    pub default_value: String,
+    /// For INSTANCE_SWAP, keys of the components suggested in Figma
+    pub preferred_values: Vec<String>,
+}
+
+/// A variant property of a component set and its values
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantAxisInfo {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// A property value by property name
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyValueInfo {
+    pub name: String,
+    pub value: String,
+}
+
+/// The component an instance uses and its property values
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceVariantInfo {
+    pub component_id: String,
+    /// Set when the component is a variant
+    pub component_set_id: Option<String>,
+    /// The variant's values, from its name
+    pub variant: Vec<PropertyValueInfo>,
+    /// Values of the other properties, assigned or default
+    pub properties: Vec<PropertyValueInfo>,
+}
+
+/// A variable collection and its modes
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableCollectionInfo {
+    pub id: String,
+    pub name: String,
+    pub modes: Vec<VariableModeInfo>,
+    /// The mode used when rendering, outside frames that pick their own
+    pub active_mode_id: String,
+}
+
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableModeInfo {
+    pub id: String,
+    /// e.g. "Light", "Dark"
+    pub name: String,
+}
+
+/// A variable and its values
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableInfo {
+    pub id: String,
+    /// Slash separated group path and name, e.g. "system/red"
+    pub name: String,
+    /// "BOOLEAN", "FLOAT", "STRING" or "COLOR"
+    pub resolved_type: String,
+    pub values: Vec<VariableModeValueInfo>,
+}
+
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableModeValueInfo {
+    pub mode_id: String,
+    pub value: VariableValueInfo,
+}
+
+/// A variable value
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableValueInfo {
+    /// "BOOLEAN", "FLOAT", "STRING", "COLOR" or "ALIAS"
+    pub value_type: String,
+    /// Booleans, numbers and strings as text
+    pub value: String,
+    pub color: Option<ColorInfo>,
+    /// For aliases, the variable aliased, if it is in the document
+    pub alias_id: Option<String>,
+}
+
+/// A prototype flow, started from a top-level frame
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowInfo {
+    /// The frame the flow starts from
+    pub node_id: String,
+    pub page_id: String,
+    pub name: String,
+    pub description: String,
+}
+
+/// A trigger a node reacts to in a prototype, and the actions it runs in
+/// order
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct InteractionInfo {
+    /// The node reacting, the hotspot
+    pub node_id: String,
+    pub trigger: TriggerInfo,
+    pub actions: Vec<PrototypeActionInfo>,
+}
+
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerInfo {
+    /// "ON_CLICK", "ON_HOVER", "ON_PRESS", "DRAG", "AFTER_TIMEOUT",
+    /// "MOUSE_ENTER", "MOUSE_LEAVE", "MOUSE_DOWN", "MOUSE_UP", "ON_KEY_DOWN"...
+    pub trigger_type: String,
+    /// For "AFTER_TIMEOUT", seconds before the actions run
+    pub timeout: f64,
+    /// For "MOUSE_ENTER" and "MOUSE_LEAVE", seconds the pointer must stay
+    pub delay: f64,
+    /// For "ON_KEY_DOWN", the key codes pressed together
+    pub key_codes: Vec<i64>,
+}
+
+/// An action run by an interaction
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct PrototypeActionInfo {
+    /// "NAVIGATE", "OVERLAY", "SWAP" (the open overlay), "SWAP_STATE" (an
+    /// instance's variant), "SCROLL_TO", "BACK", "CLOSE", "URL",
+    /// "CONDITIONAL", or another connection type such as "SET_VARIABLE"
+    pub action_type: String,
+    /// The node navigated or scrolled to, or the variant swapped in. None
+    /// if it was deleted.
+    pub destination_id: Option<String>,
+    /// None for instant changes
+    pub transition: Option<PrototypeTransitionInfo>,
+    /// Keep the scroll position when navigating
+    pub preserve_scroll: bool,
+    /// For manually placed overlays, the overlay's position relative to the
+    /// hotspot; for "SCROLL_TO", an extra offset
+    pub offset: Option<PointInfo>,
+    /// For "OVERLAY" and "SWAP", how the destination frame is shown
+    pub overlay: Option<OverlayInfo>,
+    pub url: Option<String>,
+    pub open_in_new_tab: bool,
+    /// For "CONDITIONAL", the actions of each branch. Conditions are not
+    /// evaluated.
+    pub branches: Vec<Vec<PrototypeActionInfo>>,
+}
+
+/// How an action animates to its destination
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct PrototypeTransitionInfo {
+    /// "DISSOLVE", "SMART_ANIMATE", "SCROLL_ANIMATE", or "SLIDE_FROM_",
+    /// "SLIDE_OUT_TO_", "PUSH_FROM_", "MOVE_FROM_" or "MOVE_OUT_TO_"
+    /// followed by "LEFT", "RIGHT", "TOP" or "BOTTOM"
+    pub transition_type: String,
+    /// Seconds
+    pub duration: f64,
+    /// e.g. "OUT_CUBIC", "CUSTOM_CUBIC", "GENTLE_SPRING"
+    pub easing_type: String,
+    /// Cubic bezier control points x1, y1, x2, y2, for curves that aren't
+    /// springs
+    pub bezier: Option<Vec<f64>>,
+    pub spring: Option<SpringInfo>,
+    /// Layers with matching names animate between the frames
+    pub smart_animate: bool,
+}
+
+#[frb]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SpringInfo {
+    pub mass: f64,
+    pub stiffness: f64,
+    pub damping: f64,
+}
+
+/// Where a frame appears when opened as an overlay
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct OverlayInfo {
+    /// "CENTER", "TOP_LEFT", "TOP_CENTER", "TOP_RIGHT", "BOTTOM_LEFT",
+    /// "BOTTOM_CENTER", "BOTTOM_RIGHT" or "MANUAL"
+    pub position: String,
+    /// For "MANUAL", the offset from the hotspot
+    pub offset: PointInfo,
+    pub close_on_click_outside: bool,
+    /// Color drawn over the frame beneath, if any
+    pub background: Option<ColorInfo>,
+}
+
+/// A frame that scrolls in a prototype
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrollFrameInfo {
+    pub node_id: String,
+    /// "HORIZONTAL", "VERTICAL" or "BOTH"
+    pub direction: String,
+    /// The box holding the frame's scrolling content, relative to the frame
+    pub content_bounds: RectInfo,
+    /// The largest offset the content scrolls to
+    pub max_offset: PointInfo,
+    /// The offset set by `set_scroll_offset`
+    pub offset: PointInfo,
+    pub clips_content: bool,
+    /// Children that stay where they are as the content scrolls
+    pub fixed_children: Vec<String>,
+    /// Children that scroll until they reach the frame's top edge
+    pub sticky_children: Vec<String>,
 }
 
 /// Paint information for fills/strokes
@@ -44,15 +296,84 @@ pub struct NodeInfo {
 #[derive(Debug, Clone, Serialize)]
 pub struct PaintInfo {
     pub paint_type: String, // "solid", "gradient_linear", "gradient_radial", "image"
+    /// Hidden paints are kept so the list matches Figma's; don't draw them
+    pub visible: bool,
     pub color: Option<ColorInfo>,
     pub gradient_stops: Vec<GradientStopInfo>,
+    /// For gradients, maps the node's unit square into gradient space
+    pub gradient_transform: Option<TransformInfo>,
+    /// For gradients, the start (or center), end and width handles in the
+    /// node's unit square
+    pub gradient_handles: Vec<PointInfo>,
+    /// Set for angular gradients
+    pub sweep: Option<SweepGradientInfo>,
+    /// For diamond gradients, linear gradients that together draw it
+    pub diamond_segments: Vec<GradientSegmentInfo>,
+    /// Set for image paints
+    pub image: Option<ImagePaintInfo>,
     pub opacity: f64,
     pub blend_mode: String,
 }
 
-/// Color represented as RGBA (0-255)
+/// Where an angular gradient sweeps from, in pixels relative to the node
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepGradientInfo {
+    pub center: PointInfo,
+    /// Radians clockwise from the positive x axis, where the first stop sits
+    pub start_angle: f64,
+    /// Whether stops advance clockwise (false if the gradient is mirrored)
+    pub clockwise: bool,
+}
+
+/// A linear gradient using the paint's stops, drawn inside `clip`. All
+/// coordinates are pixels relative to the node.
 #[frb]
 #[derive(Debug, Clone, Serialize)]
+pub struct GradientSegmentInfo {
+    pub clip: PathData,
+    /// Where the first stop sits
+    pub start: PointInfo,
+    /// Where the last stop sits
+    pub end: PointInfo,
+}
+
+/// A point, in the coordinate space given by the field it's in
+#[frb]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PointInfo {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Parameters of an image paint
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct ImagePaintInfo {
+    /// Key for `get_image`
+    pub image_hash: String,
+    pub scale_mode: String, // "FILL", "FIT", "TILE", "STRETCH"
+    /// For "STRETCH", maps the node's unit square to the part of the image shown
+    pub transform: TransformInfo,
+    /// Clockwise rotation in degrees
+    pub rotation: f64,
+    /// Tile size relative to the image's natural size, for "TILE"
+    pub scale: f64,
+    pub original_width: Option<u32>,
+    pub original_height: Option<u32>,
+    /// Adjustments in -1..1, 0 meaning unchanged
+    pub exposure: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+    pub temperature: f64,
+    pub tint: f64,
+    pub highlights: f64,
+    pub shadows: f64,
+}
+
+/// Color represented as RGBA (0-255)
+#[frb]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ColorInfo {
     pub r: u8,
     pub g: u8,
@@ -62,7 +383,7 @@ pub struct ColorInfo {
 
 /// Gradient stop
 #[frb]
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct GradientStopInfo {
     pub position: f64,
     pub color: ColorInfo,
@@ -79,6 +400,9 @@ pub struct EffectInfo {
     pub offset_x: f64,
     pub offset_y: f64,
     pub spread: f64,
+    pub blend_mode: String,
+    /// For drop shadows, whether the shadow shows through translucent fills
+    pub show_shadow_behind_node: bool,
 }
 
 /// Path data for vector rendering
@@ -94,7 +418,7 @@ pub struct PathData {
 #[frb]
 #[derive(Debug, Clone, Serialize)]
 pub struct DrawCommand {
-    pub command_type: String, // "path", "rect", "ellipse", "text", "image"
+    pub command_type: String, // "path", "rect", "ellipse", "text", "image", "begin_layer", ...
     pub path: Option<PathData>,
     pub rect: Option<RectInfo>,
     pub fills: Vec<PaintInfo>,
@@ -103,11 +427,143 @@ pub struct DrawCommand {
     pub effects: Vec<EffectInfo>,
     pub transform: TransformInfo,
     pub clip_path: Option<PathData>,
+    /// Set for "image" commands
+    pub image: Option<ImageDrawInfo>,
+    /// Set for "text" commands
+    pub text: Option<TextDrawInfo>,
+    /// How `strokes` are drawn, set when there are strokes
+    pub stroke: Option<StrokeInfo>,
+    /// Set for "begin_layer" commands
+    pub layer: Option<LayerInfo>,
 }
 
-/// Rectangle info
+/// How a layer opened by a "begin_layer" command is composited onto what's
+/// behind it when the matching "end_layer" command closes it
 #[frb]
 #[derive(Debug, Clone, Serialize)]
+pub struct LayerInfo {
+    pub opacity: f64,
+    pub blend_mode: String, // "NORMAL", "MULTIPLY", ...
+}
+
+/// Stroke settings beyond the weight
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct StrokeInfo {
+    pub align: String, // "CENTER", "INSIDE", "OUTSIDE"
+    pub cap: String,   // "NONE", "ROUND", "SQUARE", "ARROW_LINES", ...
+    pub join: String,  // "MITER", "BEVEL", "ROUND"
+    pub miter_limit: f64,
+    /// Alternating dash and gap lengths; empty for a solid stroke
+    pub dash_pattern: Vec<f64>,
+    /// Set when the sides of a rectangle have their own weights
+    pub side_weights: Option<StrokeWeightsInfo>,
+}
+
+/// Per-side stroke weights
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct StrokeWeightsInfo {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+/// A stroke as geometry to fill, relative to the node's top-left
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct StrokeOutlineInfo {
+    /// Fill with the node's stroke paints
+    pub path: PathData,
+    /// Clip to apply while filling, for inside and outside strokes
+    pub clip: Option<PathData>,
+}
+
+/// An image paint to draw, clipped to the command's `clip_path`
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageDrawInfo {
+    /// Key for `get_image`
+    pub image_hash: String,
+    /// Whether the image bytes are in the document's image store
+    pub available: bool,
+    pub scale_mode: String, // "FILL", "FIT", "TILE", "STRETCH"
+    /// Where the image is drawn
+    pub dest: RectInfo,
+    /// Part of the image drawn into `dest`, in image pixels. None means the
+    /// whole image, used when the image size isn't known yet.
+    pub source: Option<RectInfo>,
+    /// For TILE, the size of one repetition starting at `dest`'s origin
+    pub tile_width: Option<f64>,
+    pub tile_height: Option<f64>,
+    /// Clockwise rotation in degrees, applied around the center of `dest`
+    pub rotation: f64,
+    pub opacity: f64,
+    pub blend_mode: String,
+}
+
+/// Laid out text of a TEXT node
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct TextDrawInfo {
+    pub runs: Vec<GlyphRunInfo>,
+    /// Underlines and strikethroughs, drawn over the glyphs
+    pub decorations: Vec<TextDecorationInfo>,
+    /// Whether overflowing lines were cut off with an ellipsis
+    pub truncated: bool,
+    /// Size of the text box after auto-resize
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Glyphs on one line that share a style
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct GlyphRunInfo {
+    /// Characters of the run after the text case transform
+    pub text: String,
+    /// Requested font
+    pub font_family: String,
+    pub font_style: String,
+    /// PostScript name of the registered face the glyph ids belong to,
+    /// empty when no registered font matched
+    pub font_postscript_name: String,
+    pub font_size: f64,
+    /// Variable font axis values the glyphs were measured with
+    pub font_variations: Vec<FontVariationInfo>,
+    pub fills: Vec<PaintInfo>,
+    pub glyphs: Vec<GlyphInfo>,
+}
+
+/// A variable font axis setting
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct FontVariationInfo {
+    /// OpenType axis tag, e.g. "wght"
+    pub axis: String,
+    pub value: f64,
+}
+
+/// A glyph positioned on its baseline
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct GlyphInfo {
+    pub glyph_id: u32,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct TextDecorationInfo {
+    pub rect: RectInfo,
+    pub fills: Vec<PaintInfo>,
+}
+
+/// Rectangle info
+#[frb]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct RectInfo {
     pub x: f64,
     pub y: f64,
@@ -118,7 +574,7 @@ pub struct RectInfo {
 
 /// 2D affine transform matrix [a, b, c, d, tx, ty]
 #[frb]
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct TransformInfo {
     pub m00: f64, // scale x
     pub m01: f64, // skew y
@@ -141,26 +597,73 @@ impl Default for TransformInfo {
 // Public API Functions (exposed to Flutter)
 // =============================================================================
 
-/// Load a Figma file from bytes
+/// Load a Figma file from bytes, either a raw fig-kiwi stream or a zipped
+/// .fig archive
 #[frb]
 pub fn load_figma_file(data: Vec<u8>) -> Result<FigmaDocument> {
-    let file = FigFile::parse(&data)?;
+    let (mut file, meta, images) = if archive::is_archive(&data) {
+        let archive = FigArchive::parse(&data)?;
+        (FigFile::parse(&archive.canvas)?, archive.meta, archive.images.into())
+    } else {
+        (FigFile::parse(&data)?, None, ImageStore::new())
+    };
+    file.expand_instances();
     Ok(FigmaDocument {
         file,
+        meta,
+        images: RwLock::new(images),
+        fonts: RwLock::new(FontRegistry::new()),
+        variable_modes: RwLock::new(HashMap::new()),
+        scroll_offsets: RwLock::new(ScrollOffsets::new()),
         render_tree: RwLock::new(None),
         spatial_index: RwLock::new(None),
         tile_grid: RwLock::new(TileGrid::new()),
     })
 }
 
+/// Serialize a document back to .fig bytes. Documents can't be edited
+/// through the bridge, so this writes the file as it was loaded; edit a
+/// `FigFile` in Rust and call [`FigFile::write`](crate::kiwi::FigFile::write)
+/// to save changes.
+#[frb]
+pub fn save_figma_file(doc: &FigmaDocument) -> Result<Vec<u8>> {
+    doc.file.write()
+}
+
+/// Get the preview image of a .fig file, without loading the document.
+/// Only the container is parsed, so this is fast enough for file browsers.
+#[frb]
+pub fn get_document_thumbnail(data: Vec<u8>) -> Result<Option<ThumbnailInfo>> {
+    Ok(crate::kiwi::read_thumbnail(&data)?.map(|data| {
+        let (width, height) = image_size(&data).unwrap_or((0, 0));
+        ThumbnailInfo { data, width, height }
+    }))
+}
+
+/// Preview image of a .fig file
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct ThumbnailInfo {
+    /// Encoded image bytes, usually PNG
+    pub data: Vec<u8>,
+    /// Pixel size read from the image header, 0 if the format is unknown
+    pub width: u32,
+    pub height: u32,
+}
+
 /// Get document metadata
 #[frb]
 pub fn get_document_info(doc: &FigmaDocument) -> DocumentInfo {
+    let meta = doc.meta.as_ref();
     DocumentInfo {
         name: doc.file.name.clone(),
         version: doc.file.version,
         node_count: doc.file.nodes.len(),
         page_ids: doc.file.page_ids.clone(),
+        file_name: meta.map(|meta| meta.file_name.clone()),
+        last_modified: meta.map(|meta| meta.exported_at.clone()),
+        background_color: meta.and_then(|meta| meta.background_color()),
+        image_count: doc.images.read().map(|images| images.len()).unwrap_or(0),
     }
 }
 
@@ -171,6 +674,138 @@ pub struct DocumentInfo {
     pub version: u32,
     pub node_count: usize,
     pub page_ids: Vec<String>,
+    /// The following come from `meta.json` and are only set for zipped files
+    pub file_name: Option<String>,
+    /// ISO 8601 timestamp
+    pub last_modified: Option<String>,
+    pub background_color: Option<ColorInfo>,
+    /// Number of images in the image store
+    pub image_count: usize,
+}
+
+/// Register image bytes for image paints that reference `hash`. Cached
+/// tiles are dropped so they pick up the image.
+#[frb]
+pub fn register_image(doc: &FigmaDocument, hash: String, data: Vec<u8>) -> Result<()> {
+    doc.images.write()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?
+        .insert(&hash, data);
+    clear_tile_cache(doc)
+}
+
+/// Get the bytes of a registered or bundled image
+#[frb]
+pub fn get_image(doc: &FigmaDocument, hash: String) -> Result<Option<Vec<u8>>> {
+    let images = doc.images.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    Ok(images.get(&hash).map(<[u8]>::to_vec))
+}
+
+/// Register a TTF, OTF or font collection for text layout, returning the
+/// "Family Style" names of the faces it contains. Text nodes use the face
+/// matching their font name; cached tiles are dropped so they pick it up.
+#[frb]
+pub fn register_font(doc: &FigmaDocument, data: Vec<u8>) -> Result<Vec<String>> {
+    let names = doc.fonts.write()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?
+        .register(data)?;
+    clear_tile_cache(doc)?;
+    Ok(names)
+}
+
+/// Set the font families tried, in order, for text whose font isn't
+/// registered and for characters its font has no glyph for
+#[frb]
+pub fn set_font_fallbacks(doc: &FigmaDocument, families: Vec<String>) -> Result<()> {
+    doc.fonts.write()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?
+        .set_fallbacks(families);
+    clear_tile_cache(doc)
+}
+
+/// List every font used by text in the document and how it resolves
+/// against the registered fonts
+#[frb]
+pub fn get_document_fonts(doc: &FigmaDocument) -> Result<Vec<FontUsageInfo>> {
+    let fonts = doc.fonts.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+
+    Ok(collect_font_usage(doc.file.ordered_nodes()).into_iter().map(|usage| {
+        let found = fonts.resolve(&usage.request());
+        FontUsageInfo {
+            status: found.as_ref().map_or("MISSING", |found| found.kind.name()).to_string(),
+            resolved_font: found.map(|found| found.font.display_name()),
+            family: usage.family,
+            style: usage.style,
+            postscript_name: usage.postscript_name,
+            node_ids: usage.node_ids,
+        }
+    }).collect())
+}
+
+/// A font used by the document
+#[frb]
+#[derive(Debug, Clone)]
+pub struct FontUsageInfo {
+    pub family: String,
+    pub style: String,
+    pub postscript_name: String,
+    /// "EXACT", "SUBSTITUTE" (another face of the family), "FALLBACK" or "MISSING"
+    pub status: String,
+    /// "Family Style" of the registered face used, if any
+    pub resolved_font: Option<String>,
+    /// TEXT nodes that use the font
+    pub node_ids: Vec<String>,
+}
+
+/// Convert a TEXT node to outline paths using the registered fonts, one per
+/// text style with its fills. Paths are relative to the node's top-left, like
+/// vector geometry. Glyphs without a registered font are left out.
+#[frb]
+pub fn text_to_paths(doc: &FigmaDocument, node_id: String) -> Result<Vec<TextPathInfo>> {
+    let node = doc.file.get_node(&node_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(node_id.clone()))?;
+    let text = node.text.as_ref()
+        .ok_or_else(|| FigmaError::UnsupportedNodeType(format!("{} has no text", node_id)))?;
+    let fonts = doc.fonts.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+
+    let layout = text.layout(node.width, node.height, &fonts);
+    Ok(layout.outlines().into_iter().map(|outline| TextPathInfo {
+        path: PathData {
+            commands: path_to_svg(&outline.path),
+            fill_rule: "nonzero".to_string(),
+        },
+        fills: outline.fills.iter().map(|paint| paint.to_paint_info(node.width, node.height)).collect(),
+    }).collect())
+}
+
+/// Text outlines in one style
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct TextPathInfo {
+    pub path: PathData,
+    pub fills: Vec<PaintInfo>,
+}
+
+/// Convert a node's strokes to geometry to fill, so clients without
+/// inside/outside stroke support can draw them. None if the node has no
+/// stroke weight or no outline.
+#[frb]
+pub fn get_stroke_outline(doc: &FigmaDocument, node_id: String) -> Result<Option<StrokeOutlineInfo>> {
+    let node = doc.file.get_node(&node_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(node_id))?;
+
+    Ok(node.stroke_outline().map(|outline| StrokeOutlineInfo {
+        path: PathData {
+            commands: path_to_svg(&outline.path),
+            fill_rule: "nonzero".to_string(),
+        },
+        clip: outline.clip.map(|(path, even_odd)| PathData {
+            commands: path_to_svg(&path),
+            fill_rule: if even_odd { "evenodd" } else { "nonzero" }.to_string(),
+        }),
+    }))
 }
 
 /// Get information about a specific node
@@ -193,7 +828,8 @@ pub fn get_children(doc: &FigmaDocument, node_id: String) -> Result<Vec<NodeInfo
         .collect())
 }
 
-/// Get render commands for a node (and optionally its children)
+/// Get render commands for a node (and optionally its children), with
+/// frames scrolled as set by [`set_scroll_offset`]
 #[frb]
 pub fn render_node(
     doc: &FigmaDocument,
@@ -203,39 +839,400 @@ pub fn render_node(
     let node = doc.file.get_node(&node_id)
         .ok_or_else(|| FigmaError::NodeNotFound(node_id))?;
 
+    let state = RenderState::read(doc)?;
+    let ctx = state.context();
+
     let mut commands = Vec::new();
-    render_node_recursive(doc, node, include_children, &mut commands)?;
+    render_node_recursive(&doc.file.nodes, node, include_children, &ctx, &mut commands)?;
     Ok(commands)
 }
 
 fn render_node_recursive(
-    doc: &FigmaDocument,
+    nodes: &HashMap<String, FigmaNode>,
     node: &FigmaNode,
     include_children: bool,
+    ctx: &RenderContext,
     commands: &mut Vec<DrawCommand>,
 ) -> Result<()> {
-    // Generate draw command for this node
-    if let Some(cmd) = node.to_draw_command() {
-        commands.push(cmd);
-    }
+    // Generate draw commands for this node
+    let content = node.content_draw_commands(ctx);
 
-    // Recursively render children
+    // Recursively render children, moved by the node's scroll offset
+    let mut children = Vec::new();
     if include_children {
+        let offset = ctx.scroll_offsets.and_then(|offsets| offsets.get(&node.id));
         for child_id in &node.children {
-            if let Some(child) = doc.file.get_node(child_id) {
-                render_node_recursive(doc, child, true, commands)?;
+            if let Some(child) = nodes.get(child_id) {
+                let scrolled = offset.and_then(|offset| scroll::scroll_child(child, *offset));
+                render_node_recursive(nodes, scrolled.as_ref().unwrap_or(child), true, ctx, &mut children)?;
             }
         }
     }
 
+    let isolate = include_children && node.blends_with_backdrop(nodes);
+    commands.extend(node.composite(content, node.clip_children(children), isolate));
     Ok(())
 }
 
-/// Calculate layout for auto-layout frames
+/// Read locks on the document state that rendering uses, held while
+/// commands are generated
+struct RenderState<'a> {
+    file: &'a FigFile,
+    images: RwLockReadGuard<'a, ImageStore>,
+    fonts: RwLockReadGuard<'a, FontRegistry>,
+    modes: RwLockReadGuard<'a, HashMap<String, String>>,
+    scroll_offsets: RwLockReadGuard<'a, ScrollOffsets>,
+}
+
+impl<'a> RenderState<'a> {
+    fn read(doc: &'a FigmaDocument) -> Result<Self> {
+        Ok(RenderState {
+            file: &doc.file,
+            images: read(&doc.images)?,
+            fonts: read(&doc.fonts)?,
+            modes: read(&doc.variable_modes)?,
+            scroll_offsets: read(&doc.scroll_offsets)?,
+        })
+    }
+
+    /// Render with the app's images, fonts, variable modes and scroll
+    /// positions
+    fn context(&self) -> RenderContext<'_> {
+        RenderContext {
+            images: &self.images,
+            fonts: &self.fonts,
+            variables: Some(VariableResolver::new(self.file, &self.modes)),
+            scroll_offsets: Some(&self.scroll_offsets),
+        }
+    }
+}
+
+fn read<T>(lock: &RwLock<T>) -> Result<RwLockReadGuard<'_, T>> {
+    lock.read().map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))
+}
+
+/// List the properties of a component set, a component, or an instance's
+/// component. Variant properties aren't included, see `get_variant_axes`.
+#[frb]
+pub fn get_component_properties(doc: &FigmaDocument, node_id: String) -> Result<Vec<ComponentPropertyInfo>> {
+    let component = component_of(doc, &node_id)?;
+    Ok(components::component_properties(&doc.file, component).into_iter()
+        .map(|def| ComponentPropertyInfo {
+            id: def.id,
+            name: def.name,
+            property_type: def.property_type.map(|t| t.name()).unwrap_or_default().to_string(),
+            default_value: def.default_value.map(|value| value.to_string()).unwrap_or_default(),
+            preferred_values: def.preferred_values,
+        })
+        .collect())
+}
+
+/// List the variant properties of a component set, or of the set a
+/// component or an instance's component belongs to, parsed from the
+/// variants' names. Empty for components that aren't variants.
+#[frb]
+pub fn get_variant_axes(doc: &FigmaDocument, node_id: String) -> Result<Vec<VariantAxisInfo>> {
+    let component = component_of(doc, &node_id)?;
+    let set = if components::is_component_set(component) {
+        Some(component)
+    } else {
+        components::component_set(&doc.file, component)
+    };
+    Ok(set.map(|set| components::variant_axes(&doc.file, set)).unwrap_or_default().into_iter()
+        .map(|axis| VariantAxisInfo { name: axis.name, values: axis.values })
+        .collect())
+}
+
+/// Get the component and variant an instance uses, and its property values
+#[frb]
+pub fn resolve_instance_variant(doc: &FigmaDocument, instance_id: String) -> Result<InstanceVariantInfo> {
+    let instance = doc.file.get_node(&instance_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(instance_id.clone()))?;
+    let component = instance.component_id()
+        .and_then(|id| doc.file.get_node(id))
+        .ok_or_else(|| FigmaError::UnsupportedNodeType(instance.node_type.clone()))?;
+    let set = components::component_set(&doc.file, component);
+
+    Ok(InstanceVariantInfo {
+        component_id: component.id.clone(),
+        component_set_id: set.map(|set| set.id.clone()),
+        variant: if set.is_some() { components::parse_variant_name(&component.name) } else { Vec::new() }.into_iter()
+            .map(|(name, value)| PropertyValueInfo { name, value })
+            .collect(),
+        properties: components::instance_properties(&doc.file, instance).into_iter()
+            .map(|(def, value)| PropertyValueInfo { name: def.name, value: value.to_string() })
+            .collect(),
+    })
+}
+
+/// Render an instance with other property values, without changing the
+/// document. Values are given by property name; variant properties switch
+/// to the matching variant. Bool values are "true" or "false", and instance
+/// swap values are component ids.
+#[frb]
+pub fn render_instance_with_props(
+    doc: &FigmaDocument,
+    instance_id: String,
+    props: Vec<PropertyValueInfo>,
+) -> Result<Vec<DrawCommand>> {
+    let instance = doc.file.get_node(&instance_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(instance_id.clone()))?;
+    let props: Vec<(String, String)> = props.into_iter().map(|prop| (prop.name, prop.value)).collect();
+    let mut instance = components::with_properties(&doc.file, instance, &props)?;
+
+    let expansion = expand_instance(&doc.file, &instance);
+    instance.children = expansion.children.into_iter().flat_map(|(_, children)| children).collect();
+    let mut nodes = expansion.nodes;
+    nodes.insert(instance.id.clone(), instance.clone());
+
+    let state = RenderState::read(doc)?;
+    let ctx = RenderContext { scroll_offsets: None, ..state.context() };
+
+    let mut commands = Vec::new();
+    render_node_recursive(&nodes, &instance, true, &ctx, &mut commands)?;
+    Ok(commands)
+}
+
+/// A node's component: the node itself for components and component sets,
+/// else an instance's component
+fn component_of<'a>(doc: &'a FigmaDocument, node_id: &str) -> Result<&'a FigmaNode> {
+    let node = doc.file.get_node(node_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(node_id.to_string()))?;
+    if components::is_component(node) || components::is_component_set(node) {
+        return Ok(node);
+    }
+    node.component_id()
+        .and_then(|id| doc.file.get_node(id))
+        .ok_or_else(|| FigmaError::UnsupportedNodeType(node.node_type.clone()))
+}
+
+/// List the variable collections in the document, with the mode each uses
+/// when rendering
+#[frb]
+pub fn get_variable_collections(doc: &FigmaDocument) -> Result<Vec<VariableCollectionInfo>> {
+    let modes = read(&doc.variable_modes)?;
+    let resolver = VariableResolver::new(&doc.file, &modes);
+    Ok(doc.file.ordered_nodes().into_iter()
+        .filter(|node| node.node_type == "VARIABLE_SET")
+        .map(|node| VariableCollectionInfo {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            modes: node.modes.iter()
+                .map(|mode| VariableModeInfo { id: mode.id.clone(), name: mode.name.clone() })
+                .collect(),
+            active_mode_id: resolver.mode(node, None).unwrap_or_default().to_string(),
+        })
+        .collect())
+}
+
+/// List the variables of a collection, with their value in each mode
+#[frb]
+pub fn get_variables(doc: &FigmaDocument, collection_id: String) -> Result<Vec<VariableInfo>> {
+    let modes = read(&doc.variable_modes)?;
+    let resolver = VariableResolver::new(&doc.file, &modes);
+    let collection = doc.file.get_node(&collection_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(collection_id.clone()))?;
+
+    Ok(doc.file.ordered_nodes().into_iter()
+        .filter_map(|node| Some((node, node.variable.as_ref()?)))
+        .filter(|(_, variable)| resolver.lookup(&variable.collection).is_some_and(|c| c.id == collection.id))
+        .map(|(node, variable)| VariableInfo {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            resolved_type: variable.resolved_type.clone(),
+            values: variable.values.iter()
+                .map(|(mode_id, value)| VariableModeValueInfo {
+                    mode_id: mode_id.clone(),
+                    value: variable_value_info(&resolver, value),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Resolve a variable's value, following aliases, in the modes used for a
+/// node's content, or the modes used when rendering if no node is given
+#[frb]
+pub fn resolve_variable(doc: &FigmaDocument, variable_id: String, node_id: Option<String>) -> Result<VariableValueInfo> {
+    let modes = read(&doc.variable_modes)?;
+    let resolver = VariableResolver::new(&doc.file, &modes);
+    let node = match node_id {
+        Some(id) => Some(doc.file.get_node(&id).ok_or_else(|| FigmaError::NodeNotFound(id.clone()))?),
+        None => None,
+    };
+    let value = resolver.resolve(&VariableRef::Id(variable_id), node)?;
+    Ok(variable_value_info(&resolver, &value))
+}
+
+/// Pick the mode a variable collection uses when rendering, such as light
+/// or dark, except in frames that pick their own. Cached tiles showing
+/// nodes bound to the collection's variables are marked dirty.
+///
+/// Returns the tiles marked dirty.
+#[frb]
+pub fn set_variable_mode(doc: &FigmaDocument, collection_id: String, mode_id: String) -> Result<Vec<TileCoordInfo>> {
+    let collection = doc.file.get_node(&collection_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(collection_id.clone()))?;
+    if !collection.modes.iter().any(|mode| mode.id == mode_id) {
+        return Err(FigmaError::VariableError(format!("{} has no mode {mode_id}", collection.name)));
+    }
+
+    let mut modes = doc.variable_modes.write()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    modes.insert(collection_id.clone(), mode_id);
+    let changed = VariableResolver::new(&doc.file, &modes).affected_nodes(&collection_id);
+    drop(modes);
+
+    invalidate_tiles(doc, changed)
+}
+
+fn variable_value_info(resolver: &VariableResolver, value: &VariableValue) -> VariableValueInfo {
+    let mut info = VariableValueInfo {
+        value_type: value.type_name().to_string(),
+        value: String::new(),
+        color: None,
+        alias_id: None,
+    };
+    match value {
+        VariableValue::Bool(value) => info.value = value.to_string(),
+        VariableValue::Float(value) => info.value = value.to_string(),
+        VariableValue::String(value) => info.value = value.clone(),
+        VariableValue::Color(color) => info.color = Some(color.clone()),
+        VariableValue::Alias(alias) => info.alias_id = resolver.lookup(alias).map(|node| node.id.clone()),
+    }
+    info
+}
+
+/// List the prototype flows of each page, in the order Figma shows them
+#[frb]
+pub fn get_prototype_flows(doc: &FigmaDocument) -> Vec<FlowInfo> {
+    prototype::flows(&doc.file)
+}
+
+/// The interactions of a node and its visible descendants, parents first.
+/// Pass a frame to get all the hotspots shown on it.
+#[frb]
+pub fn get_interactions(doc: &FigmaDocument, node_id: String) -> Result<Vec<InteractionInfo>> {
+    Ok(prototype::hotspots(&doc.file, &node_id)?.into_iter()
+        .map(|(node, interaction)| interaction.to_interaction_info(&node.id, &doc.file))
+        .collect())
+}
+
+/// The frames a prototype can show starting from a frame, following
+/// navigate, overlay and swap actions breadth first. The start comes first.
+#[frb]
+pub fn reachable_frames(doc: &FigmaDocument, start_id: String) -> Result<Vec<String>> {
+    prototype::reachable_frames(&doc.file, &start_id)
+}
+
+/// Draw commands for a moment of a prototype transition from one frame to
+/// another, `t` being the fraction of the transition's duration elapsed,
+/// from 0 to 1. Both frames are drawn with their top-left corner at the
+/// origin when at rest. Smart animate blends the layers the frames share,
+/// matched by name; dissolve, slide, push and move transitions fade or move
+/// the frames as a whole.
+#[frb]
+pub fn sample_transition(
+    doc: &FigmaDocument,
+    from_id: String,
+    to_id: String,
+    transition: PrototypeTransitionInfo,
+    t: f64,
+) -> Result<Vec<DrawCommand>> {
+    let state = RenderState::read(doc)?;
+    let ctx = state.context();
+    let frame = transitions::sample_transition(&doc.file, ctx.variables, &from_id, &to_id, &Transition::from_info(&transition), t)?;
+
+    // Variables were bound while sampling
+    let ctx = RenderContext { variables: None, scroll_offsets: None, ..ctx };
+    let mut commands = Vec::new();
+    for root in frame.roots.iter().filter_map(|id| frame.nodes.get(id)) {
+        render_node_recursive(&frame.nodes, root, true, &ctx, &mut commands)?;
+    }
+    Ok(commands)
+}
+
+/// A node and its visible descendants that scroll in a prototype, parents
+/// first
+#[frb]
+pub fn get_scroll_frames(doc: &FigmaDocument, root_id: String) -> Result<Vec<ScrollFrameInfo>> {
+    let offsets = doc.scroll_offsets.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    Ok(scroll::scrolling_frames(&doc.file, &root_id)?.into_iter()
+        .map(|frame| {
+            let (max_x, max_y) = scroll::max_offset(&doc.file, frame);
+            let (x, y) = offsets.get(&frame.id).copied().unwrap_or_default();
+            let children_with = |behavior: ScrollBehavior| frame.children.iter()
+                .filter(|id| doc.file.get_node(id).is_some_and(|child| child.scroll_behavior == behavior))
+                .cloned()
+                .collect();
+            ScrollFrameInfo {
+                node_id: frame.id.clone(),
+                direction: frame.scroll_direction.name().to_string(),
+                content_bounds: scroll::content_bounds(&doc.file, frame),
+                max_offset: PointInfo { x: max_x, y: max_y },
+                offset: PointInfo { x, y },
+                clips_content: frame.clips_content(),
+                fixed_children: children_with(ScrollBehavior::Fixed),
+                sticky_children: children_with(ScrollBehavior::Sticky),
+            }
+        })
+        .collect())
+}
+
+/// Scroll a frame's content when rendering nodes and tiles, limited to the
+/// range it scrolls over. Cached tiles showing the frame are marked dirty.
+///
+/// Returns the tiles marked dirty.
+#[frb]
+pub fn set_scroll_offset(doc: &FigmaDocument, node_id: String, x: f64, y: f64) -> Result<Vec<TileCoordInfo>> {
+    let frame = doc.file.get_node(&node_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(node_id.clone()))?;
+    let offset = scroll::clamp_offset(&doc.file, frame, (x, y));
+
+    let mut offsets = doc.scroll_offsets.write()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    if offsets.insert(node_id.clone(), offset) == Some(offset) {
+        return Ok(Vec::new());
+    }
+    drop(offsets);
+
+    // The frame and its content, before and after scrolling
+    let mut changed = Vec::new();
+    let mut pending = vec![node_id];
+    while let Some(id) = pending.pop() {
+        pending.extend(doc.file.get_node(&id).into_iter().flat_map(|node| node.children.iter().cloned()));
+        changed.push(id);
+    }
+    invalidate_tiles(doc, changed)
+}
+
+/// The boxes of a node and its visible descendants, each relative to its
+/// parent, as laid out by Figma
 #[frb]
 pub fn calculate_layout(doc: &FigmaDocument, root_id: String) -> Result<Vec<LayoutResult>> {
-    // TODO: Implement auto-layout algorithm
-    Ok(vec![])
+    layout::layout_boxes(&doc.file, &root_id)
+}
+
+/// A frame's layout grids, each with its columns, rows or lines at the
+/// frame's current size
+#[frb]
+pub fn get_layout_grids(doc: &FigmaDocument, node_id: String) -> Result<Vec<LayoutGridInfo>> {
+    let node = doc.file.get_node(&node_id)
+        .ok_or_else(|| FigmaError::NodeNotFound(node_id))?;
+    Ok(node.layout_grids.iter()
+        .map(|grid| grid.to_layout_grid_info(node.width, node.height))
+        .collect())
+}
+
+/// Draw commands for the visible layout grids of a node and its visible
+/// descendants, to draw over the commands from `render_node`
+#[frb]
+pub fn render_layout_grids(doc: &FigmaDocument, root_id: String) -> Result<Vec<DrawCommand>> {
+    Ok(layout::layout_boxes(&doc.file, &root_id)?.iter()
+        .filter_map(|layout| doc.file.get_node(&layout.node_id))
+        .flat_map(grids::overlay_commands)
+        .collect())
 }
 
 #[frb]
@@ -248,6 +1245,53 @@ pub struct LayoutResult {
     pub height: f64,
 }
 
+/// A layout grid, resolved at its frame's size
+#[frb]
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutGridInfo {
+    /// "COLUMNS", "ROWS" or "GRID"
+    pub pattern: String,
+    /// "MIN", "CENTER", "MAX" or "STRETCH"
+    pub alignment: String,
+    /// Number of columns or rows, None for as many as fit
+    pub count: Option<u32>,
+    pub section_size: f64,
+    pub gutter_size: f64,
+    pub offset: f64,
+    pub visible: bool,
+    pub color: ColorInfo,
+    /// Columns or rows relative to the frame, or for "GRID" its lines
+    pub rects: Vec<RectInfo>,
+}
+
+/// Export the document's variables and color, text and effect styles as
+/// design tokens: W3C Design Tokens JSON for "w3c", or a Dart file of
+/// Flutter constants for "dart"
+#[frb]
+pub fn export_design_tokens(doc: &FigmaDocument, format: String) -> Result<String> {
+    let format = TokenFormat::from_name(&format)
+        .ok_or_else(|| FigmaError::ExportError(format!("unknown token format \"{format}\"")))?;
+    Ok(tokens::export_design_tokens(&doc.file, format))
+}
+
+/// Generate the source of a Dart file of Flutter widgets for a node and its
+/// descendants, with a widget class for each component it uses
+#[frb]
+pub fn generate_flutter_widgets(doc: &FigmaDocument, node_id: String) -> Result<String> {
+    codegen::generate_widgets(&doc.file, &node_id)
+}
+
+/// Export a node and its descendants as a self-contained HTML page, with
+/// the registered images and fonts they use embedded
+#[frb]
+pub fn export_html(doc: &FigmaDocument, node_id: String) -> Result<String> {
+    let images = doc.images.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    let fonts = doc.fonts.read()
+        .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
+    html::export_html(&doc.file, &images, &fonts, &node_id)
+}
+
 /// Export a node as SVG path data
 #[frb]
 pub fn export_svg_path(doc: &FigmaDocument, node_id: String) -> Result<String> {
@@ -257,16 +1301,19 @@ pub fn export_svg_path(doc: &FigmaDocument, node_id: String) -> Result<String> {
     Ok(node.to_svg_path())
 }
 
-/// Decode Kiwi-encoded fill paint data (matches Figma's JsKiwiSerialization_decodeFillPaintData)
+/// Decode a Kiwi-encoded `Paint[]` value, such as a node's `fillPaints`,
+/// with the document's schema. Paint types that can't be drawn are skipped,
+/// and gradient geometry is computed for a 1x1 node.
 #[frb]
-pub fn decode_fill_paint(data: Vec<u8>) -> Result<Vec<PaintInfo>> {
-    crate::kiwi::decode_fill_paint_data(&data)
+pub fn decode_fill_paint(doc: &FigmaDocument, data: Vec<u8>) -> Result<Vec<PaintInfo>> {
+    Ok(doc.file.decode_paints(&data)?.iter().map(|paint| paint.to_paint_info(1.0, 1.0)).collect())
 }
 
-/// Decode Kiwi-encoded effect data
+/// Decode a Kiwi-encoded `Effect[]` value, such as a node's `effects`, with
+/// the document's schema. Effect types that can't be drawn are skipped.
 #[frb]
-pub fn decode_effects(data: Vec<u8>) -> Result<Vec<EffectInfo>> {
-    crate::kiwi::decode_effect_data(&data)
+pub fn decode_effects(doc: &FigmaDocument, data: Vec<u8>) -> Result<Vec<EffectInfo>> {
+    Ok(doc.file.decode_effects(&data)?.iter().map(Effect::to_effect_info).collect())
 }
 
 /// Decode Kiwi-encoded vector data
@@ -297,7 +1344,7 @@ pub struct ViewportInfo {
 
 impl ViewportInfo {
     /// Convert to internal Viewport
-    fn to_viewport(&self) -> Viewport {
+    fn to_viewport(self) -> Viewport {
         Viewport::new(self.x, self.y, self.width, self.height, self.scale)
     }
 }
@@ -391,6 +1438,8 @@ pub fn render_tiles(
     let spatial_index = spatial_lock.as_ref()
         .ok_or_else(|| FigmaError::DecodeError("Spatial index not initialized".into()))?;
 
+    let state = RenderState::read(doc)?;
+    let ctx = state.context();
     let mut grid = doc.tile_grid.write()
         .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
 
@@ -400,7 +1449,7 @@ pub fn render_tiles(
     let mut results = Vec::with_capacity(visible_coords.len());
 
     for coord in visible_coords {
-        let tile = grid.get_or_create_tile(coord, &doc.file.nodes, spatial_index);
+        let tile = grid.get_or_create_tile(coord, &doc.file.nodes, &ctx, spatial_index);
 
         results.push(TileRenderResult {
             coord: tile.coord.into(),
@@ -437,11 +1486,13 @@ pub fn render_single_tile(
     let spatial_index = spatial_lock.as_ref()
         .ok_or_else(|| FigmaError::DecodeError("Spatial index not initialized".into()))?;
 
+    let state = RenderState::read(doc)?;
+    let ctx = state.context();
     let mut grid = doc.tile_grid.write()
         .map_err(|_| FigmaError::DecodeError("Lock poisoned".into()))?;
 
     let tile_coord: TileCoord = coord.into();
-    let tile = grid.get_or_create_tile(tile_coord, &doc.file.nodes, spatial_index);
+    let tile = grid.get_or_create_tile(tile_coord, &doc.file.nodes, &ctx, spatial_index);
 
     Ok(TileRenderResult {
         coord: tile.coord.into(),
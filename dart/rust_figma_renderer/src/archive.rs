@@ -0,0 +1,333 @@
+//! Zipped .fig archives
+//!
+//! Figma exports files as ZIP archives holding the fig-kiwi stream plus
+//! metadata and the image assets it references:
+//!
+//! ```text
+//! canvas.fig        fig-kiwi stream (see KIWI_FORMAT.md)
+//! meta.json         file name, export time, background color
+//! thumbnail.png     preview image
+//! images/<hash>     image bytes, keyed by the hash used in image paints
+//! ```
+//!
+//! Only the subset of ZIP that Figma writes is supported: stored and
+//! DEFLATE entries, no ZIP64, no encryption.
+
+use crate::api::ColorInfo;
+use crate::{FigmaError, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIG: u32 = 0x0605_4b50;
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+const MAX_COMMENT_LEN: usize = 0xffff;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+pub const CANVAS_ENTRY: &str = "canvas.fig";
+pub const META_ENTRY: &str = "meta.json";
+pub const THUMBNAIL_ENTRY: &str = "thumbnail.png";
+const IMAGES_DIR: &str = "images/";
+
+/// Whether the data looks like a ZIP archive rather than a raw fig-kiwi stream
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_HEADER_SIG.to_le_bytes())
+}
+
+/// The unpacked contents of a zipped .fig file
+#[derive(Debug, Clone, Default)]
+pub struct FigArchive {
+    /// The fig-kiwi stream from `canvas.fig`
+    pub canvas: Vec<u8>,
+    pub meta: Option<FigMeta>,
+    pub thumbnail: Option<Vec<u8>>,
+    /// Image bytes keyed by hash
+    pub images: HashMap<String, Vec<u8>>,
+}
+
+impl FigArchive {
+    /// Unpack an archive. `canvas.fig` is required; everything else is optional.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut archive = FigArchive::default();
+        let mut canvas = None;
+
+        for entry in read_entries(data)? {
+            if entry.name == CANVAS_ENTRY {
+                canvas = Some(entry.read(data)?);
+            } else if entry.name == META_ENTRY {
+                // A broken meta.json shouldn't stop the file from loading
+                archive.meta = serde_json::from_slice(&entry.read(data)?).ok();
+            } else if entry.name == THUMBNAIL_ENTRY {
+                archive.thumbnail = Some(entry.read(data)?);
+            } else if let Some(hash) = entry.name.strip_prefix(IMAGES_DIR) {
+                if !hash.is_empty() && !hash.contains('/') {
+                    archive.images.insert(hash.to_string(), entry.read(data)?);
+                }
+            }
+        }
+
+        archive.canvas = canvas.ok_or_else(|| FigmaError::ArchiveError(format!("missing {}", CANVAS_ENTRY)))?;
+        Ok(archive)
+    }
+}
+
+/// Unpack a single entry, without touching the others
+pub fn read_entry(data: &[u8], name: &str) -> Result<Option<Vec<u8>>> {
+    read_entries(data)?.iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.read(data))
+        .transpose()
+}
+
+/// Fields from `meta.json`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FigMeta {
+    #[serde(default)]
+    pub file_name: String,
+    /// ISO 8601 timestamp of the export
+    #[serde(default)]
+    pub exported_at: String,
+    #[serde(default)]
+    pub client_meta: ClientMeta,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClientMeta {
+    pub background_color: Option<MetaColor>,
+}
+
+/// Color with channels in 0..1, as stored in `meta.json`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct MetaColor {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    #[serde(default = "default_alpha")]
+    pub a: f64,
+}
+
+fn default_alpha() -> f64 {
+    1.0
+}
+
+impl FigMeta {
+    pub fn background_color(&self) -> Option<ColorInfo> {
+        let color = self.client_meta.background_color?;
+        let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Some(ColorInfo {
+            r: channel(color.r),
+            g: channel(color.g),
+            b: channel(color.b),
+            a: channel(color.a),
+        })
+    }
+}
+
+// =============================================================================
+// ZIP reading
+// =============================================================================
+
+struct Entry {
+    name: String,
+    method: u16,
+    compressed_size: usize,
+    uncompressed_size: usize,
+    local_header_offset: usize,
+}
+
+impl Entry {
+    fn read(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let header = self.local_header_offset;
+        if read_u32(data, header)? != LOCAL_HEADER_SIG {
+            return Err(self.error("bad local header"));
+        }
+        let name_len = read_u16(data, header + 26)? as usize;
+        let extra_len = read_u16(data, header + 28)? as usize;
+        let start = header + 30 + name_len + extra_len;
+        let compressed = start.checked_add(self.compressed_size)
+            .and_then(|end| data.get(start..end))
+            .ok_or_else(|| self.error("data out of bounds"))?;
+
+        match self.method {
+            METHOD_STORED => Ok(compressed.to_vec()),
+            METHOD_DEFLATE => {
+                // Never trust the declared size for the allocation, and
+                // never read more than it allows
+                let mut out = Vec::new();
+                flate2::read::DeflateDecoder::new(compressed)
+                    .take(self.uncompressed_size as u64)
+                    .read_to_end(&mut out)
+                    .map_err(|e| FigmaError::DecompressionError(format!("{}: {}", self.name, e)))?;
+                Ok(out)
+            }
+            method => Err(self.error(&format!("unsupported compression method {}", method))),
+        }
+    }
+
+    fn error(&self, message: &str) -> FigmaError {
+        FigmaError::ArchiveError(format!("{}: {}", self.name, message))
+    }
+}
+
+/// Read the central directory
+fn read_entries(data: &[u8]) -> Result<Vec<Entry>> {
+    let end = find_end_of_central_dir(data)
+        .ok_or_else(|| FigmaError::ArchiveError("missing end of central directory".into()))?;
+    let count = read_u16(data, end + 10)? as usize;
+    let mut offset = read_u32(data, end + 16)? as usize;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if read_u32(data, offset)? != CENTRAL_HEADER_SIG {
+            return Err(FigmaError::ArchiveError("bad central directory entry".into()));
+        }
+        let flags = read_u16(data, offset + 8)?;
+        let method = read_u16(data, offset + 10)?;
+        let compressed_size = read_u32(data, offset + 20)? as usize;
+        let uncompressed_size = read_u32(data, offset + 24)? as usize;
+        let name_len = read_u16(data, offset + 28)? as usize;
+        let extra_len = read_u16(data, offset + 30)? as usize;
+        let comment_len = read_u16(data, offset + 32)? as usize;
+        let local_header_offset = read_u32(data, offset + 42)? as usize;
+        let name = data.get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(truncated)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+
+        if flags & 1 != 0 {
+            return Err(FigmaError::ArchiveError(format!("{}: encrypted entries are not supported", name)));
+        }
+        entries.push(Entry { name, method, compressed_size, uncompressed_size, local_header_offset });
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Scan backwards for the end of central directory record, which may be
+/// followed by a comment of up to 64K
+fn find_end_of_central_dir(data: &[u8]) -> Option<usize> {
+    if data.len() < END_OF_CENTRAL_DIR_LEN {
+        return None;
+    }
+    let last = data.len() - END_OF_CENTRAL_DIR_LEN;
+    let first = last.saturating_sub(MAX_COMMENT_LEN);
+    (first..=last).rev().find(|&offset| {
+        data[offset..offset + 4] == END_OF_CENTRAL_DIR_SIG.to_le_bytes()
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or_else(truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn truncated() -> FigmaError {
+    FigmaError::ArchiveError("archive is truncated".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Build a ZIP archive in memory, DEFLATE-compressing entries when `deflate` is set
+    fn zip(entries: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for (name, contents) in entries {
+            let (method, stored) = if deflate {
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(contents).unwrap();
+                (METHOD_DEFLATE, encoder.finish().unwrap())
+            } else {
+                (METHOD_STORED, contents.to_vec())
+            };
+            let offset = out.len() as u32;
+            out.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+            out.extend_from_slice(&[20, 0, 0, 0]);
+            out.extend_from_slice(&method.to_le_bytes());
+            out.extend_from_slice(&[0; 8]); // time, date, crc
+            out.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&stored);
+
+            central.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 8]);
+            central.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(&END_OF_CENTRAL_DIR_SIG.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&central_offset.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    const META: &[u8] = br#"{"client_meta":{"background_color":{"r":0.96,"g":0.96,"b":0.96,"a":1}},"file_name":"Mockups","exported_at":"2026-03-01T12:00:00.000Z"}"#;
+
+    #[test]
+    fn test_parse_archive() {
+        for deflate in [false, true] {
+            let data = zip(&[
+                ("canvas.fig", b"fig-kiwi"),
+                ("meta.json", META),
+                ("thumbnail.png", b"png"),
+                ("images/", b""),
+                ("images/0a1b2c", b"image bytes"),
+            ], deflate);
+            assert!(is_archive(&data));
+
+            let archive = FigArchive::parse(&data).unwrap();
+            assert_eq!(archive.canvas, b"fig-kiwi");
+            assert_eq!(archive.thumbnail.as_deref(), Some(&b"png"[..]));
+            assert_eq!(archive.images.len(), 1);
+            assert_eq!(archive.images["0a1b2c"], b"image bytes");
+
+            let meta = archive.meta.unwrap();
+            assert_eq!(meta.file_name, "Mockups");
+            assert_eq!(meta.exported_at, "2026-03-01T12:00:00.000Z");
+            let background = meta.background_color().unwrap();
+            assert_eq!((background.r, background.g, background.b, background.a), (245, 245, 245, 255));
+        }
+    }
+
+    #[test]
+    fn test_read_entry() {
+        let data = zip(&[("canvas.fig", b"fig-kiwi"), ("thumbnail.png", b"png")], true);
+        assert_eq!(read_entry(&data, THUMBNAIL_ENTRY).unwrap(), Some(b"png".to_vec()));
+        assert_eq!(read_entry(&data, META_ENTRY).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_archive_errors() {
+        let missing_canvas = zip(&[("meta.json", META)], false);
+        assert!(matches!(FigArchive::parse(&missing_canvas), Err(FigmaError::ArchiveError(_))));
+
+        let data = zip(&[("canvas.fig", b"fig-kiwi")], false);
+        for len in [0, 10, data.len() / 2, data.len() - 1] {
+            assert!(FigArchive::parse(&data[..len]).is_err(), "length {}", len);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Command line access to the renderer's exports
+//!
+//! ```text
+//! figma tokens design.fig --format dart --output lib/tokens.dart
+//! figma widgets design.fig --node 1:23 --output lib/screen.dart
+//! figma html design.fig --node 1:23 --output screen.html
+//! ```
+
+use figma_renderer::{export_design_tokens, export_html, generate_flutter_widgets, load_figma_file, FigmaDocument};
+use std::io::Write;
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+Usage: figma <command> <file.fig> [options]
+
+Commands:
+  tokens    Export variables and styles as design tokens
+  widgets   Generate Flutter widgets for a node
+  html      Export a node as an HTML page
+
+Options:
+  --format <format>  Output format: w3c (default) or dart
+  --node <id>        Node to generate widgets or HTML for
+  --output <path>    Write to a file instead of stdout
+";
+
+/// Options shared by the commands
+struct Options {
+    input: String,
+    format: Option<String>,
+    node: Option<String>,
+    output: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut input = None;
+        let mut format = None;
+        let mut node = None;
+        let mut output = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => format = Some(args.next().ok_or("Missing value for --format")?.clone()),
+                "--node" => node = Some(args.next().ok_or("Missing value for --node")?.clone()),
+                "--output" | "-o" => output = Some(args.next().ok_or("Missing value for --output")?.clone()),
+                flag if flag.starts_with('-') => return Err(format!("Unknown flag {flag}\n\n{USAGE}")),
+                path if input.is_none() => input = Some(path.to_string()),
+                extra => return Err(format!("Unexpected argument {extra}\n\n{USAGE}")),
+            }
+        }
+        Ok(Options { input: input.ok_or_else(|| format!("Missing input file\n\n{USAGE}"))?, format, node, output })
+    }
+
+    fn load(&self) -> Result<FigmaDocument, String> {
+        let data = fs::read(&self.input).map_err(|e| format!("Cannot read {}: {e}", self.input))?;
+        load_figma_file(data).map_err(|e| format!("Cannot load {}: {e}", self.input))
+    }
+
+    fn write(&self, contents: &str) -> Result<(), String> {
+        match &self.output {
+            Some(path) => fs::write(path, contents).map_err(|e| format!("Cannot write {path}: {e}")),
+            None => std::io::stdout().write_all(contents.as_bytes()).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+    match command.as_str() {
+        "tokens" => {
+            let options = Options::parse(args)?;
+            let doc = options.load()?;
+            let format = options.format.as_deref().unwrap_or("w3c");
+            let tokens = export_design_tokens(&doc, format.to_string()).map_err(|e| e.to_string())?;
+            options.write(&tokens)
+        }
+        "widgets" => {
+            let options = Options::parse(args)?;
+            let node = options.node.clone().ok_or_else(|| format!("Missing --node\n\n{USAGE}"))?;
+            let doc = options.load()?;
+            let source = generate_flutter_widgets(&doc, node).map_err(|e| e.to_string())?;
+            options.write(&source)
+        }
+        "html" => {
+            let options = Options::parse(args)?;
+            let node = options.node.clone().ok_or_else(|| format!("Missing --node\n\n{USAGE}"))?;
+            let doc = options.load()?;
+            let page = export_html(&doc, node).map_err(|e| e.to_string())?;
+            options.write(&page)
+        }
+        "help" | "--help" | "-h" => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        other => Err(format!("Unknown command {other}\n\n{USAGE}")),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        let _ = writeln!(std::io::stderr(), "{message}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,1085 @@
+//! Flutter widget code generation
+//!
+//! A node and its descendants are written as a Dart file of Flutter widgets.
+//! Auto layout frames become a `Row`, `Column` or `Wrap`, with `SizedBox`
+//! gaps and `Padding`, and children that fill the frame become `Expanded`
+//! or stretched. Other frames are a `Stack` positioning their children by
+//! their constraints. Shapes are decorated boxes, text is `Text` with its
+//! `TextStyle`s, and vectors are drawn by a `CustomPainter` with their paths
+//! baked in.
+//!
+//! Each component that is used becomes a widget class, and its instances
+//! construct it. The class takes the component's text and boolean
+//! properties as parameters, along with the texts and visibility that its
+//! instances override. Overrides inside nested instances aren't carried.
+
+use crate::api::ColorInfo;
+use crate::components::{component_properties, component_set, instance_properties, is_component, parse_variant_name, PropertyType, PropertyValue};
+use crate::effects::Effect;
+use crate::kiwi::{decode_vector_data, FigFile};
+use crate::layout::Constraint;
+use crate::nodes::{FigmaNode, NodeType};
+use crate::paints::Paint;
+use crate::strokes::{StrokeAlign, StrokeCap, StrokeJoin};
+use crate::text::{TextAlignHorizontal, TextAutoResize, TextStyle};
+use crate::tokens::{dart_identifier, dart_string, number, paint_color, text_style_fields, unique};
+use crate::{FigmaError, Result};
+use lyon::path::{Path, PathEvent};
+use std::collections::HashSet;
+
+/// Width `dart format` keeps lines to
+const LINE_WIDTH: usize = 80;
+
+/// Flutter names the generated code uses, which generated classes mustn't
+/// shadow
+const FLUTTER_NAMES: &[&str] = &[
+    "Alignment", "Axis", "Border", "BorderRadius", "BorderSide", "BoxDecoration", "BoxShadow", "BoxShape",
+    "BuildContext", "Canvas", "Color", "Column", "Container", "CrossAxisAlignment", "CustomPaint",
+    "CustomPainter", "DecoratedBox", "EdgeInsets", "Expanded", "FontStyle", "FontWeight", "IntrinsicHeight",
+    "IntrinsicWidth", "LinearGradient", "MainAxisAlignment", "MainAxisSize", "Offset", "Opacity", "Padding",
+    "Paint", "PaintingStyle", "Path", "PathFillType", "Positioned", "RadialGradient", "Radius", "Row", "Size",
+    "SizedBox", "Stack", "StatelessWidget", "StrokeCap", "StrokeJoin", "SweepGradient", "Text", "TextAlign",
+    "TextBaseline", "TextDecoration", "TextOverflow", "TextSpan", "TextStyle", "Transform", "Widget", "Wrap",
+    "WrapAlignment", "WrapCrossAlignment",
+];
+
+/// Constructors that can't be called as constants
+const NON_CONST: &[&str] = &["Container", "Border.all", "Transform.rotate"];
+
+/// Generate Flutter widget source for a node and its descendants. The node
+/// becomes a widget class named after it, followed by a class for each
+/// component its content uses.
+pub fn generate_widgets(file: &FigFile, node_id: &str) -> Result<String> {
+    let root = file.get_node(node_id).ok_or_else(|| FigmaError::NodeNotFound(node_id.to_string()))?;
+    if matches!(root.node_type.as_str(), "DOCUMENT" | "CANVAS") {
+        return Err(FigmaError::UnsupportedNodeType(root.node_type.clone()));
+    }
+
+    let mut generator = Generator {
+        file,
+        class_names: FLUTTER_NAMES.iter().map(|name| name.to_string()).collect(),
+        components: Vec::new(),
+        painters: Vec::new(),
+    };
+    // A component is written as its own class, under its class name
+    let root_class = (!is_component(root)).then(|| unique(&mut generator.class_names, dart_identifier(&root.name, true)));
+    generator.discover(root);
+
+    let mut out = format!("// Generated from the Figma node \"{}\" ({}).\n\n", root.name, root.id);
+    out.push_str("import 'package:flutter/widgets.dart';\n");
+    if let Some(name) = root_class {
+        let body = generator.content(root, &[], Fill::default());
+        write_class(&mut out, &name, &[], &body);
+    }
+    // Writing a class can add the classes of components it uses
+    let mut written = 0;
+    while written < generator.components.len() {
+        let class = &generator.components[written];
+        let (name, params) = (class.name.clone(), class.params.clone());
+        if let Some(component) = file.get_node(&class.component_id) {
+            let body = generator.content(component, &params, Fill::default());
+            write_class(&mut out, &name, &params, &body);
+        }
+        written += 1;
+    }
+    for (name, body) in &generator.painters {
+        out.push_str(&format!("\nclass {name} extends CustomPainter {{\n  const {name}();\n\n"));
+        out.push_str("  @override\n  void paint(Canvas canvas, Size size) {\n");
+        out.push_str(body);
+        out.push_str("  }\n\n  @override\n  bool shouldRepaint(covariant CustomPainter oldDelegate) => false;\n}\n");
+    }
+    Ok(out)
+}
+
+/// A Dart expression of the generated source
+#[derive(Debug, Clone)]
+enum Dart {
+    /// Source of an expression, and whether it's constant
+    Code(String, bool),
+    /// A constructor call, with positional arguments under empty names
+    Call(String, Vec<(String, Dart)>),
+    List(Vec<Dart>),
+    /// A list element shown when a bool parameter is set
+    If(String, Box<Dart>),
+}
+
+impl Dart {
+    fn is_const(&self) -> bool {
+        match self {
+            Dart::Code(_, constant) => *constant,
+            Dart::Call(name, args) => !NON_CONST.contains(&name.as_str()) && args.iter().all(|(_, arg)| arg.is_const()),
+            Dart::List(items) => items.iter().all(Dart::is_const),
+            Dart::If(..) => false,
+        }
+    }
+
+    /// The `const` keyword this expression needs, if any, and whether its
+    /// parts are in a constant context
+    fn const_prefix(&self, in_const: bool) -> (&'static str, bool) {
+        if in_const {
+            ("", true)
+        } else if matches!(self, Dart::Call(..) | Dart::List(_)) && self.is_const() {
+            ("const ", true)
+        } else {
+            ("", false)
+        }
+    }
+
+    /// The expression on one line
+    fn flat(&self, in_const: bool) -> String {
+        let (prefix, in_const) = self.const_prefix(in_const);
+        match self {
+            Dart::Code(code, _) => code.clone(),
+            Dart::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|(label, arg)| format!("{}{}", label_prefix(label), arg.flat(in_const))).collect();
+                format!("{prefix}{name}({})", args.join(", "))
+            }
+            Dart::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.flat(in_const)).collect();
+                format!("{prefix}[{}]", items.join(", "))
+            }
+            Dart::If(condition, item) => format!("if ({condition}) {}", item.flat(in_const)),
+        }
+    }
+
+    /// Write the expression starting at `column`, breaking it over lines
+    /// indented from `indent` with trailing commas when it doesn't fit
+    fn write(&self, out: &mut String, indent: usize, column: usize, in_const: bool) {
+        let flat = self.flat(in_const);
+        // Leave room for the comma or semicolon that follows
+        if column + flat.len() < LINE_WIDTH || matches!(self, Dart::Code(..)) {
+            out.push_str(&flat);
+            return;
+        }
+        let (prefix, in_const) = self.const_prefix(in_const);
+        let pad = " ".repeat(indent + 2);
+        match self {
+            Dart::Code(..) => {}
+            Dart::Call(name, args) => {
+                out.push_str(&format!("{prefix}{name}(\n"));
+                for (label, arg) in args {
+                    let label = label_prefix(label);
+                    out.push_str(&pad);
+                    out.push_str(&label);
+                    arg.write(out, indent + 2, indent + 2 + label.len(), in_const);
+                    out.push_str(",\n");
+                }
+                out.push_str(&" ".repeat(indent));
+                out.push(')');
+            }
+            Dart::List(items) => {
+                out.push_str(&format!("{prefix}[\n"));
+                for item in items {
+                    out.push_str(&pad);
+                    item.write(out, indent + 2, indent + 2, in_const);
+                    out.push_str(",\n");
+                }
+                out.push_str(&" ".repeat(indent));
+                out.push(']');
+            }
+            Dart::If(condition, item) => {
+                let head = format!("if ({condition}) ");
+                out.push_str(&head);
+                item.write(out, indent, column + head.len(), in_const);
+            }
+        }
+    }
+}
+
+fn label_prefix(label: &str) -> String {
+    if label.is_empty() { String::new() } else { format!("{label}: ") }
+}
+
+fn call(name: &str, args: Vec<(&str, Dart)>) -> Dart {
+    Dart::Call(name.to_string(), args.into_iter().map(|(label, arg)| (label.to_string(), arg)).collect())
+}
+
+fn constant(code: impl Into<String>) -> Dart {
+    Dart::Code(code.into(), true)
+}
+
+fn num(value: f64) -> Dart {
+    constant(number(value))
+}
+
+fn color(color: &ColorInfo) -> Dart {
+    call("Color", vec![("", constant(color_literal(color)))])
+}
+
+fn color_literal(color: &ColorInfo) -> String {
+    format!("0x{:02X}{:02X}{:02X}{:02X}", color.a, color.r, color.g, color.b)
+}
+
+/// A point in the unit square as a Flutter `Alignment`
+fn alignment((x, y): (f64, f64)) -> Dart {
+    call("Alignment", vec![("", num(x * 2.0 - 1.0)), ("", num(y * 2.0 - 1.0))])
+}
+
+/// A widget class generated for a component
+struct ComponentClass {
+    component_id: String,
+    name: String,
+    params: Vec<Param>,
+}
+
+/// A parameter of a component's widget class
+#[derive(Debug, Clone)]
+struct Param {
+    name: String,
+    source: ParamSource,
+    /// Dart literal of the component's own value
+    default: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ParamSource {
+    /// A text or boolean component property, by id
+    Property(String, PropertyType),
+    /// The characters of a text node in the component, overridden by
+    /// instances
+    Text(String),
+    /// Whether a node in the component is visible, overridden by instances
+    Visible(String),
+}
+
+impl Param {
+    fn dart_type(&self) -> &'static str {
+        match self.source {
+            ParamSource::Property(_, PropertyType::Bool) | ParamSource::Visible(_) => "bool",
+            _ => "String",
+        }
+    }
+}
+
+/// Sizes a parent sets on a child, in place of the child's own
+#[derive(Debug, Clone, Copy, Default)]
+struct Fill {
+    width: bool,
+    height: bool,
+}
+
+struct Generator<'a> {
+    file: &'a FigFile,
+    class_names: HashSet<String>,
+    components: Vec<ComponentClass>,
+    /// Painter classes by name with their `paint` bodies, so that vectors
+    /// drawn the same share one
+    painters: Vec<(String, String)>,
+}
+
+impl<'a> Generator<'a> {
+    /// Find the components used under a node and the parameters their
+    /// instances need
+    fn discover(&mut self, node: &'a FigmaNode) {
+        if let Some(component) = node.component_id().and_then(|id| self.file.get_node(id)) {
+            let index = self.component_class(component);
+            self.discover_overrides(index, component, node);
+            return;
+        }
+        if is_component(node) {
+            self.component_class(node);
+            return;
+        }
+        for child in node.children.iter().filter_map(|id| self.file.get_node(id)) {
+            self.discover(child);
+        }
+    }
+
+    /// The index of a component's class, adding it with its property
+    /// parameters the first time
+    fn component_class(&mut self, component: &'a FigmaNode) -> usize {
+        if let Some(index) = self.components.iter().position(|class| class.component_id == component.id) {
+            return index;
+        }
+        // Variants are named by their set and values
+        let name = match component_set(self.file, component) {
+            Some(set) => {
+                let values: Vec<String> = parse_variant_name(&component.name).into_iter().map(|(_, value)| value).collect();
+                format!("{} {}", set.name, values.join(" "))
+            }
+            None => component.name.clone(),
+        };
+        let name = unique(&mut self.class_names, dart_identifier(&name, true));
+
+        let mut params: Vec<Param> = Vec::new();
+        for def in component_properties(self.file, component) {
+            let (property_type, default) = match (def.property_type, &def.default_value) {
+                (Some(PropertyType::Text), Some(PropertyValue::Text(text))) => (PropertyType::Text, dart_string(text)),
+                (Some(PropertyType::Bool), Some(PropertyValue::Bool(value))) => (PropertyType::Bool, value.to_string()),
+                _ => continue,
+            };
+            // Figma names properties like `Label#12:3`
+            let label = def.name.split('#').next().unwrap_or_default();
+            let name = param_name(&params, label, "value");
+            params.push(Param { name, source: ParamSource::Property(def.id, property_type), default });
+        }
+        self.components.push(ComponentClass { component_id: component.id.clone(), name, params });
+
+        let index = self.components.len() - 1;
+        for child in component.children.iter().filter_map(|id| self.file.get_node(id)) {
+            self.discover(child);
+        }
+        index
+    }
+
+    /// Add parameters for the texts and visibility an instance overrides
+    /// in its component's content, comparing each node with its copy
+    fn discover_overrides(&mut self, index: usize, node: &FigmaNode, instance: &FigmaNode) {
+        for source in node.children.iter().filter_map(|id| self.file.get_node(id)) {
+            let Some(copy) = self.file.get_node(&copy_id(instance, &source.id)) else {
+                continue;
+            };
+            let params = &mut self.components[index].params;
+            let bound = |field: &str| source.property_refs.iter().any(|r| r.node_field == field);
+            if let (Some(text), Some(copied)) = (&source.text, &copy.text) {
+                let source_param = ParamSource::Text(source.id.clone());
+                if text.characters != copied.characters && !bound("TEXT_DATA") && !params.iter().any(|p| p.source == source_param) {
+                    let name = param_name(params, &source.name, "text");
+                    params.push(Param { name, source: source_param, default: dart_string(&text.characters) });
+                }
+            }
+            let source_param = ParamSource::Visible(source.id.clone());
+            if source.visible != copy.visible && !bound("VISIBLE") && !params.iter().any(|p| p.source == source_param) {
+                let name = param_name(params, &format!("show {}", source.name), "show");
+                params.push(Param { name, source: source_param, default: source.visible.to_string() });
+            }
+            // Nested instances have their own classes
+            if source.component_id().is_none() {
+                self.discover_overrides(index, source, instance);
+            }
+        }
+    }
+
+    /// A node's widget, with its opacity and rotation
+    fn widget(&mut self, node: &'a FigmaNode, params: &[Param], fill: Fill) -> Dart {
+        let mut widget = if is_component(node) {
+            // Components in the content, such as a set's variants, are
+            // shown by their classes
+            let index = self.component_class(node);
+            call(&self.components[index].name, vec![])
+        } else {
+            self.content(node, params, fill)
+        };
+        if node.opacity < 1.0 {
+            widget = call("Opacity", vec![("opacity", num(node.opacity)), ("child", widget)]);
+        }
+        // Figma rotates counterclockwise about the node's origin
+        if number(node.rotation) != "0" {
+            widget = call("Transform.rotate", vec![
+                ("angle", num(-node.rotation.to_radians())),
+                ("alignment", constant("Alignment.topLeft")),
+                ("child", widget),
+            ]);
+        }
+        widget
+    }
+
+    fn content(&mut self, node: &FigmaNode, params: &[Param], fill: Fill) -> Dart {
+        if node.symbol.is_some() {
+            return self.instance(node, fill);
+        }
+        match NodeType::from(node.node_type.as_str()) {
+            NodeType::Text => self.text(node, params, fill),
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Ellipse => {
+                boxed(size(node, (true, true), fill), None, decoration(node), None)
+            }
+            NodeType::Vector | NodeType::Star | NodeType::Line | NodeType::RegularPolygon => self.vector(node),
+            _ => self.frame(node, params, fill),
+        }
+    }
+
+    /// The children shown: visible ones and ones a parameter shows
+    fn children(&self, node: &FigmaNode, params: &[Param]) -> Vec<&'a FigmaNode> {
+        node.children.iter()
+            .filter_map(|id| self.file.get_node(id))
+            .filter(|child| child.visible || visibility_param(child, params).is_some())
+            .collect()
+    }
+
+    fn frame(&mut self, node: &FigmaNode, params: &[Param], fill: Fill) -> Dart {
+        let decoration = decoration(node);
+        if !matches!(node.layout_mode.as_str(), "HORIZONTAL" | "VERTICAL") {
+            let children: Vec<Dart> = self.children(node, params).into_iter()
+                .map(|child| {
+                    let widget = self.positioned(child, node, params);
+                    with_visibility(child, params, widget)
+                })
+                .collect();
+            let stack = (!children.is_empty()).then(|| call("Stack", vec![("children", Dart::List(children))]));
+            return boxed(size(node, (true, true), fill), None, decoration, stack);
+        }
+
+        let horizontal = node.layout_mode == "HORIZONTAL";
+        let fixed = if horizontal {
+            (!node.hugs_primary_axis(), !node.hugs_counter_axis())
+        } else {
+            (!node.hugs_counter_axis(), !node.hugs_primary_axis())
+        };
+        let (flow, absolute): (Vec<&FigmaNode>, Vec<&FigmaNode>) = self.children(node, params).into_iter()
+            .partition(|child| !child.absolute_position);
+        let flow = self.auto_layout(node, &flow, params);
+        let padding = edge_insets(node.padding);
+        if absolute.is_empty() {
+            return boxed(size(node, fixed, fill), padding, decoration, flow);
+        }
+
+        // Absolute children are placed in the frame, not its padding
+        let mut children: Vec<Dart> = flow.into_iter()
+            .map(|flow| match padding.clone() {
+                Some(padding) => call("Padding", vec![("padding", padding), ("child", flow)]),
+                None => flow,
+            })
+            .collect();
+        for child in absolute {
+            let widget = self.positioned(child, node, params);
+            children.push(with_visibility(child, params, widget));
+        }
+        boxed(size(node, fixed, fill), None, decoration, Some(call("Stack", vec![("children", Dart::List(children))])))
+    }
+
+    /// A `Row`, `Column` or `Wrap` of an auto layout frame's children
+    fn auto_layout(&mut self, node: &FigmaNode, flow: &[&'a FigmaNode], params: &[Param]) -> Option<Dart> {
+        if flow.is_empty() {
+            return None;
+        }
+        let horizontal = node.layout_mode == "HORIZONTAL";
+        let wrap = node.layout_wrap;
+        let all_stretch = !wrap && flow.iter().all(|child| child.layout_align == "STRETCH");
+        let gaps = !wrap && node.item_spacing > 0.0 && node.primary_axis_align != "SPACE_BETWEEN";
+
+        let mut children = Vec::new();
+        for (i, &child) in flow.iter().enumerate() {
+            let grow = !wrap && child.layout_grow > 0.0;
+            let stretch = !wrap && child.layout_align == "STRETCH";
+            let fill = if horizontal { Fill { width: grow, height: stretch } } else { Fill { width: stretch, height: grow } };
+            let mut widget = self.widget(child, params, fill);
+            if stretch && !all_stretch {
+                widget = with_size(widget, if horizontal { "height" } else { "width" }, constant("double.infinity"));
+            }
+            if grow {
+                widget = call("Expanded", vec![("child", widget)]);
+            }
+            if gaps && i > 0 {
+                let axis = if horizontal { "width" } else { "height" };
+                children.push(call("SizedBox", vec![(axis, num(node.item_spacing))]));
+            }
+            children.push(with_visibility(child, params, widget));
+        }
+
+        let mut args = Vec::new();
+        if wrap {
+            if !horizontal {
+                args.push(("direction", constant("Axis.vertical")));
+            }
+            if node.item_spacing > 0.0 {
+                args.push(("spacing", num(node.item_spacing)));
+            }
+            let run_spacing = node.counter_axis_spacing.unwrap_or(node.item_spacing);
+            if run_spacing > 0.0 {
+                args.push(("runSpacing", num(run_spacing)));
+            }
+            if let Some(alignment) = main_axis_alignment(&node.primary_axis_align) {
+                args.push(("alignment", constant(format!("WrapAlignment.{alignment}"))));
+            }
+            match node.counter_axis_align.as_str() {
+                "CENTER" => args.push(("crossAxisAlignment", constant("WrapCrossAlignment.center"))),
+                "MAX" => args.push(("crossAxisAlignment", constant("WrapCrossAlignment.end"))),
+                _ => {}
+            }
+            args.push(("children", Dart::List(children)));
+            return Some(call("Wrap", args));
+        }
+
+        if let Some(alignment) = main_axis_alignment(&node.primary_axis_align) {
+            args.push(("mainAxisAlignment", constant(format!("MainAxisAlignment.{alignment}"))));
+        }
+        if node.hugs_primary_axis() {
+            args.push(("mainAxisSize", constant("MainAxisSize.min")));
+        }
+        // Flutter centers across the axis by default, Figma aligns to the start
+        match node.counter_axis_align.as_str() {
+            _ if all_stretch => args.push(("crossAxisAlignment", constant("CrossAxisAlignment.stretch"))),
+            "CENTER" => {}
+            "MAX" => args.push(("crossAxisAlignment", constant("CrossAxisAlignment.end"))),
+            "BASELINE" => {
+                args.push(("crossAxisAlignment", constant("CrossAxisAlignment.baseline")));
+                args.push(("textBaseline", constant("TextBaseline.alphabetic")));
+            }
+            _ => args.push(("crossAxisAlignment", constant("CrossAxisAlignment.start"))),
+        }
+        args.push(("children", Dart::List(children)));
+        let flex = call(if horizontal { "Row" } else { "Column" }, args);
+        // Children can only stretch to a size the frame has
+        if node.hugs_counter_axis() && flow.iter().any(|child| child.layout_align == "STRETCH") {
+            return Some(call(if horizontal { "IntrinsicHeight" } else { "IntrinsicWidth" }, vec![("child", flex)]));
+        }
+        Some(flex)
+    }
+
+    /// A child of a frame without auto layout, placed by its constraints
+    fn positioned(&mut self, child: &'a FigmaNode, parent: &FigmaNode, params: &[Param]) -> Dart {
+        let right = parent.width - child.x - child.width;
+        let bottom = parent.height - child.y - child.height;
+        let mut args = Vec::new();
+        let fill_width = match child.horizontal_constraint {
+            Constraint::Max => {
+                args.push(("right", num(right)));
+                false
+            }
+            Constraint::Stretch => {
+                args.push(("left", num(child.x)));
+                args.push(("right", num(right)));
+                true
+            }
+            _ => {
+                args.push(("left", num(child.x)));
+                false
+            }
+        };
+        let fill_height = match child.vertical_constraint {
+            Constraint::Max => {
+                args.push(("bottom", num(bottom)));
+                false
+            }
+            Constraint::Stretch => {
+                args.push(("top", num(child.y)));
+                args.push(("bottom", num(bottom)));
+                true
+            }
+            _ => {
+                args.push(("top", num(child.y)));
+                false
+            }
+        };
+        // Flutter wants them in this order
+        let order = ["left", "top", "right", "bottom"];
+        args.sort_by_key(|(label, _)| order.iter().position(|o| o == label));
+        args.push(("child", self.widget(child, params, Fill { width: fill_width, height: fill_height })));
+        call("Positioned", args)
+    }
+
+    fn text(&mut self, node: &FigmaNode, params: &[Param], fill: Fill) -> Dart {
+        let Some(text) = &node.text else {
+            return boxed(size(node, (true, true), fill), None, None, None);
+        };
+        // Runs of characters in one style
+        let mut runs: Vec<(String, u32)> = Vec::new();
+        for (c, style_id) in text.styled_chars() {
+            match runs.last_mut() {
+                Some((run, id)) if *id == style_id => run.push(c),
+                _ => runs.push((c.to_string(), style_id)),
+            }
+        }
+
+        let param = params.iter().find(|param| match &param.source {
+            ParamSource::Text(id) => *id == node.id,
+            ParamSource::Property(def_id, _) => {
+                node.property_refs.iter().any(|r| r.node_field == "TEXT_DATA" && r.def_id == *def_id)
+            }
+            ParamSource::Visible(_) => false,
+        });
+        let mut args = Vec::new();
+        let name = if param.is_some() || runs.len() <= 1 {
+            let content = match param {
+                Some(param) => Dart::Code(param.name.clone(), false),
+                None => constant(dart_string(&text.characters)),
+            };
+            args.push(("", content));
+            let style_id = runs.first().map(|(_, id)| *id).unwrap_or(0);
+            args.push(("style", text_style(&text.style_for(style_id), node)));
+            "Text"
+        } else {
+            let spans = runs.iter()
+                .map(|(run, id)| call("TextSpan", vec![("text", constant(dart_string(run))), ("style", text_style(&text.style_for(*id), node))]))
+                .collect();
+            args.push(("", call("TextSpan", vec![("children", Dart::List(spans))])));
+            "Text.rich"
+        };
+        match text.align_horizontal {
+            TextAlignHorizontal::Left => {}
+            TextAlignHorizontal::Center => args.push(("textAlign", constant("TextAlign.center"))),
+            TextAlignHorizontal::Right => args.push(("textAlign", constant("TextAlign.right"))),
+            TextAlignHorizontal::Justified => args.push(("textAlign", constant("TextAlign.justify"))),
+        }
+        if text.truncate {
+            args.push(("maxLines", num(text.max_lines.unwrap_or(1) as f64)));
+            args.push(("overflow", constant("TextOverflow.ellipsis")));
+        }
+        let fixed = match text.auto_resize {
+            TextAutoResize::None => (true, true),
+            TextAutoResize::Height => (true, false),
+            TextAutoResize::WidthAndHeight => (false, false),
+        };
+        boxed(size(node, fixed, fill), None, None, Some(call(name, args)))
+    }
+
+    /// A vector drawn by a generated painter, scaled to the widget's size
+    fn vector(&mut self, node: &FigmaNode) -> Dart {
+        let size_arg = call("Size", vec![("", num(node.width)), ("", num(node.height))]);
+        let Some(outline) = node.outline() else {
+            return call("SizedBox", vec![("width", num(node.width)), ("height", num(node.height))]);
+        };
+        let even_odd = node.node_type != "LINE"
+            && decode_vector_data(&node.vector_data).is_ok_and(|path| path.fill_rule == "evenodd");
+        let body = painter_body(node, &outline, even_odd);
+
+        let name = match self.painters.iter().find(|(_, existing)| *existing == body) {
+            Some((name, _)) => name.clone(),
+            None => {
+                let name = unique(&mut self.class_names, format!("_{}Painter", dart_identifier(&node.name, true)));
+                self.painters.push((name.clone(), body));
+                name
+            }
+        };
+        call("CustomPaint", vec![("size", size_arg), ("painter", call(&name, vec![]))])
+    }
+
+    /// A call of an instance's component class, passing the values the
+    /// instance sets
+    fn instance(&mut self, node: &FigmaNode, fill: Fill) -> Dart {
+        let Some(component) = node.component_id().and_then(|id| self.file.get_node(id)) else {
+            return self.frame(node, &[], fill);
+        };
+        let index = self.component_class(component);
+        let class = &self.components[index];
+        let properties = instance_properties(self.file, node);
+
+        let mut args = Vec::new();
+        for param in &class.params {
+            let value = match &param.source {
+                ParamSource::Property(def_id, _) => {
+                    properties.iter().find(|(def, _)| def.id == *def_id).and_then(|(_, value)| match value {
+                        PropertyValue::Text(text) => Some(dart_string(text)),
+                        PropertyValue::Bool(value) => Some(value.to_string()),
+                        _ => None,
+                    })
+                }
+                ParamSource::Text(id) => self.file.get_node(&copy_id(node, id))
+                    .and_then(|copy| copy.text.as_ref())
+                    .map(|text| dart_string(&text.characters)),
+                ParamSource::Visible(id) => self.file.get_node(&copy_id(node, id)).map(|copy| copy.visible.to_string()),
+            };
+            if let Some(value) = value.filter(|value| *value != param.default) {
+                args.push((param.name.as_str(), constant(value)));
+            }
+        }
+        let widget = call(&class.name, args);
+
+        let resized = |from: f64, to: f64| number(from) != number(to);
+        let mut size = Vec::new();
+        if !fill.width && resized(component.width, node.width) {
+            size.push(("width", num(node.width)));
+        }
+        if !fill.height && resized(component.height, node.height) {
+            size.push(("height", num(node.height)));
+        }
+        if size.is_empty() {
+            return widget;
+        }
+        size.push(("child", widget));
+        call("SizedBox", size)
+    }
+}
+
+/// Id of the copy of a component's node in an instance's content
+fn copy_id(instance: &FigmaNode, id: &str) -> String {
+    format!("I{};{id}", instance.id.strip_prefix('I').unwrap_or(&instance.id))
+}
+
+/// A parameter name from a label, not used by the class's other
+/// parameters. Labels without a word, like `#` or `24,210`, give `fallback`.
+fn param_name(params: &[Param], label: &str, fallback: &str) -> String {
+    let mut used: HashSet<String> = params.iter().map(|param| param.name.clone()).collect();
+    // Names the class already has
+    used.extend(["key", "build", "context"].map(String::from));
+    let name = dart_identifier(label, false);
+    let name = if label.chars().any(|c| c.is_ascii_alphabetic()) && !name.starts_with('$') { name } else { fallback.to_string() };
+    unique(&mut used, name)
+}
+
+/// The bool parameter that shows a node, if any
+fn visibility_param<'p>(node: &FigmaNode, params: &'p [Param]) -> Option<&'p Param> {
+    params.iter().find(|param| match &param.source {
+        ParamSource::Visible(id) => *id == node.id,
+        ParamSource::Property(def_id, PropertyType::Bool) => {
+            node.property_refs.iter().any(|r| r.node_field == "VISIBLE" && r.def_id == *def_id)
+        }
+        _ => false,
+    })
+}
+
+fn with_visibility(node: &FigmaNode, params: &[Param], widget: Dart) -> Dart {
+    match visibility_param(node, params) {
+        Some(param) => Dart::If(param.name.clone(), Box::new(widget)),
+        None => widget,
+    }
+}
+
+/// A widget given a width or height, set on it if it's a box without one
+fn with_size(widget: Dart, axis: &str, value: Dart) -> Dart {
+    match widget {
+        Dart::Call(name, mut args) if matches!(name.as_str(), "SizedBox" | "Container") && !args.iter().any(|(label, _)| label == axis) => {
+            let at = if axis == "height" { args.iter().take_while(|(label, _)| label == "width").count() } else { 0 };
+            args.insert(at, (axis.to_string(), value));
+            Dart::Call(name, args)
+        }
+        widget => call("SizedBox", vec![(axis, value), ("child", widget)]),
+    }
+}
+
+/// Width and height arguments for the dimensions a node fixes, leaving out
+/// ones its parent fills
+fn size(node: &FigmaNode, (fixed_width, fixed_height): (bool, bool), fill: Fill) -> Vec<(&'static str, Dart)> {
+    let mut args = Vec::new();
+    if fixed_width && !fill.width {
+        args.push(("width", num(node.width)));
+    }
+    if fixed_height && !fill.height {
+        args.push(("height", num(node.height)));
+    }
+    args
+}
+
+/// The lightest widget giving a child a size, padding and decoration
+fn boxed(size: Vec<(&'static str, Dart)>, padding: Option<Dart>, decoration: Option<Dart>, child: Option<Dart>) -> Dart {
+    let mut args = size;
+    let name = match (&padding, &decoration) {
+        (None, None) if args.is_empty() => match child {
+            Some(child) => return child,
+            None => "SizedBox.shrink",
+        },
+        (None, None) => "SizedBox",
+        (Some(_), None) if args.is_empty() => "Padding",
+        (None, Some(_)) if args.is_empty() => "DecoratedBox",
+        _ => "Container",
+    };
+    args.extend(padding.map(|padding| ("padding", padding)));
+    args.extend(decoration.map(|decoration| ("decoration", decoration)));
+    args.extend(child.map(|child| ("child", child)));
+    call(name, args)
+}
+
+/// Padding as `EdgeInsets`, from Figma's left, top, right, bottom order
+fn edge_insets([left, top, right, bottom]: [f64; 4]) -> Option<Dart> {
+    if [left, top, right, bottom].iter().all(|&p| p == 0.0) {
+        None
+    } else if left == top && left == right && left == bottom {
+        Some(call("EdgeInsets.all", vec![("", num(left))]))
+    } else if left == right && top == bottom {
+        let mut args = Vec::new();
+        if left != 0.0 {
+            args.push(("horizontal", num(left)));
+        }
+        if top != 0.0 {
+            args.push(("vertical", num(top)));
+        }
+        Some(call("EdgeInsets.symmetric", args))
+    } else {
+        Some(call("EdgeInsets.fromLTRB", vec![("", num(left)), ("", num(top)), ("", num(right)), ("", num(bottom))]))
+    }
+}
+
+fn main_axis_alignment(align: &str) -> Option<&'static str> {
+    match align {
+        "CENTER" => Some("center"),
+        "MAX" => Some("end"),
+        "SPACE_BETWEEN" => Some("spaceBetween"),
+        "SPACE_EVENLY" => Some("spaceEvenly"),
+        _ => None,
+    }
+}
+
+/// A node's fills, strokes, corners and shadows as a `BoxDecoration`
+fn decoration(node: &FigmaNode) -> Option<Dart> {
+    let mut args = Vec::new();
+    match node.fill_paints.iter().rev().find(|paint| paint.visible() && !matches!(paint, Paint::Image(_))) {
+        Some(Paint::Solid(paint)) => args.push(("color", color(&paint_color(&paint.color, paint.opacity)))),
+        Some(paint) => args.extend(gradient(paint).map(|gradient| ("gradient", gradient))),
+        None => {}
+    }
+
+    if node.node_type == "ELLIPSE" {
+        if number(node.width) == number(node.height) {
+            args.push(("shape", constant("BoxShape.circle")));
+        } else {
+            let radius = call("Radius.elliptical", vec![("", num(node.width / 2.0)), ("", num(node.height / 2.0))]);
+            args.push(("borderRadius", call("BorderRadius.all", vec![("", radius)])));
+        }
+    } else {
+        let radii = node.effective_corner_radii();
+        if radii.iter().all(|&r| r == radii[0]) && radii[0] > 0.0 {
+            args.push(("borderRadius", call("BorderRadius.circular", vec![("", num(radii[0]))])));
+        } else if radii.iter().any(|&r| r > 0.0) {
+            let corners = ["topLeft", "topRight", "bottomRight", "bottomLeft"].into_iter().zip(radii)
+                .filter(|(_, radius)| *radius > 0.0)
+                .map(|(corner, radius)| (corner, call("Radius.circular", vec![("", num(radius))])))
+                .collect();
+            args.push(("borderRadius", call("BorderRadius.only", corners)));
+        }
+    }
+
+    if let Some(Paint::Solid(paint)) = node.stroke_paints.iter().rev().find(|paint| paint.visible()) {
+        if node.stroke_weight > 0.0 {
+            let stroke_color = color(&paint_color(&paint.color, paint.opacity));
+            let side = |width: f64| {
+                let mut side = vec![("color", stroke_color.clone()), ("width", num(width))];
+                match node.stroke.align {
+                    StrokeAlign::Inside => {}
+                    StrokeAlign::Center => side.push(("strokeAlign", constant("BorderSide.strokeAlignCenter"))),
+                    StrokeAlign::Outside => side.push(("strokeAlign", constant("BorderSide.strokeAlignOutside"))),
+                }
+                side
+            };
+            let border = match node.stroke.side_weights {
+                Some(weights) => {
+                    let sides = ["top", "right", "bottom", "left"].into_iter().zip(weights)
+                        .filter(|(_, width)| *width > 0.0)
+                        .map(|(label, width)| (label, call("BorderSide", side(width))))
+                        .collect();
+                    call("Border", sides)
+                }
+                None => call("Border.all", side(node.stroke_weight)),
+            };
+            args.push(("border", border));
+        }
+    }
+
+    let shadows: Vec<Dart> = node.effects.iter()
+        .filter_map(|effect| match effect {
+            Effect::DropShadow(shadow) if shadow.visible => {
+                let mut args = vec![
+                    ("color", color(&shadow.color)),
+                    ("offset", call("Offset", vec![("", num(shadow.offset.0)), ("", num(shadow.offset.1))])),
+                    ("blurRadius", num(shadow.radius)),
+                ];
+                if shadow.spread != 0.0 {
+                    args.push(("spreadRadius", num(shadow.spread)));
+                }
+                Some(call("BoxShadow", args))
+            }
+            _ => None,
+        })
+        .collect();
+    if !shadows.is_empty() {
+        args.push(("boxShadow", Dart::List(shadows)));
+    }
+
+    // Corners alone draw nothing
+    let paints = args.iter().any(|(label, _)| matches!(*label, "color" | "gradient" | "border" | "boxShadow"));
+    paints.then(|| call("BoxDecoration", args))
+}
+
+/// A gradient paint as a Flutter gradient. Diamond gradients are drawn
+/// radial.
+fn gradient(paint: &Paint) -> Option<Dart> {
+    let [start, end, _] = paint.gradient_handles()?;
+    let (Paint::GradientLinear(gradient) | Paint::GradientRadial(gradient) | Paint::GradientAngular(gradient) | Paint::GradientDiamond(gradient)) = paint else {
+        return None;
+    };
+    let colors = gradient.stops.iter().map(|stop| color(&paint_color(&stop.color, gradient.opacity))).collect();
+    let stops = gradient.stops.iter().map(|stop| num(stop.position)).collect();
+    let (name, mut args) = match paint {
+        Paint::GradientLinear(_) => ("LinearGradient", vec![("begin", alignment(start)), ("end", alignment(end))]),
+        Paint::GradientAngular(_) => {
+            let angle = (end.1 - start.1).atan2(end.0 - start.0);
+            ("SweepGradient", vec![("center", alignment(start)), ("startAngle", num(angle)), ("endAngle", num(angle + std::f64::consts::TAU))])
+        }
+        _ => {
+            let radius = (end.0 - start.0).hypot(end.1 - start.1);
+            ("RadialGradient", vec![("center", alignment(start)), ("radius", num(radius))])
+        }
+    };
+    args.push(("colors", Dart::List(colors)));
+    args.push(("stops", Dart::List(stops)));
+    Some(call(name, args))
+}
+
+/// A `TextStyle` with its fill color, falling back to the node's fills
+fn text_style(style: &TextStyle, node: &FigmaNode) -> Dart {
+    let mut args: Vec<(&str, Dart)> = text_style_fields(style).into_iter().map(|(label, value)| (label, constant(value))).collect();
+    let fills = if style.fills.is_empty() { &node.fill_paints } else { &style.fills };
+    if let Some(Paint::Solid(paint)) = fills.iter().rev().find(|paint| paint.visible()) {
+        args.push(("color", color(&paint_color(&paint.color, paint.opacity))));
+    }
+    call("TextStyle", args)
+}
+
+/// The statements of a painter's `paint` method, drawing a node's outline
+/// with its solid fill and stroke
+fn painter_body(node: &FigmaNode, outline: &Path, even_odd: bool) -> String {
+    let mut body = String::new();
+    let scale = |size: &str, extent: f64| if extent > 0.0 { format!("size.{size} / {}", number(extent)) } else { "1".to_string() };
+    if node.width > 0.0 || node.height > 0.0 {
+        body.push_str(&format!("    canvas.scale({}, {});\n", scale("width", node.width), scale("height", node.height)));
+    }
+    body.push_str("    final path = Path()");
+    if even_odd {
+        body.push_str("\n      ..fillType = PathFillType.evenOdd");
+    }
+    let point = |p: lyon::math::Point| format!("{}, {}", number(p.x as f64), number(p.y as f64));
+    for event in outline.iter() {
+        let op = match event {
+            PathEvent::Begin { at } => format!("moveTo({})", point(at)),
+            PathEvent::Line { to, .. } => format!("lineTo({})", point(to)),
+            PathEvent::Quadratic { ctrl, to, .. } => format!("quadraticBezierTo({}, {})", point(ctrl), point(to)),
+            PathEvent::Cubic { ctrl1, ctrl2, to, .. } => format!("cubicTo({}, {}, {})", point(ctrl1), point(ctrl2), point(to)),
+            PathEvent::End { close: true, .. } => "close()".to_string(),
+            PathEvent::End { close: false, .. } => continue,
+        };
+        body.push_str(&format!("\n      ..{op}"));
+    }
+    body.push_str(";\n");
+
+    let solid = |paints: &[Paint]| match paints.iter().rev().find(|paint| paint.visible()) {
+        Some(Paint::Solid(paint)) => Some(paint_color(&paint.color, paint.opacity)),
+        _ => None,
+    };
+    if node.node_type != "LINE" {
+        if let Some(fill) = solid(&node.fill_paints) {
+            body.push_str(&format!("    canvas.drawPath(path, Paint()..color = const Color({}));\n", color_literal(&fill)));
+        }
+    }
+    if let Some(stroke) = solid(&node.stroke_paints).filter(|_| node.stroke_weight > 0.0) {
+        body.push_str("    canvas.drawPath(\n      path,\n      Paint()\n        ..style = PaintingStyle.stroke\n");
+        body.push_str(&format!("        ..strokeWidth = {}\n", number(node.stroke_weight)));
+        match node.stroke.cap {
+            StrokeCap::Round => body.push_str("        ..strokeCap = StrokeCap.round\n"),
+            StrokeCap::Square => body.push_str("        ..strokeCap = StrokeCap.square\n"),
+            _ => {}
+        }
+        match node.stroke.join {
+            StrokeJoin::Round => body.push_str("        ..strokeJoin = StrokeJoin.round\n"),
+            StrokeJoin::Bevel => body.push_str("        ..strokeJoin = StrokeJoin.bevel\n"),
+            StrokeJoin::Miter => {}
+        }
+        body.push_str(&format!("        ..color = const Color({}),\n    );\n", color_literal(&stroke)));
+    }
+    body
+}
+
+/// Write a `StatelessWidget` class building `body`
+fn write_class(out: &mut String, name: &str, params: &[Param], body: &Dart) {
+    out.push_str(&format!("\nclass {name} extends StatelessWidget {{\n"));
+    let mut fields = vec!["super.key".to_string()];
+    fields.extend(params.iter().map(|param| format!("this.{} = {}", param.name, param.default)));
+    let constructor = format!("  const {name}({{{}}});\n", fields.join(", "));
+    if constructor.len() <= LINE_WIDTH + 1 {
+        out.push_str(&constructor);
+    } else {
+        out.push_str(&format!("  const {name}({{\n"));
+        for field in &fields {
+            out.push_str(&format!("    {field},\n"));
+        }
+        out.push_str("  });\n");
+    }
+    if !params.is_empty() {
+        out.push('\n');
+        for param in params {
+            out.push_str(&format!("  final {} {};\n", param.dart_type(), param.name));
+        }
+    }
+    out.push_str("\n  @override\n  Widget build(BuildContext context) {\n    return ");
+    body.write(out, 4, 11, false);
+    out.push_str(";\n  }\n}\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_dart_formatting() {
+        let short = call("SizedBox", vec![("width", num(8.0))]);
+        let mut out = String::new();
+        short.write(&mut out, 4, 11, false);
+        assert_eq!(out, "const SizedBox(width: 8)");
+
+        let text = call("Text", vec![("", Dart::Code("title".into(), false)), ("style", call("TextStyle", vec![("fontSize", num(17.0))]))]);
+        let column = call("Column", vec![
+            ("mainAxisSize", constant("MainAxisSize.min")),
+            ("children", Dart::List(vec![Dart::If("showTitle".into(), Box::new(text)), short])),
+        ]);
+        let mut out = String::new();
+        column.write(&mut out, 4, 11, false);
+        assert_eq!(out, "\
+Column(
+      mainAxisSize: MainAxisSize.min,
+      children: [
+        if (showTitle) Text(title, style: const TextStyle(fontSize: 17)),
+        const SizedBox(width: 8),
+      ],
+    )");
+
+        // Containers can't be constant, but their arguments can
+        let container = call("Container", vec![("width", num(10.0)), ("decoration", call("BoxDecoration", vec![("color", color(&ColorInfo { r: 255, g: 0, b: 0, a: 255 }))]))]);
+        assert_eq!(container.flat(false), "Container(width: 10, decoration: const BoxDecoration(color: Color(0xFFFF0000)))");
+    }
+
+    #[test]
+    fn test_vector_painter() {
+        use crate::paints::SolidPaint;
+
+        let black = ColorInfo { r: 0, g: 0, b: 0, a: 255 };
+        let line = FigmaNode {
+            name: "Divider".into(),
+            node_type: "LINE".into(),
+            visible: true,
+            opacity: 1.0,
+            width: 24.0,
+            stroke_weight: 2.0,
+            stroke_paints: vec![Paint::Solid(SolidPaint { color: black, visible: true, opacity: 0.5, blend_mode: Default::default(), color_variable: None })],
+            ..Default::default()
+        };
+        // Lines are stroked, never filled, and don't scale by their zero height
+        let body = painter_body(&line, &line.outline().unwrap(), false);
+        assert!(body.starts_with("    canvas.scale(size.width / 24, 1);\n    final path = Path()\n      ..moveTo(0, 0)\n      ..lineTo(24, 0);\n"));
+        assert!(body.contains("        ..strokeWidth = 2\n"));
+        assert!(body.contains("        ..color = const Color(0x80000000),\n    );\n"));
+        assert!(!body.contains("Paint()..color"));
+    }
+
+    #[test]
+    fn test_edge_insets() {
+        assert!(edge_insets([0.0; 4]).is_none());
+        assert_eq!(edge_insets([8.0; 4]).unwrap().flat(true), "EdgeInsets.all(8)");
+        assert_eq!(edge_insets([16.0, 0.0, 16.0, 0.0]).unwrap().flat(true), "EdgeInsets.symmetric(horizontal: 16)");
+        assert_eq!(edge_insets([1.0, 2.0, 3.0, 4.0]).unwrap().flat(true), "EdgeInsets.fromLTRB(1, 2, 3, 4)");
+    }
+
+    #[test]
+    fn test_generate_widgets() {
+        let file = test_fixture::expanded();
+        let feedback = file.get_node("48:18412").unwrap();
+        assert!(feedback.hugs_primary_axis() && !feedback.hugs_counter_axis());
+        assert!(file.nodes.values().any(|node| node.layout_grow == 1.0 && node.layout_align == "STRETCH"));
+        assert!(file.nodes.values().any(|node| node.absolute_position));
+        assert!(file.nodes.values().any(|node| node.layout_wrap && node.counter_axis_spacing.is_some()));
+
+        // Frames without auto layout stack their children by constraints,
+        // and instances construct their component's class
+        let dart = generate_widgets(file, "84:30697").unwrap();
+        assert!(dart.contains("import 'package:flutter/widgets.dart';"));
+        assert!(dart.contains("\nclass CalculatorCopy extends StatelessWidget {\n  const CalculatorCopy({super.key});\n"));
+        assert!(dart.contains("          Positioned(left: 0, top: 0, child: CalculatorTypeNumber(text: '51')),\n"));
+        assert!(dart.contains("            child: SizedBox(width: 68, child: EditMenuDefault()),\n"));
+
+        // Text the instances override becomes a parameter
+        assert!(dart.contains("  const CalculatorTypeNumber({super.key, this.text = '24,210'});\n\n  final String text;\n"));
+        assert!(dart.contains("Text(\n                                  text,\n"));
+        // and so do component properties, with the values instances assign
+        assert!(dart.contains("CalculatorButtonIPhone14ProMaxDefault(text: '7'),"));
+
+        // Auto layout becomes flexes with gaps and padding
+        let dart = generate_widgets(file, "61:24635").unwrap();
+        assert!(dart.contains("SizedBox(width: 30),"));
+        assert!(dart.contains("padding: const EdgeInsets.fromLTRB(20, 3, 20, 8),"));
+        assert!(dart.contains("mainAxisSize: MainAxisSize.min,"));
+        assert!(dart.contains("Expanded(\n"));
+        assert!(dart.contains("if (hasEyebrow) Column("));
+        assert!(dart.contains("borderRadius: BorderRadius.circular(100),"));
+
+        assert!(matches!(generate_widgets(file, "0:2"), Err(FigmaError::UnsupportedNodeType(_))));
+        assert!(matches!(generate_widgets(file, "1:1234567"), Err(FigmaError::NodeNotFound(_))));
+    }
+}
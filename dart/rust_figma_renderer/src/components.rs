@@ -0,0 +1,430 @@
+//! Component properties and variants
+//!
+//! A component declares properties (`componentPropDefs`), nodes in its
+//! content are bound to them (`componentPropRefs`), and each instance
+//! assigns values (`componentPropAssignments`). Variants are components
+//! grouped under a component set, named by their values like
+//! `Size=Large, State=Hover`. The set declares the properties shared by all
+//! of its variants; each variant redeclares them under its own ids, which
+//! its content and its instances use, pointing back with `parentPropDefId`.
+
+use crate::kiwi::{FigFile, KiwiMessage, KiwiValue};
+use crate::nodes::FigmaNode;
+use crate::{FigmaError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyType {
+    Bool,
+    Text,
+    Color,
+    InstanceSwap,
+    Variant,
+    Number,
+    Image,
+    Slot,
+}
+
+impl PropertyType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "BOOL" => PropertyType::Bool,
+            "TEXT" => PropertyType::Text,
+            "COLOR" => PropertyType::Color,
+            "INSTANCE_SWAP" => PropertyType::InstanceSwap,
+            "VARIANT" => PropertyType::Variant,
+            "NUMBER" => PropertyType::Number,
+            "IMAGE" => PropertyType::Image,
+            "SLOT" => PropertyType::Slot,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PropertyType::Bool => "BOOL",
+            PropertyType::Text => "TEXT",
+            PropertyType::Color => "COLOR",
+            PropertyType::InstanceSwap => "INSTANCE_SWAP",
+            PropertyType::Variant => "VARIANT",
+            PropertyType::Number => "NUMBER",
+            PropertyType::Image => "IMAGE",
+            PropertyType::Slot => "SLOT",
+        }
+    }
+}
+
+/// A `ComponentPropValue`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Text(String),
+    /// Component id, for instance swap properties
+    Component(String),
+    Number(f64),
+}
+
+impl PropertyValue {
+    pub fn from_kiwi(value: &KiwiMessage) -> Option<Self> {
+        if let Some(value) = value.bool("boolValue") {
+            Some(PropertyValue::Bool(value))
+        } else if let Some(text) = value.message("textValue") {
+            Some(PropertyValue::Text(text.str("characters").unwrap_or_default().to_string()))
+        } else if let Some(id) = value.guid("guidValue") {
+            Some(PropertyValue::Component(id))
+        } else {
+            value.float("floatValue").map(PropertyValue::Number)
+        }
+    }
+
+    /// Parse a value given as a string, as it is passed from Flutter
+    pub fn parse(property_type: PropertyType, value: &str) -> Option<Self> {
+        match property_type {
+            PropertyType::Bool => value.parse().ok().map(PropertyValue::Bool),
+            PropertyType::Text | PropertyType::Variant => Some(PropertyValue::Text(value.to_string())),
+            PropertyType::InstanceSwap => Some(PropertyValue::Component(value.to_string())),
+            PropertyType::Number => value.parse().ok().map(PropertyValue::Number),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Bool(value) => write!(f, "{value}"),
+            PropertyValue::Text(value) | PropertyValue::Component(value) => f.write_str(value),
+            PropertyValue::Number(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// A `ComponentPropDef`. A variant's redeclaration of its set's property
+/// only has `id` and `parent_id`.
+#[derive(Debug, Clone)]
+pub struct PropertyDef {
+    pub id: String,
+    pub name: String,
+    pub property_type: Option<PropertyType>,
+    pub default_value: Option<PropertyValue>,
+    /// The set's property, for a variant's property
+    pub parent_id: Option<String>,
+    /// Fractional index ordering the properties in Figma's panel
+    pub sort_position: String,
+    /// Keys of the components and sets suggested for instance swap
+    /// properties
+    pub preferred_values: Vec<String>,
+}
+
+impl PropertyDef {
+    /// Build definitions from a decoded `ComponentPropDef[]` field, leaving
+    /// out deleted ones
+    pub fn list_from_kiwi(defs: &KiwiValue) -> Vec<PropertyDef> {
+        defs.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter(|def| !def.bool("isDeleted").unwrap_or(false))
+            .filter_map(|def| Some(PropertyDef {
+                id: def.guid("id")?,
+                name: def.str("name").unwrap_or_default().to_string(),
+                property_type: def.str("type").and_then(PropertyType::from_name),
+                default_value: def.message("initialValue").and_then(PropertyValue::from_kiwi),
+                parent_id: def.guid("parentPropDefId"),
+                sort_position: def.str("sortPosition").unwrap_or_default().to_string(),
+                preferred_values: def.message("preferredValues").into_iter()
+                    .flat_map(|values| values.array("instanceSwapValues"))
+                    .filter_map(|value| Some(value.as_message()?.str("key")?.to_string()))
+                    .collect(),
+            }))
+            .collect()
+    }
+}
+
+/// A `ComponentPropRef`: a field of a node bound to a property
+#[derive(Debug, Clone)]
+pub struct PropertyRef {
+    pub def_id: String,
+    /// The `ComponentPropNodeField` set, such as `VISIBLE` or `TEXT_DATA`
+    pub node_field: String,
+}
+
+impl PropertyRef {
+    pub fn list_from_kiwi(refs: &KiwiValue) -> Vec<PropertyRef> {
+        refs.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter(|r| !r.bool("isDeleted").unwrap_or(false))
+            .filter_map(|r| Some(PropertyRef {
+                def_id: r.guid("defID")?,
+                node_field: r.str("componentPropNodeField")?.to_string(),
+            }))
+            .collect()
+    }
+}
+
+/// A `ComponentPropAssignment`: an instance's value for a property
+#[derive(Debug, Clone)]
+pub struct PropertyAssignment {
+    pub def_id: String,
+    pub value: PropertyValue,
+}
+
+impl PropertyAssignment {
+    pub fn list_from_kiwi(assignments: &KiwiValue) -> Vec<PropertyAssignment> {
+        assignments.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter_map(|a| Some(PropertyAssignment {
+                def_id: a.guid("defID")?,
+                value: PropertyValue::from_kiwi(a.message("value")?)?,
+            }))
+            .collect()
+    }
+}
+
+/// The property values in a variant's name, like `Size=Large, State=Hover`.
+/// Parts without a `=` are skipped.
+pub fn parse_variant_name(name: &str) -> Vec<(String, String)> {
+    name.split(',')
+        .filter_map(|part| part.split_once('='))
+        .map(|(property, value)| (property.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+pub fn is_component(node: &FigmaNode) -> bool {
+    matches!(node.node_type.as_str(), "SYMBOL" | "COMPONENT")
+}
+
+/// Component sets are frames marked as a state group in .fig files
+pub fn is_component_set(node: &FigmaNode) -> bool {
+    node.node_type == "COMPONENT_SET" || node.is_state_group
+}
+
+/// The set a component is a variant of
+pub fn component_set<'a>(file: &'a FigFile, component: &FigmaNode) -> Option<&'a FigmaNode> {
+    component.parent_id.as_deref()
+        .and_then(|id| file.get_node(id))
+        .filter(|parent| is_component_set(parent))
+}
+
+/// A variant property and its values, in Figma's order
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantAxis {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// The variant properties of a component set, from its variants' names.
+/// Values are in the order set in Figma, then in the order variants use them.
+pub fn variant_axes(file: &FigFile, set: &FigmaNode) -> Vec<VariantAxis> {
+    let mut axes: Vec<VariantAxis> = Vec::new();
+    for variant in set.children.iter().filter_map(|id| file.get_node(id)).filter(|node| is_component(node)) {
+        for (name, value) in parse_variant_name(&variant.name) {
+            match axes.iter_mut().find(|axis| axis.name == name) {
+                Some(axis) if !axis.values.contains(&value) => axis.values.push(value),
+                Some(_) => {}
+                None => axes.push(VariantAxis { name, values: vec![value] }),
+            }
+        }
+    }
+    for axis in &mut axes {
+        if let Some((_, order)) = set.variant_orders.iter().find(|(name, _)| *name == axis.name) {
+            axis.values.sort_by_key(|value| order.iter().position(|v| v == value).unwrap_or(order.len()));
+        }
+    }
+    axes
+}
+
+/// The variant of a set with the given values
+pub fn find_variant<'a>(file: &'a FigFile, set: &FigmaNode, values: &[(String, String)]) -> Option<&'a FigmaNode> {
+    set.children.iter()
+        .filter_map(|id| file.get_node(id))
+        .filter(|node| is_component(node))
+        .find(|variant| {
+            let mut variant_values = parse_variant_name(&variant.name);
+            let mut values = values.to_vec();
+            variant_values.sort();
+            values.sort();
+            variant_values == values
+        })
+}
+
+/// A component's properties other than its variant properties. For a
+/// variant, these are its set's properties under the variant's own ids.
+/// Ordered as in Figma's panel.
+pub fn component_properties(file: &FigFile, component: &FigmaNode) -> Vec<PropertyDef> {
+    let set_defs = component_set(file, component).map(|set| set.property_defs.as_slice()).unwrap_or_default();
+    let mut defs: Vec<PropertyDef> = component.property_defs.iter()
+        .filter_map(|def| match &def.parent_id {
+            Some(parent_id) => set_defs.iter()
+                .find(|parent| parent.id == *parent_id)
+                .map(|parent| PropertyDef { id: def.id.clone(), parent_id: Some(parent.id.clone()), ..parent.clone() }),
+            None => Some(def.clone()),
+        })
+        .filter(|def| def.property_type.is_some_and(|t| t != PropertyType::Variant))
+        .collect();
+    defs.sort_by(|a, b| a.sort_position.cmp(&b.sort_position));
+    defs
+}
+
+/// The value of each of the component's properties for an instance: the
+/// instance's assignment, else the property's default
+pub fn instance_properties(file: &FigFile, instance: &FigmaNode) -> Vec<(PropertyDef, PropertyValue)> {
+    let Some(component) = instance.component_id().and_then(|id| file.get_node(id)) else {
+        return Vec::new();
+    };
+    component_properties(file, component).into_iter()
+        .filter_map(|def| {
+            let value = instance.property_assignments.iter().rev()
+                .find(|a| a.def_id == def.id)
+                .map(|a| a.value.clone())
+                .or_else(|| def.default_value.clone())?;
+            Some((def, value))
+        })
+        .collect()
+}
+
+/// An instance as it would be with other property values, given by name.
+/// Variant properties switch the component to the matching variant, keeping
+/// the values of the other properties. Instance content isn't copied; expand
+/// the result with [`crate::instances::expand_instance`].
+pub fn with_properties(file: &FigFile, instance: &FigmaNode, props: &[(String, String)]) -> Result<FigmaNode> {
+    let component = instance.component_id()
+        .and_then(|id| file.get_node(id))
+        .ok_or_else(|| FigmaError::NodeNotFound(format!("component of {}", instance.id)))?;
+
+    // Pick the variant first, since its properties have their own ids
+    let mut target = component;
+    let axes = component_set(file, component).map(|set| variant_axes(file, set)).unwrap_or_default();
+    let is_axis = |name: &str| axes.iter().any(|axis| axis.name == name);
+    if let Some(set) = component_set(file, component) {
+        let mut values = parse_variant_name(&component.name);
+        let mut changed = false;
+        for (name, value) in props.iter().filter(|(name, _)| is_axis(name)) {
+            match values.iter_mut().find(|(n, _)| n == name) {
+                Some(current) => current.1 = value.clone(),
+                None => values.push((name.clone(), value.clone())),
+            }
+            changed = true;
+        }
+        if changed {
+            target = find_variant(file, set, &values).ok_or_else(|| {
+                let values: Vec<String> = values.iter().map(|(n, v)| format!("{n}={v}")).collect();
+                FigmaError::PropertyError(format!("no variant {}", values.join(", ")))
+            })?;
+        }
+    }
+
+    let mut result = instance.clone();
+    let defs = component_properties(file, target);
+    let current = instance_properties(file, instance);
+    // Carry over the other values by name, since a new variant's ids differ
+    result.property_assignments = current.into_iter()
+        .filter_map(|(def, value)| {
+            let def = defs.iter().find(|d| d.name == def.name)?;
+            Some(PropertyAssignment { def_id: def.id.clone(), value })
+        })
+        .collect();
+
+    for (name, value) in props.iter().filter(|(name, _)| !is_axis(name)) {
+        let def = defs.iter().find(|def| def.name == *name)
+            .ok_or_else(|| FigmaError::PropertyError(format!("unknown property {name}")))?;
+        let value = def.property_type
+            .and_then(|t| PropertyValue::parse(t, value))
+            .ok_or_else(|| FigmaError::PropertyError(format!("invalid value for {name}: {value}")))?;
+        result.property_assignments.retain(|a| a.def_id != def.id);
+        result.property_assignments.push(PropertyAssignment { def_id: def.id.clone(), value });
+    }
+
+    if target.id != component.id {
+        result.swapped_component_id = Some(target.id.clone());
+        // Figma's recorded layouts and size are for the old variant
+        result.derived_layouts.clear();
+        result.width = target.width;
+        result.height = target.height;
+    }
+    Ok(result)
+}
+
+/// Set the fields of a node in an instance's content bound to properties
+pub(crate) fn apply_properties(file: &FigFile, node: &mut FigmaNode, assignments: &[PropertyAssignment]) {
+    for property_ref in node.property_refs.clone() {
+        let Some(assignment) = assignments.iter().rev().find(|a| a.def_id == property_ref.def_id) else {
+            continue;
+        };
+        match (property_ref.node_field.as_str(), &assignment.value) {
+            ("VISIBLE", PropertyValue::Bool(visible)) => node.visible = *visible,
+            ("TEXT_DATA", PropertyValue::Text(characters)) => {
+                if let Ok(field) = file.text_data_field(characters) {
+                    if let Ok(text) = file.apply_override(node, &[field]) {
+                        *node = text;
+                    }
+                }
+            }
+            ("OVERRIDDEN_SYMBOL_ID", PropertyValue::Component(id)) => node.swapped_component_id = Some(id.clone()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instances::expand_instance;
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_component_properties() {
+        let file = test_fixture::expanded();
+
+        // A variant's properties are its set's, under the variant's own ids
+        let instance = file.get_node("6:96").unwrap();
+        let component = file.get_node("6:94").unwrap();
+        let set = component_set(file, component).unwrap();
+        assert_eq!(set.id, "37:43569");
+        assert!(set.is_state_group);
+        let properties = component_properties(file, component);
+        let title = properties.iter().find(|def| def.name == "Title").unwrap();
+        assert_eq!((title.id.as_str(), title.parent_id.as_deref()), ("6:0", Some("37:2")));
+        assert_eq!(title.property_type, Some(PropertyType::Text));
+        assert!(component_properties(file, set).iter().any(|def| def.id == "37:2"));
+        let axes = variant_axes(file, set);
+        assert_eq!(axes[0].name, "Variant");
+        assert!(axes[0].values.contains(&"Component".to_string()));
+
+        // Instances show their assigned text
+        let values = instance_properties(file, instance);
+        assert!(values.iter().any(|(def, value)| def.name == "Title" && *value == PropertyValue::Text("Colors".into())));
+        assert_eq!(file.get_node("I6:96;6:98").unwrap().text.as_ref().unwrap().characters, "Colors");
+
+        // Other values, without changing the document
+        let props = [("Title".to_string(), "Hello".to_string())];
+        let changed = with_properties(file, instance, &props).unwrap();
+        let expansion = expand_instance(file, &changed);
+        assert_eq!(expansion.nodes["I6:96;6:98"].text.as_ref().unwrap().characters, "Hello");
+        assert_eq!(file.get_node("I6:96;6:98").unwrap().text.as_ref().unwrap().characters, "Colors");
+        let invalid = [("Missing".to_string(), "x".to_string())];
+        assert!(with_properties(file, instance, &invalid).is_err());
+
+        // Switching a variant property picks the variant with the other
+        // values unchanged
+        let (instance, set) = file.ordered_nodes().into_iter()
+            .filter_map(|node| {
+                let component = file.get_node(node.component_id()?)?;
+                let set = component_set(file, component)?;
+                variant_axes(file, set).iter().any(|axis| axis.name == "Theme").then_some((node, set))
+            })
+            .next()
+            .unwrap();
+        let current = parse_variant_name(&file.get_node(instance.component_id().unwrap()).unwrap().name);
+        let theme = variant_axes(file, set).into_iter().find(|axis| axis.name == "Theme").unwrap();
+        let other = theme.values.iter().find(|value| !current.contains(&("Theme".to_string(), value.to_string()))).unwrap();
+        let changed = with_properties(file, instance, &[("Theme".to_string(), other.clone())]).unwrap();
+        let variant = file.get_node(changed.component_id().unwrap()).unwrap();
+        let mut expected: Vec<_> = current.into_iter()
+            .map(|(name, value)| if name == "Theme" { (name, other.clone()) } else { (name, value) })
+            .collect();
+        let mut values = parse_variant_name(&variant.name);
+        expected.sort();
+        values.sort();
+        assert_eq!(values, expected);
+        assert_eq!(variant.parent_id.as_deref(), Some(set.id.as_str()));
+        assert!(!expand_instance(file, &changed).nodes.is_empty());
+    }
+}
@@ -1,6 +1,8 @@
 //! Effect types (shadows, blurs)
 
-use crate::api::ColorInfo;
+use crate::api::{ColorInfo, EffectInfo};
+use crate::kiwi::{KiwiMessage, KiwiValue};
+use crate::paints::{color_from_kiwi, BlendMode};
 
 #[derive(Debug, Clone)]
 pub enum Effect {
@@ -17,6 +19,10 @@ pub struct ShadowEffect {
     pub radius: f64,
     pub spread: f64,
     pub visible: bool,
+    pub blend_mode: BlendMode,
+    /// For drop shadows, whether the shadow also shows through the node
+    /// where its fills are translucent
+    pub show_behind_node: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -24,3 +30,161 @@ pub struct BlurEffect {
     pub radius: f64,
     pub visible: bool,
 }
+
+impl Effect {
+    /// Build an effect from a decoded `Effect` message. Effect types the
+    /// renderer can't draw (repeat, symmetry, grain, noise, glass) return None.
+    pub fn from_kiwi(effect: &KiwiMessage) -> Option<Effect> {
+        let radius = effect.float("radius").unwrap_or(0.0).max(0.0);
+        let visible = effect.bool("visible").unwrap_or(true);
+
+        let shadow = || ShadowEffect {
+            color: effect.message("color").map(color_from_kiwi)
+                .unwrap_or(ColorInfo { r: 0, g: 0, b: 0, a: 64 }),
+            offset: effect.message("offset")
+                .map(|offset| (offset.float("x").unwrap_or(0.0), offset.float("y").unwrap_or(0.0)))
+                .unwrap_or((0.0, 0.0)),
+            radius,
+            spread: effect.float("spread").unwrap_or(0.0),
+            visible,
+            blend_mode: BlendMode::from_name(effect.str("blendMode").unwrap_or("NORMAL")),
+            show_behind_node: effect.bool("showShadowBehindNode").unwrap_or(false),
+        };
+
+        match effect.str("type")? {
+            "DROP_SHADOW" => Some(Effect::DropShadow(shadow())),
+            "INNER_SHADOW" => Some(Effect::InnerShadow(shadow())),
+            "FOREGROUND_BLUR" => Some(Effect::LayerBlur(BlurEffect { radius, visible })),
+            "BACKGROUND_BLUR" => Some(Effect::BackgroundBlur(BlurEffect { radius, visible })),
+            _ => None,
+        }
+    }
+
+    /// Build effects from a decoded `Effect[]` field
+    pub fn list_from_kiwi(effects: &KiwiValue) -> Vec<Effect> {
+        effects.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter_map(Effect::from_kiwi)
+            .collect()
+    }
+
+    pub fn visible(&self) -> bool {
+        match self {
+            Effect::DropShadow(e) | Effect::InnerShadow(e) => e.visible,
+            Effect::LayerBlur(e) | Effect::BackgroundBlur(e) => e.visible,
+        }
+    }
+
+    /// Convert for draw commands
+    pub fn to_effect_info(&self) -> EffectInfo {
+        match self {
+            Effect::DropShadow(e) | Effect::InnerShadow(e) => EffectInfo {
+                effect_type: if matches!(self, Effect::DropShadow(_)) { "drop_shadow" } else { "inner_shadow" }.to_string(),
+                visible: e.visible,
+                radius: e.radius,
+                color: Some(e.color.clone()),
+                offset_x: e.offset.0,
+                offset_y: e.offset.1,
+                spread: e.spread,
+                blend_mode: e.blend_mode.name().to_string(),
+                show_shadow_behind_node: e.show_behind_node,
+            },
+            Effect::LayerBlur(e) | Effect::BackgroundBlur(e) => EffectInfo {
+                effect_type: if matches!(self, Effect::LayerBlur(_)) { "layer_blur" } else { "background_blur" }.to_string(),
+                visible: e.visible,
+                radius: e.radius,
+                color: None,
+                offset_x: 0.0,
+                offset_y: 0.0,
+                spread: 0.0,
+                blend_mode: BlendMode::Normal.name().to_string(),
+                show_shadow_behind_node: false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+    use crate::render::RenderContext;
+
+    #[test]
+    fn test_from_kiwi() {
+        let message = |fields: Vec<(&str, KiwiValue)>| KiwiMessage {
+            fields: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        };
+        let shadow = message(vec![
+            ("type", KiwiValue::Enum("DROP_SHADOW".into())),
+            ("offset", KiwiValue::Message(message(vec![("x", KiwiValue::Float(2.0)), ("y", KiwiValue::Float(4.0))]))),
+            ("radius", KiwiValue::Float(8.0)),
+            ("spread", KiwiValue::Float(-1.0)),
+            ("blendMode", KiwiValue::Enum("MULTIPLY".into())),
+            ("showShadowBehindNode", KiwiValue::Bool(true)),
+        ]);
+        let Some(Effect::DropShadow(effect)) = Effect::from_kiwi(&shadow) else {
+            panic!("expected a drop shadow");
+        };
+        assert_eq!(effect.offset, (2.0, 4.0));
+        assert_eq!(effect.spread, -1.0);
+        assert_eq!(effect.blend_mode, BlendMode::Multiply);
+        assert!(effect.visible && effect.show_behind_node);
+        assert_eq!(effect.color.a, 64);
+
+        let blur = message(vec![("type", KiwiValue::Enum("FOREGROUND_BLUR".into())), ("visible", KiwiValue::Bool(false))]);
+        let info = Effect::from_kiwi(&blur).unwrap().to_effect_info();
+        assert_eq!(info.effect_type, "layer_blur");
+        assert!(!info.visible);
+
+        assert!(Effect::from_kiwi(&message(vec![("type", KiwiValue::Enum("GRAIN".into()))])).is_none());
+    }
+
+    #[test]
+    fn test_decode_effects() {
+        let file = test_fixture::file();
+        let nodes = file.ordered_nodes();
+        let ctx = RenderContext::empty();
+
+        let shadowed = nodes.iter()
+            .find(|node| node.node_type == "FRAME" && node.visible && node.effects.iter()
+                .any(|effect| matches!(effect, Effect::DropShadow(s) if s.visible && !s.show_behind_node)))
+            .unwrap();
+        let commands = shadowed.draw_commands(&ctx);
+        let types: Vec<&str> = commands.iter().map(|c| c.command_type.as_str()).collect();
+        let shape = types.iter().position(|t| *t == "rect").unwrap();
+        let shadow = types.iter().position(|t| *t == "drop_shadow").unwrap();
+        assert!(shadow < shape);
+        let clip = commands[shadow].clip_path.as_ref().unwrap();
+        assert_eq!(clip.fill_rule, "evenodd");
+        assert_eq!(commands[shadow].effects[0].effect_type, "drop_shadow");
+
+        let behind = nodes.iter()
+            .find(|node| node.visible && node.shape_path().is_some() && node.effects.iter()
+                .any(|effect| matches!(effect, Effect::DropShadow(s) if s.visible && s.show_behind_node)))
+            .unwrap();
+        let commands = behind.draw_commands(&ctx);
+        let shadow = commands.iter().find(|c| c.command_type == "drop_shadow").unwrap();
+        assert!(shadow.clip_path.is_none());
+        assert!(shadow.effects[0].show_shadow_behind_node);
+
+        let inner = nodes.iter()
+            .find(|node| node.visible && node.shape_path().is_some() && node.effects.iter()
+                .any(|effect| matches!(effect, Effect::InnerShadow(s) if s.visible)))
+            .unwrap();
+        let commands = inner.draw_commands(&ctx);
+        let last = commands.iter().rposition(|c| c.command_type == "inner_shadow").unwrap();
+        let shape = commands.iter().position(|c| c.effects.len() == inner.effects.len() && c.command_type != "inner_shadow").unwrap();
+        assert!(shape < last);
+        assert_eq!(commands[last].path.as_ref().unwrap().fill_rule, "evenodd");
+        assert!(commands[last].clip_path.is_some());
+
+        let blurred = nodes.iter()
+            .find(|node| node.visible && node.shape_path().is_some() && node.effects.iter()
+                .any(|effect| matches!(effect, Effect::BackgroundBlur(b) if b.visible)))
+            .unwrap();
+        let commands = blurred.draw_commands(&ctx);
+        let blur = commands.iter().find(|c| c.command_type == "background_blur").unwrap();
+        assert_eq!(blur.clip_path.as_ref().unwrap().commands, blurred.shape_path().unwrap().commands);
+    }
+}
@@ -0,0 +1,548 @@
+//! Fonts supplied by the app for text layout
+//!
+//! .fig files only name their fonts, so the bytes have to come from the
+//! app. Fonts are parsed with `ttf-parser`; the registry keeps the raw bytes
+//! and parses a face whenever text is laid out, which is cheap.
+//!
+//! A Figma `FontName` is matched to the registered faces by PostScript name,
+//! then by family and style. Failing that, the closest weight and slant in
+//! the same family is substituted, and then the registry's fallback families
+//! are tried in order.
+
+use crate::{FigmaError, Result};
+use std::sync::Arc;
+use ttf_parser::{name_id, Face, GlyphId, Tag};
+
+/// Tag of the weight axis of variable fonts
+pub const WEIGHT_AXIS: Tag = Tag::from_bytes(b"wght");
+
+/// One face of a registered font file
+#[derive(Debug, Clone)]
+pub struct Font {
+    data: Arc<[u8]>,
+    index: u32,
+    pub family: String,
+    pub style: String,
+    pub postscript_name: String,
+    /// OS/2 weight class, 100-900
+    pub weight: u16,
+    pub italic: bool,
+    /// Range of the `wght` axis, for variable fonts
+    pub weight_range: Option<(f32, f32)>,
+    pub metrics: FontMetrics,
+}
+
+/// Vertical metrics in font units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    pub units_per_em: f64,
+    pub ascender: f64,
+    /// Negative, below the baseline
+    pub descender: f64,
+    pub line_gap: f64,
+    /// Top of the underline relative to the baseline (negative is below) and its thickness
+    pub underline: Option<(f64, f64)>,
+    pub strikeout: Option<(f64, f64)>,
+}
+
+impl Font {
+    /// Parse every face in a font file or collection
+    pub fn parse_all(data: Vec<u8>) -> Result<Vec<Font>> {
+        let data: Arc<[u8]> = data.into();
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+        (0..count).map(|index| Font::parse(data.clone(), index)).collect()
+    }
+
+    fn parse(data: Arc<[u8]>, index: u32) -> Result<Font> {
+        let face = Face::parse(&data, index)
+            .map_err(|e| FigmaError::FontError(e.to_string()))?;
+
+        let name = |ids: &[u16]| {
+            ids.iter().find_map(|&id| {
+                face.names().into_iter()
+                    .filter(|name| name.name_id == id && name.is_unicode())
+                    .find_map(|name| name.to_string())
+            }).unwrap_or_default()
+        };
+        let family = name(&[name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]);
+        let style = name(&[name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY]);
+        let postscript_name = name(&[name_id::POST_SCRIPT_NAME]);
+
+        let metrics = FontMetrics {
+            units_per_em: face.units_per_em() as f64,
+            ascender: face.ascender() as f64,
+            descender: face.descender() as f64,
+            line_gap: face.line_gap() as f64,
+            underline: face.underline_metrics().map(|m| (m.position as f64, m.thickness as f64)),
+            strikeout: face.strikeout_metrics().map(|m| (m.position as f64, m.thickness as f64)),
+        };
+        let weight = face.weight().to_number();
+        let italic = face.is_italic() || face.is_oblique();
+        let weight_range = face.variation_axes().into_iter()
+            .find(|axis| axis.tag == WEIGHT_AXIS)
+            .map(|axis| (axis.min_value, axis.max_value));
+
+        Ok(Font {
+            data: data.clone(),
+            index,
+            family,
+            style: if style.is_empty() { "Regular".to_string() } else { style },
+            postscript_name,
+            weight,
+            italic,
+            weight_range,
+            metrics,
+        })
+    }
+
+    /// Parse the face for glyph lookups. The data was validated when the
+    /// font was registered, so this only fails on a corrupted registry.
+    pub fn face(&self) -> Option<Face<'_>> {
+        Face::parse(&self.data, self.index).ok()
+    }
+
+    /// Parse the face with variation coordinates applied. Axes the face
+    /// doesn't have are ignored.
+    pub fn face_with_variations(&self, variations: &[(Tag, f32)]) -> Option<Face<'_>> {
+        let mut face = self.face()?;
+        for &(tag, value) in variations {
+            face.set_variation(tag, value);
+        }
+        Some(face)
+    }
+
+    /// How far the face is from a requested weight, 0 if a variable font
+    /// covers it
+    fn weight_distance(&self, weight: u16) -> u16 {
+        match self.weight_range {
+            Some((min, max)) => {
+                let weight = weight as f32;
+                if weight < min { (min - weight) as u16 } else if weight > max { (weight - max) as u16 } else { 0 }
+            }
+            None => self.weight.abs_diff(weight),
+        }
+    }
+
+    /// The registered file the face was read from, and the face's index in
+    /// it when the file is a collection
+    pub fn file_data(&self) -> (&[u8], u32) {
+        (&self.data, self.index)
+    }
+
+    /// "Family Style", as shown in Figma's font picker
+    pub fn display_name(&self) -> String {
+        format!("{} {}", self.family, self.style)
+    }
+}
+
+/// Horizontal kerning between two glyphs from the `kern` table, in font
+/// units. GPOS kerning needs a shaper and isn't applied.
+pub fn kerning(face: &Face, left: GlyphId, right: GlyphId) -> f64 {
+    let Some(kern) = face.tables().kern else {
+        return 0.0;
+    };
+    kern.subtables.into_iter()
+        .filter(|subtable| subtable.horizontal && !subtable.variable)
+        .find_map(|subtable| subtable.glyphs_kerning(left, right))
+        .unwrap_or(0) as f64
+}
+
+/// Weight and slant implied by a Figma style name such as "Semibold Italic"
+pub fn style_traits(style: &str) -> (u16, bool) {
+    let style: String = style.to_ascii_lowercase().chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    let italic = style.contains("italic") || style.contains("oblique");
+    const WEIGHTS: &[(&str, u16)] = &[
+        ("extralight", 200), ("ultralight", 200), ("semibold", 600), ("demibold", 600),
+        ("extrabold", 800), ("ultrabold", 800), ("thin", 100), ("hairline", 100),
+        ("light", 300), ("medium", 500), ("bold", 700), ("heavy", 800), ("black", 900),
+    ];
+    let weight = WEIGHTS.iter().find(|(name, _)| style.contains(name)).map_or(400, |&(_, weight)| weight);
+    (weight, italic)
+}
+
+/// The weight axis setting for a variable font, clamped to its range
+fn weight_variation(font: &Font, weight: u16) -> Option<(Tag, f32)> {
+    let (min, max) = font.weight_range?;
+    Some((WEIGHT_AXIS, (weight as f32).clamp(min, max)))
+}
+
+/// How a requested font was resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontMatchKind {
+    /// The requested face, or a variable font covering it
+    Exact,
+    /// Another face of the requested family
+    Substitute,
+    /// A face from the fallback families
+    Fallback,
+}
+
+impl FontMatchKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontMatchKind::Exact => "EXACT",
+            FontMatchKind::Substitute => "SUBSTITUTE",
+            FontMatchKind::Fallback => "FALLBACK",
+        }
+    }
+}
+
+/// A registered face chosen for a requested font
+#[derive(Debug, Clone)]
+pub struct FontMatch<'a> {
+    pub font: &'a Font,
+    pub kind: FontMatchKind,
+    /// Variation coordinates to lay out with, for variable fonts
+    pub variations: Vec<(Tag, f32)>,
+}
+
+impl<'a> FontMatch<'a> {
+    pub fn face(&self) -> Option<Face<'a>> {
+        self.font.face_with_variations(&self.variations)
+    }
+}
+
+/// A font as referenced by text: a Figma `FontName` plus any
+/// `fontVariations`, with axis tags as stored in the file
+#[derive(Debug, Clone, Copy)]
+pub struct FontRequest<'a> {
+    pub family: &'a str,
+    pub style: &'a str,
+    pub postscript_name: &'a str,
+    pub variations: &'a [(u32, f64)],
+}
+
+impl FontRequest<'_> {
+    /// Requested weight and slant, with an explicit weight axis value
+    /// taking precedence over the style name
+    pub fn traits(&self) -> (u16, bool) {
+        let (weight, italic) = style_traits(self.style);
+        let weight = self.variations.iter()
+            .find(|&&(tag, _)| Tag(tag) == WEIGHT_AXIS)
+            .map_or(weight, |&(_, value)| value.round() as u16);
+        (weight, italic)
+    }
+}
+
+/// Fonts registered by the app
+#[derive(Debug, Clone, Default)]
+pub struct FontRegistry {
+    fonts: Vec<Font>,
+    /// Families tried in order for fonts that aren't registered and for
+    /// characters the matched font has no glyph for
+    fallbacks: Vec<String>,
+}
+
+impl FontRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a TTF, OTF or collection, returning the names of the faces
+    /// it contains. A face with the same PostScript name replaces the old one.
+    pub fn register(&mut self, data: Vec<u8>) -> Result<Vec<String>> {
+        let fonts = Font::parse_all(data)?;
+        let names = fonts.iter().map(Font::display_name).collect();
+        for font in fonts {
+            if !font.postscript_name.is_empty() {
+                self.fonts.retain(|f| f.postscript_name != font.postscript_name);
+            }
+            self.fonts.push(font);
+        }
+        Ok(names)
+    }
+
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    pub fn fallbacks(&self) -> &[String] {
+        &self.fallbacks
+    }
+
+    /// Set the fallback families, tried in order
+    pub fn set_fallbacks(&mut self, families: Vec<String>) {
+        self.fallbacks = families;
+    }
+
+    /// Resolve a requested font to a registered face: the exact face, else
+    /// the closest face of the same family, else the closest face of the
+    /// first fallback family that is registered
+    pub fn resolve(&self, request: &FontRequest) -> Option<FontMatch<'_>> {
+        let (weight, italic) = request.traits();
+
+        let (font, kind) = if let Some(font) = self.find(request.family, request.style, request.postscript_name) {
+            (font, FontMatchKind::Exact)
+        } else if let Some(font) = self.closest(request.family, weight, italic) {
+            let covers = font.weight_distance(weight) == 0 && font.italic == italic && font.weight_range.is_some();
+            (font, if covers { FontMatchKind::Exact } else { FontMatchKind::Substitute })
+        } else {
+            return self.fallback_fonts(request).into_iter().next();
+        };
+
+        let mut variations: Vec<(Tag, f32)> = request.variations.iter()
+            .map(|&(tag, value)| (Tag(tag), value as f32))
+            .collect();
+        variations.extend(weight_variation(font, weight).filter(|_| !variations.iter().any(|(tag, _)| *tag == WEIGHT_AXIS)));
+        Some(FontMatch { font, kind, variations })
+    }
+
+    /// The face of each registered fallback family closest to the request,
+    /// in fallback order
+    pub fn fallback_fonts(&self, request: &FontRequest) -> Vec<FontMatch<'_>> {
+        let (weight, italic) = request.traits();
+        self.fallbacks.iter()
+            .filter_map(|family| self.closest(family, weight, italic))
+            .map(|font| FontMatch {
+                font,
+                kind: FontMatchKind::Fallback,
+                variations: weight_variation(font, weight).into_iter().collect(),
+            })
+            .collect()
+    }
+
+    /// The face of a family closest to a weight and slant, preferring the
+    /// right slant over the right weight
+    fn closest(&self, family: &str, weight: u16, italic: bool) -> Option<&Font> {
+        self.fonts.iter()
+            .filter(|f| f.family.eq_ignore_ascii_case(family))
+            .min_by_key(|f| (f.italic != italic, f.weight_distance(weight)))
+    }
+
+    /// Find the face for a Figma `FontName`, by PostScript name or by
+    /// family and style, ignoring case
+    pub fn find(&self, family: &str, style: &str, postscript_name: &str) -> Option<&Font> {
+        if !postscript_name.is_empty() {
+            if let Some(font) = self.fonts.iter().find(|f| f.postscript_name == postscript_name) {
+                return Some(font);
+            }
+        }
+        self.fonts.iter().find(|f| {
+            f.family.eq_ignore_ascii_case(family) && f.style.eq_ignore_ascii_case(style)
+        })
+    }
+}
+
+/// Builds minimal TrueType fonts with known metrics for tests
+#[cfg(test)]
+pub(crate) mod test_font {
+    /// A font with 1000 units per em, ascender 800, descender -200 and the
+    /// given glyph advances. Glyph 0 is .notdef with advance 500 and no
+    /// outline; the other glyphs are boxes from (50, 0) to (advance - 50, 700).
+    pub fn build(family: &str, style: &str, weight: u16, italic: bool, glyphs: &[(char, u16)]) -> Vec<u8> {
+        build_variable(family, style, weight, italic, glyphs, None)
+    }
+
+    /// Like `build`, with a `wght` axis over the given range. The axis has
+    /// no effect on the glyphs.
+    pub fn build_variable(
+        family: &str,
+        style: &str,
+        weight: u16,
+        italic: bool,
+        glyphs: &[(char, u16)],
+        weight_range: Option<(i16, i16)>,
+    ) -> Vec<u8> {
+        let mut glyphs = glyphs.to_vec();
+        glyphs.sort();
+        let num_glyphs = glyphs.len() as u16 + 1;
+
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5f0f_3cf5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+
+        let mut hhea = vec![0u8; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&num_glyphs.to_be_bytes());
+
+        let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+        maxp.extend_from_slice(&num_glyphs.to_be_bytes());
+
+        let mut hmtx = Vec::new();
+        for advance in std::iter::once(500).chain(glyphs.iter().map(|&(_, a)| a)) {
+            hmtx.extend_from_slice(&advance.to_be_bytes());
+            hmtx.extend_from_slice(&[0, 0]);
+        }
+
+        // Format 12 cmap with one group per character
+        let mut cmap = Vec::new();
+        for value in [0u16, 1, 3, 10] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+        cmap.extend_from_slice(&12u32.to_be_bytes());
+        cmap.extend_from_slice(&[0, 12, 0, 0]);
+        cmap.extend_from_slice(&(16 + 12 * glyphs.len() as u32).to_be_bytes());
+        cmap.extend_from_slice(&[0; 4]);
+        cmap.extend_from_slice(&(glyphs.len() as u32).to_be_bytes());
+        for (index, &(c, _)) in glyphs.iter().enumerate() {
+            cmap.extend_from_slice(&(c as u32).to_be_bytes());
+            cmap.extend_from_slice(&(c as u32).to_be_bytes());
+            cmap.extend_from_slice(&(index as u32 + 1).to_be_bytes());
+        }
+
+        let mut os2 = vec![0u8; 78];
+        os2[4..6].copy_from_slice(&weight.to_be_bytes());
+        os2[6..8].copy_from_slice(&5u16.to_be_bytes());
+        os2[62..64].copy_from_slice(&(if italic { 1u16 } else { 0x40 }).to_be_bytes());
+
+        let postscript = format!("{}-{}", family.replace(' ', ""), style.replace(' ', ""));
+        let name = name_table(&[(1, family), (2, style), (6, &postscript)]);
+
+        // Short loca offsets, so glyph data stays 2-byte aligned
+        let mut glyf = Vec::new();
+        let mut loca = vec![0, 0];
+        for &(_, advance) in &glyphs {
+            let (x0, x1) = (50i16, advance as i16 - 50);
+            for value in [1, x0, 0, x1, 700, 3, 0] {
+                glyf.extend_from_slice(&value.to_be_bytes());
+            }
+            glyf.extend_from_slice(&[1; 4]);
+            for delta in [x0, x1 - x0, 0, x0 - x1, 0, 0, 700, 0] {
+                glyf.extend_from_slice(&delta.to_be_bytes());
+            }
+            loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+        }
+
+        let mut tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (b"OS/2", os2), (b"cmap", cmap), (b"glyf", glyf), (b"head", head), (b"hhea", hhea),
+            (b"hmtx", hmtx), (b"loca", loca), (b"maxp", maxp), (b"name", name),
+        ];
+        if let Some((min, max)) = weight_range {
+            // One axis record with 16.16 fixed values, no named instances
+            let mut fvar = Vec::new();
+            for field in [1u16, 0, 16, 2, 1, 20, 0, 8] {
+                fvar.extend_from_slice(&field.to_be_bytes());
+            }
+            fvar.extend_from_slice(b"wght");
+            for value in [min, weight as i16, max] {
+                fvar.extend_from_slice(&value.to_be_bytes());
+                fvar.extend_from_slice(&[0, 0]);
+            }
+            fvar.extend_from_slice(&[0, 0, 0, 0]);
+            tables.push((b"fvar", fvar));
+            tables.sort_by_key(|(tag, _)| **tag);
+        }
+        let mut font = 0x0001_0000u32.to_be_bytes().to_vec();
+        font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        font.extend_from_slice(&[0; 6]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend_from_slice(*tag);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += (data.len() + 3) & !3;
+        }
+        for (_, data) in &tables {
+            font.extend_from_slice(data);
+            font.resize((font.len() + 3) & !3, 0);
+        }
+        font
+    }
+
+    fn name_table(names: &[(u16, &str)]) -> Vec<u8> {
+        let mut records = Vec::new();
+        let mut strings = Vec::new();
+        for &(id, value) in names {
+            let encoded: Vec<u8> = value.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            for field in [3u16, 1, 0x409, id, encoded.len() as u16, strings.len() as u16] {
+                records.extend_from_slice(&field.to_be_bytes());
+            }
+            strings.extend_from_slice(&encoded);
+        }
+        let mut table = Vec::new();
+        for field in [0u16, names.len() as u16, 6 + records.len() as u16] {
+            table.extend_from_slice(&field.to_be_bytes());
+        }
+        table.extend_from_slice(&records);
+        table.extend_from_slice(&strings);
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_find() {
+        let mut registry = FontRegistry::new();
+        let names = registry.register(test_font::build("Inter", "Bold", 700, false, &[('a', 600)])).unwrap();
+        assert_eq!(names, vec!["Inter Bold"]);
+
+        let font = registry.find("inter", "bold", "").unwrap();
+        assert_eq!(font.postscript_name, "Inter-Bold");
+        assert_eq!(font.weight, 700);
+        assert_eq!(font.metrics.units_per_em, 1000.0);
+        assert!(registry.find("Inter", "Regular", "").is_none());
+        assert!(registry.find("", "", "Inter-Bold").is_some());
+
+        let face = font.face().unwrap();
+        let glyph = face.glyph_index('a').unwrap();
+        assert_eq!(face.glyph_hor_advance(glyph), Some(600));
+
+        // Registering the same face again replaces it
+        registry.register(test_font::build("Inter", "Bold", 700, false, &[])).unwrap();
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_style_traits() {
+        assert_eq!(style_traits("Regular"), (400, false));
+        assert_eq!(style_traits("Semibold Italic"), (600, true));
+        assert_eq!(style_traits("Extra Light"), (200, false));
+        assert_eq!(style_traits("ExtraBold"), (800, false));
+        assert_eq!(style_traits("Black Oblique"), (900, true));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut registry = FontRegistry::new();
+        for (style, weight, italic) in [("Regular", 400, false), ("Bold", 700, false), ("Italic", 400, true)] {
+            registry.register(test_font::build("Inter", style, weight, italic, &[])).unwrap();
+        }
+        registry.register(test_font::build("Noto Sans", "Regular", 400, false, &[])).unwrap();
+        registry.register(test_font::build_variable("Roboto Flex", "Regular", 400, false, &[], Some((100, 1000)))).unwrap();
+
+        let request = |family, style| FontRequest { family, style, postscript_name: "", variations: &[] };
+        let resolve = |family, style| {
+            let found = registry.resolve(&request(family, style)).unwrap();
+            (found.font.display_name(), found.kind)
+        };
+        assert_eq!(resolve("Inter", "Bold"), ("Inter Bold".into(), FontMatchKind::Exact));
+        assert_eq!(resolve("Inter", "Semibold"), ("Inter Bold".into(), FontMatchKind::Substitute));
+        // Slant wins over weight
+        assert_eq!(resolve("Inter", "Bold Italic"), ("Inter Italic".into(), FontMatchKind::Substitute));
+        assert!(registry.resolve(&request("SF Pro", "Regular")).is_none());
+
+        registry.set_fallbacks(vec!["Missing".into(), "Noto Sans".into()]);
+        let found = registry.resolve(&request("SF Pro", "Bold")).unwrap();
+        assert_eq!((found.font.family.as_str(), found.kind), ("Noto Sans", FontMatchKind::Fallback));
+
+        // A variable font covers every weight on its axis
+        let found = registry.resolve(&request("Roboto Flex", "Semibold")).unwrap();
+        assert_eq!(found.kind, FontMatchKind::Exact);
+        assert_eq!(found.variations, vec![(WEIGHT_AXIS, 600.0)]);
+        let variations = [(WEIGHT_AXIS.0, 850.0)];
+        let found = registry.resolve(&FontRequest { variations: &variations, ..request("Roboto Flex", "Regular") }).unwrap();
+        assert_eq!(found.variations, vec![(WEIGHT_AXIS, 850.0)]);
+        assert!(found.face().unwrap().is_variable());
+    }
+
+    #[test]
+    fn test_register_invalid_font() {
+        let mut registry = FontRegistry::new();
+        assert!(matches!(registry.register(b"not a font".to_vec()), Err(FigmaError::FontError(_))));
+    }
+}
@@ -0,0 +1,362 @@
+//! Layout grids
+//!
+//! A frame's `layoutGrids` divide it into columns, rows or a square grid.
+//! Columns and rows are a number of sections separated by gutters, either
+//! stretched to fill the frame less the offset on both sides, or of a fixed
+//! size placed against one edge or in the middle. Children's constraints
+//! are relative to the area a stretched grid covers rather than to the
+//! whole frame.
+
+use crate::api::{ColorInfo, DrawCommand, LayoutGridInfo, PaintInfo, RectInfo, TransformInfo};
+use crate::kiwi::{KiwiMessage, KiwiValue};
+use crate::nodes::FigmaNode;
+use crate::paints::{color_from_kiwi, Paint, SolidPaint};
+
+/// Figma's color for new grids, red at 10%
+const DEFAULT_COLOR: ColorInfo = ColorInfo { r: 255, g: 0, b: 0, a: 26 };
+
+/// What a grid divides its frame into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GridPattern {
+    /// Sections side by side along the x axis
+    #[default]
+    Columns,
+    /// Sections stacked along the y axis
+    Rows,
+    /// Squares of `section_size`
+    Grid,
+}
+
+impl GridPattern {
+    /// From the schema's `pattern` and `axis`
+    pub fn from_kiwi(pattern: Option<&str>, axis: Option<&str>) -> Self {
+        match (pattern, axis) {
+            (Some("GRID"), _) => GridPattern::Grid,
+            (_, Some("Y")) => GridPattern::Rows,
+            _ => GridPattern::Columns,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GridPattern::Columns => "COLUMNS",
+            GridPattern::Rows => "ROWS",
+            GridPattern::Grid => "GRID",
+        }
+    }
+}
+
+/// Where columns or rows sit in the frame (schema `LayoutGridType`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GridAlignment {
+    #[default]
+    Min,
+    Center,
+    Max,
+    /// Sections share what the offset and gutters leave of the frame
+    Stretch,
+}
+
+impl GridAlignment {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "CENTER" => GridAlignment::Center,
+            "MAX" => GridAlignment::Max,
+            "STRETCH" => GridAlignment::Stretch,
+            _ => GridAlignment::Min,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GridAlignment::Min => "MIN",
+            GridAlignment::Center => "CENTER",
+            GridAlignment::Max => "MAX",
+            GridAlignment::Stretch => "STRETCH",
+        }
+    }
+}
+
+/// A `LayoutGrid`
+#[derive(Debug, Clone)]
+pub struct LayoutGrid {
+    pub pattern: GridPattern,
+    pub alignment: GridAlignment,
+    /// Number of sections, or None to fit as many as the frame holds
+    pub count: Option<u32>,
+    /// Size of each section, unless stretched
+    pub section_size: f64,
+    pub gutter_size: f64,
+    /// Distance from the edge the sections are aligned to, or from both
+    /// edges when stretched
+    pub offset: f64,
+    pub visible: bool,
+    pub color: ColorInfo,
+}
+
+impl LayoutGrid {
+    pub fn from_kiwi(grid: &KiwiMessage) -> Self {
+        LayoutGrid {
+            pattern: GridPattern::from_kiwi(grid.str("pattern"), grid.str("axis")),
+            alignment: GridAlignment::from_name(grid.str("type").unwrap_or("MIN")),
+            // "Auto" is stored as a count out of range
+            count: grid.float("numSections")
+                .filter(|&count| count >= 1.0 && count < i32::MAX as f64)
+                .map(|count| count as u32),
+            section_size: grid.float("sectionSize").unwrap_or(0.0),
+            gutter_size: grid.float("gutterSize").unwrap_or(0.0),
+            offset: grid.float("offset").unwrap_or(0.0),
+            visible: grid.bool("visible").unwrap_or(true),
+            color: grid.message("color").map(color_from_kiwi).unwrap_or(DEFAULT_COLOR),
+        }
+    }
+
+    pub fn list_from_kiwi(grids: &KiwiValue) -> Vec<LayoutGrid> {
+        grids.as_array().iter().filter_map(KiwiValue::as_message).map(LayoutGrid::from_kiwi).collect()
+    }
+
+    /// The grid's sections in a frame of this size, relative to the frame.
+    /// A square grid gives its lines, 1 pixel wide.
+    pub fn resolve(&self, width: f64, height: f64) -> Vec<RectInfo> {
+        let rect = |x, y, width, height| RectInfo { x, y, width, height, corner_radii: [0.0; 4] };
+        match self.pattern {
+            GridPattern::Columns => self.sections(width).into_iter().map(|(x, size)| rect(x, 0.0, size, height)).collect(),
+            GridPattern::Rows => self.sections(height).into_iter().map(|(y, size)| rect(0.0, y, width, size)).collect(),
+            GridPattern::Grid if self.section_size > 0.0 => {
+                let lines = |length: f64| (1..).map(|i| i as f64 * self.section_size).take_while(move |&at| at < length);
+                lines(width).map(|x| rect(x, 0.0, 1.0, height))
+                    .chain(lines(height).map(|y| rect(0.0, y, width, 1.0)))
+                    .collect()
+            }
+            GridPattern::Grid => Vec::new(),
+        }
+    }
+
+    /// Start and size of each section along a frame's length
+    fn sections(&self, length: f64) -> Vec<(f64, f64)> {
+        let gutter = self.gutter_size;
+        let (count, size) = match (self.alignment, self.count) {
+            (GridAlignment::Stretch, Some(count)) => {
+                (count, (length - 2.0 * self.offset - (count - 1) as f64 * gutter) / count as f64)
+            }
+            (_, Some(count)) => (count, self.section_size),
+            (_, None) if self.section_size > 0.0 => {
+                let room = match self.alignment {
+                    GridAlignment::Center => length,
+                    GridAlignment::Stretch => length - 2.0 * self.offset,
+                    _ => length - self.offset,
+                };
+                (((room + gutter) / (self.section_size + gutter)).floor().max(0.0) as u32, self.section_size)
+            }
+            (_, None) => (0, 0.0),
+        };
+        if count == 0 || size <= 0.0 {
+            return Vec::new();
+        }
+        let total = count as f64 * size + (count - 1) as f64 * gutter;
+        let start = match self.alignment {
+            GridAlignment::Min | GridAlignment::Stretch => self.offset,
+            GridAlignment::Center => (length - total) / 2.0,
+            GridAlignment::Max => length - self.offset - total,
+        };
+        (0..count).map(|i| (start + i as f64 * (size + gutter), size)).collect()
+    }
+
+    pub fn to_layout_grid_info(&self, width: f64, height: f64) -> LayoutGridInfo {
+        LayoutGridInfo {
+            pattern: self.pattern.name().to_string(),
+            alignment: self.alignment.name().to_string(),
+            count: self.count,
+            section_size: self.section_size,
+            gutter_size: self.gutter_size,
+            offset: self.offset,
+            visible: self.visible,
+            color: self.color.clone(),
+            rects: self.resolve(width, height),
+        }
+    }
+}
+
+/// The start and length of the area along a frame's width (for columns) or
+/// height (for rows) that children's constraints are relative to: that of
+/// the first visible stretched grid, or the whole length
+pub fn constraint_area(grids: &[LayoutGrid], pattern: GridPattern, length: f64) -> (f64, f64) {
+    grids.iter()
+        .find(|grid| grid.visible && grid.pattern == pattern && grid.alignment == GridAlignment::Stretch)
+        .map(|grid| (grid.offset, length - 2.0 * grid.offset))
+        .filter(|&(_, area)| area > 0.0)
+        .unwrap_or((0.0, length))
+}
+
+/// "rect" commands drawing a frame's visible grids over it, in the same
+/// space as the frame's own commands
+pub fn overlay_commands(node: &FigmaNode) -> Vec<DrawCommand> {
+    if !node.visible {
+        return Vec::new();
+    }
+    let mut commands = Vec::new();
+    for grid in node.layout_grids.iter().filter(|grid| grid.visible) {
+        let paint = Paint::Solid(SolidPaint {
+            color: grid.color.clone(),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: Default::default(),
+            color_variable: None,
+        });
+        for rect in grid.resolve(node.width, node.height) {
+            let fills: Vec<PaintInfo> = vec![paint.to_paint_info(rect.width, rect.height)];
+            commands.push(DrawCommand {
+                command_type: "rect".to_string(),
+                path: None,
+                rect: Some(RectInfo { x: node.x + rect.x, y: node.y + rect.y, ..rect }),
+                fills,
+                strokes: vec![],
+                stroke_weight: 0.0,
+                effects: vec![],
+                transform: TransformInfo::default(),
+                clip_path: None,
+                image: None,
+                text: None,
+                stroke: None,
+                layer: None,
+            });
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+    use crate::layout::Constraint;
+
+    fn columns(alignment: GridAlignment, count: Option<u32>) -> LayoutGrid {
+        LayoutGrid {
+            pattern: GridPattern::Columns,
+            alignment,
+            count,
+            section_size: 60.0,
+            gutter_size: 20.0,
+            offset: 10.0,
+            visible: true,
+            color: DEFAULT_COLOR,
+        }
+    }
+
+    #[test]
+    fn test_resolve_columns() {
+        let xs = |grid: &LayoutGrid| grid.resolve(400.0, 100.0).iter().map(|rect| (rect.x, rect.width)).collect::<Vec<_>>();
+        assert_eq!(xs(&columns(GridAlignment::Stretch, Some(4))), [(10.0, 80.0), (110.0, 80.0), (210.0, 80.0), (310.0, 80.0)]);
+        assert_eq!(xs(&columns(GridAlignment::Min, Some(2))), [(10.0, 60.0), (90.0, 60.0)]);
+        assert_eq!(xs(&columns(GridAlignment::Max, Some(2))), [(250.0, 60.0), (330.0, 60.0)]);
+        assert_eq!(xs(&columns(GridAlignment::Center, Some(2))), [(130.0, 60.0), (210.0, 60.0)]);
+        // As many as fit after the offset
+        assert_eq!(xs(&columns(GridAlignment::Min, None)).len(), 5);
+
+        let grid = LayoutGrid { pattern: GridPattern::Grid, section_size: 50.0, ..columns(GridAlignment::Min, None) };
+        assert_eq!(grid.resolve(120.0, 100.0).len(), 3);
+    }
+
+    #[test]
+    fn test_from_kiwi() {
+        let message = |fields: Vec<(&str, KiwiValue)>| KiwiMessage {
+            fields: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        };
+        let grids = KiwiValue::Array(vec![
+            KiwiValue::Message(message(vec![
+                ("type", KiwiValue::Enum("STRETCH".into())),
+                ("axis", KiwiValue::Enum("X".into())),
+                ("numSections", KiwiValue::Int(4)),
+                ("offset", KiwiValue::Float(16.0)),
+                ("gutterSize", KiwiValue::Float(8.0)),
+                ("color", KiwiValue::Message(message(vec![
+                    ("r", KiwiValue::Float(0.0)),
+                    ("g", KiwiValue::Float(0.0)),
+                    ("b", KiwiValue::Float(1.0)),
+                    ("a", KiwiValue::Float(0.5)),
+                ]))),
+            ])),
+            KiwiValue::Message(message(vec![
+                ("pattern", KiwiValue::Enum("GRID".into())),
+                ("sectionSize", KiwiValue::Float(8.0)),
+                ("visible", KiwiValue::Bool(false)),
+            ])),
+            // "Auto" rows
+            KiwiValue::Message(message(vec![
+                ("axis", KiwiValue::Enum("Y".into())),
+                ("numSections", KiwiValue::Int(i32::MAX as i64)),
+            ])),
+        ]);
+        let grids = LayoutGrid::list_from_kiwi(&grids);
+        assert_eq!(grids.len(), 3);
+        assert_eq!((grids[0].pattern, grids[0].alignment, grids[0].count, grids[0].visible), (GridPattern::Columns, GridAlignment::Stretch, Some(4), true));
+        assert_eq!((grids[0].offset, grids[0].gutter_size), (16.0, 8.0));
+        assert_eq!((grids[0].color.b, grids[0].color.a), (255, 128));
+        assert_eq!((grids[1].pattern, grids[1].section_size, grids[1].visible), (GridPattern::Grid, 8.0, false));
+        assert_eq!((grids[1].color.r, grids[1].color.a), (255, 26));
+        assert_eq!((grids[2].pattern, grids[2].alignment, grids[2].count), (GridPattern::Rows, GridAlignment::Min, None));
+    }
+
+    #[test]
+    fn test_overlay_commands() {
+        let stretched = LayoutGrid { offset: 16.0, gutter_size: 8.0, ..columns(GridAlignment::Stretch, Some(4)) };
+        let hidden = LayoutGrid { visible: false, ..columns(GridAlignment::Min, Some(2)) };
+        let mut frame = FigmaNode {
+            x: 100.0,
+            y: 50.0,
+            width: 300.0,
+            height: 200.0,
+            visible: true,
+            layout_grids: vec![stretched, hidden],
+            ..Default::default()
+        };
+
+        // Four columns sharing what the offset and gutters leave, in the
+        // frame's parent's space
+        let commands = overlay_commands(&frame);
+        let rects: Vec<&RectInfo> = commands.iter().map(|command| command.rect.as_ref().unwrap()).collect();
+        let xs: Vec<f64> = rects.iter().map(|rect| rect.x).collect();
+        assert_eq!(xs, [116.0, 185.0, 254.0, 323.0]);
+        assert!(rects.iter().all(|rect| rect.y == 50.0 && rect.width == 61.0 && rect.height == 200.0));
+        assert_eq!(commands[0].fills[0].color.as_ref().unwrap().a, 26);
+
+        frame.visible = false;
+        assert!(overlay_commands(&frame).is_empty());
+    }
+
+    #[test]
+    fn test_constraint_area() {
+        let grids = [columns(GridAlignment::Min, Some(2)), columns(GridAlignment::Stretch, Some(12))];
+        assert_eq!(constraint_area(&grids, GridPattern::Columns, 400.0), (10.0, 380.0));
+        assert_eq!(constraint_area(&grids, GridPattern::Rows, 400.0), (0.0, 400.0));
+
+        // Scaling keeps a child's place among the columns, not the frame
+        let (old, new) = (constraint_area(&grids, GridPattern::Columns, 400.0), constraint_area(&grids, GridPattern::Columns, 780.0));
+        assert_eq!(Constraint::Scale.apply_in(105.0, 95.0, old, new), (200.0, 190.0));
+        assert_eq!(Constraint::Max.apply_in(300.0, 50.0, old, new), (680.0, 50.0));
+    }
+
+    #[test]
+    fn test_decode_layout_grids() {
+        // The fixture has no grids, so give a frame four stretched columns
+        let grids = KiwiValue::Array(vec![KiwiValue::message(vec![
+            ("type", KiwiValue::Enum("STRETCH".into())),
+            ("axis", KiwiValue::Enum("X".into())),
+            ("numSections", KiwiValue::Int(4)),
+            ("offset", KiwiValue::Float(16.0)),
+            ("gutterSize", KiwiValue::Float(8.0)),
+            ("color", KiwiValue::message(vec![
+                ("r", KiwiValue::Float(0.0)),
+                ("g", KiwiValue::Float(0.0)),
+                ("b", KiwiValue::Float(1.0)),
+                ("a", KiwiValue::Float(0.5)),
+            ])),
+        ])]);
+        let frame = test_fixture::with_field("418:23858", "layoutGrids", &grids);
+
+        let grid = &frame.layout_grids[0];
+        assert_eq!((grid.pattern, grid.alignment, grid.count, grid.visible), (GridPattern::Columns, GridAlignment::Stretch, Some(4), true));
+        assert_eq!((grid.color.b, grid.color.a), (255, 128));
+    }
+}
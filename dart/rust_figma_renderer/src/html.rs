@@ -0,0 +1,992 @@
+//! HTML export
+//!
+//! A node and its descendants are written as a static HTML page that needs
+//! nothing else to display. Auto layout frames become flexboxes with their
+//! gap, padding, alignment and sizing, and the children of other frames are
+//! positioned absolutely at the boxes `layout_boxes` reports. Fills become
+//! CSS backgrounds, with images inlined as data URLs, strokes and shadows
+//! become box shadows, and blurs become filters. Vectors are inline SVG, and
+//! text is a span per styled run, embedding the registered fonts it uses.
+//!
+//! The page is the size of the node, on a transparent background, so it can
+//! be compared with the node rendered to PNG. Image rotation and filters,
+//! per-paint blend modes and angular or diamond gradients on vectors are
+//! approximated or left out.
+
+use crate::api::{ColorInfo, LayoutResult};
+use crate::effects::Effect;
+use crate::fonts::{FontMatchKind, FontRegistry, FontRequest};
+use crate::images::{place_image, ImageStore};
+use crate::kiwi::{decode_vector_data, FigFile};
+use crate::layout::layout_boxes;
+use crate::nodes::{FigmaNode, NodeType};
+use crate::paints::{BlendMode, Paint, ScaleMode};
+use crate::strokes::{StrokeAlign, StrokeCap, StrokeJoin};
+use crate::text::{LetterSpacing, LineHeight, TextAlignHorizontal, TextAlignVertical, TextAutoResize, TextCase, TextDecoration, TextStyle};
+use crate::tokens::{number, paint_color};
+use crate::vector::path_to_svg;
+use crate::{FigmaError, Result};
+use std::collections::HashMap;
+
+/// CSS declarations in the order they're written
+type Declarations = Vec<(&'static str, String)>;
+
+/// Line height of fonts that aren't registered, as a multiple of the size
+const DEFAULT_LINE_HEIGHT: f64 = 1.2;
+
+/// Export a node and its descendants as a self-contained HTML page, using
+/// the images and fonts the app registered
+pub fn export_html(file: &FigFile, images: &ImageStore, fonts: &FontRegistry, node_id: &str) -> Result<String> {
+    let root = file.get_node(node_id).ok_or_else(|| FigmaError::NodeNotFound(node_id.to_string()))?;
+    if matches!(root.node_type.as_str(), "DOCUMENT" | "CANVAS") {
+        return Err(FigmaError::UnsupportedNodeType(root.node_type.clone()));
+    }
+
+    let mut page = Page {
+        file,
+        images,
+        fonts,
+        boxes: layout_boxes(file, node_id)?.into_iter().map(|layout| (layout.node_id.clone(), layout)).collect(),
+        font_faces: Vec::new(),
+        rules: String::new(),
+        elements: 0,
+        defs: 0,
+    };
+    let mut body = String::new();
+    page.element(&mut body, root, None, 0);
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape(&root.name)));
+    out.push_str("<style>\n");
+    for (_, face) in &page.font_faces {
+        out.push_str(face);
+    }
+    out.push_str("body {\n  margin: 0;\n}\n* {\n  box-sizing: border-box;\n}\np {\n  margin: 0;\n}\n");
+    out.push_str(&page.rules);
+    out.push_str("</style>\n</head>\n<body>\n");
+    out.push_str(&body);
+    out.push_str("</body>\n</html>\n");
+    Ok(out)
+}
+
+struct Page<'a> {
+    file: &'a FigFile,
+    images: &'a ImageStore,
+    fonts: &'a FontRegistry,
+    boxes: HashMap<String, LayoutResult>,
+    /// `@font-face` rules by family, weight and italic
+    font_faces: Vec<((String, u16, bool), String)>,
+    rules: String,
+    /// Elements written so far, numbering their classes
+    elements: usize,
+    /// SVG gradients and clip paths written so far, numbering their ids
+    defs: usize,
+}
+
+impl<'a> Page<'a> {
+    /// Write a node's element and its children's, indented by `depth`
+    fn element(&mut self, out: &mut String, node: &'a FigmaNode, parent: Option<(&FigmaNode, bool)>, depth: usize) {
+        let class = format!("n{}", self.elements);
+        self.elements += 1;
+        let indent = "  ".repeat(depth);
+        let attributes = format!("class=\"{class}\" data-id=\"{}\"", escape(&node.id));
+        let mut css = self.placement(node, parent);
+
+        match NodeType::from(node.node_type.as_str()) {
+            NodeType::Text => return self.text(out, node, &class, css, &indent, &attributes),
+            NodeType::Vector | NodeType::Star | NodeType::RegularPolygon | NodeType::Line => {
+                return self.vector(out, node, &class, css, &indent, &attributes);
+            }
+            _ => {}
+        }
+
+        css.extend(self.backgrounds(node));
+        css.extend(border_radius(node));
+        css.extend(box_effects(node));
+        let children: Vec<&FigmaNode> = node.children.iter()
+            .filter_map(|id| self.file.get_node(id))
+            .filter(|child| child.visible)
+            .collect();
+        let auto_layout = matches!(node.layout_mode.as_str(), "HORIZONTAL" | "VERTICAL");
+        if auto_layout && !children.is_empty() {
+            css.extend(flex(node));
+        }
+        if !children.is_empty() {
+            css.extend(overflow(node));
+        }
+        self.rule(&format!(".{class}"), &css);
+
+        if children.is_empty() {
+            out.push_str(&format!("{indent}<div {attributes}></div>\n"));
+            return;
+        }
+        out.push_str(&format!("{indent}<div {attributes}>\n"));
+        let mut first = true;
+        for child in children {
+            let in_flow = auto_layout && !child.absolute_position;
+            self.element(out, child, Some((node, in_flow && first)), depth + 1);
+            first &= !in_flow;
+        }
+        out.push_str(&format!("{indent}</div>\n"));
+    }
+
+    /// Position and size of a node in its parent, with its opacity, blend
+    /// mode and rotation. The parent comes with whether the node is the
+    /// first child its auto layout places.
+    fn placement(&self, node: &FigmaNode, parent: Option<(&FigmaNode, bool)>) -> Declarations {
+        let layout = &self.boxes[&node.id];
+        let mut css = Vec::new();
+        let Some((parent, first)) = parent else {
+            // The page is the root's size, whatever its content
+            css.push(("position", "relative".to_string()));
+            css.push(("width", px(layout.width)));
+            css.push(("height", px(layout.height)));
+            css.extend(layer(node));
+            return css;
+        };
+
+        let (mut fixed_width, mut fixed_height) = fixed_size(node);
+        let in_flow = matches!(parent.layout_mode.as_str(), "HORIZONTAL" | "VERTICAL") && !node.absolute_position;
+        if in_flow {
+            let horizontal = parent.layout_mode == "HORIZONTAL";
+            css.push(("position", "relative".to_string()));
+            if node.layout_grow > 0.0 {
+                css.push(("flex", format!("{} 1 0", number(node.layout_grow))));
+                css.push((if horizontal { "min-width" } else { "min-height" }, "0".to_string()));
+                if horizontal { fixed_width = false } else { fixed_height = false }
+            } else {
+                css.push(("flex-shrink", "0".to_string()));
+            }
+            if node.layout_align == "STRETCH" {
+                css.push(("align-self", "stretch".to_string()));
+                if horizontal { fixed_height = false } else { fixed_width = false }
+            }
+            // Flex gaps can't be negative, so overlapping children are
+            // pulled back by a margin instead
+            if !first && parent.item_spacing < 0.0 && parent.primary_axis_align != "SPACE_BETWEEN" {
+                css.push((if horizontal { "margin-left" } else { "margin-top" }, px(parent.item_spacing)));
+            }
+        } else {
+            css.push(("position", "absolute".to_string()));
+            css.push(("left", px(layout.x)));
+            css.push(("top", px(layout.y)));
+        }
+        if fixed_width {
+            css.push(("width", px(layout.width)));
+        }
+        if fixed_height {
+            css.push(("height", px(layout.height)));
+        }
+        css.extend(layer(node));
+        if number(node.rotation) != "0" {
+            // Figma rotates counterclockwise about the node's origin
+            if !in_flow {
+                css.push(("transform-origin", "0 0".to_string()));
+            }
+            css.push(("transform", format!("rotate({}deg)", number(-node.rotation))));
+        }
+        css
+    }
+
+    /// A text node as a span per run of characters sharing a style
+    fn text(&mut self, out: &mut String, node: &FigmaNode, class: &str, mut css: Declarations, indent: &str, attributes: &str) {
+        let Some(text) = &node.text else {
+            self.rule(&format!(".{class}"), &css);
+            out.push_str(&format!("{indent}<div {attributes}></div>\n"));
+            return;
+        };
+
+        let mut runs: Vec<(u32, String)> = Vec::new();
+        for (c, style_id) in text.styled_chars() {
+            match runs.last_mut() {
+                Some((id, run)) if *id == style_id => run.push(c),
+                _ => runs.push((style_id, c.to_string())),
+            }
+        }
+        let mut style_ids: Vec<u32> = runs.iter().map(|(id, _)| *id).collect();
+        style_ids.sort_unstable();
+        style_ids.dedup();
+
+        css.push(("white-space", if text.auto_resize == TextAutoResize::WidthAndHeight { "pre" } else { "pre-wrap" }.to_string()));
+        css.push(("overflow-wrap", "break-word".to_string()));
+        match text.align_horizontal {
+            TextAlignHorizontal::Left => {}
+            TextAlignHorizontal::Center => css.push(("text-align", "center".to_string())),
+            TextAlignHorizontal::Right => css.push(("text-align", "right".to_string())),
+            TextAlignHorizontal::Justified => css.push(("text-align", "justify".to_string())),
+        }
+        // Only a box taller than its text has room to align it vertically
+        let aligned = text.auto_resize == TextAutoResize::None && text.align_vertical != TextAlignVertical::Top;
+        if aligned {
+            css.push(("display", "flex".to_string()));
+            css.push(("flex-direction", "column".to_string()));
+            let justify = if text.align_vertical == TextAlignVertical::Center { "center" } else { "flex-end" };
+            css.push(("justify-content", justify.to_string()));
+        }
+        let mut truncation = Vec::new();
+        if text.truncate {
+            truncation.push(("overflow", "hidden".to_string()));
+            match text.max_lines {
+                Some(lines) => {
+                    truncation.push(("display", "-webkit-box".to_string()));
+                    truncation.push(("-webkit-box-orient", "vertical".to_string()));
+                    truncation.push(("-webkit-line-clamp", lines.to_string()));
+                }
+                None => truncation.push(("text-overflow", "ellipsis".to_string())),
+            }
+        }
+        if !aligned {
+            css.append(&mut truncation);
+        }
+        css.extend(shape_effects(node));
+
+        // Text in a single style is styled by its element, without spans
+        let spans = style_ids.len() > 1;
+        if !spans {
+            css.extend(self.text_style(&text.style_for(style_ids.first().copied().unwrap_or(0)), node));
+        }
+        self.rule(&format!(".{class}"), &css);
+        if aligned {
+            self.rule(&format!(".{class} > p"), &truncation);
+        }
+        if spans {
+            for &id in &style_ids {
+                let style = self.text_style(&text.style_for(id), node);
+                self.rule(&format!(".{class} .s{id}"), &style);
+            }
+        }
+
+        let mut content = String::new();
+        for (id, run) in &runs {
+            if spans {
+                content.push_str(&format!("<span class=\"s{id}\">{}</span>", escape(run)));
+            } else {
+                content.push_str(&escape(run));
+            }
+        }
+        if aligned {
+            content = format!("<p>{content}</p>");
+        }
+        out.push_str(&format!("{indent}<div {attributes}>{content}</div>\n"));
+    }
+
+    /// Font, spacing, decoration and color of a run of text
+    fn text_style(&mut self, style: &TextStyle, node: &FigmaNode) -> Declarations {
+        let request = FontRequest {
+            family: &style.font_family,
+            style: &style.font_style,
+            postscript_name: &style.font_postscript_name,
+            variations: &style.font_variations,
+        };
+        let (weight, italic) = request.traits();
+        self.embed_font(&request, weight, italic);
+
+        let families: Vec<String> = std::iter::once(&style.font_family)
+            .chain(self.fonts.fallbacks())
+            .map(|family| format!("\"{}\"", family.replace('"', "\\\"")))
+            .collect();
+        let mut css = vec![("font-family", families.join(", ")), ("font-size", px(style.font_size))];
+        if weight != 400 {
+            css.push(("font-weight", weight.to_string()));
+        }
+        if italic {
+            css.push(("font-style", "italic".to_string()));
+        }
+        let axes: Vec<String> = style.font_variations.iter()
+            .filter(|(tag, _)| &tag.to_be_bytes() != b"wght")
+            .map(|(tag, value)| format!("\"{}\" {}", String::from_utf8_lossy(&tag.to_be_bytes()), number(*value)))
+            .collect();
+        if !axes.is_empty() {
+            css.push(("font-variation-settings", axes.join(", ")));
+        }
+
+        match style.line_height {
+            LineHeight::Auto => {}
+            LineHeight::Pixels(pixels) => css.push(("line-height", px(pixels))),
+            LineHeight::Percent(percent) => {
+                // A percentage of the font's own line height, not its size
+                let natural = self.fonts.resolve(&request)
+                    .map(|found| found.font.metrics)
+                    .filter(|metrics| metrics.units_per_em > 0.0)
+                    .map_or(DEFAULT_LINE_HEIGHT, |metrics| {
+                        (metrics.ascender - metrics.descender + metrics.line_gap) / metrics.units_per_em
+                    });
+                css.push(("line-height", number(natural * percent / 100.0)));
+            }
+            LineHeight::Multiple(multiple) => css.push(("line-height", number(multiple))),
+        }
+        match style.letter_spacing {
+            LetterSpacing::None => {}
+            LetterSpacing::Pixels(pixels) => css.push(("letter-spacing", px(pixels))),
+            LetterSpacing::Percent(percent) => css.push(("letter-spacing", format!("{}em", number(percent / 100.0)))),
+        }
+        match style.text_case {
+            TextCase::Original => {}
+            TextCase::Upper => css.push(("text-transform", "uppercase".to_string())),
+            TextCase::Lower => css.push(("text-transform", "lowercase".to_string())),
+            TextCase::Title => css.push(("text-transform", "capitalize".to_string())),
+            TextCase::SmallCaps => css.push(("font-variant-caps", "small-caps".to_string())),
+        }
+        match style.decoration {
+            TextDecoration::None => {}
+            TextDecoration::Underline => css.push(("text-decoration", "underline".to_string())),
+            TextDecoration::Strikethrough => css.push(("text-decoration", "line-through".to_string())),
+        }
+
+        let fills = if style.fills.is_empty() { &node.fill_paints } else { &style.fills };
+        match fills.iter().rev().find(|paint| paint.visible()) {
+            Some(Paint::Solid(paint)) => css.push(("color", css_color(&paint_color(&paint.color, paint.opacity)))),
+            Some(paint) => {
+                if let Some(gradient) = css_gradient(paint, node.width, node.height) {
+                    css.push(("background", gradient));
+                    css.push(("-webkit-background-clip", "text".to_string()));
+                    css.push(("background-clip", "text".to_string()));
+                    css.push(("color", "transparent".to_string()));
+                }
+            }
+            None => css.push(("color", "transparent".to_string())),
+        }
+        css
+    }
+
+    /// Add an `@font-face` rule with the registered face a font resolves
+    /// to, under the family and weight the text asks for
+    fn embed_font(&mut self, request: &FontRequest, weight: u16, italic: bool) {
+        let key = (request.family.to_string(), weight, italic);
+        if self.font_faces.iter().any(|(existing, _)| *existing == key) {
+            return;
+        }
+        let Some(found) = self.fonts.resolve(request).filter(|found| found.kind != FontMatchKind::Fallback) else {
+            return;
+        };
+        // Browsers only load the first face of a collection
+        let (data, index) = found.font.file_data();
+        if index != 0 {
+            return;
+        }
+        let format = if data.starts_with(b"OTTO") { "font/otf" } else { "font/ttf" };
+        let weight = match found.font.weight_range {
+            Some((min, max)) => format!("{} {}", number(min as f64), number(max as f64)),
+            None => weight.to_string(),
+        };
+        let face = format!(
+            "@font-face {{\n  font-family: \"{}\";\n  font-weight: {weight};\n  font-style: {};\n  src: url(\"data:{format};base64,{}\");\n}}\n",
+            request.family.replace('"', "\\\""),
+            if italic { "italic" } else { "normal" },
+            base64(data),
+        );
+        self.font_faces.push((key, face));
+    }
+
+    /// A vector as inline SVG of its outline, with a path per fill and
+    /// stroke paint
+    fn vector(&mut self, out: &mut String, node: &FigmaNode, class: &str, mut css: Declarations, indent: &str, attributes: &str) {
+        let Some(outline) = node.outline() else {
+            self.rule(&format!(".{class}"), &css);
+            out.push_str(&format!("{indent}<div {attributes}></div>\n"));
+            return;
+        };
+        css.push(("overflow", "visible".to_string()));
+        css.extend(shape_effects(node));
+        self.rule(&format!(".{class}"), &css);
+
+        let mut defs = String::new();
+        let mut paths = Vec::new();
+        let d = path_to_svg(&outline);
+        let even_odd = node.node_type != "LINE"
+            && decode_vector_data(&node.vector_data).is_ok_and(|path| path.fill_rule == "evenodd");
+        let fill_rule = if even_odd { " fill-rule=\"evenodd\"" } else { "" };
+        if node.node_type != "LINE" {
+            for paint in node.fill_paints.iter().filter(|paint| paint.visible()) {
+                if let Some(fill) = self.svg_paint("fill", paint, node, &mut defs) {
+                    paths.push(format!("<path d=\"{d}\"{fill}{fill_rule}/>"));
+                }
+            }
+        }
+
+        let strokes: Vec<&Paint> = node.stroke_paints.iter().filter(|paint| paint.visible()).collect();
+        if node.stroke_weight > 0.0 && !strokes.is_empty() {
+            if node.stroke.align == StrokeAlign::Center || node.node_type == "LINE" {
+                let attributes = stroke_attributes(node);
+                for paint in strokes {
+                    if let Some(stroke) = self.svg_paint("stroke", paint, node, &mut defs) {
+                        paths.push(format!("<path d=\"{d}\" fill=\"none\"{stroke}{attributes}/>"));
+                    }
+                }
+            } else if let Some(stroke) = node.stroke_outline() {
+                // Inside and outside strokes are the outline of a doubled
+                // stroke, clipped to the shape or its outside
+                let clip = stroke.clip.map(|(clip, even_odd)| {
+                    let id = self.def_id("c");
+                    let rule = if even_odd { " clip-rule=\"evenodd\"" } else { "" };
+                    defs.push_str(&format!("<clipPath id=\"{id}\"><path d=\"{}\"{rule}/></clipPath>", path_to_svg(&clip)));
+                    format!(" clip-path=\"url(#{id})\"")
+                });
+                let d = path_to_svg(&stroke.path);
+                for paint in strokes {
+                    if let Some(fill) = self.svg_paint("fill", paint, node, &mut defs) {
+                        paths.push(format!("<path d=\"{d}\"{fill}{}/>", clip.as_deref().unwrap_or("")));
+                    }
+                }
+            }
+        }
+
+        out.push_str(&format!("{indent}<svg {attributes}>\n"));
+        if !defs.is_empty() {
+            out.push_str(&format!("{indent}  <defs>{defs}</defs>\n"));
+        }
+        for path in paths {
+            out.push_str(&format!("{indent}  {path}\n"));
+        }
+        out.push_str(&format!("{indent}</svg>\n"));
+    }
+
+    /// The attributes painting an SVG shape's fill or stroke with a paint,
+    /// adding gradients to `defs`. Image paints aren't drawn.
+    fn svg_paint(&mut self, attribute: &str, paint: &Paint, node: &FigmaNode, defs: &mut String) -> Option<String> {
+        let gradient = match paint {
+            Paint::Solid(solid) => return Some(svg_color(attribute, &paint_color(&solid.color, solid.opacity))),
+            Paint::GradientLinear(gradient) | Paint::GradientRadial(gradient)
+            | Paint::GradientAngular(gradient) | Paint::GradientDiamond(gradient) => gradient,
+            Paint::Image(_) => return None,
+        };
+        let [start, end, width] = paint.gradient_handles()?
+            .map(|(x, y)| (x * node.width, y * node.height));
+        // Maps the unit gradient onto the handles, keeping any skew
+        let transform = format!(
+            "matrix({} {} {} {} {} {})",
+            number(end.0 - start.0), number(end.1 - start.1),
+            number(width.0 - start.0), number(width.1 - start.1),
+            number(start.0), number(start.1),
+        );
+        let id = self.def_id("g");
+        let (element, geometry) = match paint {
+            Paint::GradientLinear(_) => ("linearGradient", "x1=\"0\" y1=\"0\" x2=\"1\" y2=\"0\""),
+            // SVG has no sweep gradient, so angular gradients are radial too
+            _ => ("radialGradient", "cx=\"0\" cy=\"0\" r=\"1\""),
+        };
+        defs.push_str(&format!("<{element} id=\"{id}\" gradientUnits=\"userSpaceOnUse\" {geometry} gradientTransform=\"{transform}\">"));
+        for stop in &gradient.stops {
+            defs.push_str(&format!("<stop offset=\"{}\"{}/>", number(stop.position), svg_color("stop-color", &paint_color(&stop.color, gradient.opacity))));
+        }
+        defs.push_str(&format!("</{element}>"));
+        Some(format!(" {attribute}=\"url(#{id})\""))
+    }
+
+    /// A node's fills as CSS backgrounds, the topmost first
+    fn backgrounds(&self, node: &FigmaNode) -> Declarations {
+        let layers: Vec<(String, bool)> = node.fill_paints.iter().rev()
+            .filter(|paint| paint.visible())
+            .filter_map(|paint| self.background(paint, node))
+            .collect();
+        match layers.as_slice() {
+            [] => vec![],
+            [(color, true)] => vec![("background-color", color.clone())],
+            layers => {
+                let layers: Vec<String> = layers.iter()
+                    .map(|(layer, is_color)| if *is_color { format!("linear-gradient({layer}, {layer})") } else { layer.clone() })
+                    .collect();
+                vec![("background", layers.join(", "))]
+            }
+        }
+    }
+
+    /// A paint as a background layer, with whether it's a plain color
+    fn background(&self, paint: &Paint, node: &FigmaNode) -> Option<(String, bool)> {
+        let image = match paint {
+            Paint::Solid(solid) => return Some((css_color(&paint_color(&solid.color, solid.opacity)), true)),
+            Paint::Image(image) => image,
+            gradient => return css_gradient(gradient, node.width, node.height).map(|gradient| (gradient, false)),
+        };
+        let data = self.images.get(&image.image_ref)?;
+        let url = format!("url(\"data:{};base64,{}\")", image_type(data), base64(data));
+        let placement = place_image(image, node.width, node.height, self.images.size(&image.image_ref));
+        let sizing = match (image.scale_mode, placement.source, placement.tile_size) {
+            (ScaleMode::Fill, ..) => "center / cover no-repeat".to_string(),
+            (ScaleMode::Fit, ..) => "center / contain no-repeat".to_string(),
+            (ScaleMode::Tile, _, Some((width, height))) => format!("0 0 / {} {} repeat", px(width), px(height)),
+            // Scale the image so its cropped part covers the node
+            (ScaleMode::Stretch, Some(source), _) if source.width > 0.0 && source.height > 0.0 => {
+                let (scale_x, scale_y) = (node.width / source.width, node.height / source.height);
+                let (image_width, image_height) = self.images.size(&image.image_ref).or(image.original_size)?;
+                format!(
+                    "{} {} / {} {} no-repeat",
+                    px(-source.x * scale_x), px(-source.y * scale_y),
+                    px(image_width as f64 * scale_x), px(image_height as f64 * scale_y),
+                )
+            }
+            _ => "0 0 / 100% 100% no-repeat".to_string(),
+        };
+        Some((format!("{url} {sizing}"), false))
+    }
+
+    fn def_id(&mut self, prefix: &str) -> String {
+        self.defs += 1;
+        format!("{prefix}{}", self.defs)
+    }
+
+    fn rule(&mut self, selector: &str, css: &Declarations) {
+        if css.is_empty() {
+            return;
+        }
+        self.rules.push_str(&format!("{selector} {{\n"));
+        for (property, value) in css {
+            self.rules.push_str(&format!("  {property}: {value};\n"));
+        }
+        self.rules.push_str("}\n");
+    }
+}
+
+/// Whether a node keeps its width and height, rather than fitting its
+/// content
+fn fixed_size(node: &FigmaNode) -> (bool, bool) {
+    if let Some(text) = &node.text {
+        return match text.auto_resize {
+            TextAutoResize::None => (true, true),
+            TextAutoResize::Height => (true, false),
+            TextAutoResize::WidthAndHeight => (false, false),
+        };
+    }
+    match node.layout_mode.as_str() {
+        "HORIZONTAL" => (!node.hugs_primary_axis(), !node.hugs_counter_axis()),
+        "VERTICAL" => (!node.hugs_counter_axis(), !node.hugs_primary_axis()),
+        _ => (true, true),
+    }
+}
+
+/// Opacity and blend mode
+fn layer(node: &FigmaNode) -> Declarations {
+    let mut css = Vec::new();
+    if node.opacity < 1.0 {
+        css.push(("opacity", number(node.opacity)));
+    }
+    if let Some(mode) = blend_mode(node.layer_blend_mode()) {
+        css.push(("mix-blend-mode", mode.to_string()));
+    }
+    css
+}
+
+/// The flexbox of an auto layout frame
+fn flex(node: &FigmaNode) -> Declarations {
+    let horizontal = node.layout_mode == "HORIZONTAL";
+    let mut css = vec![
+        ("display", "flex".to_string()),
+        ("flex-direction", if horizontal { "row" } else { "column" }.to_string()),
+    ];
+    if node.layout_wrap {
+        css.push(("flex-wrap", "wrap".to_string()));
+        css.push(("align-content", "flex-start".to_string()));
+    }
+    let justify = match node.primary_axis_align.as_str() {
+        "CENTER" => Some("center"),
+        "MAX" => Some("flex-end"),
+        "SPACE_BETWEEN" => Some("space-between"),
+        "SPACE_EVENLY" => Some("space-evenly"),
+        _ => None,
+    };
+    if let Some(justify) = justify {
+        css.push(("justify-content", justify.to_string()));
+    }
+    // CSS stretches children across the axis by default, Figma aligns them
+    // to the start
+    let align = match node.counter_axis_align.as_str() {
+        "CENTER" => "center",
+        "MAX" => "flex-end",
+        "BASELINE" => "baseline",
+        _ => "flex-start",
+    };
+    css.push(("align-items", align.to_string()));
+
+    // Spacing is automatic when the children are spread out
+    let spacing = if node.primary_axis_align == "SPACE_BETWEEN" { 0.0 } else { node.item_spacing.max(0.0) };
+    match node.counter_axis_spacing.filter(|_| node.layout_wrap) {
+        Some(counter) if horizontal => css.push(("gap", format!("{} {}", px(counter), px(spacing)))),
+        Some(counter) => css.push(("gap", format!("{} {}", px(spacing), px(counter)))),
+        None if spacing > 0.0 => css.push(("gap", px(spacing))),
+        None => {}
+    }
+    let [left, top, right, bottom] = node.padding;
+    if node.padding.iter().all(|&padding| padding == left) {
+        if left != 0.0 {
+            css.push(("padding", px(left)));
+        }
+    } else {
+        css.push(("padding", format!("{} {} {} {}", px(top), px(right), px(bottom), px(left))));
+    }
+    css
+}
+
+fn border_radius(node: &FigmaNode) -> Declarations {
+    if node.node_type == "ELLIPSE" {
+        return vec![("border-radius", "50%".to_string())];
+    }
+    let radii = node.effective_corner_radii();
+    if radii.iter().all(|&radius| radius == radii[0]) {
+        if radii[0] > 0.0 {
+            return vec![("border-radius", px(radii[0]))];
+        }
+        return vec![];
+    }
+    vec![("border-radius", radii.map(px).join(" "))]
+}
+
+/// Clipping, with scroll bars along the axes a frame scrolls
+fn overflow(node: &FigmaNode) -> Declarations {
+    match (node.scroll_direction.horizontal(), node.scroll_direction.vertical()) {
+        (false, false) if node.clips_content() => vec![("overflow", "hidden".to_string())],
+        (false, false) => vec![],
+        (x, y) => {
+            let axis = |scrolls: bool| if scrolls { "auto" } else { "hidden" }.to_string();
+            vec![("overflow-x", axis(x)), ("overflow-y", axis(y))]
+        }
+    }
+}
+
+/// Strokes as outlines or spread box shadows, and the node's shadows and
+/// blurs. A node without fills casts its drop shadows from its content.
+fn box_effects(node: &FigmaNode) -> Declarations {
+    let mut css = Vec::new();
+    let mut shadows = Vec::new();
+    let mut filters = Vec::new();
+    let mut backdrop = Vec::new();
+
+    let stroke = node.stroke_paints.iter().rev().find(|paint| paint.visible());
+    if let (Some(Paint::Solid(paint)), true) = (stroke, node.stroke_weight > 0.0 || node.stroke.side_weights.is_some()) {
+        let color = css_color(&paint_color(&paint.color, paint.opacity));
+        let weight = node.stroke_weight;
+        match node.stroke.side_weights {
+            // Sides of their own weight are drawn inside
+            Some([top, right, bottom, left]) => {
+                for (weight, x, y) in [(top, 0.0, 1.0), (right, -1.0, 0.0), (bottom, 0.0, -1.0), (left, 1.0, 0.0)] {
+                    if weight > 0.0 {
+                        shadows.push(format!("inset {} {} 0 0 {color}", px(x * weight), px(y * weight)));
+                    }
+                }
+            }
+            None if !node.stroke.dash_pattern.is_empty() => {
+                let offset = match node.stroke.align {
+                    StrokeAlign::Inside => -weight,
+                    StrokeAlign::Center => -weight / 2.0,
+                    StrokeAlign::Outside => 0.0,
+                };
+                css.push(("outline", format!("{} dashed {color}", px(weight))));
+                css.push(("outline-offset", px(offset)));
+            }
+            None => match node.stroke.align {
+                StrokeAlign::Inside => shadows.push(format!("inset 0 0 0 {} {color}", px(weight))),
+                StrokeAlign::Outside => shadows.push(format!("0 0 0 {} {color}", px(weight))),
+                StrokeAlign::Center => {
+                    shadows.push(format!("inset 0 0 0 {} {color}", px(weight / 2.0)));
+                    shadows.push(format!("0 0 0 {} {color}", px(weight / 2.0)));
+                }
+            },
+        }
+    }
+
+    let filled = node.fill_paints.iter().any(|paint| paint.visible());
+    for effect in node.effects.iter().rev().filter(|effect| effect.visible()) {
+        match effect {
+            Effect::DropShadow(shadow) if filled => shadows.push(format!(
+                "{} {} {} {} {}",
+                px(shadow.offset.0), px(shadow.offset.1), px(shadow.radius), px(shadow.spread), css_color(&shadow.color),
+            )),
+            Effect::DropShadow(shadow) => filters.push(drop_shadow(shadow.offset, shadow.radius, &shadow.color)),
+            Effect::InnerShadow(shadow) => shadows.push(format!(
+                "inset {} {} {} {} {}",
+                px(shadow.offset.0), px(shadow.offset.1), px(shadow.radius), px(shadow.spread), css_color(&shadow.color),
+            )),
+            Effect::LayerBlur(blur) => filters.push(format!("blur({})", px(blur.radius / 2.0))),
+            Effect::BackgroundBlur(blur) => backdrop.push(format!("blur({})", px(blur.radius / 2.0))),
+        }
+    }
+    if !shadows.is_empty() {
+        css.push(("box-shadow", shadows.join(", ")));
+    }
+    if !filters.is_empty() {
+        css.push(("filter", filters.join(" ")));
+    }
+    if !backdrop.is_empty() {
+        css.push(("-webkit-backdrop-filter", backdrop.join(" ")));
+        css.push(("backdrop-filter", backdrop.join(" ")));
+    }
+    css
+}
+
+/// Drop shadows and blurs of text and vectors, which follow their shape.
+/// Inner shadows and background blurs have no CSS form there.
+fn shape_effects(node: &FigmaNode) -> Declarations {
+    let filters: Vec<String> = node.effects.iter().rev()
+        .filter(|effect| effect.visible())
+        .filter_map(|effect| match effect {
+            Effect::DropShadow(shadow) => Some(drop_shadow(shadow.offset, shadow.radius, &shadow.color)),
+            Effect::LayerBlur(blur) => Some(format!("blur({})", px(blur.radius / 2.0))),
+            _ => None,
+        })
+        .collect();
+    if filters.is_empty() {
+        return vec![];
+    }
+    vec![("filter", filters.join(" "))]
+}
+
+fn drop_shadow((x, y): (f64, f64), radius: f64, color: &ColorInfo) -> String {
+    format!("drop-shadow({} {} {} {})", px(x), px(y), px(radius), css_color(color))
+}
+
+/// A gradient paint as a CSS gradient on a box of the given size. Linear
+/// stops are moved to where the handles fall on the CSS gradient line, and
+/// diamond gradients are drawn radial.
+fn css_gradient(paint: &Paint, width: f64, height: f64) -> Option<String> {
+    let (Paint::GradientLinear(gradient) | Paint::GradientRadial(gradient) | Paint::GradientAngular(gradient) | Paint::GradientDiamond(gradient)) = paint else {
+        return None;
+    };
+    let [start, end, across] = paint.gradient_handles()?.map(|(x, y)| (x * width, y * height));
+    let stops = |position: &dyn Fn(f64) -> f64| {
+        gradient.stops.iter()
+            .map(|stop| format!("{} {}%", css_color(&paint_color(&stop.color, gradient.opacity)), number(position(stop.position) * 100.0)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    // CSS angles start upwards and turn clockwise
+    let angle = (end.0 - start.0).atan2(start.1 - end.1);
+    match paint {
+        Paint::GradientLinear(_) => {
+            let (sin, cos) = angle.sin_cos();
+            let length = (width * sin).abs() + (height * cos).abs();
+            if length <= 0.0 {
+                return None;
+            }
+            let along = |(x, y): (f64, f64)| ((x - width / 2.0) * sin - (y - height / 2.0) * cos) / length + 0.5;
+            let (from, to) = (along(start), along(end));
+            Some(format!("linear-gradient({}deg, {})", number(angle.to_degrees()), stops(&|position| from + position * (to - from))))
+        }
+        Paint::GradientAngular(_) => Some(format!(
+            "conic-gradient(from {}deg at {} {}, {})",
+            number(angle.to_degrees()), px(start.0), px(start.1), stops(&|position| position),
+        )),
+        _ => {
+            let radius_x = (end.0 - start.0).hypot(end.1 - start.1);
+            let radius_y = (across.0 - start.0).hypot(across.1 - start.1);
+            Some(format!(
+                "radial-gradient({} {} at {} {}, {})",
+                px(radius_x), px(radius_y), px(start.0), px(start.1), stops(&|position| position),
+            ))
+        }
+    }
+}
+
+fn stroke_attributes(node: &FigmaNode) -> String {
+    let mut attributes = format!(" stroke-width=\"{}\"", number(node.stroke_weight));
+    match node.stroke.cap {
+        StrokeCap::Round => attributes.push_str(" stroke-linecap=\"round\""),
+        StrokeCap::Square => attributes.push_str(" stroke-linecap=\"square\""),
+        _ => {}
+    }
+    match node.stroke.join {
+        StrokeJoin::Miter if node.stroke.miter_limit != 4.0 => {
+            attributes.push_str(&format!(" stroke-miterlimit=\"{}\"", number(node.stroke.miter_limit)));
+        }
+        StrokeJoin::Miter => {}
+        StrokeJoin::Bevel => attributes.push_str(" stroke-linejoin=\"bevel\""),
+        StrokeJoin::Round => attributes.push_str(" stroke-linejoin=\"round\""),
+    }
+    if !node.stroke.dash_pattern.is_empty() {
+        let dashes: Vec<String> = node.stroke.dash_pattern.iter().map(|&length| number(length)).collect();
+        attributes.push_str(&format!(" stroke-dasharray=\"{}\"", dashes.join(" ")));
+    }
+    attributes
+}
+
+/// CSS `mix-blend-mode` of a layer blend mode, None for normal
+fn blend_mode(mode: BlendMode) -> Option<&'static str> {
+    Some(match mode {
+        BlendMode::PassThrough | BlendMode::Normal => return None,
+        BlendMode::LinearBurn => "plus-darker",
+        BlendMode::LinearDodge => "plus-lighter",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Screen => "screen",
+        BlendMode::Overlay => "overlay",
+        BlendMode::Darken => "darken",
+        BlendMode::Lighten => "lighten",
+        BlendMode::ColorDodge => "color-dodge",
+        BlendMode::ColorBurn => "color-burn",
+        BlendMode::HardLight => "hard-light",
+        BlendMode::SoftLight => "soft-light",
+        BlendMode::Difference => "difference",
+        BlendMode::Exclusion => "exclusion",
+        BlendMode::Hue => "hue",
+        BlendMode::Saturation => "saturation",
+        BlendMode::Color => "color",
+        BlendMode::Luminosity => "luminosity",
+    })
+}
+
+fn css_color(color: &ColorInfo) -> String {
+    match color.a {
+        255 => format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+        a => format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, number(a as f64 / 255.0)),
+    }
+}
+
+/// An SVG color attribute, with its opacity when translucent
+fn svg_color(attribute: &str, color: &ColorInfo) -> String {
+    let mut out = format!(" {attribute}=\"#{:02x}{:02x}{:02x}\"", color.r, color.g, color.b);
+    if color.a < 255 {
+        let opacity = if attribute == "stop-color" { "stop-opacity".to_string() } else { format!("{attribute}-opacity") };
+        out.push_str(&format!(" {opacity}=\"{}\"", number(color.a as f64 / 255.0)));
+    }
+    out
+}
+
+fn px(value: f64) -> String {
+    match number(value) {
+        zero if zero == "0" => zero,
+        value => format!("{value}px"),
+    }
+}
+
+/// Media type of image data, from its signature
+fn image_type(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.len() > 12 && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Escape text for HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{GradientStopInfo, TransformInfo};
+    use crate::fonts::test_font;
+    use crate::kiwi::test_fixture;
+    use crate::paints::{GradientPaint, SolidPaint};
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xFB, 0xFF]), "+/8=");
+    }
+
+    #[test]
+    fn test_linear_gradient() {
+        let color = |r| ColorInfo { r, g: 0, b: 0, a: 255 };
+        let gradient = |transform| Paint::GradientLinear(GradientPaint {
+            stops: vec![
+                GradientStopInfo { position: 0.0, color: color(0) },
+                GradientStopInfo { position: 1.0, color: color(255) },
+            ],
+            transform,
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        });
+
+        // Left to right across the box spans the whole CSS gradient line
+        let identity = TransformInfo { m00: 1.0, m01: 0.0, m02: 0.0, m10: 0.0, m11: 1.0, m12: 0.0 };
+        assert_eq!(css_gradient(&gradient(identity), 200.0, 100.0).unwrap(), "linear-gradient(90deg, #000000 0%, #ff0000 100%)");
+
+        // Handles from the middle to the right edge cover its second half
+        let half = TransformInfo { m00: 2.0, m01: 0.0, m02: -1.0, m10: 0.0, m11: 1.0, m12: 0.0 };
+        assert_eq!(css_gradient(&gradient(half), 200.0, 100.0).unwrap(), "linear-gradient(90deg, #000000 50%, #ff0000 100%)");
+    }
+
+    #[test]
+    fn test_export_html() {
+        let mut file = test_fixture::expanded().clone();
+
+        // Boxes are relative to their parent, parents first
+        let boxes = layout_boxes(&file, "61:24640").unwrap();
+        assert_eq!(boxes[0].node_id, "61:24640");
+        assert_eq!((boxes[0].width, boxes[0].height), (1400.0, 280.0));
+        assert_eq!((boxes[1].node_id.as_str(), boxes[1].x, boxes[1].y), ("61:24641", 320.0, -712.0));
+
+        // A line stroked with round caps, drawn as SVG
+        let red = ColorInfo { r: 255, g: 0, b: 0, a: 255 };
+        let mut line = FigmaNode {
+            id: "1:900000".into(),
+            parent_id: Some("61:24640".into()),
+            node_type: "LINE".into(),
+            visible: true,
+            opacity: 1.0,
+            x: 8.0,
+            y: 16.0,
+            width: 40.0,
+            stroke_weight: 2.0,
+            stroke_paints: vec![Paint::Solid(SolidPaint { color: red, visible: true, opacity: 1.0, blend_mode: Default::default(), color_variable: None })],
+            ..Default::default()
+        };
+        line.stroke.cap = StrokeCap::Round;
+        file.nodes.insert(line.id.clone(), line);
+        file.get_node_mut("61:24640").unwrap().children.push("1:900000".into());
+
+        let mut fonts = FontRegistry::new();
+        fonts.register(test_font::build("SF Pro", "Regular", 400, false, &[('a', 500)])).unwrap();
+        let html = export_html(&file, &ImageStore::new(), &fonts, "61:24635").unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>📘 Tab Bars</title>\n"));
+        assert!(html.contains("@font-face {\n  font-family: \"SF Pro\";\n  font-weight: 400;\n  font-style: normal;\n  src: url(\"data:font/ttf;base64,"));
+
+        // Auto layout frames are flexboxes, and the page is the root's size
+        assert!(html.contains(".n0 {\n  position: relative;\n  width: 1560px;\n  height: 1643px;\n  background-color: #ffffff;\n  display: flex;\n  flex-direction: column;\n  justify-content: center;\n  align-items: flex-start;\n}\n"));
+        assert!(html.contains("  align-self: stretch;\n"));
+        assert!(html.contains("  padding: 80px;\n"));
+        assert!(html.contains("  padding: 0 80px 80px 80px;\n"));
+        assert!(html.contains("  gap: 24px;\n"));
+        // while other frames position their children
+        assert!(html.contains(".n13 {\n  position: absolute;\n  left: 320px;\n  top: -712px;\n  display: flex;\n"));
+
+        assert!(html.contains("  background: linear-gradient(90deg, #ffe347 0%, #ffc926 100%);\n  border-radius: 16px;\n"));
+        assert!(html.contains("  backdrop-filter: blur(40px);\n"));
+        assert!(html.contains("  font-family: \"SF Pro Text\";\n  font-size: 34px;\n  font-weight: 600;\n  line-height: 41px;\n"));
+        assert!(html.contains("<div class=\"n7\" data-id=\"I61:24636;6:98\">Tab Bars</div>\n"));
+        assert!(html.contains("<svg class=\"n"));
+        assert!(html.contains(" data-id=\"1:900000\">\n"));
+        assert!(html.contains("<path d=\"M 0 0 L 40 0\" fill=\"none\" stroke=\"#ff0000\" stroke-width=\"2\" stroke-linecap=\"round\"/>\n"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+
+        assert!(matches!(export_html(&file, &ImageStore::new(), &fonts, "0:2"), Err(FigmaError::UnsupportedNodeType(_))));
+        assert!(matches!(layout_boxes(&file, "1:1234567"), Err(FigmaError::NodeNotFound(_))));
+    }
+}
@@ -0,0 +1,286 @@
+//! Image storage and image paint placement
+//!
+//! Image paints reference images by the hex hash of their bytes. The bytes
+//! live outside the node tree: zipped .fig files bundle them under
+//! `images/`, and callers can register more (e.g. fetched from the REST API).
+
+use crate::api::RectInfo;
+use crate::paints::{ImagePaint, ScaleMode};
+use std::collections::HashMap;
+
+/// Image bytes keyed by hash
+#[derive(Debug, Clone, Default)]
+pub struct ImageStore {
+    images: HashMap<String, Vec<u8>>,
+}
+
+impl ImageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register image bytes, replacing any previous image with the same hash
+    pub fn insert(&mut self, hash: &str, data: Vec<u8>) {
+        self.images.insert(hash.to_ascii_lowercase(), data);
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&[u8]> {
+        self.images.get(&hash.to_ascii_lowercase()).map(Vec::as_slice)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.images.contains_key(&hash.to_ascii_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Pixel size of a registered image, if its format is recognized
+    pub fn size(&self, hash: &str) -> Option<(u32, u32)> {
+        image_size(self.get(hash)?)
+    }
+}
+
+impl From<HashMap<String, Vec<u8>>> for ImageStore {
+    fn from(images: HashMap<String, Vec<u8>>) -> Self {
+        let mut store = ImageStore::new();
+        for (hash, data) in images {
+            store.insert(&hash, data);
+        }
+        store
+    }
+}
+
+/// Read the pixel size from a PNG, JPEG, GIF or WebP header without decoding
+/// the image
+pub fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    png_size(data)
+        .or_else(|| jpeg_size(data))
+        .or_else(|| gif_size(data))
+        .or_else(|| webp_size(data))
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The IHDR chunk always comes first, right after the signature
+pub fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(PNG_SIGNATURE) || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some((be_u32(data, 16)?, be_u32(data, 20)?))
+}
+
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        let len = be_u16(data, pos + 2)? as usize;
+        // Start-of-frame markers, excluding DHT, JPG and DAC
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let height = be_u16(data, pos + 5)? as u32;
+            let width = be_u16(data, pos + 7)? as u32;
+            return Some((width, height));
+        }
+        pos += 2 + len;
+    }
+}
+
+fn gif_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(b"GIF8") {
+        return None;
+    }
+    let width = u16::from_le_bytes([*data.get(6)?, *data.get(7)?]) as u32;
+    let height = u16::from_le_bytes([*data.get(8)?, *data.get(9)?]) as u32;
+    Some((width, height))
+}
+
+fn webp_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(b"RIFF") || data.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let le24 = |pos: usize| -> Option<u32> {
+        Some(u32::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?, *data.get(pos + 2)?, 0]))
+    };
+    match data.get(12..16)? {
+        b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+        b"VP8 " => {
+            let width = u16::from_le_bytes([*data.get(26)?, *data.get(27)?]) & 0x3fff;
+            let height = u16::from_le_bytes([*data.get(28)?, *data.get(29)?]) & 0x3fff;
+            Some((width as u32, height as u32))
+        }
+        b"VP8L" => {
+            let bits = u32::from_le_bytes([*data.get(21)?, *data.get(22)?, *data.get(23)?, *data.get(24)?]);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        _ => None,
+    }
+}
+
+fn be_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]))
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?, *data.get(pos + 2)?, *data.get(pos + 3)?]))
+}
+
+/// Where an image paint draws inside a node's bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePlacement {
+    /// Area of the node covered by the image, in node coordinates
+    pub dest: RectInfo,
+    /// Part of the image that is drawn into `dest`, in image pixels. None
+    /// when the image size is unknown, meaning the whole image.
+    pub source: Option<RectInfo>,
+    /// Size of one tile in node coordinates, for `Tile`
+    pub tile_size: Option<(f64, f64)>,
+}
+
+/// Compute the destination and source rects of an image paint in a node of
+/// the given size. `image_size` is the image's natural size in pixels.
+pub fn place_image(paint: &ImagePaint, width: f64, height: f64, image_size: Option<(u32, u32)>) -> ImagePlacement {
+    let node_rect = rect(0.0, 0.0, width, height);
+    let Some((iw, ih)) = image_size.or(paint.original_size).filter(|&(w, h)| w > 0 && h > 0) else {
+        return ImagePlacement { dest: node_rect, source: None, tile_size: None };
+    };
+    let (iw, ih) = (iw as f64, ih as f64);
+    let full_image = rect(0.0, 0.0, iw, ih);
+
+    // A quarter turn swaps the image's width and height on screen
+    let quarter_turns = (paint.rotation / 90.0).round() as i64;
+    let (rw, rh) = if quarter_turns % 2 != 0 { (ih, iw) } else { (iw, ih) };
+
+    match paint.scale_mode {
+        ScaleMode::Fill => {
+            // Cover the node, cropping the overflowing axis around the center
+            let scale = (width / rw).max(height / rh);
+            let (sw, sh) = (width / scale, height / scale);
+            let source = if quarter_turns % 2 != 0 {
+                rect((iw - sh) / 2.0, (ih - sw) / 2.0, sh, sw)
+            } else {
+                rect((iw - sw) / 2.0, (ih - sh) / 2.0, sw, sh)
+            };
+            ImagePlacement { dest: node_rect, source: Some(source), tile_size: None }
+        }
+        ScaleMode::Fit => {
+            // Fit inside the node, letterboxing the other axis
+            let scale = (width / rw).min(height / rh);
+            let (dw, dh) = (rw * scale, rh * scale);
+            let dest = rect((width - dw) / 2.0, (height - dh) / 2.0, dw, dh);
+            ImagePlacement { dest, source: Some(full_image), tile_size: None }
+        }
+        ScaleMode::Tile => ImagePlacement {
+            dest: node_rect,
+            source: Some(full_image),
+            tile_size: Some((rw * paint.scale, rh * paint.scale)),
+        },
+        ScaleMode::Stretch => {
+            // The transform maps the node's unit square into the image's unit
+            // square, so its image is the visible crop
+            let t = &paint.transform;
+            let xs = [t.m02, t.m00 + t.m02, t.m01 + t.m02, t.m00 + t.m01 + t.m02];
+            let ys = [t.m12, t.m10 + t.m12, t.m11 + t.m12, t.m10 + t.m11 + t.m12];
+            let (x0, x1) = min_max(&xs);
+            let (y0, y1) = min_max(&ys);
+            let source = rect(x0 * iw, y0 * ih, (x1 - x0) * iw, (y1 - y0) * ih);
+            ImagePlacement { dest: node_rect, source: Some(source), tile_size: None }
+        }
+    }
+}
+
+fn min_max(values: &[f64]) -> (f64, f64) {
+    values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+}
+
+fn rect(x: f64, y: f64, width: f64, height: f64) -> RectInfo {
+    RectInfo { x, y, width, height, corner_radii: [0.0; 4] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::TransformInfo;
+    use crate::paints::{BlendMode, ImageFilters};
+
+    fn paint(scale_mode: ScaleMode) -> ImagePaint {
+        ImagePaint {
+            image_ref: "abc".into(),
+            scale_mode,
+            transform: TransformInfo::default(),
+            rotation: 0.0,
+            scale: 0.5,
+            original_size: None,
+            filters: ImageFilters::default(),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_image_size() {
+        assert_eq!(image_size(&png(640, 480)), Some((640, 480)));
+        let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc0, 0, 11, 8, 1, 0x2c, 0, 0xc8];
+        assert_eq!(image_size(&jpeg), Some((200, 300)));
+        assert_eq!(image_size(b"GIF89a\x10\x00\x20\x00"), Some((16, 32)));
+        assert_eq!(image_size(b"not an image"), None);
+        assert_eq!(image_size(&png(1, 1)[..20]), None);
+    }
+
+    #[test]
+    fn test_image_store() {
+        let mut store = ImageStore::new();
+        store.insert("ABCDEF", png(10, 20));
+        assert!(store.contains("abcdef"));
+        assert_eq!(store.size("abcdef"), Some((10, 20)));
+        assert_eq!(store.get("missing"), None);
+    }
+
+    #[test]
+    fn test_place_fill_and_fit() {
+        // A 200x100 image in a 100x100 node
+        let fill = place_image(&paint(ScaleMode::Fill), 100.0, 100.0, Some((200, 100)));
+        assert_eq!(fill.dest, rect(0.0, 0.0, 100.0, 100.0));
+        assert_eq!(fill.source, Some(rect(50.0, 0.0, 100.0, 100.0)));
+
+        let fit = place_image(&paint(ScaleMode::Fit), 100.0, 100.0, Some((200, 100)));
+        assert_eq!(fit.dest, rect(0.0, 25.0, 100.0, 50.0));
+        assert_eq!(fit.source, Some(rect(0.0, 0.0, 200.0, 100.0)));
+    }
+
+    #[test]
+    fn test_place_tile_and_stretch() {
+        let tile = place_image(&paint(ScaleMode::Tile), 100.0, 100.0, Some((40, 20)));
+        assert_eq!(tile.tile_size, Some((20.0, 10.0)));
+
+        // Show the right half of the image
+        let mut stretch = paint(ScaleMode::Stretch);
+        stretch.transform = TransformInfo { m00: 0.5, m01: 0.0, m02: 0.5, m10: 0.0, m11: 1.0, m12: 0.0 };
+        let placed = place_image(&stretch, 100.0, 100.0, Some((200, 100)));
+        assert_eq!(placed.source, Some(rect(100.0, 0.0, 100.0, 100.0)));
+
+        let unknown = place_image(&paint(ScaleMode::Fill), 100.0, 50.0, None);
+        assert_eq!(unknown.dest, rect(0.0, 0.0, 100.0, 50.0));
+        assert_eq!(unknown.source, None);
+    }
+}
@@ -0,0 +1,333 @@
+//! Component instances
+//!
+//! An instance's content isn't stored in the file: it is a copy of its
+//! component's children with the instance's overrides applied. Overrides
+//! address a node of the copy by a path of ids, one per level of nested
+//! instance, where each id is the node's `overrideKey` or else its guid.
+//! Nodes bound to component properties then take the instance's values.
+
+use crate::api::TransformInfo;
+use crate::components::{apply_properties, PropertyAssignment};
+use crate::grids::{self, GridPattern, LayoutGrid};
+use crate::kiwi::{matrix_rotation, FigFile, RawField};
+use crate::layout::Constraint;
+use crate::nodes::FigmaNode;
+use std::collections::HashMap;
+
+/// Instances nested deeper than this are left empty
+const MAX_INSTANCE_DEPTH: usize = 32;
+
+/// An instance's `symbolData`
+#[derive(Debug, Clone)]
+pub struct SymbolData {
+    pub component_id: String,
+    pub overrides: Vec<NodeOverride>,
+    /// Scale of the whole copy, from resizing the instance with the scale tool
+    pub scale: f64,
+}
+
+/// `NodeChange` fields an instance sets on one node of its copy, kept encoded
+#[derive(Debug, Clone)]
+pub struct NodeOverride {
+    pub guid_path: Vec<String>,
+    pub fields: Vec<RawField>,
+}
+
+/// Size and position Figma computed for one node of an instance's copy,
+/// such as after auto layout or text resizing (`derivedSymbolData`)
+#[derive(Debug, Clone)]
+pub struct DerivedLayout {
+    pub guid_path: Vec<String>,
+    pub size: Option<(f64, f64)>,
+    pub transform: Option<TransformInfo>,
+}
+
+/// Instance content copied by [`expand_instances`]
+pub struct Expansion {
+    /// Copied nodes by id
+    pub nodes: HashMap<String, FigmaNode>,
+    /// Each expanded instance's id and the ids of its copied children
+    pub children: Vec<(String, Vec<String>)>,
+}
+
+/// Copy the content of every instance in the file.
+///
+/// Copies get ids like in Figma's REST API: "I", the ids of the instance and
+/// of any nested instances the node is in, then the id of the node copied,
+/// separated by ";". Content is moved and resized by its constraints when
+/// the instance's size differs from the component's, then by the sizes and
+/// positions Figma recorded for the instance.
+pub fn expand_instances(file: &FigFile) -> Expansion {
+    let mut expander = Expander { file, nodes: HashMap::new(), components: Vec::new() };
+    let mut children = Vec::new();
+    for instance in file.nodes.values().filter(|node| node.symbol.is_some()) {
+        let copies = expander.copy_component(instance, &instance.id, std::slice::from_ref(&instance.id), &Scope::of(instance));
+        if !copies.is_empty() {
+            children.push((instance.id.clone(), copies));
+        }
+    }
+    Expansion { nodes: expander.nodes, children }
+}
+
+/// Copy the content of one instance, which needn't be in the file, such as
+/// an instance with other property values
+pub fn expand_instance(file: &FigFile, instance: &FigmaNode) -> Expansion {
+    let mut expander = Expander { file, nodes: HashMap::new(), components: Vec::new() };
+    let copies = expander.copy_component(instance, &instance.id, std::slice::from_ref(&instance.id), &Scope::of(instance));
+    Expansion { nodes: expander.nodes, children: vec![(instance.id.clone(), copies)] }
+}
+
+/// Overrides and derived layouts addressed relative to one instance, and
+/// the instance's property values, in increasing precedence
+struct Scope<'a> {
+    overrides: Vec<(&'a [String], &'a NodeOverride)>,
+    derived: Vec<(&'a [String], &'a DerivedLayout)>,
+    assignments: Vec<PropertyAssignment>,
+}
+
+impl<'a> Scope<'a> {
+    /// An instance's own overrides
+    fn of(instance: &'a FigmaNode) -> Self {
+        Scope {
+            overrides: instance.symbol.iter()
+                .flat_map(|symbol| &symbol.overrides)
+                .map(|o| (o.guid_path.as_slice(), o))
+                .collect(),
+            derived: instance.derived_layouts.iter()
+                .map(|d| (d.guid_path.as_slice(), d))
+                .collect(),
+            assignments: instance.property_assignments.clone(),
+        }
+    }
+
+    /// Overrides of a nested instance, addressed by `key` in this scope:
+    /// its own, then the ones this scope sets through it. `copy` is the
+    /// instance with this scope's overrides applied, which may assign it
+    /// other property values.
+    fn nested(&self, instance: &'a FigmaNode, copy: &FigmaNode, key: &str) -> Scope<'a> {
+        let mut scope = Scope::of(instance);
+        scope.overrides.extend(self.overrides.iter().filter_map(|&(path, o)| Some((strip_key(path, key)?, o))));
+        scope.derived.extend(self.derived.iter().filter_map(|&(path, d)| Some((strip_key(path, key)?, d))));
+        scope.assignments.extend(copy.property_assignments.iter().cloned());
+        scope
+    }
+}
+
+/// The rest of a path that goes through `key`
+fn strip_key<'a>(path: &'a [String], key: &str) -> Option<&'a [String]> {
+    match path {
+        [first, rest @ ..] if first == key && !rest.is_empty() => Some(rest),
+        _ => None,
+    }
+}
+
+/// A parent's size change, applied to its children by their constraints
+struct Resize {
+    from: (f64, f64),
+    to: (f64, f64),
+    /// Scale everything rather than follow constraints
+    uniform: bool,
+    /// The parent's layout grids, which constraints are relative to
+    grids: Vec<LayoutGrid>,
+}
+
+impl Resize {
+    fn apply(&self, node: &mut FigmaNode) {
+        if self.from == self.to {
+            return;
+        }
+        let (horizontal, vertical) = if self.uniform {
+            (Constraint::Scale, Constraint::Scale)
+        } else {
+            (node.horizontal_constraint, node.vertical_constraint)
+        };
+        let area = |pattern, length| grids::constraint_area(&self.grids, pattern, length);
+        (node.x, node.width) = horizontal.apply_in(node.x, node.width, area(GridPattern::Columns, self.from.0), area(GridPattern::Columns, self.to.0));
+        (node.y, node.height) = vertical.apply_in(node.y, node.height, area(GridPattern::Rows, self.from.1), area(GridPattern::Rows, self.to.1));
+        node.transform.m02 = node.x;
+        node.transform.m12 = node.y;
+    }
+}
+
+struct Expander<'a> {
+    file: &'a FigFile,
+    nodes: HashMap<String, FigmaNode>,
+    /// Components being copied, to stop at instances that contain themselves
+    components: Vec<&'a str>,
+}
+
+impl<'a> Expander<'a> {
+    /// Copy the children of an instance's component, returning their ids
+    fn copy_component(&mut self, instance: &FigmaNode, instance_id: &str, path: &[String], scope: &Scope<'a>) -> Vec<String> {
+        let Some(component) = instance.component_id().and_then(|id| self.file.get_node(id)) else {
+            return Vec::new();
+        };
+        if self.components.len() >= MAX_INSTANCE_DEPTH || self.components.contains(&component.id.as_str()) {
+            return Vec::new();
+        }
+
+        self.components.push(&component.id);
+        let resize = Resize {
+            from: (component.width, component.height),
+            to: (instance.width, instance.height),
+            uniform: instance.symbol.as_ref().is_some_and(|symbol| symbol.scale != 1.0),
+            grids: component.layout_grids.clone(),
+        };
+        let ids = component.children.iter()
+            .filter_map(|id| self.file.get_node(id))
+            .map(|child| self.copy(child, instance_id, path, scope, &resize))
+            .collect();
+        self.components.pop();
+        ids
+    }
+
+    /// Copy a node and its descendants, returning the copy's id
+    fn copy(&mut self, source: &'a FigmaNode, parent_id: &str, path: &[String], scope: &Scope<'a>, resize: &Resize) -> String {
+        let key = source.override_key.as_deref().unwrap_or(&source.id);
+        let mut node = source.clone();
+        resize.apply(&mut node);
+
+        for (guid_path, o) in &scope.overrides {
+            if matches!(guid_path, [k] if k == key) {
+                // An override that doesn't decode is left out rather than
+                // losing the whole instance
+                if let Ok(overridden) = self.file.apply_override(&node, &o.fields) {
+                    node = overridden;
+                }
+            }
+        }
+        for (guid_path, derived) in &scope.derived {
+            if matches!(guid_path, [k] if k == key) {
+                if let Some((width, height)) = derived.size {
+                    (node.width, node.height) = (width, height);
+                }
+                if let Some(transform) = &derived.transform {
+                    node.x = transform.m02;
+                    node.y = transform.m12;
+                    node.rotation = matrix_rotation(transform);
+                    node.transform = transform.clone();
+                }
+            }
+        }
+        apply_properties(self.file, &mut node, &scope.assignments);
+
+        let id = format!("I{};{}", path.join(";"), source.id);
+        node.id = id.clone();
+        node.parent_id = Some(parent_id.to_string());
+        node.children = if node.component_id().is_some() {
+            let mut nested_path = path.to_vec();
+            nested_path.push(source.id.clone());
+            self.copy_component(&node, &id, &nested_path, &scope.nested(source, &node, key))
+        } else {
+            // Auto layout positions children itself; Figma's derived
+            // layouts carry the result
+            let auto_layout = !matches!(node.layout_mode.as_str(), "" | "NONE");
+            let resize = Resize {
+                from: (source.width, source.height),
+                to: if auto_layout && !resize.uniform { (source.width, source.height) } else { (node.width, node.height) },
+                uniform: resize.uniform,
+                grids: node.layout_grids.clone(),
+            };
+            source.children.iter()
+                .filter_map(|id| self.file.get_node(id))
+                .map(|child| self.copy(child, &id, path, scope, &resize))
+                .collect()
+        };
+
+        self.nodes.insert(id.clone(), node);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::{test_fixture, KiwiValue};
+
+    #[test]
+    fn test_expand_instances() {
+        let mut file = test_fixture::file().clone();
+        let node_count = file.ordered_nodes().len();
+        file.expand_instances();
+
+        let instance = file.get_node("6:605").unwrap();
+        assert_eq!(instance.component_id(), Some("128:43585"));
+        assert_eq!(instance.children, ["I6:605;128:43586"]);
+        assert_eq!(file.get_node("I6:605;128:43586").unwrap().parent_id.as_deref(), Some("6:605"));
+
+        let mut copies: Vec<&FigmaNode> = file.nodes.values().filter(|node| node.id.starts_with('I')).collect();
+        copies.sort_by_key(|copy| &copy.id);
+        let source = |copy: &FigmaNode| file.get_node(copy.id.rsplit(';').next().unwrap()).unwrap();
+        // Text and visibility overrides
+        assert!(copies.iter().any(|copy| copy.text.as_ref().zip(source(copy).text.as_ref())
+            .is_some_and(|(text, original)| text.characters != original.characters)));
+        assert!(copies.iter().any(|copy| !copy.visible && source(copy).visible));
+        // Swapped nested instances copy the component swapped in
+        let swapped = copies.iter()
+            .find(|copy| copy.swapped_component_id.is_some() && !copy.children.is_empty())
+            .unwrap();
+        let component = file.get_node(swapped.component_id().unwrap()).unwrap();
+        assert_eq!(swapped.children.len(), component.children.len());
+        for (copy, original) in swapped.children.iter().zip(&component.children) {
+            assert_eq!(copy, &format!("{};{}", swapped.id, original));
+        }
+
+        // Resizing an instance moves content by its constraints
+        let (instance_id, child_id) = copies.iter()
+            .filter(|copy| copy.horizontal_constraint == Constraint::Max)
+            .filter_map(|copy| Some((copy.parent_id.clone()?, copy.id.clone())))
+            .find(|(parent_id, id)| !parent_id.starts_with('I') && file.get_node(parent_id).unwrap().derived_layouts.iter()
+                .all(|layout| layout.transform.is_none() || !id.ends_with(&format!(";{}", layout.guid_path[0]))))
+            .unwrap();
+        let x = file.get_node(&child_id).unwrap().x;
+        file.get_node_mut(&instance_id).unwrap().width += 100.0;
+        file.expand_instances();
+        assert_eq!(file.get_node(&child_id).unwrap().x, x + 100.0);
+        assert_eq!(file.get_node(&instance_id).unwrap().children.iter().filter(|id| id.starts_with('I')).count(),
+            file.get_node(&instance_id).unwrap().children.len());
+
+        // Copies aren't written
+        assert_eq!(file.ordered_nodes().len(), node_count);
+        let reparsed = FigFile::parse(&file.write().unwrap()).unwrap();
+        assert_eq!(reparsed.nodes.len(), node_count);
+    }
+
+    #[test]
+    fn test_bad_override() {
+        let file = test_fixture::file();
+        let child = file.get_node("128:43586").unwrap();
+        // Not a NodeChange field, so the override doesn't decode
+        let bad = vec![RawField { id: 9999, data: vec![1] }];
+        assert!(file.apply_override(child, &bad).is_err());
+
+        let mut instance = file.get_node("6:605").unwrap().clone();
+        let key = child.override_key.clone().unwrap_or(child.id.clone());
+        instance.symbol.as_mut().unwrap().overrides = vec![
+            NodeOverride { guid_path: vec![key.clone()], fields: vec![test_fixture::raw_field("opacity", &KiwiValue::Float(0.5))] },
+            NodeOverride { guid_path: vec![key], fields: bad },
+        ];
+        let expansion = expand_instance(file, &instance);
+        assert_eq!(expansion.children, [("6:605".to_string(), vec!["I6:605;128:43586".to_string()])]);
+        // The other override still applies
+        let copy = &expansion.nodes["I6:605;128:43586"];
+        assert_eq!(copy.opacity, 0.5);
+        assert_eq!(copy.name, child.name);
+    }
+
+    #[test]
+    fn test_nested_instance_ids() {
+        let file = test_fixture::expanded();
+        // 128:44608 is in the component of 128:45939, a nested instance in
+        // 128:45938 of the component of 104:40039
+        let copy = file.get_node("I104:40039;128:45939;128:44608").unwrap();
+        let nested_copy = file.get_node("I104:40039;128:45939").unwrap();
+        assert_eq!(copy.parent_id.as_deref(), Some(nested_copy.id.as_str()));
+        assert!(nested_copy.children.contains(&copy.id));
+        assert_eq!(nested_copy.parent_id.as_deref(), Some("I104:40039;128:45938"));
+        assert!(file.get_node("128:45939").unwrap().symbol.is_some());
+
+        let source = file.get_node("128:44608").unwrap();
+        assert_eq!(source.parent_id.as_deref(), nested_copy.component_id());
+        assert_eq!((copy.node_type.as_str(), copy.name.as_str()), (source.node_type.as_str(), source.name.as_str()));
+    }
+}
@@ -4,14 +4,151 @@
 //! - .fig file structure (header, chunks)
 //! - DEFLATE decompression (schema chunk)
 //! - ZSTD decompression (data chunk)
-//! - Kiwi message decoding
+//! - Kiwi message decoding and encoding using the embedded schema
 
 use crate::{FigmaError, Result};
-use crate::api::{PaintInfo, EffectInfo, PathData, ColorInfo, GradientStopInfo};
+use crate::api::{PathData, TransformInfo};
+use crate::archive;
+use crate::components::{PropertyAssignment, PropertyDef, PropertyRef};
+use crate::effects::Effect;
+use crate::grids::LayoutGrid;
+use crate::images::image_size;
+use crate::instances::{self, DerivedLayout, NodeOverride, SymbolData};
+use crate::layout::Constraint;
 use crate::nodes::FigmaNode;
+use crate::paints::Paint;
+use crate::prototype::{FlowStart, Interaction, OverlaySettings, LEGACY_LINK_FIELDS, OVERLAY_FIELDS};
+use crate::scroll::{ScrollBehavior, ScrollDirection};
+use crate::strokes::{StrokeStyle, STROKE_FIELDS};
+use crate::text::{TextContent, TextStyle, TEXT_STYLE_FIELDS};
+use crate::variables::{Variable, VariableBinding, VariableMode, VariableRef};
+
+use brine_kiwi_schema::{ByteBuffer, ByteBufferMut, Def, DefKind, Field as SchemaField, Schema};
+use brine_kiwi_schema::{TYPE_BOOL, TYPE_BYTE, TYPE_INT, TYPE_UINT, TYPE_FLOAT, TYPE_STRING, TYPE_INT64, TYPE_UINT64};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+/// Magic bytes at the start of every .fig file
+const FIG_KIWI_HEADER: &[u8; 8] = b"fig-kiwi";
+
+/// Oldest container version we accept
+pub const MIN_FILE_VERSION: u32 = 1;
+
+/// Newest container version we have tested against. Newer files are still
+/// read: each carries the schema its message is encoded with, and fields
+/// we don't know are kept as they are.
+pub const MAX_FILE_VERSION: u32 = 106;
+
+/// Nesting limit when skipping over values, so that a hostile file with
+/// deeply nested messages can't overflow the stack
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// The chunked container around the schema and message, before any
+/// decompression. Parsing this is cheap and never allocates per chunk.
+#[derive(Debug, Clone)]
+pub struct FigContainer<'a> {
+    pub version: u32,
+    /// Every chunk in the file, in order. There are always at least two.
+    pub chunks: Vec<&'a [u8]>,
+}
+
+impl<'a> FigContainer<'a> {
+    /// Parse and validate the header, version and chunk table
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let header_len = FIG_KIWI_HEADER.len();
+        if data.len() < header_len {
+            return Err(if FIG_KIWI_HEADER.starts_with(data) {
+                FigmaError::TruncatedFile("header".into())
+            } else {
+                FigmaError::InvalidHeader
+            });
+        }
+        if &data[..header_len] != FIG_KIWI_HEADER {
+            return Err(FigmaError::InvalidHeader);
+        }
+
+        let mut offset = header_len;
+        let version = read_u32_le(data, &mut offset)
+            .ok_or_else(|| FigmaError::TruncatedFile("version".into()))?;
+        if version < MIN_FILE_VERSION {
+            return Err(FigmaError::UnsupportedVersion(version));
+        }
+
+        let mut chunks = Vec::new();
+        while offset < data.len() {
+            let index = chunks.len();
+            let size = read_u32_le(data, &mut offset)
+                .ok_or_else(|| FigmaError::TruncatedFile(format!("size of chunk {}", index)))? as usize;
+            let remaining = data.len() - offset;
+            if size > remaining {
+                return Err(FigmaError::ChunkTooLarge { index, size, remaining });
+            }
+            chunks.push(&data[offset..offset + size]);
+            offset += size;
+        }
+
+        match chunks.len() {
+            0 => Err(FigmaError::MissingChunk("schema".into())),
+            1 => Err(FigmaError::MissingChunk("message".into())),
+            _ => Ok(FigContainer { version, chunks }),
+        }
+    }
 
-use std::collections::HashMap;
-use std::io::{Read, Cursor};
+    /// The DEFLATE-compressed binary schema
+    pub fn schema_chunk(&self) -> &'a [u8] {
+        self.chunks[0]
+    }
+
+    /// The ZSTD-compressed message
+    pub fn message_chunk(&self) -> &'a [u8] {
+        self.chunks[1]
+    }
+
+    /// Chunks after the message, such as the preview image
+    pub fn extra_chunks(&self) -> &[&'a [u8]] {
+        &self.chunks[2..]
+    }
+
+    /// The embedded preview image, if any. Nothing is decompressed, so this
+    /// is cheap even for very large files.
+    pub fn thumbnail(&self) -> Option<&'a [u8]> {
+        find_thumbnail(self.extra_chunks().iter().copied())
+    }
+}
+
+/// Read the preview image of a raw or zipped .fig file without decoding the
+/// document. Zipped files use `thumbnail.png`, falling back to the preview
+/// chunk inside `canvas.fig`.
+pub fn read_thumbnail(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !archive::is_archive(data) {
+        return Ok(FigContainer::parse(data)?.thumbnail().map(<[u8]>::to_vec));
+    }
+    if let Some(thumbnail) = archive::read_entry(data, archive::THUMBNAIL_ENTRY)? {
+        return Ok(Some(thumbnail));
+    }
+    let canvas = archive::read_entry(data, archive::CANVAS_ENTRY)?
+        .ok_or_else(|| FigmaError::ArchiveError(format!("missing {}", archive::CANVAS_ENTRY)))?;
+    Ok(FigContainer::parse(&canvas)?.thumbnail().map(<[u8]>::to_vec))
+}
+
+/// The first extra chunk that holds a recognizable image
+fn find_thumbnail<'a>(mut chunks: impl Iterator<Item = &'a [u8]>) -> Option<&'a [u8]> {
+    chunks.find(|chunk| image_size(chunk).is_some())
+}
+
+fn read_u32_le(data: &[u8], offset: &mut usize) -> Option<u32> {
+    let bytes = data.get(*offset..*offset + 4)?;
+    *offset += 4;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A message field that is carried through untouched, stored as its
+/// field id and the encoded bytes of its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawField {
+    pub id: u32,
+    pub data: Vec<u8>,
+}
 
 /// Parsed Figma file
 pub struct FigFile {
@@ -19,78 +156,235 @@ pub struct FigFile {
     pub version: u32,
     pub nodes: HashMap<String, FigmaNode>,
     pub page_ids: Vec<String>,
-    schema: Vec<u8>,
+    /// Node ids in the order they appeared in the message
+    node_order: Vec<String>,
+    /// Ids of the instance content copies in `nodes`, which aren't written
+    instance_copies: HashSet<String>,
+    /// Ids of nodes by library key, the first in file order for each key
+    keys: HashMap<String, String>,
+    /// Fields of the root message other than `nodeChanges` (blobs, etc)
+    message_fields: Vec<RawField>,
+    /// Chunks after the message chunk, such as the preview image
+    extra_chunks: Vec<Vec<u8>>,
+    schema: Schema,
+    schema_data: Vec<u8>,
+}
+
+impl Clone for FigFile {
+    fn clone(&self) -> Self {
+        FigFile {
+            name: self.name.clone(),
+            version: self.version,
+            nodes: self.nodes.clone(),
+            page_ids: self.page_ids.clone(),
+            node_order: self.node_order.clone(),
+            instance_copies: self.instance_copies.clone(),
+            keys: self.keys.clone(),
+            message_fields: self.message_fields.clone(),
+            extra_chunks: self.extra_chunks.clone(),
+            // The schema isn't Clone, but decoded the first time
+            schema: Schema::decode(&self.schema_data).expect("schema decoded before"),
+            schema_data: self.schema_data.clone(),
+        }
+    }
 }
 
 impl FigFile {
     /// Parse a .fig file from bytes
     pub fn parse(data: &[u8]) -> Result<Self> {
-        // Check header
-        let header = &data[0..8];
-        let (header_len, _encrypted) = match header {
-            b"fig-kiwi" => (8, false),
-            _ if &data[0..9] == b"fig-kiwie" => (9, true),
-            _ => return Err(FigmaError::InvalidHeader),
-        };
-
-        // Parse chunks
-        let mut cursor = Cursor::new(&data[header_len..]);
-        let chunks = parse_chunks(&mut cursor)?;
+        let container = FigContainer::parse(data)?;
 
         // Decompress schema (chunk 0) - raw DEFLATE
-        let schema = decompress_deflate(&chunks[0])?;
+        let schema_data = decompress_deflate(container.schema_chunk())?;
+        let schema = Schema::decode(&schema_data)
+            .map_err(|_| FigmaError::SchemaError("Invalid binary schema".into()))?;
 
         // Decompress message data (chunk 1) - ZSTD
-        let message_data = decompress_zstd(&chunks[1])?;
+        let message_data = decompress_zstd(container.message_chunk())?;
 
         // Parse the Kiwi message using the schema
-        let (nodes, page_ids) = decode_figma_message(&schema, &message_data)?;
+        let message = decode_figma_message(&schema, &message_data)?;
+
+        let name = message.nodes.values()
+            .find(|node| node.node_type == "DOCUMENT")
+            .map(|node| node.name.clone())
+            .unwrap_or_default();
+
+        let mut keys = HashMap::new();
+        for id in message.node_order.iter().rev() {
+            if let Some(key) = message.nodes.get(id).and_then(|node| node.key.clone()) {
+                keys.insert(key, id.clone());
+            }
+        }
 
         Ok(FigFile {
-            name: String::new(), // Extracted from message
-            version: 1,
-            nodes,
-            page_ids,
+            name,
+            version: container.version,
+            nodes: message.nodes,
+            page_ids: message.page_ids,
+            node_order: message.node_order,
+            instance_copies: HashSet::new(),
+            keys,
+            message_fields: message.fields,
+            extra_chunks: container.extra_chunks().iter().map(|chunk| chunk.to_vec()).collect(),
             schema,
+            schema_data,
         })
     }
 
+    /// Serialize back to the .fig format.
+    ///
+    /// The node map is re-encoded with the schema the file was loaded with,
+    /// so edits to nodes (renaming, moving, recoloring) are written out.
+    /// Nodes are emitted in their original order followed by any new nodes.
+    pub fn write(&self) -> Result<Vec<u8>> {
+        let message = encode_figma_message(self)?;
+
+        let schema_chunk = compress_deflate(&self.schema_data)?;
+        let message_chunk = compress_zstd(&message)?;
+
+        let mut out = Vec::with_capacity(12 + schema_chunk.len() + message_chunk.len() + 8);
+        out.extend_from_slice(FIG_KIWI_HEADER);
+        out.extend_from_slice(&self.version.to_le_bytes());
+
+        for chunk in [&schema_chunk, &message_chunk].into_iter().chain(&self.extra_chunks) {
+            out.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            out.extend_from_slice(chunk);
+        }
+
+        Ok(out)
+    }
+
+    /// The preview image embedded after the message chunk, usually a PNG
+    pub fn thumbnail(&self) -> Option<&[u8]> {
+        find_thumbnail(self.extra_chunks.iter().map(Vec::as_slice))
+    }
+
     /// Get a node by ID
     pub fn get_node(&self, id: &str) -> Option<&FigmaNode> {
         self.nodes.get(id)
     }
 
-    /// Get all root nodes (pages)
-    pub fn get_pages(&self) -> Vec<&FigmaNode> {
-        self.page_ids.iter()
+    /// Get a component, style or variable by its library key
+    pub fn get_node_by_key(&self, key: &str) -> Option<&FigmaNode> {
+        self.keys.get(key).and_then(|id| self.nodes.get(id))
+    }
+
+    /// All nodes in file order, followed by nodes added since parsing.
+    /// Instance content copies aren't included.
+    pub fn ordered_nodes(&self) -> Vec<&FigmaNode> {
+        let ordered: HashSet<&String> = self.node_order.iter().collect();
+        let mut added: Vec<&String> = self.nodes.keys()
+            .filter(|id| !self.instance_copies.contains(*id) && !ordered.contains(id))
+            .collect();
+        added.sort();
+        self.node_order.iter()
+            .chain(added)
             .filter_map(|id| self.nodes.get(id))
             .collect()
     }
-}
 
-/// Parse chunks from fig file
-fn parse_chunks(cursor: &mut Cursor<&[u8]>) -> Result<Vec<Vec<u8>>> {
-    let mut chunks = Vec::new();
+    /// Decode an encoded `Paint[]` value, as stored in a node's
+    /// `fill_paints_data`, with this file's schema
+    pub fn decode_paints(&self, data: &[u8]) -> Result<Vec<Paint>> {
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        let field = message_def(&self.schema, "NodeChange")?.field("fillPaints")
+            .ok_or_else(|| FigmaError::SchemaError("Missing field: fillPaints".into()))?;
+        Ok(Paint::list_from_kiwi(&decode_raw(&self.schema, field, data)?))
+    }
 
-    loop {
-        // Read chunk size (4 bytes, little-endian)
-        let mut size_buf = [0u8; 4];
-        if cursor.read_exact(&mut size_buf).is_err() {
-            break;
+    /// Decode an encoded `Effect[]` value, as stored in a node's
+    /// `effects_data`, with this file's schema
+    pub fn decode_effects(&self, data: &[u8]) -> Result<Vec<Effect>> {
+        if data.is_empty() {
+            return Ok(Vec::new());
         }
-        let size = u32::from_le_bytes(size_buf) as usize;
+        let field = message_def(&self.schema, "NodeChange")?.field("effects")
+            .ok_or_else(|| FigmaError::SchemaError("Missing field: effects".into()))?;
+        Ok(Effect::list_from_kiwi(&decode_raw(&self.schema, field, data)?))
+    }
 
-        if size == 0 {
-            break;
+    /// Add copies of each instance's content to `nodes`, as the instance's
+    /// children, replacing copies from an earlier call. See
+    /// [`instances::expand_instances`] for how copies are made and named.
+    ///
+    /// Copies aren't written by [`FigFile::write`]: edit the component or
+    /// the instance's overrides and expand again instead.
+    pub fn expand_instances(&mut self) {
+        let copies = std::mem::take(&mut self.instance_copies);
+        for node in self.nodes.values_mut().filter(|node| node.symbol.is_some()) {
+            node.children.retain(|id| !copies.contains(id));
+        }
+        for id in &copies {
+            self.nodes.remove(id);
         }
 
-        // Read chunk data
-        let mut chunk_data = vec![0u8; size];
-        cursor.read_exact(&mut chunk_data)?;
-        chunks.push(chunk_data);
+        let expansion = instances::expand_instances(self);
+        for (id, children) in expansion.children {
+            if let Some(instance) = self.nodes.get_mut(&id) {
+                instance.children.extend(children);
+            }
+        }
+        self.instance_copies = expansion.nodes.keys().cloned().collect();
+        self.nodes.extend(expansion.nodes);
+    }
+
+    /// Apply the fields of an override (a partial `NodeChange`) to a node,
+    /// decoding the result as if the node had been read with them
+    pub(crate) fn apply_override(&self, node: &FigmaNode, fields: &[RawField]) -> Result<FigmaNode> {
+        let def = message_def(&self.schema, "NodeChange")?;
+        let mut encoded = ByteBufferMut::new();
+        encode_node_change(&self.schema, def, node, &mut encoded)?;
+        let encoded = encoded.data();
+
+        let mut bb = ByteBuffer::new(&encoded);
+        let mut merged = ByteBufferMut::new();
+        while let Some(field) = next_field(def, &mut bb)? {
+            let data = read_raw(&self.schema, &mut bb, field)?;
+            if !fields.iter().any(|f| f.id == field.value) {
+                merged.write_var_uint(field.value);
+                merged.write_bytes(&data);
+            }
+        }
+        for field in fields {
+            merged.write_var_uint(field.id);
+            merged.write_bytes(&field.data);
+        }
+        merged.write_var_uint(0);
+
+        let merged = merged.data();
+        let mut result = decode_node_change(&self.schema, def, &mut ByteBuffer::new(&merged))?;
+        result.children = node.children.clone();
+        Ok(result)
+    }
+
+    /// A `textData` override setting a text node's characters, in the
+    /// node's own style
+    pub(crate) fn text_data_field(&self, characters: &str) -> Result<RawField> {
+        let field = message_def(&self.schema, "NodeChange")?.field("textData")
+            .ok_or_else(|| FigmaError::SchemaError("Missing field: textData".into()))?;
+        let characters_field = message_def_for(&self.schema, field)?.field("characters")
+            .ok_or_else(|| FigmaError::SchemaError("Missing field: characters".into()))?;
+        let mut bb = ByteBufferMut::new();
+        bb.write_var_uint(characters_field.value);
+        bb.write_string(characters);
+        bb.write_var_uint(0);
+        Ok(RawField { id: field.value, data: bb.data() })
+    }
+
+    /// Get a node by ID for editing before calling [`FigFile::write`]
+    pub fn get_node_mut(&mut self, id: &str) -> Option<&mut FigmaNode> {
+        self.nodes.get_mut(id)
     }
 
-    Ok(chunks)
+    /// Get all root nodes (pages)
+    pub fn get_pages(&self) -> Vec<&FigmaNode> {
+        self.page_ids.iter()
+            .filter_map(|id| self.nodes.get(id))
+            .collect()
+    }
 }
 
 /// Decompress DEFLATE data (for schema chunk)
@@ -105,6 +399,16 @@ fn decompress_deflate(data: &[u8]) -> Result<Vec<u8>> {
     Ok(decompressed)
 }
 
+/// Compress with raw DEFLATE (for schema chunk)
+fn compress_deflate(data: &[u8]) -> Result<Vec<u8>> {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
 /// Decompress ZSTD data (for message chunk)
 #[cfg(not(target_arch = "wasm32"))]
 fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>> {
@@ -127,497 +431,1063 @@ fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>> {
     Ok(decompressed)
 }
 
+/// Compress with ZSTD (for message chunk)
+#[cfg(not(target_arch = "wasm32"))]
+fn compress_zstd(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(data, 0)?)
+}
+
+/// Compress with ZSTD (for message chunk) - WASM version using pure Rust ruzstd
+#[cfg(target_arch = "wasm32")]
+fn compress_zstd(data: &[u8]) -> Result<Vec<u8>> {
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    Ok(compress_to_vec(data, CompressionLevel::Fastest))
+}
+
+// =============================================================================
+// Message decoding
+// =============================================================================
+
+/// The parts of the root `Message` that `FigFile` keeps
+struct DecodedMessage {
+    nodes: HashMap<String, FigmaNode>,
+    node_order: Vec<String>,
+    page_ids: Vec<String>,
+    fields: Vec<RawField>,
+}
+
 /// Decode Figma message from Kiwi binary
-fn decode_figma_message(
-    schema: &[u8],
-    data: &[u8],
-) -> Result<(HashMap<String, FigmaNode>, Vec<String>)> {
-    let mut nodes = HashMap::new();
-    let mut page_ids = Vec::new();
+fn decode_figma_message(schema: &Schema, data: &[u8]) -> Result<DecodedMessage> {
+    let message_def = message_def(schema, "Message")?;
+    let mut bb = ByteBuffer::new(data);
 
-    // Parse using Kiwi decoder
-    let mut decoder = KiwiDecoder::new(schema, data)?;
+    let mut nodes = HashMap::new();
+    let mut node_order = Vec::new();
+    let mut fields = Vec::new();
 
     // The root message contains nodeChanges array
-    while let Some(field) = decoder.next_field()? {
-        match field.name.as_str() {
-            "nodeChanges" => {
-                // Array of node change messages
-                for _ in 0..field.array_length {
-                    if let Some(node) = decode_node_change(&mut decoder)? {
-                        if node.node_type == "CANVAS" {
-                            page_ids.push(node.id.clone());
-                        }
-                        nodes.insert(node.id.clone(), node);
+    while let Some(field) = next_field(message_def, &mut bb)? {
+        match (field.name.as_str(), field.is_array) {
+            ("nodeChanges", true) => {
+                let node_def = message_def_for(schema, field)?;
+                let count = read_count(&mut bb)?;
+                for _ in 0..count {
+                    let node = decode_node_change(schema, node_def, &mut bb)?;
+                    if !nodes.contains_key(&node.id) {
+                        node_order.push(node.id.clone());
                     }
+                    nodes.insert(node.id.clone(), node);
                 }
             }
-            _ => decoder.skip_field(&field)?,
+            _ => fields.push(RawField { id: field.value, data: read_raw(schema, &mut bb, field)? }),
         }
     }
 
-    Ok((nodes, page_ids))
+    let page_ids = link_children(&mut nodes, &node_order);
+
+    Ok(DecodedMessage { nodes, node_order, page_ids, fields })
+}
+
+/// Fill in `children` from each node's `parentIndex`, ordered by position,
+/// and return the pages (canvases) in document order.
+fn link_children(nodes: &mut HashMap<String, FigmaNode>, node_order: &[String]) -> Vec<String> {
+    let mut children: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for id in node_order {
+        let node = &nodes[id];
+        if let Some(parent_id) = &node.parent_id {
+            children.entry(parent_id.clone())
+                .or_default()
+                .push((node.parent_position.clone(), id.clone()));
+        }
+    }
+
+    for (parent_id, mut entries) in children {
+        entries.sort();
+        if let Some(parent) = nodes.get_mut(&parent_id) {
+            parent.children = entries.into_iter().map(|(_, id)| id).collect();
+        }
+    }
+
+    let mut pages: Vec<&FigmaNode> = nodes.values()
+        .filter(|node| node.node_type == "CANVAS")
+        .collect();
+    pages.sort_by(|a, b| (&a.parent_position, &a.id).cmp(&(&b.parent_position, &b.id)));
+    pages.into_iter().map(|node| node.id.clone()).collect()
 }
 
 /// Decode a single node change message
-fn decode_node_change(decoder: &mut KiwiDecoder) -> Result<Option<FigmaNode>> {
+fn decode_node_change(schema: &Schema, def: &Def, bb: &mut ByteBuffer) -> Result<FigmaNode> {
     let mut node = FigmaNode::default();
-
-    while let Some(field) = decoder.next_field()? {
-        match field.name.as_str() {
-            "guid" => node.id = decoder.read_guid()?,
-            "parentIndex" => {
-                let parent = decoder.read_parent_index()?;
-                node.parent_id = parent.0;
-            }
-            "type" => node.node_type = decoder.read_node_type()?,
-            "name" => node.name = decoder.read_string()?,
-            "visible" => node.visible = decoder.read_bool()?,
-            "opacity" => node.opacity = decoder.read_float()? as f64,
-            "transform" => {
-                let t = decoder.read_transform()?;
-                node.x = t.tx;
-                node.y = t.ty;
-                node.rotation = t.rotation();
-            }
-            "size" => {
-                let (w, h) = decoder.read_size()?;
-                node.width = w;
-                node.height = h;
-            }
-            "fillPaints" => node.fill_paints_data = decoder.read_bytes()?,
-            "strokePaints" => node.stroke_paints_data = decoder.read_bytes()?,
-            "effects" => node.effects_data = decoder.read_bytes()?,
-            "vectorData" => node.vector_data = decoder.read_bytes()?,
-            "strokeWeight" => node.stroke_weight = decoder.read_float()? as f64,
-            "cornerRadius" => node.corner_radius = decoder.read_float()? as f64,
-            "rectangleCornerRadii" => {
-                node.corner_radii = [
-                    decoder.read_float()? as f64,
-                    decoder.read_float()? as f64,
-                    decoder.read_float()? as f64,
-                    decoder.read_float()? as f64,
-                ];
-            }
-            "children" => {
-                for _ in 0..field.array_length {
-                    node.children.push(decoder.read_guid()?);
-                }
+    // Text and stroke styling fields stay in unknown_fields, but are also
+    // decoded for rendering
+    let mut text_fields = KiwiMessage::default();
+    let mut stroke_fields = KiwiMessage::default();
+    // Prototype links from before interactions, and overlay settings
+    let mut link_fields = KiwiMessage::default();
+    let mut overlay_fields = KiwiMessage::default();
+    // A variable's fields, combined once all are read
+    let mut variable_set = None;
+    let mut variable_type = String::new();
+    let mut variable_values = None;
+
+    while let Some(field) = next_field(def, bb)? {
+        let known = match (field.name.as_str(), type_name(schema, field), field.is_array) {
+            ("guid", "GUID", false) => { node.id = read_guid(bb)?; true }
+            ("parentIndex", "ParentIndex", false) => {
+                node.parent_id = Some(read_guid(bb)?);
+                node.parent_position = read_string(bb)?;
+                true
+            }
+            ("type", _, false) if is_enum(schema, field) => { node.node_type = read_enum(schema, field, bb)?; true }
+            ("name", "string", false) => { node.name = read_string(bb)?; true }
+            ("visible", "bool", false) => { node.visible = read_bool(bb)?; true }
+            ("opacity", "float", false) => { node.opacity = read_float(bb)?; true }
+            ("blendMode", _, false) if is_enum(schema, field) => { node.blend_mode = read_enum(schema, field, bb)?; true }
+            ("transform", "Matrix", false) => {
+                let t = read_matrix(bb)?;
+                node.x = t.m02;
+                node.y = t.m12;
+                node.rotation = matrix_rotation(&t);
+                node.transform = t;
+                true
+            }
+            ("size", "Vector", false) => {
+                node.width = read_float(bb)?;
+                node.height = read_float(bb)?;
+                true
             }
+            ("fillPaints", "Paint", true) => {
+                node.fill_paints_data = read_raw(schema, bb, field)?;
+                node.fill_paints = Paint::list_from_kiwi(&decode_raw(schema, field, &node.fill_paints_data)?);
+                true
+            }
+            ("strokePaints", "Paint", true) => {
+                node.stroke_paints_data = read_raw(schema, bb, field)?;
+                node.stroke_paints = Paint::list_from_kiwi(&decode_raw(schema, field, &node.stroke_paints_data)?);
+                true
+            }
+            ("effects", "Effect", true) => {
+                node.effects_data = read_raw(schema, bb, field)?;
+                node.effects = Effect::list_from_kiwi(&decode_raw(schema, field, &node.effects_data)?);
+                true
+            }
+            ("vectorData", "VectorData", false) => { node.vector_data = read_raw(schema, bb, field)?; true }
+            ("strokeWeight", "float", false) => { node.stroke_weight = read_float(bb)?; true }
+            ("cornerRadius", "float", false) => { node.corner_radius = read_float(bb)?; true }
+            ("rectangleTopLeftCornerRadius", "float", false) => { node.corner_radii[0] = read_float(bb)?; true }
+            ("rectangleTopRightCornerRadius", "float", false) => { node.corner_radii[1] = read_float(bb)?; true }
+            ("rectangleBottomRightCornerRadius", "float", false) => { node.corner_radii[2] = read_float(bb)?; true }
+            ("rectangleBottomLeftCornerRadius", "float", false) => { node.corner_radii[3] = read_float(bb)?; true }
             // Text properties
-            "textData" => node.text_data = decoder.read_bytes()?,
-            "fontName" => node.font_name = decoder.read_string()?,
-            "fontSize" => node.font_size = decoder.read_float()? as f64,
+            ("textData", "TextData", false) => { node.text_data = read_raw(schema, bb, field)?; true }
+            ("fontName", "FontName", false) => {
+                node.font_name = read_string(bb)?;
+                node.font_style = read_string(bb)?;
+                node.font_postscript_name = read_string(bb)?;
+                true
+            }
+            ("fontSize", "float", false) => { node.font_size = read_float(bb)?; true }
             // Layout properties
-            "layoutMode" => node.layout_mode = decoder.read_enum()?,
-            "primaryAxisSizingMode" => node.primary_axis_sizing = decoder.read_enum()?,
-            "counterAxisSizingMode" => node.counter_axis_sizing = decoder.read_enum()?,
-            "itemSpacing" => node.item_spacing = decoder.read_float()? as f64,
-            "paddingLeft" => node.padding[0] = decoder.read_float()? as f64,
-            "paddingTop" => node.padding[1] = decoder.read_float()? as f64,
-            "paddingRight" => node.padding[2] = decoder.read_float()? as f64,
-            "paddingBottom" => node.padding[3] = decoder.read_float()? as f64,
-            _ => decoder.skip_field(&field)?,
+            ("stackMode", _, false) if is_enum(schema, field) => { node.layout_mode = read_enum(schema, field, bb)?; true }
+            ("stackPrimarySizing", _, false) if is_enum(schema, field) => { node.primary_axis_sizing = read_enum(schema, field, bb)?; true }
+            ("stackCounterSizing", _, false) if is_enum(schema, field) => { node.counter_axis_sizing = read_enum(schema, field, bb)?; true }
+            ("stackSpacing", "float", false) => { node.item_spacing = read_float(bb)?; true }
+            ("stackHorizontalPadding", "float", false) => { node.padding[0] = read_float(bb)?; true }
+            ("stackVerticalPadding", "float", false) => { node.padding[1] = read_float(bb)?; true }
+            ("stackPaddingRight", "float", false) => { node.padding[2] = read_float(bb)?; true }
+            ("stackPaddingBottom", "float", false) => { node.padding[3] = read_float(bb)?; true }
+            // Keep everything else verbatim so writing the file back is lossless
+            _ => {
+                let data = read_raw(schema, bb, field)?;
+                match field.name.as_str() {
+                    name if TEXT_STYLE_FIELDS.contains(&name) => {
+                        text_fields.fields.push((field.name.clone(), decode_raw(schema, field, &data)?));
+                    }
+                    name if STROKE_FIELDS.contains(&name) => {
+                        stroke_fields.fields.push((field.name.clone(), decode_raw(schema, field, &data)?));
+                    }
+                    name if LEGACY_LINK_FIELDS.contains(&name) => {
+                        link_fields.fields.push((field.name.clone(), decode_raw(schema, field, &data)?));
+                    }
+                    name if OVERLAY_FIELDS.contains(&name) => {
+                        overlay_fields.fields.push((field.name.clone(), decode_raw(schema, field, &data)?));
+                    }
+                    // Component instances
+                    "symbolData" => node.symbol = Some(decode_symbol_data(schema, field, &data)?),
+                    "derivedSymbolData" => node.derived_layouts = decode_derived_layouts(schema, field, &data)?,
+                    "overrideKey" => node.override_key = Some(read_guid(&mut ByteBuffer::new(&data))?),
+                    "overriddenSymbolID" => node.swapped_component_id = Some(read_guid(&mut ByteBuffer::new(&data))?),
+                    // Component properties
+                    "componentPropDefs" => node.property_defs = PropertyDef::list_from_kiwi(&decode_raw(schema, field, &data)?),
+                    "componentPropRefs" => node.property_refs = PropertyRef::list_from_kiwi(&decode_raw(schema, field, &data)?),
+                    "componentPropAssignments" => {
+                        node.property_assignments = PropertyAssignment::list_from_kiwi(&decode_raw(schema, field, &data)?);
+                    }
+                    "isStateGroup" => node.is_state_group = read_bool(&mut ByteBuffer::new(&data))?,
+                    "stateGroupPropertyValueOrders" => node.variant_orders = decode_raw(schema, field, &data)?.as_array().iter()
+                        .filter_map(KiwiValue::as_message)
+                        .map(|order| (
+                            order.str("property").unwrap_or_default().to_string(),
+                            order.array("values").iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+                        ))
+                        .collect(),
+                    "key" => node.key = Some(read_string(&mut ByteBuffer::new(&data))?),
+                    // Variables
+                    "variableSetID" => variable_set = decode_raw(schema, field, &data)?.as_message().and_then(VariableRef::from_kiwi),
+                    "variableResolvedType" if is_enum(schema, field) => {
+                        variable_type = read_enum(schema, field, &mut ByteBuffer::new(&data))?;
+                    }
+                    "variableDataValues" => variable_values = decode_raw(schema, field, &data)?.as_message().cloned(),
+                    "variableSetModes" => node.modes = VariableMode::list_from_kiwi(&decode_raw(schema, field, &data)?),
+                    "variableConsumptionMap" => {
+                        if let Some(map) = decode_raw(schema, field, &data)?.as_message() {
+                            node.variable_bindings = VariableBinding::list_from_kiwi(map);
+                        }
+                    }
+                    "variableModeBySetMap" => {
+                        node.explicit_modes = decode_raw(schema, field, &data)?.as_message().into_iter()
+                            .flat_map(|map| map.array("entries"))
+                            .filter_map(KiwiValue::as_message)
+                            .filter_map(|entry| Some((
+                                VariableRef::from_kiwi(entry.message("variableSetID")?)?,
+                                entry.guid("variableModeID")?,
+                            )))
+                            .collect();
+                    }
+                    // Styles
+                    "styleType" if is_enum(schema, field) => {
+                        node.style_type = Some(read_enum(schema, field, &mut ByteBuffer::new(&data))?);
+                    }
+                    "description" | "styleDescription" => node.description = read_string(&mut ByteBuffer::new(&data))?,
+                    "isSoftDeleted" | "isSoftDeletedStyle" => node.soft_deleted = read_bool(&mut ByteBuffer::new(&data))?,
+                    // Auto layout alignment, and how children take part in it
+                    "stackPrimaryAlignItems" if is_enum(schema, field) => {
+                        node.primary_axis_align = read_enum(schema, field, &mut ByteBuffer::new(&data))?;
+                    }
+                    "stackCounterAlignItems" if is_enum(schema, field) => {
+                        node.counter_axis_align = read_enum(schema, field, &mut ByteBuffer::new(&data))?;
+                    }
+                    "stackWrap" if is_enum(schema, field) => node.layout_wrap = read_enum(schema, field, &mut ByteBuffer::new(&data))? == "WRAP",
+                    "stackCounterSpacing" => node.counter_axis_spacing = Some(read_float(&mut ByteBuffer::new(&data))?).filter(|spacing| spacing.is_finite()),
+                    "stackChildPrimaryGrow" => node.layout_grow = read_float(&mut ByteBuffer::new(&data))?,
+                    "stackChildAlignSelf" if is_enum(schema, field) => {
+                        node.layout_align = read_enum(schema, field, &mut ByteBuffer::new(&data))?;
+                    }
+                    "stackPositioning" if is_enum(schema, field) => {
+                        node.absolute_position = read_enum(schema, field, &mut ByteBuffer::new(&data))? == "ABSOLUTE";
+                    }
+                    "horizontalConstraint" if is_enum(schema, field) => {
+                        node.horizontal_constraint = Constraint::from_name(&read_enum(schema, field, &mut ByteBuffer::new(&data))?);
+                    }
+                    "verticalConstraint" if is_enum(schema, field) => {
+                        node.vertical_constraint = Constraint::from_name(&read_enum(schema, field, &mut ByteBuffer::new(&data))?);
+                    }
+                    "layoutGrids" => node.layout_grids = LayoutGrid::list_from_kiwi(&decode_raw(schema, field, &data)?),
+                    // Scrolling and clipping
+                    "frameMaskDisabled" => node.clip_disabled = read_bool(&mut ByteBuffer::new(&data))?,
+                    "scrollDirection" if is_enum(schema, field) => {
+                        node.scroll_direction = ScrollDirection::from_name(&read_enum(schema, field, &mut ByteBuffer::new(&data))?);
+                    }
+                    "scrollBehavior" if is_enum(schema, field) => {
+                        node.scroll_behavior = ScrollBehavior::from_name(&read_enum(schema, field, &mut ByteBuffer::new(&data))?);
+                    }
+                    // Prototyping
+                    "prototypeInteractions" => node.interactions = Interaction::list_from_kiwi(&decode_raw(schema, field, &data)?),
+                    "prototypeStartingPoint" => node.flow_start = decode_raw(schema, field, &data)?.as_message().map(FlowStart::from_kiwi),
+                    _ => {}
+                }
+                node.unknown_fields.push(RawField { id: field.value, data });
+                false
+            }
+        };
+
+        if known {
+            node.decoded_fields.push(field.value);
         }
     }
 
-    Ok(Some(node))
-}
+    node.stroke = StrokeStyle::from_kiwi(&stroke_fields);
+    if node.interactions.is_empty() {
+        node.interactions.extend(Interaction::from_legacy(&link_fields));
+    }
+    if !overlay_fields.fields.is_empty() {
+        node.overlay = Some(OverlaySettings::from_kiwi(&overlay_fields));
+    }
+    if let (Some(collection), Some(values)) = (variable_set, variable_values) {
+        node.variable = Some(Variable::from_kiwi(collection, variable_type, &values));
+    }
 
-// =============================================================================
-// Kiwi Decoder
-// =============================================================================
+    if !node.text_data.is_empty() {
+        if let Some(field) = def.field("textData") {
+            let text_data = decode_raw(schema, field, &node.text_data)?;
+            let style = TextStyle {
+                font_family: node.font_name.clone(),
+                font_style: node.font_style.clone(),
+                font_postscript_name: node.font_postscript_name.clone(),
+                font_size: node.font_size,
+                fills: node.fill_paints.clone(),
+                ..Default::default()
+            };
+            node.text = text_data.as_message()
+                .map(|text_data| TextContent::from_kiwi(text_data, &text_fields, style));
+        }
+    }
 
-/// Low-level Kiwi binary decoder
-pub struct KiwiDecoder<'a> {
-    schema: &'a [u8],
-    data: &'a [u8],
-    pos: usize,
-    field_defs: Vec<FieldDef>,
+    Ok(node)
 }
 
-struct FieldDef {
-    name: String,
-    field_type: u8,
-    is_array: bool,
-}
+/// Decode an instance's `SymbolData`, keeping each override's fields encoded
+/// so they can be applied with [`FigFile::apply_override`]
+fn decode_symbol_data(schema: &Schema, field: &SchemaField, data: &[u8]) -> Result<SymbolData> {
+    let def = message_def_for(schema, field)?;
+    let mut bb = ByteBuffer::new(data);
+    let mut symbol = SymbolData { component_id: String::new(), overrides: Vec::new(), scale: 1.0 };
+
+    while let Some(field) = next_field(def, &mut bb)? {
+        match (field.name.as_str(), type_name(schema, field), field.is_array) {
+            ("symbolID", "GUID", false) => symbol.component_id = read_guid(&mut bb)?,
+            ("symbolOverrides", _, true) => {
+                let override_def = message_def_for(schema, field)?;
+                for _ in 0..read_count(&mut bb)? {
+                    symbol.overrides.push(read_node_override(schema, override_def, &mut bb)?);
+                }
+            }
+            ("uniformScaleFactor", "float", false) => symbol.scale = read_float(&mut bb)?,
+            _ => skip_field(schema, &mut bb, field, 0)?,
+        }
+    }
 
-pub struct Field {
-    pub name: String,
-    pub field_type: u8,
-    pub array_length: usize,
+    Ok(symbol)
 }
 
-impl<'a> KiwiDecoder<'a> {
-    pub fn new(schema: &'a [u8], data: &'a [u8]) -> Result<Self> {
-        // Parse schema to get field definitions
-        let field_defs = parse_schema(schema)?;
-
-        Ok(KiwiDecoder {
-            schema,
-            data,
-            pos: 0,
-            field_defs,
-        })
+/// Decode the sizes and transforms of an instance's `derivedSymbolData`
+fn decode_derived_layouts(schema: &Schema, field: &SchemaField, data: &[u8]) -> Result<Vec<DerivedLayout>> {
+    let def = message_def_for(schema, field)?;
+    let mut bb = ByteBuffer::new(data);
+    let mut layouts = Vec::new();
+
+    for _ in 0..read_count(&mut bb)? {
+        let mut layout = DerivedLayout { guid_path: Vec::new(), size: None, transform: None };
+        while let Some(field) = next_field(def, &mut bb)? {
+            match (field.name.as_str(), type_name(schema, field), field.is_array) {
+                ("guidPath", "GUIDPath", false) => layout.guid_path = read_guid_path(schema, field, &mut bb)?,
+                ("size", "Vector", false) => layout.size = Some((read_float(&mut bb)?, read_float(&mut bb)?)),
+                ("transform", "Matrix", false) => layout.transform = Some(read_matrix(&mut bb)?),
+                _ => skip_field(schema, &mut bb, field, 0)?,
+            }
+        }
+        layouts.push(layout);
     }
 
-    pub fn next_field(&mut self) -> Result<Option<Field>> {
-        if self.pos >= self.data.len() {
-            return Ok(None);
+    Ok(layouts)
+}
+
+/// Read one `NodeChange` of an instance's overrides
+fn read_node_override(schema: &Schema, def: &Def, bb: &mut ByteBuffer) -> Result<NodeOverride> {
+    let mut node_override = NodeOverride { guid_path: Vec::new(), fields: Vec::new() };
+    while let Some(field) = next_field(def, bb)? {
+        match (field.name.as_str(), type_name(schema, field), field.is_array) {
+            ("guidPath", "GUIDPath", false) => node_override.guid_path = read_guid_path(schema, field, bb)?,
+            _ => node_override.fields.push(RawField { id: field.value, data: read_raw(schema, bb, field)? }),
         }
+    }
+    Ok(node_override)
+}
 
-        let field_index = self.read_varint()? as usize;
-        if field_index == 0 {
-            return Ok(None);
+fn read_guid_path(schema: &Schema, field: &SchemaField, bb: &mut ByteBuffer) -> Result<Vec<String>> {
+    let def = message_def_for(schema, field)?;
+    let mut guids = Vec::new();
+    while let Some(field) = next_field(def, bb)? {
+        match (field.name.as_str(), field.is_array) {
+            ("guids", true) => {
+                for _ in 0..read_count(bb)? {
+                    guids.push(read_guid(bb)?);
+                }
+            }
+            _ => skip_field(schema, bb, field, 0)?,
         }
+    }
+    Ok(guids)
+}
 
-        // Clone the field definition data to avoid borrow conflict
-        let (name, field_type, is_array) = {
-            let def = self.field_defs.get(field_index - 1)
-                .ok_or_else(|| FigmaError::DecodeError(format!("Unknown field index: {}", field_index)))?;
-            (def.name.clone(), def.field_type, def.is_array)
-        };
+// =============================================================================
+// Message encoding
+// =============================================================================
 
-        let array_length = if is_array {
-            self.read_varint()? as usize
-        } else {
-            1
-        };
+/// Encode the node map and preserved fields back into a root `Message`
+fn encode_figma_message(file: &FigFile) -> Result<Vec<u8>> {
+    let schema = &file.schema;
+    let message_def = message_def(schema, "Message")?;
+    let node_changes = message_def.field("nodeChanges")
+        .ok_or_else(|| FigmaError::SchemaError("Message has no nodeChanges field".into()))?;
+    let node_def = message_def_for(schema, node_changes)?;
+
+    let ordered = file.ordered_nodes();
+
+    let mut bb = ByteBufferMut::new();
+    bb.write_var_uint(node_changes.value);
+    bb.write_var_uint(ordered.len() as u32);
+    for node in ordered {
+        encode_node_change(schema, node_def, node, &mut bb)?;
+    }
 
-        Ok(Some(Field {
-            name,
-            field_type,
-            array_length,
-        }))
+    for field in &file.message_fields {
+        bb.write_var_uint(field.id);
+        bb.write_bytes(&field.data);
     }
+    bb.write_var_uint(0);
 
-    pub fn skip_field(&mut self, field: &Field) -> Result<()> {
-        for _ in 0..field.array_length {
-            match field.field_type {
-                0 => { self.read_bool()?; }
-                1 => { self.read_varint()?; } // byte
-                2 | 3 => { self.read_varint()?; } // int/uint
-                4 => { self.read_float()?; }
-                5 => { self.read_string()?; }
-                6 | 7 => { self.read_varint()?; self.read_varint()?; } // int64/uint64
-                _ => { self.skip_message()?; }
-            }
-        }
+    Ok(bb.data())
+}
+
+/// Encode a single node change message from the typed node model, followed
+/// by the fields the model doesn't cover.
+///
+/// A field is written if it was present when the node was decoded or if its
+/// value differs from the default, so untouched nodes keep the same fields.
+fn encode_node_change(schema: &Schema, def: &Def, node: &FigmaNode, bb: &mut ByteBufferMut) -> Result<()> {
+    let mut writer = NodeWriter { def, node, bb };
+
+    writer.field("guid", true, |bb| write_guid(bb, &node.id))?;
+    if let Some(parent_id) = &node.parent_id {
+        writer.field("parentIndex", true, |bb| {
+            write_guid(bb, parent_id)?;
+            bb.write_string(&node.parent_position);
+            Ok(())
+        })?;
+    }
+    writer.field("type", !node.node_type.is_empty(), |bb| write_enum(schema, def, "type", &node.node_type, bb))?;
+    writer.field("name", !node.name.is_empty(), |bb| { bb.write_string(&node.name); Ok(()) })?;
+    writer.field("visible", true, |bb| { bb.write_bool(node.visible); Ok(()) })?;
+    writer.field("opacity", true, |bb| { bb.write_var_float(node.opacity as f32); Ok(()) })?;
+    writer.enum_field(schema, "blendMode", &node.blend_mode)?;
+    writer.field("size", node.width != 0.0 || node.height != 0.0, |bb| {
+        bb.write_var_float(node.width as f32);
+        bb.write_var_float(node.height as f32);
         Ok(())
+    })?;
+    let transform = node_matrix(node);
+    writer.field("transform", !is_identity(&transform), |bb| { write_matrix(bb, &transform); Ok(()) })?;
+    writer.paints_field(schema, "fillPaints", &node.fill_paints, &node.fill_paints_data)?;
+    writer.paints_field(schema, "strokePaints", &node.stroke_paints, &node.stroke_paints_data)?;
+    writer.raw_field("effects", &node.effects_data);
+    writer.raw_field("vectorData", &node.vector_data);
+    writer.float_field("strokeWeight", node.stroke_weight)?;
+    writer.float_field("cornerRadius", node.corner_radius)?;
+    writer.float_field("rectangleTopLeftCornerRadius", node.corner_radii[0])?;
+    writer.float_field("rectangleTopRightCornerRadius", node.corner_radii[1])?;
+    writer.float_field("rectangleBottomRightCornerRadius", node.corner_radii[2])?;
+    writer.float_field("rectangleBottomLeftCornerRadius", node.corner_radii[3])?;
+
+    // Text properties
+    writer.raw_field("textData", &node.text_data);
+    let has_font = !node.font_name.is_empty() || !node.font_style.is_empty() || !node.font_postscript_name.is_empty();
+    writer.field("fontName", has_font, |bb| {
+        bb.write_string(&node.font_name);
+        bb.write_string(&node.font_style);
+        bb.write_string(&node.font_postscript_name);
+        Ok(())
+    })?;
+    writer.float_field("fontSize", node.font_size)?;
+
+    // Layout properties
+    writer.enum_field(schema, "stackMode", &node.layout_mode)?;
+    writer.enum_field(schema, "stackPrimarySizing", &node.primary_axis_sizing)?;
+    writer.enum_field(schema, "stackCounterSizing", &node.counter_axis_sizing)?;
+    writer.float_field("stackSpacing", node.item_spacing)?;
+    writer.float_field("stackHorizontalPadding", node.padding[0])?;
+    writer.float_field("stackVerticalPadding", node.padding[1])?;
+    writer.float_field("stackPaddingRight", node.padding[2])?;
+    writer.float_field("stackPaddingBottom", node.padding[3])?;
+
+    for field in &node.unknown_fields {
+        writer.bb.write_var_uint(field.id);
+        writer.bb.write_bytes(&field.data);
     }
 
-    fn skip_message(&mut self) -> Result<()> {
-        loop {
-            let field_index = self.read_varint()?;
-            if field_index == 0 {
-                break;
-            }
-            // Would need schema info to properly skip - for now just skip varint
-            self.read_varint()?;
+    writer.bb.write_var_uint(0);
+    Ok(())
+}
+
+/// Writes the fields of one `NodeChange`, skipping fields the schema lacks
+struct NodeWriter<'a> {
+    def: &'a Def,
+    node: &'a FigmaNode,
+    bb: &'a mut ByteBufferMut,
+}
+
+impl NodeWriter<'_> {
+    fn field(&mut self, name: &str, non_default: bool, write: impl FnOnce(&mut ByteBufferMut) -> Result<()>) -> Result<()> {
+        let Some(field) = self.def.field(name) else {
+            return Ok(());
+        };
+        if non_default || self.node.decoded_fields.contains(&field.value) {
+            self.bb.write_var_uint(field.value);
+            write(self.bb)?;
         }
         Ok(())
     }
 
-    // Primitive readers
-
-    pub fn read_bool(&mut self) -> Result<bool> {
-        let b = self.data.get(self.pos)
-            .ok_or_else(|| FigmaError::DecodeError("Unexpected end of data".into()))?;
-        self.pos += 1;
-        Ok(*b != 0)
+    fn float_field(&mut self, name: &str, value: f64) -> Result<()> {
+        self.field(name, value != 0.0, |bb| { bb.write_var_float(value as f32); Ok(()) })
     }
 
-    pub fn read_varint(&mut self) -> Result<u64> {
-        let mut result: u64 = 0;
-        let mut shift = 0;
-
-        loop {
-            let byte = *self.data.get(self.pos)
-                .ok_or_else(|| FigmaError::DecodeError("Unexpected end of data".into()))?;
-            self.pos += 1;
+    fn enum_field(&mut self, schema: &Schema, name: &str, value: &str) -> Result<()> {
+        let def = self.def;
+        self.field(name, !value.is_empty(), |bb| write_enum(schema, def, name, value, bb))
+    }
 
-            result |= ((byte & 0x7F) as u64) << shift;
-            if byte & 0x80 == 0 {
-                break;
+    /// Fields kept as encoded bytes are only present when they were decoded
+    fn raw_field(&mut self, name: &str, data: &[u8]) {
+        if let Some(field) = self.def.field(name) {
+            if !data.is_empty() {
+                self.bb.write_var_uint(field.value);
+                self.bb.write_bytes(data);
             }
-            shift += 7;
         }
-
-        Ok(result)
     }
 
-    pub fn read_signed_varint(&mut self) -> Result<i64> {
-        let unsigned = self.read_varint()?;
-        // Zigzag decoding
-        Ok(((unsigned >> 1) as i64) ^ (-((unsigned & 1) as i64)))
+    /// Paints are written as decoded unless the typed list was edited, in
+    /// which case the edited paints are encoded over the decoded ones
+    fn paints_field(&mut self, schema: &Schema, name: &str, paints: &[Paint], data: &[u8]) -> Result<()> {
+        let Some(field) = self.def.field(name) else {
+            return Ok(());
+        };
+        let decoded = if data.is_empty() { KiwiValue::Array(Vec::new()) } else { decode_raw(schema, field, data)? };
+        match Paint::list_to_kiwi(paints, decoded.as_array()) {
+            Some(edited) => {
+                self.bb.write_var_uint(field.value);
+                encode_field(schema, self.bb, field, &KiwiValue::Array(edited), 0)
+            }
+            None => {
+                self.raw_field(name, data);
+                Ok(())
+            }
+        }
     }
+}
 
-    pub fn read_float(&mut self) -> Result<f32> {
-        // Kiwi float encoding: first byte indicates encoding type
-        let first = *self.data.get(self.pos)
-            .ok_or_else(|| FigmaError::DecodeError("Unexpected end of data".into()))?;
-        self.pos += 1;
-
-        if first == 0 {
-            // Zero value
-            return Ok(0.0);
-        }
+// =============================================================================
+// Schema-driven readers and writers
+// =============================================================================
 
-        // Read remaining 3 bytes
-        if self.pos + 3 > self.data.len() {
-            return Err(FigmaError::DecodeError("Unexpected end of data".into()));
-        }
+fn truncated() -> FigmaError {
+    FigmaError::DecodeError("Unexpected end of data".into())
+}
 
-        let mut bits = (first as u32) << 24
-            | (self.data[self.pos] as u32) << 16
-            | (self.data[self.pos + 1] as u32) << 8
-            | (self.data[self.pos + 2] as u32);
-        self.pos += 3;
+fn message_def<'a>(schema: &'a Schema, name: &str) -> Result<&'a Def> {
+    schema.def(name)
+        .filter(|def| def.kind == DefKind::Message)
+        .ok_or_else(|| FigmaError::SchemaError(format!("Missing message type: {}", name)))
+}
 
-        // Kiwi float bit rotation
-        bits = (bits << 23) | (bits >> 9);
+fn message_def_for<'a>(schema: &'a Schema, field: &SchemaField) -> Result<&'a Def> {
+    message_def(schema, type_name(schema, field))
+}
 
-        Ok(f32::from_bits(bits))
+/// Name of a field's type, either a built-in type or a schema definition
+fn type_name<'a>(schema: &'a Schema, field: &SchemaField) -> &'a str {
+    match field.type_id {
+        -1 => "bool",
+        -2 => "byte",
+        -3 => "int",
+        -4 => "uint",
+        -5 => "float",
+        -6 => "string",
+        -7 => "int64",
+        -8 => "uint64",
+        id => schema.defs.get(id as usize).map(|def| def.name.as_str()).unwrap_or(""),
     }
+}
 
-    pub fn read_string(&mut self) -> Result<String> {
-        let mut end = self.pos;
-        while end < self.data.len() && self.data[end] != 0 {
-            end += 1;
-        }
+fn is_enum(schema: &Schema, field: &SchemaField) -> bool {
+    field.type_id >= 0 && schema.defs.get(field.type_id as usize).is_some_and(|def| def.kind == DefKind::Enum)
+}
 
-        let s = String::from_utf8_lossy(&self.data[self.pos..end]).to_string();
-        self.pos = end + 1; // Skip null terminator
+/// Read the next field id of a message, returning None at the end marker
+fn next_field<'a>(def: &'a Def, bb: &mut ByteBuffer) -> Result<Option<&'a SchemaField>> {
+    let value = bb.read_var_uint().map_err(|_| truncated())?;
+    if value == 0 {
+        return Ok(None);
+    }
+    def.field_value_to_index.get(&value)
+        .map(|&index| Some(&def.fields[index]))
+        .ok_or_else(|| FigmaError::DecodeError(format!("Unknown field {} in {}", value, def.name)))
+}
+
+/// Read a field's value as the raw encoded bytes
+fn read_raw(schema: &Schema, bb: &mut ByteBuffer, field: &SchemaField) -> Result<Vec<u8>> {
+    let start = bb.index();
+    skip_field(schema, bb, field, 0)?;
+    Ok(bb.data()[start..bb.index()].to_vec())
+}
 
-        Ok(s)
+/// Read an array length, rejecting lengths that can't fit in the remaining
+/// data so a corrupt count can't trigger a huge loop
+fn read_count(bb: &mut ByteBuffer) -> Result<u32> {
+    let count = read_uint(bb)?;
+    if count as usize > bb.data().len() - bb.index() {
+        return Err(truncated());
     }
+    Ok(count)
+}
 
-    pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
-        let len = self.read_varint()? as usize;
-        if self.pos + len > self.data.len() {
-            return Err(FigmaError::DecodeError("Unexpected end of data".into()));
+/// Skip over a field's value. Unlike `Schema::skip_field` this limits
+/// nesting depth and accepts enum values the schema doesn't list.
+fn skip_field(schema: &Schema, bb: &mut ByteBuffer, field: &SchemaField, depth: usize) -> Result<()> {
+    if field.is_array {
+        let count = read_count(bb)?;
+        for _ in 0..count {
+            skip_value(schema, bb, field.type_id, depth)?;
         }
-        let bytes = self.data[self.pos..self.pos + len].to_vec();
-        self.pos += len;
-        Ok(bytes)
+        Ok(())
+    } else {
+        skip_value(schema, bb, field.type_id, depth)
     }
+}
 
-    pub fn read_guid(&mut self) -> Result<String> {
-        // GUID is two uint32 values
-        let session = self.read_varint()? as u32;
-        let local = self.read_varint()? as u32;
-        Ok(format!("{}:{}", session, local))
-    }
+fn skip_value(schema: &Schema, bb: &mut ByteBuffer, type_id: i32, depth: usize) -> Result<()> {
+    let skipped = match type_id {
+        TYPE_BOOL => bb.read_bool().map(|_| ()),
+        TYPE_BYTE => bb.read_byte().map(|_| ()),
+        TYPE_INT => bb.read_var_int().map(|_| ()),
+        TYPE_UINT => bb.read_var_uint().map(|_| ()),
+        TYPE_FLOAT => bb.read_var_float().map(|_| ()),
+        TYPE_STRING => bb.read_string().map(|_| ()),
+        TYPE_INT64 => bb.read_var_int64().map(|_| ()),
+        TYPE_UINT64 => bb.read_var_uint64().map(|_| ()),
+        _ => {
+            let def = schema.defs.get(type_id as usize)
+                .ok_or_else(|| FigmaError::SchemaError(format!("Invalid type id: {}", type_id)))?;
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(FigmaError::DecodeError(format!("{} is nested too deeply", def.name)));
+            }
+            match def.kind {
+                DefKind::Enum => bb.read_var_uint().map(|_| ()),
+                DefKind::Struct => {
+                    for field in &def.fields {
+                        skip_field(schema, bb, field, depth + 1)?;
+                    }
+                    Ok(())
+                }
+                DefKind::Message => {
+                    while let Some(field) = next_field(def, bb)? {
+                        skip_field(schema, bb, field, depth + 1)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    };
+    skipped.map_err(|_| truncated())
+}
 
-    pub fn read_parent_index(&mut self) -> Result<(Option<String>, String)> {
-        let guid = self.read_guid()?;
-        let position = self.read_string()?;
-        Ok((Some(guid), position))
-    }
+fn read_bool(bb: &mut ByteBuffer) -> Result<bool> {
+    bb.read_bool().map_err(|_| truncated())
+}
 
-    pub fn read_node_type(&mut self) -> Result<String> {
-        let type_id = self.read_varint()? as u32;
-        Ok(node_type_name(type_id))
-    }
+fn read_uint(bb: &mut ByteBuffer) -> Result<u32> {
+    bb.read_var_uint().map_err(|_| truncated())
+}
 
-    pub fn read_enum(&mut self) -> Result<String> {
-        let val = self.read_varint()? as u32;
-        Ok(format!("{}", val))
-    }
+fn read_float(bb: &mut ByteBuffer) -> Result<f64> {
+    bb.read_var_float().map(|f| f as f64).map_err(|_| truncated())
+}
 
-    pub fn read_transform(&mut self) -> Result<Transform> {
-        Ok(Transform {
-            m00: self.read_float()? as f64,
-            m01: self.read_float()? as f64,
-            m10: self.read_float()? as f64,
-            m11: self.read_float()? as f64,
-            tx: self.read_float()? as f64,
-            ty: self.read_float()? as f64,
-        })
-    }
+fn read_string(bb: &mut ByteBuffer) -> Result<String> {
+    bb.read_string().map(|s| s.into_owned()).map_err(|_| truncated())
+}
 
-    pub fn read_size(&mut self) -> Result<(f64, f64)> {
-        Ok((
-            self.read_float()? as f64,
-            self.read_float()? as f64,
-        ))
-    }
+/// GUID is two uint32 values, formatted as "session:local"
+fn read_guid(bb: &mut ByteBuffer) -> Result<String> {
+    let session = read_uint(bb)?;
+    let local = read_uint(bb)?;
+    Ok(format!("{}:{}", session, local))
+}
+
+fn write_guid(bb: &mut ByteBufferMut, id: &str) -> Result<()> {
+    let parsed = id.split_once(':')
+        .and_then(|(session, local)| Some((session.parse().ok()?, local.parse().ok()?)));
+    let (session, local) = parsed
+        .ok_or_else(|| FigmaError::EncodeError(format!("Invalid node id: {}", id)))?;
+    bb.write_var_uint(session);
+    bb.write_var_uint(local);
+    Ok(())
+}
+
+/// Read an enum value as its name in the schema
+fn read_enum(schema: &Schema, field: &SchemaField, bb: &mut ByteBuffer) -> Result<String> {
+    let value = read_uint(bb)?;
+    let def = &schema.defs[field.type_id as usize];
+    def.field_value_to_index.get(&value)
+        .map(|&index| def.fields[index].name.clone())
+        .ok_or_else(|| FigmaError::DecodeError(format!("Invalid {} value: {}", def.name, value)))
 }
 
-pub struct Transform {
-    pub m00: f64,
-    pub m01: f64,
-    pub m10: f64,
-    pub m11: f64,
-    pub tx: f64,
-    pub ty: f64,
+fn write_enum(schema: &Schema, def: &Def, field_name: &str, name: &str, bb: &mut ByteBufferMut) -> Result<()> {
+    let enum_def = def.field(field_name)
+        .and_then(|field| schema.defs.get(field.type_id as usize))
+        .ok_or_else(|| FigmaError::SchemaError(format!("Missing enum for field {}", field_name)))?;
+    let value = enum_def.field(name)
+        .ok_or_else(|| FigmaError::EncodeError(format!("Invalid {} value: {}", enum_def.name, name)))?;
+    bb.write_var_uint(value.value);
+    Ok(())
 }
 
-impl Transform {
-    pub fn rotation(&self) -> f64 {
-        self.m01.atan2(self.m00).to_degrees()
+fn read_matrix(bb: &mut ByteBuffer) -> Result<TransformInfo> {
+    Ok(TransformInfo {
+        m00: read_float(bb)?,
+        m01: read_float(bb)?,
+        m02: read_float(bb)?,
+        m10: read_float(bb)?,
+        m11: read_float(bb)?,
+        m12: read_float(bb)?,
+    })
+}
+
+fn write_matrix(bb: &mut ByteBufferMut, t: &TransformInfo) {
+    for value in [t.m00, t.m01, t.m02, t.m10, t.m11, t.m12] {
+        bb.write_var_float(value as f32);
     }
 }
 
-/// Parse Kiwi schema binary
-fn parse_schema(data: &[u8]) -> Result<Vec<FieldDef>> {
-    // Schema format: definitions of enums, structs, messages
-    // For now, return empty - real impl would parse schema binary
-    Ok(vec![])
+fn is_identity(t: &TransformInfo) -> bool {
+    t.m00 == 1.0 && t.m01 == 0.0 && t.m02 == 0.0 && t.m10 == 0.0 && t.m11 == 1.0 && t.m12 == 0.0
 }
 
-/// Convert node type ID to name
-fn node_type_name(id: u32) -> String {
-    match id {
-        0 => "DOCUMENT",
-        1 => "CANVAS",
-        2 => "FRAME",
-        3 => "GROUP",
-        4 => "VECTOR",
-        5 => "BOOLEAN_OPERATION",
-        6 => "STAR",
-        7 => "LINE",
-        8 => "ELLIPSE",
-        9 => "REGULAR_POLYGON",
-        10 => "RECTANGLE",
-        11 => "TEXT",
-        12 => "SLICE",
-        13 => "COMPONENT",
-        14 => "COMPONENT_SET",
-        15 => "INSTANCE",
-        16 => "STICKY",
-        17 => "SHAPE_WITH_TEXT",
-        18 => "CONNECTOR",
-        19 => "SECTION",
-        _ => "UNKNOWN",
-    }.to_string()
+/// Rotation in degrees of a Figma transform matrix
+pub(crate) fn matrix_rotation(t: &TransformInfo) -> f64 {
+    t.m01.atan2(t.m00).to_degrees()
+}
+
+/// The node's transform with `x`, `y` and `rotation` applied, so edits to
+/// those fields are written while scale and skew are preserved.
+pub(crate) fn node_matrix(node: &FigmaNode) -> TransformInfo {
+    let mut t = node.transform.clone();
+    t.m02 = node.x;
+    t.m12 = node.y;
+
+    let delta = (node.rotation - matrix_rotation(&t)).to_radians();
+    if delta.abs() > 1e-6 {
+        let (s, c) = delta.sin_cos();
+        let (m00, m01, m10, m11) = (t.m00, t.m01, t.m10, t.m11);
+        t.m00 = c * m00 + s * m10;
+        t.m01 = c * m01 + s * m11;
+        t.m10 = c * m10 - s * m00;
+        t.m11 = c * m11 - s * m01;
+    }
+    t
 }
 
 // =============================================================================
-// Paint/Effect/Vector decoders (match Figma's JsKiwiSerialization_*)
+// Generic value decoding
 // =============================================================================
 
-/// Decode fill paint data (matches JsKiwiSerialization_decodeFillPaintData)
-pub fn decode_fill_paint_data(data: &[u8]) -> Result<Vec<PaintInfo>> {
-    let mut paints = Vec::new();
+/// A kiwi value decoded without a Rust type for it, used for the nested
+/// messages (paints, text, symbol overrides...) that typed models are built from
+#[derive(Debug, Clone, PartialEq)]
+pub enum KiwiValue {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(String),
+    /// The name of an enum value, or its number if the schema doesn't list it
+    Enum(String),
+    /// A `byte[]` field
+    Bytes(Vec<u8>),
+    /// A struct or message
+    Message(KiwiMessage),
+    Array(Vec<KiwiValue>),
+}
 
-    if data.is_empty() {
-        return Ok(paints);
-    }
+/// The fields of a decoded struct or message, in encoded order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KiwiMessage {
+    pub fields: Vec<(String, KiwiValue)>,
+}
 
-    let mut pos = 0;
+impl KiwiValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            KiwiValue::Float(v) => Some(v),
+            KiwiValue::Int(v) => Some(v as f64),
+            KiwiValue::Uint(v) => Some(v as f64),
+            _ => None,
+        }
+    }
 
-    // Read paint count
-    let count = read_varint_at(data, &mut pos)? as usize;
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            KiwiValue::Uint(v) => Some(v),
+            KiwiValue::Int(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
 
-    for _ in 0..count {
-        let paint_type = read_varint_at(data, &mut pos)? as u8;
-
-        let mut paint = PaintInfo {
-            paint_type: paint_type_name(paint_type),
-            color: None,
-            gradient_stops: vec![],
-            opacity: 1.0,
-            blend_mode: "NORMAL".to_string(),
-        };
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            KiwiValue::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
 
-        // Read based on paint type
-        match paint_type {
-            0 => {
-                // Solid color
-                let r = data.get(pos).copied().unwrap_or(0);
-                let g = data.get(pos + 1).copied().unwrap_or(0);
-                let b = data.get(pos + 2).copied().unwrap_or(0);
-                let a = data.get(pos + 3).copied().unwrap_or(255);
-                pos += 4;
-
-                paint.color = Some(ColorInfo { r, g, b, a });
-            }
-            1..=4 => {
-                // Gradient (linear, radial, angular, diamond)
-                let stop_count = read_varint_at(data, &mut pos)? as usize;
-                for _ in 0..stop_count {
-                    let position = read_float_at(data, &mut pos)?;
-                    let r = data.get(pos).copied().unwrap_or(0);
-                    let g = data.get(pos + 1).copied().unwrap_or(0);
-                    let b = data.get(pos + 2).copied().unwrap_or(0);
-                    let a = data.get(pos + 3).copied().unwrap_or(255);
-                    pos += 4;
-
-                    paint.gradient_stops.push(GradientStopInfo {
-                        position: position as f64,
-                        color: ColorInfo { r, g, b, a },
-                    });
-                }
-            }
-            5 => {
-                // Image paint - read image ref
-                let _image_ref = read_string_at(data, &mut pos)?;
-            }
-            _ => {}
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KiwiValue::String(v) | KiwiValue::Enum(v) => Some(v),
+            _ => None,
         }
+    }
 
-        // Read opacity
-        paint.opacity = read_float_at(data, &mut pos)? as f64;
+    pub fn as_message(&self) -> Option<&KiwiMessage> {
+        match self {
+            KiwiValue::Message(v) => Some(v),
+            _ => None,
+        }
+    }
 
-        // Read blend mode
-        let blend = read_varint_at(data, &mut pos)? as u8;
-        paint.blend_mode = blend_mode_name(blend);
+    pub fn as_array(&self) -> &[KiwiValue] {
+        match self {
+            KiwiValue::Array(v) => v,
+            _ => &[],
+        }
+    }
 
-        paints.push(paint);
+    /// A struct or message with the given fields
+    pub fn message(fields: Vec<(&str, KiwiValue)>) -> KiwiValue {
+        KiwiValue::Message(KiwiMessage {
+            fields: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        })
     }
 
-    Ok(paints)
+    /// A `Matrix` value
+    pub fn matrix(t: &TransformInfo) -> KiwiValue {
+        KiwiValue::message(vec![
+            ("m00", KiwiValue::Float(t.m00)),
+            ("m01", KiwiValue::Float(t.m01)),
+            ("m02", KiwiValue::Float(t.m02)),
+            ("m10", KiwiValue::Float(t.m10)),
+            ("m11", KiwiValue::Float(t.m11)),
+            ("m12", KiwiValue::Float(t.m12)),
+        ])
+    }
 }
 
-/// Decode effect data (matches JsKiwiSerialization_decodeEffectData)
-pub fn decode_effect_data(data: &[u8]) -> Result<Vec<EffectInfo>> {
-    let mut effects = Vec::new();
+impl KiwiMessage {
+    pub fn get(&self, name: &str) -> Option<&KiwiValue> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
 
-    if data.is_empty() {
-        return Ok(effects);
+    /// Replace a field's value, or add the field after the others
+    pub fn set(&mut self, name: &str, value: KiwiValue) {
+        match self.fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, old)) => *old = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
     }
 
-    let mut pos = 0;
-    let count = read_varint_at(data, &mut pos)? as usize;
+    pub fn remove(&mut self, name: &str) {
+        self.fields.retain(|(field, _)| field != name);
+    }
 
-    for _ in 0..count {
-        let effect_type = read_varint_at(data, &mut pos)? as u8;
-        let visible = data.get(pos).copied().unwrap_or(1) != 0;
-        pos += 1;
+    pub fn float(&self, name: &str) -> Option<f64> {
+        self.get(name)?.as_f64()
+    }
+
+    pub fn uint(&self, name: &str) -> Option<u64> {
+        self.get(name)?.as_u64()
+    }
 
-        let radius = read_float_at(data, &mut pos)? as f64;
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.as_bool()
+    }
 
-        let mut effect = EffectInfo {
-            effect_type: effect_type_name(effect_type),
-            visible,
-            radius,
-            color: None,
-            offset_x: 0.0,
-            offset_y: 0.0,
-            spread: 0.0,
-        };
+    /// A string or enum field
+    pub fn str(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_str()
+    }
+
+    pub fn message(&self, name: &str) -> Option<&KiwiMessage> {
+        self.get(name)?.as_message()
+    }
 
-        // Shadow-specific fields
-        if effect_type == 0 || effect_type == 1 {
-            let r = data.get(pos).copied().unwrap_or(0);
-            let g = data.get(pos + 1).copied().unwrap_or(0);
-            let b = data.get(pos + 2).copied().unwrap_or(0);
-            let a = data.get(pos + 3).copied().unwrap_or(255);
-            pos += 4;
+    /// An array field, empty if absent
+    pub fn array(&self, name: &str) -> &[KiwiValue] {
+        self.get(name).map(KiwiValue::as_array).unwrap_or(&[])
+    }
 
-            effect.color = Some(ColorInfo { r, g, b, a });
-            effect.offset_x = read_float_at(data, &mut pos)? as f64;
-            effect.offset_y = read_float_at(data, &mut pos)? as f64;
-            effect.spread = read_float_at(data, &mut pos)? as f64;
+    pub fn bytes(&self, name: &str) -> Option<&[u8]> {
+        match self.get(name)? {
+            KiwiValue::Bytes(v) => Some(v),
+            _ => None,
         }
+    }
+
+    /// A `GUID` field formatted like node ids
+    pub fn guid(&self, name: &str) -> Option<String> {
+        let guid = self.message(name)?;
+        Some(format!("{}:{}", guid.uint("sessionID")?, guid.uint("localID")?))
+    }
 
-        effects.push(effect);
+    /// A `Matrix` field
+    pub fn matrix(&self, name: &str) -> Option<TransformInfo> {
+        let m = self.message(name)?;
+        Some(TransformInfo {
+            m00: m.float("m00")?,
+            m01: m.float("m01")?,
+            m02: m.float("m02")?,
+            m10: m.float("m10")?,
+            m11: m.float("m11")?,
+            m12: m.float("m12")?,
+        })
     }
+}
 
-    Ok(effects)
+/// Decode a field value that was kept as raw bytes by `read_raw`
+pub(crate) fn decode_raw(schema: &Schema, field: &SchemaField, data: &[u8]) -> Result<KiwiValue> {
+    decode_field(schema, &mut ByteBuffer::new(data), field, 0)
+}
+
+fn decode_field(schema: &Schema, bb: &mut ByteBuffer, field: &SchemaField, depth: usize) -> Result<KiwiValue> {
+    if !field.is_array {
+        return decode_value(schema, bb, field.type_id, depth);
+    }
+    let count = read_count(bb)?;
+    if field.type_id == TYPE_BYTE {
+        let start = bb.index();
+        for _ in 0..count {
+            bb.read_byte().map_err(|_| truncated())?;
+        }
+        return Ok(KiwiValue::Bytes(bb.data()[start..bb.index()].to_vec()));
+    }
+    let mut values = Vec::new();
+    for _ in 0..count {
+        values.push(decode_value(schema, bb, field.type_id, depth)?);
+    }
+    Ok(KiwiValue::Array(values))
 }
 
+fn decode_value(schema: &Schema, bb: &mut ByteBuffer, type_id: i32, depth: usize) -> Result<KiwiValue> {
+    let value = match type_id {
+        TYPE_BOOL => bb.read_bool().map(KiwiValue::Bool),
+        TYPE_BYTE => bb.read_byte().map(|v| KiwiValue::Uint(v as u64)),
+        TYPE_INT => bb.read_var_int().map(|v| KiwiValue::Int(v as i64)),
+        TYPE_UINT => bb.read_var_uint().map(|v| KiwiValue::Uint(v as u64)),
+        TYPE_FLOAT => bb.read_var_float().map(|v| KiwiValue::Float(v as f64)),
+        TYPE_STRING => bb.read_string().map(|v| KiwiValue::String(v.into_owned())),
+        TYPE_INT64 => bb.read_var_int64().map(KiwiValue::Int),
+        TYPE_UINT64 => bb.read_var_uint64().map(KiwiValue::Uint),
+        _ => {
+            let def = schema.defs.get(type_id as usize)
+                .ok_or_else(|| FigmaError::SchemaError(format!("Invalid type id: {}", type_id)))?;
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(FigmaError::DecodeError(format!("{} is nested too deeply", def.name)));
+            }
+            let mut message = KiwiMessage::default();
+            match def.kind {
+                DefKind::Enum => {
+                    let value = read_uint(bb)?;
+                    let name = def.field_value_to_index.get(&value)
+                        .map(|&index| def.fields[index].name.clone())
+                        .unwrap_or_else(|| value.to_string());
+                    return Ok(KiwiValue::Enum(name));
+                }
+                DefKind::Struct => {
+                    for field in &def.fields {
+                        message.fields.push((field.name.clone(), decode_field(schema, bb, field, depth + 1)?));
+                    }
+                }
+                DefKind::Message => {
+                    while let Some(field) = next_field(def, bb)? {
+                        message.fields.push((field.name.clone(), decode_field(schema, bb, field, depth + 1)?));
+                    }
+                }
+            }
+            return Ok(KiwiValue::Message(message));
+        }
+    };
+    value.map_err(|_| truncated())
+}
+
+/// Encode a value of a field's type, the inverse of `decode_field`.
+/// Message fields the schema doesn't list are left out.
+fn encode_field(schema: &Schema, bb: &mut ByteBufferMut, field: &SchemaField, value: &KiwiValue, depth: usize) -> Result<()> {
+    if !field.is_array {
+        return encode_value(schema, bb, field.type_id, value, depth);
+    }
+    if let KiwiValue::Bytes(bytes) = value {
+        bb.write_var_uint(bytes.len() as u32);
+        bb.write_bytes(bytes);
+        return Ok(());
+    }
+    let values = value.as_array();
+    bb.write_var_uint(values.len() as u32);
+    for value in values {
+        encode_value(schema, bb, field.type_id, value, depth)?;
+    }
+    Ok(())
+}
+
+fn encode_value(schema: &Schema, bb: &mut ByteBufferMut, type_id: i32, value: &KiwiValue, depth: usize) -> Result<()> {
+    let invalid = || FigmaError::EncodeError(format!("Invalid value for type {}: {:?}", type_id, value));
+    let int = || match *value {
+        KiwiValue::Int(v) => Some(v),
+        KiwiValue::Uint(v) => i64::try_from(v).ok(),
+        _ => None,
+    };
+    match type_id {
+        TYPE_BOOL => bb.write_bool(value.as_bool().ok_or_else(invalid)?),
+        TYPE_BYTE => bb.write_byte(value.as_u64().ok_or_else(invalid)? as u8),
+        TYPE_INT => bb.write_var_int(int().ok_or_else(invalid)? as i32),
+        TYPE_UINT => bb.write_var_uint(value.as_u64().ok_or_else(invalid)? as u32),
+        TYPE_FLOAT => bb.write_var_float(value.as_f64().ok_or_else(invalid)? as f32),
+        TYPE_STRING => bb.write_string(value.as_str().ok_or_else(invalid)?),
+        TYPE_INT64 => bb.write_var_int64(int().ok_or_else(invalid)?),
+        TYPE_UINT64 => bb.write_var_uint64(value.as_u64().ok_or_else(invalid)?),
+        _ => {
+            let def = schema.defs.get(type_id as usize)
+                .ok_or_else(|| FigmaError::SchemaError(format!("Invalid type id: {}", type_id)))?;
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(FigmaError::EncodeError(format!("{} is nested too deeply", def.name)));
+            }
+            match def.kind {
+                DefKind::Enum => {
+                    let name = value.as_str().ok_or_else(invalid)?;
+                    let number = match def.field(name) {
+                        Some(field) => field.value,
+                        None => name.parse().map_err(|_| FigmaError::EncodeError(format!("Invalid {} value: {}", def.name, name)))?,
+                    };
+                    bb.write_var_uint(number);
+                }
+                DefKind::Struct => {
+                    let message = value.as_message().ok_or_else(invalid)?;
+                    for field in &def.fields {
+                        let value = message.get(&field.name)
+                            .ok_or_else(|| FigmaError::EncodeError(format!("Missing field: {}.{}", def.name, field.name)))?;
+                        encode_field(schema, bb, field, value, depth + 1)?;
+                    }
+                }
+                DefKind::Message => {
+                    let message = value.as_message().ok_or_else(invalid)?;
+                    for (name, value) in &message.fields {
+                        if let Some(field) = def.field(name) {
+                            bb.write_var_uint(field.value);
+                            encode_field(schema, bb, field, value, depth + 1)?;
+                        }
+                    }
+                    bb.write_var_uint(0);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Paint/Effect/Vector decoders (match Figma's JsKiwiSerialization_*)
+// =============================================================================
+
 /// Decode vector data (matches JsKiwiSerialization_decodeVectorData)
 pub fn decode_vector_data(data: &[u8]) -> Result<PathData> {
     if data.is_empty() {
@@ -688,25 +1558,6 @@ pub fn decode_vector_data(data: &[u8]) -> Result<PathData> {
 
 // Helper functions
 
-fn read_varint_at(data: &[u8], pos: &mut usize) -> Result<u64> {
-    let mut result: u64 = 0;
-    let mut shift = 0;
-
-    loop {
-        let byte = *data.get(*pos)
-            .ok_or_else(|| FigmaError::DecodeError("Unexpected end of data".into()))?;
-        *pos += 1;
-
-        result |= ((byte & 0x7F) as u64) << shift;
-        if byte & 0x80 == 0 {
-            break;
-        }
-        shift += 7;
-    }
-
-    Ok(result)
-}
-
 fn read_float_at(data: &[u8], pos: &mut usize) -> Result<f32> {
     let first = *data.get(*pos)
         .ok_or_else(|| FigmaError::DecodeError("Unexpected end of data".into()))?;
@@ -731,59 +1582,318 @@ fn read_float_at(data: &[u8], pos: &mut usize) -> Result<f32> {
     Ok(f32::from_bits(bits))
 }
 
-fn read_string_at(data: &[u8], pos: &mut usize) -> Result<String> {
-    let mut end = *pos;
-    while end < data.len() && data[end] != 0 {
-        end += 1;
-    }
-    let s = String::from_utf8_lossy(&data[*pos..end]).to_string();
-    *pos = end + 1;
-    Ok(s)
-}
-
-fn paint_type_name(id: u8) -> String {
-    match id {
-        0 => "solid",
-        1 => "gradient_linear",
-        2 => "gradient_radial",
-        3 => "gradient_angular",
-        4 => "gradient_diamond",
-        5 => "image",
-        _ => "unknown",
-    }.to_string()
-}
-
-fn effect_type_name(id: u8) -> String {
-    match id {
-        0 => "drop_shadow",
-        1 => "inner_shadow",
-        2 => "layer_blur",
-        3 => "background_blur",
-        _ => "unknown",
-    }.to_string()
-}
-
-fn blend_mode_name(id: u8) -> String {
-    match id {
-        0 => "PASS_THROUGH",
-        1 => "NORMAL",
-        2 => "DARKEN",
-        3 => "MULTIPLY",
-        4 => "LINEAR_BURN",
-        5 => "COLOR_BURN",
-        6 => "LIGHTEN",
-        7 => "SCREEN",
-        8 => "LINEAR_DODGE",
-        9 => "COLOR_DODGE",
-        10 => "OVERLAY",
-        11 => "SOFT_LIGHT",
-        12 => "HARD_LIGHT",
-        13 => "DIFFERENCE",
-        14 => "EXCLUSION",
-        15 => "HUE",
-        16 => "SATURATION",
-        17 => "COLOR",
-        18 => "LUMINOSITY",
-        _ => "NORMAL",
-    }.to_string()
+/// The Apple UI kit fixture for tests, read and parsed once. Tests that
+/// change the file clone it first.
+#[cfg(test)]
+pub(crate) mod test_fixture {
+    use super::{encode_field, message_def, ByteBufferMut, FigFile, KiwiValue, RawField};
+    use crate::nodes::FigmaNode;
+    use std::sync::OnceLock;
+
+    pub fn data() -> &'static [u8] {
+        static DATA: OnceLock<Vec<u8>> = OnceLock::new();
+        DATA.get_or_init(|| {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/fixtures/apple_ui_kit.fig");
+            std::fs::read(path).expect("missing test fixture")
+        })
+    }
+
+    /// The file as parsed
+    pub fn file() -> &'static FigFile {
+        static FILE: OnceLock<FigFile> = OnceLock::new();
+        FILE.get_or_init(|| FigFile::parse(data()).unwrap())
+    }
+
+    /// The file with its instances expanded
+    pub fn expanded() -> &'static FigFile {
+        static FILE: OnceLock<FigFile> = OnceLock::new();
+        FILE.get_or_init(|| {
+            let mut file = file().clone();
+            file.expand_instances();
+            file
+        })
+    }
+
+    /// A `NodeChange` field encoded as an override sets it
+    pub fn raw_field(name: &str, value: &KiwiValue) -> RawField {
+        let schema = &file().schema;
+        let field = message_def(schema, "NodeChange").unwrap().field(name).unwrap();
+        let mut bb = ByteBufferMut::new();
+        encode_field(schema, &mut bb, field, value, 0).unwrap();
+        RawField { id: field.value, data: bb.data() }
+    }
+
+    /// A fixture node with one `NodeChange` field set, for properties the
+    /// fixture doesn't use
+    pub fn with_field(id: &str, name: &str, value: &KiwiValue) -> FigmaNode {
+        let file = file();
+        file.apply_override(file.get_node(id).unwrap(), &[raw_field(name, value)]).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fixture() {
+        let file = test_fixture::file();
+        assert_eq!(file.version, 101);
+        assert!(!file.page_ids.is_empty());
+
+        let page = file.get_node(&file.page_ids[0]).unwrap();
+        assert_eq!(page.node_type, "CANVAS");
+        assert!(!page.children.is_empty());
+        for child_id in &page.children {
+            assert_eq!(file.nodes[child_id].parent_id.as_ref(), Some(&page.id));
+        }
+    }
+
+    fn container(version: u32, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut data = FIG_KIWI_HEADER.to_vec();
+        data.extend_from_slice(&version.to_le_bytes());
+        for chunk in chunks {
+            data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    #[test]
+    fn test_container_chunks() {
+        let data = container(101, &[b"schema", b"message", b"preview"]);
+        let container = FigContainer::parse(&data).unwrap();
+        assert_eq!(container.version, 101);
+        assert_eq!(container.schema_chunk(), b"schema");
+        assert_eq!(container.message_chunk(), b"message");
+        assert_eq!(container.extra_chunks(), &[&b"preview"[..]]);
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_container_thumbnail() {
+        let preview = png(320, 200);
+        let data = container(101, &[b"schema", b"message", &preview]);
+        assert_eq!(FigContainer::parse(&data).unwrap().thumbnail(), Some(&preview[..]));
+        assert_eq!(read_thumbnail(&data).unwrap(), Some(preview));
+
+        let data = container(101, &[b"schema", b"message", b"not an image"]);
+        assert_eq!(FigContainer::parse(&data).unwrap().thumbnail(), None);
+    }
+
+    #[test]
+    fn test_container_errors() {
+        assert!(matches!(FigContainer::parse(b"PK\x03\x04"), Err(FigmaError::InvalidHeader)));
+        assert!(matches!(FigContainer::parse(b"fig-k"), Err(FigmaError::TruncatedFile(_))));
+        assert!(matches!(FigContainer::parse(b"fig-kiwi\x65\x00"), Err(FigmaError::TruncatedFile(_))));
+        assert!(matches!(FigContainer::parse(&container(0, &[b"a", b"b"])), Err(FigmaError::UnsupportedVersion(0))));
+        // Newer versions than we've seen are read
+        assert!(FigContainer::parse(&container(MAX_FILE_VERSION + 1, &[b"a", b"b"])).is_ok());
+        assert!(matches!(FigContainer::parse(&container(101, &[])), Err(FigmaError::MissingChunk(_))));
+        assert!(matches!(FigContainer::parse(&container(101, &[b"a"])), Err(FigmaError::MissingChunk(_))));
+
+        // Trailing bytes too short to hold a chunk size
+        let mut data = container(101, &[b"a", b"b"]);
+        data.extend_from_slice(&[1, 0]);
+        assert!(matches!(FigContainer::parse(&data), Err(FigmaError::TruncatedFile(_))));
+
+        // A chunk claiming more bytes than the file holds
+        let mut data = container(101, &[b"a"]);
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(b"short");
+        assert!(matches!(
+            FigContainer::parse(&data),
+            Err(FigmaError::ChunkTooLarge { index: 1, size, remaining: 5 }) if size == u32::MAX as usize
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_fixture() {
+        let data = test_fixture::data();
+        for len in [0, 4, 11, 12, 16, 100, data.len() / 2, data.len() - 1] {
+            assert!(FigFile::parse(&data[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let file = test_fixture::file();
+        let written = file.write().unwrap();
+        let reparsed = FigFile::parse(&written).unwrap();
+
+        assert_eq!(reparsed.version, file.version);
+        assert_eq!(reparsed.node_order, file.node_order);
+        assert_eq!(reparsed.page_ids, file.page_ids);
+        assert_eq!(reparsed.message_fields, file.message_fields);
+        assert_eq!(reparsed.extra_chunks, file.extra_chunks);
+        for (id, node) in &file.nodes {
+            let other = &reparsed.nodes[id];
+            assert_eq!(other.name, node.name);
+            assert_eq!(other.node_type, node.node_type);
+            assert_eq!((other.x, other.y, other.width, other.height), (node.x, node.y, node.width, node.height));
+            assert_eq!(other.children, node.children);
+            assert_eq!(other.fill_paints_data, node.fill_paints_data);
+            let mut fields = other.decoded_fields.clone();
+            fields.sort();
+            let mut expected = node.decoded_fields.clone();
+            expected.sort();
+            assert_eq!(fields, expected);
+        }
+
+        // Writing an unmodified file is stable
+        assert_eq!(encode_figma_message(&reparsed).unwrap(), encode_figma_message(file).unwrap());
+    }
+
+    /// Split every NodeChange in a message into its raw fields, sorted by id
+    fn node_change_fields(file: &FigFile, message: &[u8]) -> Vec<Vec<RawField>> {
+        let schema = &file.schema;
+        let message_def = message_def(schema, "Message").unwrap();
+        let node_def = schema.def("NodeChange").unwrap();
+        let mut bb = ByteBuffer::new(message);
+        let mut result = Vec::new();
+
+        while let Some(field) = next_field(message_def, &mut bb).unwrap() {
+            if field.name != "nodeChanges" {
+                schema.skip_field(&mut bb, field).unwrap();
+                continue;
+            }
+            for _ in 0..bb.read_var_uint().unwrap() {
+                let mut fields = Vec::new();
+                while let Some(field) = next_field(node_def, &mut bb).unwrap() {
+                    fields.push(RawField { id: field.value, data: read_raw(schema, &mut bb, field).unwrap() });
+                }
+                fields.sort_by_key(|field| field.id);
+                result.push(fields);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_write_is_lossless() {
+        let data = test_fixture::data();
+        let file = FigFile::parse(data).unwrap();
+        assert!(file.nodes.values().any(|node| !node.unknown_fields.is_empty()));
+
+        let container = FigContainer::parse(data).unwrap();
+        let original = decompress_zstd(container.message_chunk()).unwrap();
+        let written = encode_figma_message(&file).unwrap();
+
+        // Every field of every node survives, byte for byte
+        assert_eq!(node_change_fields(&file, &written), node_change_fields(&file, &original));
+    }
+
+    #[test]
+    fn test_thumbnail_round_trip() {
+        let mut file = test_fixture::file().clone();
+        assert_eq!(file.thumbnail(), None);
+
+        let preview = png(64, 64);
+        file.extra_chunks.push(preview.clone());
+        let data = file.write().unwrap();
+        assert_eq!(read_thumbnail(&data).unwrap(), Some(preview.clone()));
+        assert_eq!(FigFile::parse(&data).unwrap().thumbnail(), Some(&preview[..]));
+    }
+
+    #[test]
+    fn test_write_edits() {
+        use crate::api::ColorInfo;
+        use crate::paints::SolidPaint;
+
+        let mut file = test_fixture::file().clone();
+        let id = file.get_pages()[0].children[0].clone();
+        {
+            let node = file.get_node_mut(&id).unwrap();
+            node.name = "Renamed".to_string();
+            node.x += 10.0;
+            node.rotation = 90.0;
+        }
+
+        // Swap a solid fill's color and add a stroke
+        let red = ColorInfo { r: 255, g: 0, b: 0, a: 255 };
+        let filled = file.ordered_nodes().into_iter()
+            .find(|node| matches!(node.fill_paints.as_slice(), [Paint::Solid(fill)] if fill.color_variable.is_none()) && node.stroke_paints.is_empty())
+            .map(|node| node.id.clone())
+            .unwrap();
+        {
+            let node = file.get_node_mut(&filled).unwrap();
+            let Paint::Solid(fill) = &mut node.fill_paints[0] else { unreachable!() };
+            fill.color = red.clone();
+            fill.opacity = 0.5;
+            node.stroke_paints.push(Paint::Solid(SolidPaint { color: red.clone(), visible: true, opacity: 1.0, blend_mode: Default::default(), color_variable: None }));
+        }
+
+        let reparsed = FigFile::parse(&file.write().unwrap()).unwrap();
+        let node = reparsed.get_node(&id).unwrap();
+        assert_eq!(node.name, "Renamed");
+        assert_eq!(node.x, file.nodes[&id].x);
+        assert!((node.rotation - 90.0).abs() < 1e-3);
+
+        let node = reparsed.get_node(&filled).unwrap();
+        let [Paint::Solid(fill)] = node.fill_paints.as_slice() else { panic!("{:?}", node.fill_paints) };
+        assert_eq!((&fill.color, fill.opacity), (&red, 0.5));
+        // keeping the fields the model doesn't cover
+        let field = message_def(&file.schema, "NodeChange").unwrap().field("fillPaints").unwrap();
+        let field_names = |data: &[u8]| -> Vec<String> {
+            let paints = decode_raw(&file.schema, field, data).unwrap();
+            paints.as_array()[0].as_message().unwrap().fields.iter().map(|(name, _)| name.clone()).collect()
+        };
+        let written = field_names(&node.fill_paints_data);
+        assert!(field_names(&file.nodes[&filled].fill_paints_data).iter().all(|name| written.contains(name)));
+        assert_eq!(node.stroke_paints, file.nodes[&filled].stroke_paints);
+        // Unedited paints are written as they were read
+        let untouched = file.ordered_nodes().into_iter().find(|node| node.id != filled && !node.fill_paints_data.is_empty()).unwrap();
+        assert_eq!(reparsed.nodes[&untouched.id].fill_paints_data, untouched.fill_paints_data);
+    }
+
+    #[test]
+    fn test_write_hidden_node() {
+        // A new node has no decoded fields, so hidden and transparent must
+        // be written explicitly rather than left to the reader's defaults
+        let mut file = test_fixture::file().clone();
+        let page = file.get_pages()[0].id.clone();
+        let hidden = FigmaNode {
+            id: "1:900001".into(),
+            parent_id: Some(page.clone()),
+            parent_position: "~".into(),
+            name: "Hidden".into(),
+            node_type: "RECTANGLE".into(),
+            visible: false,
+            opacity: 0.0,
+            width: 10.0,
+            height: 10.0,
+            ..Default::default()
+        };
+        file.nodes.insert(hidden.id.clone(), hidden);
+        file.get_node_mut(&page).unwrap().children.push("1:900001".into());
+
+        let reparsed = FigFile::parse(&file.write().unwrap()).unwrap();
+        let node = reparsed.get_node("1:900001").unwrap();
+        assert_eq!((node.visible, node.opacity), (false, 0.0));
+        let def = message_def(&reparsed.schema, "NodeChange").unwrap();
+        for name in ["visible", "opacity"] {
+            let field = def.fields.iter().find(|field| field.name == name).unwrap();
+            assert!(node.decoded_fields.contains(&field.value), "{name} wasn't written");
+        }
+    }
+
+    #[test]
+    fn test_node_matrix_preserves_scale() {
+        let node = FigmaNode {
+            transform: TransformInfo { m00: 2.0, m01: 0.0, m02: 0.0, m10: 0.0, m11: 2.0, m12: 0.0 },
+            x: 5.0,
+            rotation: 90.0,
+            ..Default::default()
+        };
+        let t = node_matrix(&node);
+        assert_eq!(t.m02, 5.0);
+        assert!((t.m00).abs() < 1e-9 && (t.m01 - 2.0).abs() < 1e-9);
+        assert!((t.m10 + 2.0).abs() < 1e-9 && (t.m11).abs() < 1e-9);
+    }
 }
@@ -1,5 +1,9 @@
 //! Layout calculations (auto-layout, constraints)
 
+use crate::api::LayoutResult;
+use crate::kiwi::FigFile;
+use crate::{FigmaError, Result};
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum LayoutMode {
     #[default]
@@ -101,3 +105,84 @@ pub fn calculate_auto_layout(
     
     results
 }
+
+/// The boxes of a node and its visible descendants, each relative to its
+/// parent, with parents before their children. Figma lays out auto layout
+/// frames when it saves a file, so these are the boxes the file records.
+pub fn layout_boxes(file: &FigFile, root_id: &str) -> Result<Vec<LayoutResult>> {
+    let root = file.get_node(root_id).ok_or_else(|| FigmaError::NodeNotFound(root_id.to_string()))?;
+    let mut results = Vec::new();
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        results.push(LayoutResult {
+            node_id: node.id.clone(),
+            x: node.x,
+            y: node.y,
+            width: node.width,
+            height: node.height,
+        });
+        pending.extend(node.children.iter().rev().filter_map(|id| file.get_node(id)).filter(|child| child.visible));
+    }
+    Ok(results)
+}
+
+/// How a child moves and resizes when its parent is resized, along one axis
+/// (schema `ConstraintType`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Constraint {
+    #[default]
+    Min,
+    Center,
+    Max,
+    Stretch,
+    Scale,
+}
+
+impl Constraint {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "CENTER" => Constraint::Center,
+            "MAX" => Constraint::Max,
+            "STRETCH" => Constraint::Stretch,
+            "SCALE" => Constraint::Scale,
+            _ => Constraint::Min,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Constraint::Min => "MIN",
+            Constraint::Center => "CENTER",
+            Constraint::Max => "MAX",
+            Constraint::Stretch => "STRETCH",
+            Constraint::Scale => "SCALE",
+        }
+    }
+
+    /// A child's position and length along this axis after its parent's
+    /// length changes from `old_parent` to `new_parent`
+    pub fn apply(self, position: f64, length: f64, old_parent: f64, new_parent: f64) -> (f64, f64) {
+        self.apply_in(position, length, (0.0, old_parent), (0.0, new_parent))
+    }
+
+    /// A child's position and length along this axis after the area it's
+    /// constrained to, a start and length in its parent, changes from `old`
+    /// to `new`. The area is the parent's, or that of the parent's
+    /// stretched layout grid.
+    pub fn apply_in(self, position: f64, length: f64, old: (f64, f64), new: (f64, f64)) -> (f64, f64) {
+        let ((old_start, old_length), (new_start, new_length)) = (old, new);
+        let moved = new_start - old_start;
+        let delta = new_length - old_length;
+        match self {
+            Constraint::Min => (position + moved, length),
+            Constraint::Center => (position + moved + delta / 2.0, length),
+            Constraint::Max => (position + moved + delta, length),
+            Constraint::Stretch => (position + moved, length + delta),
+            Constraint::Scale if old_length > 0.0 => {
+                let ratio = new_length / old_length;
+                (new_start + (position - old_start) * ratio, length * ratio)
+            }
+            Constraint::Scale => (position + moved, length),
+        }
+    }
+}
@@ -9,13 +9,28 @@
 mod frb_generated; // AUTO INJECTED BY flutter_rust_bridge
 
 pub mod kiwi;
+pub mod archive;
 pub mod schema;
 pub mod nodes;
 pub mod paints;
+pub mod strokes;
+pub mod images;
+pub mod fonts;
+pub mod text;
 pub mod effects;
+pub mod instances;
+pub mod components;
+pub mod variables;
+pub mod tokens;
+pub mod codegen;
+pub mod html;
+pub mod prototype;
+pub mod transitions;
 pub mod layout;
+pub mod grids;
 pub mod vector;
 pub mod render;
+pub mod scroll;
 pub mod spatial;
 pub mod tiles;
 pub mod api;
@@ -27,9 +42,27 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum FigmaError {
-    #[error("Invalid file header: expected 'fig-kiwi' or 'fig-kiwie'")]
+    #[error("Invalid file header: expected 'fig-kiwi'")]
     InvalidHeader,
 
+    #[error("File is truncated: missing {0}")]
+    TruncatedFile(String),
+
+    #[error("Missing {0} chunk")]
+    MissingChunk(String),
+
+    #[error("Chunk {index} claims {size} bytes but only {remaining} remain")]
+    ChunkTooLarge { index: usize, size: usize, remaining: usize },
+
+    #[error("Unsupported file version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Invalid .fig archive: {0}")]
+    ArchiveError(String),
+
+    #[error("Invalid font: {0}")]
+    FontError(String),
+
     #[error("Decompression failed: {0}")]
     DecompressionError(String),
 
@@ -39,12 +72,24 @@ pub enum FigmaError {
     #[error("Message decoding failed: {0}")]
     DecodeError(String),
 
+    #[error("Message encoding failed: {0}")]
+    EncodeError(String),
+
     #[error("Node not found: {0}")]
     NodeNotFound(String),
 
     #[error("Unsupported node type: {0}")]
     UnsupportedNodeType(String),
 
+    #[error("Invalid component property: {0}")]
+    PropertyError(String),
+
+    #[error("Variable resolution failed: {0}")]
+    VariableError(String),
+
+    #[error("Export failed: {0}")]
+    ExportError(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
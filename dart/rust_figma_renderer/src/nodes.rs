@@ -1,7 +1,26 @@
 //! Figma node type definitions and rendering
 
-use crate::api::{NodeInfo, DrawCommand, PathData, RectInfo, TransformInfo};
-use crate::kiwi::{decode_fill_paint_data, decode_effect_data, decode_vector_data};
+use crate::api::{NodeInfo, DrawCommand, EffectInfo, FontVariationInfo, GlyphInfo, GlyphRunInfo, ImageDrawInfo, LayerInfo, PaintInfo, PathData, RectInfo, StrokeInfo, StrokeWeightsInfo, TextDecorationInfo, TextDrawInfo, TransformInfo};
+use crate::images::{place_image, ImageStore};
+use crate::components::{PropertyAssignment, PropertyDef, PropertyRef};
+use crate::effects::{BlurEffect, Effect, ShadowEffect};
+use crate::grids::LayoutGrid;
+use crate::instances::{DerivedLayout, SymbolData};
+use crate::kiwi::{decode_vector_data, RawField};
+use crate::layout::Constraint;
+use crate::paints::{BlendMode, Paint};
+use crate::prototype::{FlowStart, Interaction, OverlaySettings};
+use crate::render::RenderContext;
+use crate::scroll::{ScrollBehavior, ScrollDirection};
+use crate::strokes::{border_outline, stroke_outline, StrokeAlign, StrokeOutline, StrokeStyle};
+use crate::vector::{parse_svg_path, path_to_svg};
+use lyon::geom::Angle;
+use std::collections::HashMap;
+use lyon::math::{point, vector, Box2D, Transform};
+use lyon::path::builder::BorderRadii;
+use lyon::path::{Path, Winding};
+use crate::text::{TextContent, TextLayout};
+use crate::variables::{Variable, VariableBinding, VariableMode, VariableRef};
 
 /// Node type enumeration matching Figma's types
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,6 +36,7 @@ pub enum NodeType {
     Ellipse,
     RegularPolygon,
     Rectangle,
+    RoundedRectangle,
     Text,
     Slice,
     Component,
@@ -43,6 +63,7 @@ impl From<&str> for NodeType {
             "ELLIPSE" => NodeType::Ellipse,
             "REGULAR_POLYGON" => NodeType::RegularPolygon,
             "RECTANGLE" => NodeType::Rectangle,
+            "ROUNDED_RECTANGLE" => NodeType::RoundedRectangle,
             "TEXT" => NodeType::Text,
             "SLICE" => NodeType::Slice,
             "COMPONENT" => NodeType::Component,
@@ -62,10 +83,14 @@ impl From<&str> for NodeType {
 pub struct FigmaNode {
     pub id: String,
     pub parent_id: Option<String>,
+    /// Fractional index that orders this node among its siblings
+    pub parent_position: String,
     pub name: String,
     pub node_type: String,
     pub visible: bool,
     pub opacity: f64,
+    /// Layer blend mode name, empty when the file doesn't set one (pass-through)
+    pub blend_mode: String,
 
     // Transform
     pub x: f64,
@@ -73,17 +98,26 @@ pub struct FigmaNode {
     pub width: f64,
     pub height: f64,
     pub rotation: f64,
+    /// Full transform as stored in the file (x, y and rotation are derived from it)
+    pub transform: TransformInfo,
 
     // Children
     pub children: Vec<String>,
 
-    // Paint data (Kiwi-encoded)
+    // Paint data (Kiwi-encoded field values)
     pub fill_paints_data: Vec<u8>,
     pub stroke_paints_data: Vec<u8>,
     pub effects_data: Vec<u8>,
 
+    // Paints and effects decoded from the data above
+    pub fill_paints: Vec<Paint>,
+    pub stroke_paints: Vec<Paint>,
+    pub effects: Vec<Effect>,
+
     // Stroke
     pub stroke_weight: f64,
+    /// Align, caps, joins and dashes decoded from the node's stroke fields
+    pub stroke: StrokeStyle,
 
     // Corner radius
     pub corner_radius: f64,
@@ -95,7 +129,69 @@ pub struct FigmaNode {
     // Text properties
     pub text_data: Vec<u8>,
     pub font_name: String,
+    pub font_style: String,
+    pub font_postscript_name: String,
     pub font_size: f64,
+    /// Characters and styles decoded from `text_data` and the node's text fields
+    pub text: Option<TextContent>,
+
+    // Components and instances
+    /// Set on instances: the component they copy and their overrides
+    pub symbol: Option<SymbolData>,
+    /// Sizes and positions Figma computed for an instance's content
+    pub derived_layouts: Vec<DerivedLayout>,
+    /// The id overrides address this node by, if not its own
+    pub override_key: Option<String>,
+    /// Component swapped in by an override, replacing the one in `symbol`
+    pub swapped_component_id: Option<String>,
+    pub horizontal_constraint: Constraint,
+    pub vertical_constraint: Constraint,
+    /// Properties a component or component set declares
+    pub property_defs: Vec<PropertyDef>,
+    /// Fields of this node bound to its component's properties
+    pub property_refs: Vec<PropertyRef>,
+    /// An instance's property values
+    pub property_assignments: Vec<PropertyAssignment>,
+    /// Set on frames that are component sets
+    pub is_state_group: bool,
+    /// For component sets, the order of each variant property's values
+    pub variant_orders: Vec<(String, Vec<String>)>,
+    /// Key of a component, style or variable published to a library
+    pub key: Option<String>,
+
+    // Variables
+    /// Set on `VARIABLE` nodes
+    pub variable: Option<Variable>,
+    /// For `VARIABLE_SET` nodes, the collection's modes
+    pub modes: Vec<VariableMode>,
+    /// Properties bound to variables, other than paint colors
+    pub variable_bindings: Vec<VariableBinding>,
+    /// Modes picked for this node's content, by collection
+    pub explicit_modes: Vec<(VariableRef, String)>,
+
+    // Styles
+    /// "FILL", "TEXT", "EFFECT" or "GRID" for nodes that define a style
+    pub style_type: Option<String>,
+    /// Description of a style, variable or component
+    pub description: String,
+    /// Deleted styles and variables, kept for the nodes still using them
+    pub soft_deleted: bool,
+
+    // Scrolling and clipping
+    /// Set on frames whose content shows outside them (`frameMaskDisabled`)
+    pub clip_disabled: bool,
+    /// Axes a frame scrolls along in a prototype
+    pub scroll_direction: ScrollDirection,
+    /// How the node moves when the frame it's in scrolls
+    pub scroll_behavior: ScrollBehavior,
+
+    // Prototyping
+    /// Triggers this node reacts to in a prototype, and what they do
+    pub interactions: Vec<Interaction>,
+    /// Set on frames that start a prototype flow
+    pub flow_start: Option<FlowStart>,
+    /// How the frame appears when opened as an overlay
+    pub overlay: Option<OverlaySettings>,
 
     // Layout properties
     pub layout_mode: String,
@@ -103,6 +199,31 @@ pub struct FigmaNode {
     pub counter_axis_sizing: String,
     pub item_spacing: f64,
     pub padding: [f64; 4],
+    /// "MIN", "CENTER", "MAX", "SPACE_BETWEEN" or "SPACE_EVENLY", empty for MIN
+    pub primary_axis_align: String,
+    /// "MIN", "CENTER", "MAX" or "BASELINE", empty for MIN
+    pub counter_axis_align: String,
+    /// Children wrap onto more lines
+    pub layout_wrap: bool,
+    /// Spacing between wrapped lines, when set apart from `item_spacing`
+    pub counter_axis_spacing: Option<f64>,
+    // As a child of an auto layout frame
+    /// 1 to fill the parent along its primary axis
+    pub layout_grow: f64,
+    /// "STRETCH" to fill the parent along its counter axis
+    pub layout_align: String,
+    /// Positioned by constraints rather than by the parent's auto layout
+    pub absolute_position: bool,
+    /// Columns, rows and square grids drawn over a frame
+    pub layout_grids: Vec<LayoutGrid>,
+
+    /// NodeChange fields not covered by this model, kept as encoded bytes and
+    /// written back unchanged
+    pub unknown_fields: Vec<RawField>,
+
+    /// Field ids that were present when the node was decoded, so that
+    /// writing the file back doesn't drop fields that held default values
+    pub(crate) decoded_fields: Vec<u32>,
 }
 
 impl FigmaNode {
@@ -120,9 +241,33 @@ impl FigmaNode {
             opacity: self.opacity,
             visible: self.visible,
             children: self.children.clone(),
+            component_id: self.component_id().map(str::to_string),
         }
     }
 
+    /// The component an instance copies, if this is an instance
+    pub fn component_id(&self) -> Option<&str> {
+        self.swapped_component_id.as_deref()
+            .or(self.symbol.as_ref().map(|symbol| symbol.component_id.as_str()))
+    }
+
+    /// Whether auto layout sizes the frame to its content along its primary
+    /// axis. Figma leaves the field out for hugging frames.
+    pub fn hugs_primary_axis(&self) -> bool {
+        self.primary_axis_sizing != "FIXED"
+    }
+
+    /// Whether auto layout sizes the frame to its content along its counter
+    /// axis
+    pub fn hugs_counter_axis(&self) -> bool {
+        self.counter_axis_sizing.starts_with("RESIZE_TO_FIT")
+    }
+
+    /// Whether the node is a frame that clips its children to its shape
+    pub fn clips_content(&self) -> bool {
+        matches!(self.node_type.as_str(), "FRAME" | "SYMBOL" | "COMPONENT" | "INSTANCE") && !self.clip_disabled
+    }
+
     /// Generate draw command for this node
     pub fn to_draw_command(&self) -> Option<DrawCommand> {
         if !self.visible {
@@ -131,13 +276,13 @@ impl FigmaNode {
 
         let node_type = NodeType::from(self.node_type.as_str());
 
-        // Decode paints and effects
-        let fills = decode_fill_paint_data(&self.fill_paints_data).unwrap_or_default();
-        let strokes = decode_fill_paint_data(&self.stroke_paints_data).unwrap_or_default();
-        let effects = decode_effect_data(&self.effects_data).unwrap_or_default();
+        let fills = self.paint_infos(&self.fill_paints);
+        let strokes = self.paint_infos(&self.stroke_paints);
+        let effects = self.effects.iter().map(Effect::to_effect_info).collect();
+        let stroke = self.stroke_info();
 
         match node_type {
-            NodeType::Rectangle | NodeType::Frame | NodeType::Component | NodeType::Instance => {
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Frame | NodeType::Component | NodeType::Instance => {
                 Some(DrawCommand {
                     command_type: "rect".to_string(),
                     path: None,
@@ -146,11 +291,7 @@ impl FigmaNode {
                         y: self.y,
                         width: self.width,
                         height: self.height,
-                        corner_radii: if self.corner_radii.iter().any(|&r| r != 0.0) {
-                            self.corner_radii
-                        } else {
-                            [self.corner_radius; 4]
-                        },
+                        corner_radii: self.effective_corner_radii(),
                     }),
                     fills,
                     strokes,
@@ -165,6 +306,10 @@ impl FigmaNode {
                         m12: self.y,
                     },
                     clip_path: None,
+                    image: None,
+                    text: None,
+                    stroke: stroke.clone(),
+                    layer: None,
                 })
             }
 
@@ -187,6 +332,10 @@ impl FigmaNode {
                     effects,
                     transform: TransformInfo::default(),
                     clip_path: None,
+                    image: None,
+                    text: None,
+                    stroke: stroke.clone(),
+                    layer: None,
                 })
             }
 
@@ -213,6 +362,10 @@ impl FigmaNode {
                         m12: self.y,
                     },
                     clip_path: None,
+                    image: None,
+                    text: None,
+                    stroke: stroke.clone(),
+                    layer: None,
                 })
             }
 
@@ -234,6 +387,10 @@ impl FigmaNode {
                     effects,
                     transform: TransformInfo::default(),
                     clip_path: None,
+                    image: None,
+                    text: None,
+                    stroke: stroke.clone(),
+                    layer: None,
                 })
             }
 
@@ -251,18 +408,432 @@ impl FigmaNode {
         }
     }
 
+    /// Draw commands for this node without its children, composited as
+    /// described in [`FigmaNode::composite`]
+    pub fn draw_commands(&self, ctx: &RenderContext) -> Vec<DrawCommand> {
+        self.composite(self.content_draw_commands(ctx), Vec::new(), false)
+    }
+
+    /// Draw commands for the node's own content: drop shadows, background
+    /// blurs, the shape, an "image" command for each visible image fill,
+    /// then inner shadows. Text commands carry the laid out glyphs.
+    pub fn content_draw_commands(&self, ctx: &RenderContext) -> Vec<DrawCommand> {
+        if let Some(bound) = ctx.variables.and_then(|variables| variables.bind(self)) {
+            return bound.content_draw_commands(&RenderContext { variables: None, ..*ctx });
+        }
+        let Some(mut shape) = self.to_draw_command() else {
+            return Vec::new();
+        };
+        if let Some(text) = &self.text {
+            let layout = text.layout(self.width, self.height, ctx.fonts);
+            shape.text = Some(self.text_draw_info(&layout));
+        }
+
+        let mut commands = Vec::new();
+        for effect in self.effects.iter().filter(|effect| effect.visible()) {
+            if let Effect::DropShadow(shadow) = effect {
+                commands.extend(self.drop_shadow_command(effect, shadow));
+            }
+        }
+        for effect in self.effects.iter().filter(|effect| effect.visible()) {
+            if let Effect::BackgroundBlur(_) = effect {
+                commands.push(self.effect_command("background_blur", vec![effect.to_effect_info()], None, self.shape_path()));
+            }
+        }
+        commands.push(shape);
+        commands.extend(self.image_draw_commands(ctx.images));
+        for effect in self.effects.iter().filter(|effect| effect.visible()) {
+            if let Effect::InnerShadow(shadow) = effect {
+                commands.extend(self.inner_shadow_command(effect, shadow));
+            }
+        }
+        commands
+    }
+
+    /// The node's blend mode, pass-through when the file doesn't set one
+    pub fn layer_blend_mode(&self) -> BlendMode {
+        if self.blend_mode.is_empty() {
+            BlendMode::PassThrough
+        } else {
+            BlendMode::from_name(&self.blend_mode)
+        }
+    }
+
+    /// The layer the node and its children are drawn into, if they can't be
+    /// drawn straight onto what's behind them: for opacity below 1, a blend
+    /// mode other than pass-through or normal, or a layer blur. A normal
+    /// node also needs one to `isolate` children that blend, so they don't
+    /// blend with what's behind the node. Pass-through nodes only need a
+    /// layer for their opacity, and then composite it normally.
+    pub fn layer(&self, isolate: bool) -> Option<LayerInfo> {
+        let blend_mode = self.layer_blend_mode();
+        let needs_layer = self.opacity < 1.0
+            || self.layer_blur().is_some()
+            || match blend_mode {
+                BlendMode::PassThrough => false,
+                BlendMode::Normal => isolate,
+                _ => true,
+            };
+        needs_layer.then(|| LayerInfo {
+            opacity: self.opacity,
+            blend_mode: match blend_mode {
+                BlendMode::PassThrough => BlendMode::Normal,
+                mode => mode,
+            }.name().to_string(),
+        })
+    }
+
+    /// Whether anything among the node's descendants blends with what's
+    /// behind the node: a blend mode or paint other than normal, reached
+    /// through pass-through children that don't need a layer of their own
+    pub fn blends_with_backdrop(&self, nodes: &HashMap<String, FigmaNode>) -> bool {
+        self.children.iter()
+            .filter_map(|id| nodes.get(id))
+            .filter(|child| child.visible)
+            .any(|child| {
+                child.fill_paints.iter().chain(&child.stroke_paints)
+                    .any(|paint| paint.visible() && paint.blend_mode() != BlendMode::Normal)
+                    || match child.layer_blend_mode() {
+                        BlendMode::PassThrough => child.layer(false).is_none() && child.blends_with_backdrop(nodes),
+                        BlendMode::Normal => false,
+                        _ => true,
+                    }
+            })
+    }
+
+    /// The node's content followed by its children's draw commands, between
+    /// "begin_layer" and "end_layer" commands if the node needs a layer (see
+    /// [`FigmaNode::layer`]). Children share the layer, so overlapping
+    /// translucent children don't show through each other. Content drawn
+    /// with a single paint takes the opacity and blend mode directly instead.
+    pub fn composite(&self, mut content: Vec<DrawCommand>, children: Vec<DrawCommand>, isolate: bool) -> Vec<DrawCommand> {
+        let layer = match self.layer(isolate) {
+            Some(layer) if self.visible && !(content.is_empty() && children.is_empty()) => layer,
+            _ => {
+                content.extend(children);
+                return content;
+            }
+        };
+
+        if children.is_empty() && self.layer_blur().is_none() {
+            if let [command] = content.as_mut_slice() {
+                if let Some(paint) = single_paint(command) {
+                    paint.opacity *= layer.opacity;
+                    paint.blend_mode = layer.blend_mode;
+                    return content;
+                }
+            }
+        }
+
+        let blur: Vec<EffectInfo> = self.layer_blur()
+            .map(|blur| Effect::LayerBlur(blur.clone()).to_effect_info())
+            .into_iter()
+            .collect();
+        let mut begin = self.effect_command("begin_layer", blur, None, None);
+        begin.layer = Some(layer);
+        let mut commands = vec![begin];
+        commands.extend(content);
+        commands.extend(children);
+        commands.push(self.effect_command("end_layer", Vec::new(), None, None));
+        commands
+    }
+
+    /// Wrap children's commands in "begin_clip" and "end_clip" commands if
+    /// the node clips them. The clip path is in the frame's own space, like
+    /// its children.
+    pub fn clip_children(&self, children: Vec<DrawCommand>) -> Vec<DrawCommand> {
+        if children.is_empty() || !self.clips_content() {
+            return children;
+        }
+        let mut commands = Vec::with_capacity(children.len() + 2);
+        commands.push(self.effect_command("begin_clip", Vec::new(), None, Some(self.clip_path())));
+        commands.extend(children);
+        commands.push(self.effect_command("end_clip", Vec::new(), None, None));
+        commands
+    }
+
+    /// The frame's rounded rectangle at the origin of its own space, which
+    /// its children are clipped to
+    pub fn clip_path(&self) -> PathData {
+        PathData {
+            commands: generate_rounded_rect_svg(0.0, 0.0, self.width, self.height, self.effective_corner_radii()),
+            fill_rule: "nonzero".to_string(),
+        }
+    }
+
+    fn layer_blur(&self) -> Option<&BlurEffect> {
+        self.effects.iter().find_map(|effect| match effect {
+            Effect::LayerBlur(blur) if blur.visible && blur.radius > 0.0 => Some(blur),
+            _ => None,
+        })
+    }
+
+    /// The shadow shape, with translucent parts of the node left
+    /// unshadowed unless the effect shows behind the node
+    fn drop_shadow_command(&self, effect: &Effect, shadow: &ShadowEffect) -> Option<DrawCommand> {
+        let path = self.shadow_shape(shadow.spread, shadow.offset)?;
+        let clip_path = if shadow.show_behind_node {
+            None
+        } else {
+            Some(PathData {
+                commands: format!("{} {}", self.effect_bounds(shadow), self.shape_path()?.commands),
+                fill_rule: "evenodd".to_string(),
+            })
+        };
+        let path = PathData { commands: path, fill_rule: "nonzero".to_string() };
+        Some(self.effect_command("drop_shadow", vec![effect.to_effect_info()], Some(path), clip_path))
+    }
+
+    /// Everything around the shrunken, offset shape, to blur and clip to the node
+    fn inner_shadow_command(&self, effect: &Effect, shadow: &ShadowEffect) -> Option<DrawCommand> {
+        let hole = self.shadow_shape(-shadow.spread, shadow.offset)?;
+        let path = PathData {
+            commands: format!("{} {}", self.effect_bounds(shadow), hole),
+            fill_rule: "evenodd".to_string(),
+        };
+        Some(self.effect_command("inner_shadow", vec![effect.to_effect_info()], Some(path), self.shape_path()))
+    }
+
+    /// A rectangle around the node that covers a shadow and its blur
+    fn effect_bounds(&self, shadow: &ShadowEffect) -> String {
+        let margin = shadow.radius * 2.0 + shadow.spread.abs() + shadow.offset.0.abs() + shadow.offset.1.abs() + 1.0;
+        generate_rounded_rect_svg(self.x - margin, self.y - margin, self.width + margin * 2.0, self.height + margin * 2.0, [0.0; 4])
+    }
+
+    /// The node's outline grown by `spread` and moved by `offset`, in the
+    /// same space as `shape_path`. As in Figma, spread only applies to
+    /// rectangles, frames and ellipses.
+    fn shadow_shape(&self, spread: f64, offset: (f64, f64)) -> Option<String> {
+        let (dx, dy) = offset;
+        let spread = spread.max(-self.width.min(self.height) / 2.0);
+        let (x, y) = (self.x - spread + dx, self.y - spread + dy);
+        let (w, h) = (self.width + spread * 2.0, self.height + spread * 2.0);
+        match NodeType::from(self.node_type.as_str()) {
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Frame | NodeType::Component | NodeType::Instance => {
+                let radii = self.effective_corner_radii().map(|r| if r > 0.0 { (r + spread).max(0.0) } else { 0.0 });
+                Some(generate_rounded_rect_svg(x, y, w, h, radii))
+            }
+            NodeType::Ellipse => Some(generate_ellipse_path(x, y, w, h).commands),
+            _ => {
+                let path = parse_svg_path(&self.shape_path()?.commands)?;
+                Some(path_to_svg(&path.transformed(&Transform::translation(dx as f32, dy as f32))))
+            }
+        }
+    }
+
+    /// A command that applies effects, positioned at the node
+    fn effect_command(&self, command_type: &str, effects: Vec<EffectInfo>, path: Option<PathData>, clip_path: Option<PathData>) -> DrawCommand {
+        DrawCommand {
+            command_type: command_type.to_string(),
+            path,
+            rect: Some(RectInfo {
+                x: self.x,
+                y: self.y,
+                width: self.width,
+                height: self.height,
+                corner_radii: [0.0; 4],
+            }),
+            fills: vec![],
+            strokes: vec![],
+            stroke_weight: 0.0,
+            effects,
+            transform: TransformInfo::default(),
+            clip_path,
+            image: None,
+            text: None,
+            stroke: None,
+            layer: None,
+        }
+    }
+
+    /// Convert paints for draw commands, with geometry sized to this node
+    fn paint_infos(&self, paints: &[Paint]) -> Vec<PaintInfo> {
+        paints.iter().map(|paint| paint.to_paint_info(self.width, self.height)).collect()
+    }
+
+    /// Convert a text layout to draw info at the node's position
+    fn text_draw_info(&self, layout: &TextLayout) -> TextDrawInfo {
+        let paints = |paints: &[Paint]| self.paint_infos(paints);
+        TextDrawInfo {
+            runs: layout.runs.iter().map(|run| GlyphRunInfo {
+                text: run.text.clone(),
+                font_family: run.style.font_family.clone(),
+                font_style: run.style.font_style.clone(),
+                font_postscript_name: run.font.as_ref().map(|font| font.postscript_name.clone()).unwrap_or_default(),
+                font_size: run.style.font_size,
+                font_variations: run.variations.iter().map(|(tag, value)| FontVariationInfo {
+                    axis: String::from_utf8_lossy(&tag.to_bytes()).into_owned(),
+                    value: *value as f64,
+                }).collect(),
+                fills: paints(&run.style.fills),
+                glyphs: run.glyphs.iter().map(|glyph| GlyphInfo {
+                    glyph_id: glyph.glyph_id as u32,
+                    x: self.x + glyph.x,
+                    y: self.y + glyph.y,
+                }).collect(),
+            }).collect(),
+            decorations: layout.decorations.iter().map(|rect| TextDecorationInfo {
+                rect: RectInfo {
+                    x: self.x + rect.x,
+                    y: self.y + rect.y,
+                    width: rect.width,
+                    height: rect.height,
+                    corner_radii: [0.0; 4],
+                },
+                fills: paints(&rect.fills),
+            }).collect(),
+            truncated: layout.truncated,
+            width: layout.width,
+            height: layout.height,
+        }
+    }
+
+    /// One "image" command per visible image fill, clipped to the node's shape
+    pub fn image_draw_commands(&self, images: &ImageStore) -> Vec<DrawCommand> {
+        let Some(clip_path) = self.shape_path() else {
+            return Vec::new();
+        };
+
+        self.fill_paints.iter()
+            .filter_map(|paint| match paint {
+                Paint::Image(image) if image.visible && image.opacity > 0.0 => Some(image),
+                _ => None,
+            })
+            .map(|paint| {
+                let placement = place_image(paint, self.width, self.height, images.size(&paint.image_ref));
+                let offset = |rect: RectInfo| RectInfo { x: rect.x + self.x, y: rect.y + self.y, ..rect };
+                DrawCommand {
+                    command_type: "image".to_string(),
+                    path: None,
+                    rect: Some(RectInfo {
+                        x: self.x,
+                        y: self.y,
+                        width: self.width,
+                        height: self.height,
+                        corner_radii: [0.0; 4],
+                    }),
+                    fills: vec![],
+                    strokes: vec![],
+                    stroke_weight: 0.0,
+                    effects: vec![],
+                    transform: TransformInfo::default(),
+                    clip_path: Some(clip_path.clone()),
+                    image: Some(ImageDrawInfo {
+                        image_hash: paint.image_ref.clone(),
+                        available: images.contains(&paint.image_ref),
+                        scale_mode: paint.scale_mode.name().to_string(),
+                        dest: offset(placement.dest),
+                        source: placement.source,
+                        tile_width: placement.tile_size.map(|(w, _)| w),
+                        tile_height: placement.tile_size.map(|(_, h)| h),
+                        rotation: paint.rotation,
+                        opacity: paint.opacity,
+                        blend_mode: paint.blend_mode.name().to_string(),
+                    }),
+                    text: None,
+                    stroke: None,
+                    layer: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Outline of the node at its position, for clipping fills
+    pub(crate) fn shape_path(&self) -> Option<PathData> {
+        match NodeType::from(self.node_type.as_str()) {
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Frame | NodeType::Component | NodeType::Instance => Some(PathData {
+                commands: generate_rounded_rect_svg(self.x, self.y, self.width, self.height, self.effective_corner_radii()),
+                fill_rule: "nonzero".to_string(),
+            }),
+            NodeType::Ellipse => Some(generate_ellipse_path(self.x, self.y, self.width, self.height)),
+            NodeType::Vector | NodeType::Star | NodeType::RegularPolygon => {
+                let path = decode_vector_data(&self.vector_data).ok().filter(|path| !path.commands.is_empty())?;
+                let outline = parse_svg_path(&path.commands)?
+                    .transformed(&Transform::translation(self.x as f32, self.y as f32));
+                Some(PathData { commands: path_to_svg(&outline), fill_rule: path.fill_rule })
+            }
+            _ => None,
+        }
+    }
+
+    /// Outline of the node in its own coordinates, for stroking
+    pub fn outline(&self) -> Option<Path> {
+        let (w, h) = (self.width as f32, self.height as f32);
+        let mut builder = Path::builder();
+        match NodeType::from(self.node_type.as_str()) {
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Frame | NodeType::Component | NodeType::Instance => {
+                let [tl, tr, br, bl] = self.effective_corner_radii().map(|r| r as f32);
+                builder.add_rounded_rectangle(
+                    &Box2D::new(point(0.0, 0.0), point(w, h)),
+                    &BorderRadii { top_left: tl, top_right: tr, bottom_left: bl, bottom_right: br },
+                    Winding::Positive,
+                );
+            }
+            NodeType::Ellipse => {
+                builder.add_ellipse(point(w / 2.0, h / 2.0), vector(w / 2.0, h / 2.0), Angle::zero(), Winding::Positive);
+            }
+            NodeType::Line => {
+                builder.begin(point(0.0, 0.0));
+                builder.line_to(point(w, 0.0));
+                builder.end(false);
+            }
+            NodeType::Vector | NodeType::Star | NodeType::RegularPolygon => {
+                let path = decode_vector_data(&self.vector_data).ok()?;
+                return parse_svg_path(&path.commands).filter(|path| path.iter().next().is_some());
+            }
+            _ => return None,
+        }
+        Some(builder.build())
+    }
+
+    /// Geometry to fill in place of the node's strokes, for clients that
+    /// can't align strokes inside or outside a shape
+    pub fn stroke_outline(&self) -> Option<StrokeOutline> {
+        let node_type = NodeType::from(self.node_type.as_str());
+        if let Some(weights) = self.stroke.side_weights {
+            if matches!(node_type, NodeType::Rectangle | NodeType::Frame | NodeType::Component | NodeType::Instance) {
+                return border_outline(self.width, self.height, weights, &self.stroke);
+            }
+        }
+        if node_type == NodeType::Line {
+            // Lines have no inside, so their strokes are always centered
+            let style = StrokeStyle { align: StrokeAlign::Center, ..self.stroke.clone() };
+            return stroke_outline(&self.outline()?, self.stroke_weight, &style);
+        }
+        stroke_outline(&self.outline()?, self.stroke_weight, &self.stroke)
+    }
+
+    /// Stroke style for draw commands, if the node has strokes
+    fn stroke_info(&self) -> Option<StrokeInfo> {
+        if self.stroke_paints.is_empty() {
+            return None;
+        }
+        Some(StrokeInfo {
+            align: self.stroke.align.name().to_string(),
+            cap: self.stroke.cap.name().to_string(),
+            join: self.stroke.join.name().to_string(),
+            miter_limit: self.stroke.miter_limit,
+            dash_pattern: self.stroke.dash_pattern.clone(),
+            side_weights: self.stroke.side_weights.map(|[top, right, bottom, left]| StrokeWeightsInfo { top, right, bottom, left }),
+        })
+    }
+
+    /// Per-corner radii, falling back to the uniform radius
+    pub(crate) fn effective_corner_radii(&self) -> [f64; 4] {
+        if self.corner_radii.iter().any(|&r| r != 0.0) {
+            self.corner_radii
+        } else {
+            [self.corner_radius; 4]
+        }
+    }
+
     /// Export as SVG path
     pub fn to_svg_path(&self) -> String {
         let node_type = NodeType::from(self.node_type.as_str());
 
         match node_type {
-            NodeType::Rectangle | NodeType::Frame => {
-                let r = if self.corner_radii.iter().any(|&r| r != 0.0) {
-                    self.corner_radii
-                } else {
-                    [self.corner_radius; 4]
-                };
-                generate_rounded_rect_svg(0.0, 0.0, self.width, self.height, r)
+            NodeType::Rectangle | NodeType::RoundedRectangle | NodeType::Frame => {
+                generate_rounded_rect_svg(0.0, 0.0, self.width, self.height, self.effective_corner_radii())
             }
 
             NodeType::Ellipse => {
@@ -289,6 +860,17 @@ impl FigmaNode {
     }
 }
 
+/// The only paint a shape command draws with normal blending, if it draws
+/// just one, so a layer around it can be folded into the paint
+fn single_paint(command: &mut DrawCommand) -> Option<&mut PaintInfo> {
+    if command.text.is_some() || command.image.is_some() {
+        return None;
+    }
+    let mut paints = command.fills.iter_mut().chain(command.strokes.iter_mut()).filter(|paint| paint.visible);
+    let paint = paints.next()?;
+    (paints.next().is_none() && paint.blend_mode == "NORMAL").then_some(paint)
+}
+
 /// Generate ellipse as SVG path
 fn generate_ellipse_path(x: f64, y: f64, width: f64, height: f64) -> PathData {
     let cx = x + width / 2.0;
@@ -342,3 +924,81 @@ fn generate_rounded_rect_svg(x: f64, y: f64, w: f64, h: f64, r: [f64; 4]) -> Str
         x, y, x + tl, y
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::{test_fixture, FigFile};
+
+    #[test]
+    fn test_decode_blend_modes() {
+        let file = test_fixture::file();
+        let nodes = file.ordered_nodes();
+        let ctx = RenderContext::empty();
+        assert!(nodes.iter().filter(|node| node.blend_mode.is_empty()).all(|node| node.layer_blend_mode() == BlendMode::PassThrough));
+
+        // Image fills draw separately, so the node's blend mode needs a layer
+        let difference = nodes.iter().find(|node| node.layer_blend_mode() == BlendMode::Difference).unwrap();
+        let commands = difference.draw_commands(&ctx);
+        let types: Vec<&str> = commands.iter().map(|c| c.command_type.as_str()).collect();
+        assert_eq!(types, ["begin_layer", "rect", "image", "end_layer"]);
+        assert_eq!(commands[0].layer.as_ref().unwrap().blend_mode, "DIFFERENCE");
+        let parent = file.get_node(difference.parent_id.as_ref().unwrap()).unwrap();
+        assert!(parent.blends_with_backdrop(&file.nodes));
+        assert!(parent.layer(true).is_none());
+
+        // A translucent group is drawn into one layer with its children
+        let group = nodes.iter()
+            .find(|node| node.visible && node.opacity < 0.5 && node.opacity > 0.0
+                && node.children.iter().filter(|id| file.get_node(id).is_some_and(|child| child.visible)).count() > 1)
+            .unwrap();
+        let children: Vec<_> = group.children.iter()
+            .filter_map(|id| file.get_node(id))
+            .flat_map(|child| child.draw_commands(&ctx))
+            .collect();
+        let child_count = children.len();
+        let commands = group.composite(group.content_draw_commands(&ctx), children, false);
+        assert_eq!(commands[0].command_type, "begin_layer");
+        let layer = commands[0].layer.as_ref().unwrap();
+        assert_eq!(layer.opacity, group.opacity);
+        assert_eq!(layer.blend_mode, "NORMAL");
+        assert_eq!(commands.last().unwrap().command_type, "end_layer");
+        assert!(commands.len() > child_count + 1);
+
+        // A translucent leaf drawn with one paint takes the opacity directly
+        let leaf = nodes.iter()
+            .find(|node| node.visible && node.opacity < 1.0 && node.children.is_empty() && node.node_type == "ROUNDED_RECTANGLE"
+                && node.fill_paints.len() == 1 && node.stroke_paints.is_empty() && node.effects.is_empty()
+                && matches!(node.fill_paints[0], Paint::Solid(_)))
+            .unwrap();
+        let commands = leaf.draw_commands(&ctx);
+        assert_eq!(commands.len(), 1);
+        let Paint::Solid(fill) = &leaf.fill_paints[0] else { unreachable!() };
+        assert_eq!(commands[0].fills[0].opacity, fill.opacity * leaf.opacity);
+
+        // The blend mode is written back
+        let reparsed = FigFile::parse(&file.write().unwrap()).unwrap();
+        assert_eq!(reparsed.get_node(&difference.id).unwrap().blend_mode, "DIFFERENCE");
+    }
+
+    #[test]
+    fn test_clip_children() {
+        // A frame away from its parent's origin, with a child at its corner
+        let frame = FigmaNode {
+            node_type: "FRAME".into(),
+            x: 100.0,
+            y: 50.0,
+            width: 40.0,
+            height: 30.0,
+            ..Default::default()
+        };
+        let child = DrawCommand { rect: Some(RectInfo { x: 0.0, y: 0.0, width: 10.0, height: 10.0, corner_radii: [0.0; 4] }), ..frame.effect_command("rect", Vec::new(), None, None) };
+        let commands = frame.clip_children(vec![child]);
+        let types: Vec<&str> = commands.iter().map(|c| c.command_type.as_str()).collect();
+        assert_eq!(types, ["begin_clip", "rect", "end_clip"]);
+        assert_eq!(commands[0].clip_path.as_ref().unwrap().commands, "M 0 0 H 40 V 30 H 0 Z");
+
+        let unclipped = FigmaNode { clip_disabled: true, ..frame };
+        assert_eq!(unclipped.clip_children(vec![commands[1].clone()]).len(), 1);
+    }
+}
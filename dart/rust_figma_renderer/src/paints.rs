@@ -1,8 +1,10 @@
 //! Paint types (fills, strokes, gradients)
 
-use crate::api::{ColorInfo, GradientStopInfo};
+use crate::api::{ColorInfo, GradientSegmentInfo, GradientStopInfo, ImagePaintInfo, PaintInfo, PathData, PointInfo, SweepGradientInfo, TransformInfo};
+use crate::kiwi::{KiwiMessage, KiwiValue};
+use crate::variables::VariableRef;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Paint {
     Solid(SolidPaint),
     GradientLinear(GradientPaint),
@@ -12,42 +14,76 @@ pub enum Paint {
     Image(ImagePaint),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SolidPaint {
     pub color: ColorInfo,
+    pub visible: bool,
     pub opacity: f64,
     pub blend_mode: BlendMode,
+    /// Variable the color is bound to
+    pub color_variable: Option<VariableRef>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GradientPaint {
     pub stops: Vec<GradientStopInfo>,
-    pub transform: [[f64; 3]; 2],
+    /// Maps the node's unit square into gradient space, where a linear
+    /// gradient runs from (0, 0.5) to (1, 0.5) and the others are centered
+    /// at (0.5, 0.5) with radius 0.5
+    pub transform: TransformInfo,
+    pub visible: bool,
     pub opacity: f64,
     pub blend_mode: BlendMode,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ImagePaint {
+    /// Hex-encoded hash of the image bytes, the key used by `ImageStore`
     pub image_ref: String,
     pub scale_mode: ScaleMode,
+    /// For `Stretch`, maps the node's unit square to the part of the image shown
+    pub transform: TransformInfo,
+    /// Clockwise rotation in degrees, a multiple of 90
+    pub rotation: f64,
+    /// Tile size relative to the image's natural size, for `Tile`
+    pub scale: f64,
+    /// Natural size recorded by Figma, if any
+    pub original_size: Option<(u32, u32)>,
+    pub filters: ImageFilters,
+    pub visible: bool,
     pub opacity: f64,
     pub blend_mode: BlendMode,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+/// Image adjustments, each in -1..1 with 0 meaning unchanged
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageFilters {
+    pub exposure: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+    pub temperature: f64,
+    pub tint: f64,
+    pub highlights: f64,
+    pub shadows: f64,
+}
+
+/// Schema `BlendMode`, in schema order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum BlendMode {
+    PassThrough,
     #[default]
     Normal,
-    Multiply,
-    Screen,
-    Overlay,
     Darken,
+    Multiply,
+    LinearBurn,
+    ColorBurn,
     Lighten,
+    Screen,
+    LinearDodge,
     ColorDodge,
-    ColorBurn,
-    HardLight,
+    Overlay,
     SoftLight,
+    HardLight,
     Difference,
     Exclusion,
     Hue,
@@ -56,7 +92,29 @@ pub enum BlendMode {
     Luminosity,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+const BLEND_MODES: [BlendMode; 19] = [
+    BlendMode::PassThrough,
+    BlendMode::Normal,
+    BlendMode::Darken,
+    BlendMode::Multiply,
+    BlendMode::LinearBurn,
+    BlendMode::ColorBurn,
+    BlendMode::Lighten,
+    BlendMode::Screen,
+    BlendMode::LinearDodge,
+    BlendMode::ColorDodge,
+    BlendMode::Overlay,
+    BlendMode::SoftLight,
+    BlendMode::HardLight,
+    BlendMode::Difference,
+    BlendMode::Exclusion,
+    BlendMode::Hue,
+    BlendMode::Saturation,
+    BlendMode::Color,
+    BlendMode::Luminosity,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum ScaleMode {
     #[default]
     Fill,
@@ -64,3 +122,589 @@ pub enum ScaleMode {
     Tile,
     Stretch,
 }
+
+impl BlendMode {
+    /// Parse a schema `BlendMode` name, or its number when the file's
+    /// schema didn't list it. Unknown modes fall back to `Normal`.
+    pub fn from_name(name: &str) -> Self {
+        if let Ok(value) = name.parse::<usize>() {
+            return BLEND_MODES.get(value).copied().unwrap_or_default();
+        }
+        match name {
+            "PASS_THROUGH" => BlendMode::PassThrough,
+            "LINEAR_BURN" => BlendMode::LinearBurn,
+            "LINEAR_DODGE" => BlendMode::LinearDodge,
+            "MULTIPLY" => BlendMode::Multiply,
+            "SCREEN" => BlendMode::Screen,
+            "OVERLAY" => BlendMode::Overlay,
+            "DARKEN" => BlendMode::Darken,
+            "LIGHTEN" => BlendMode::Lighten,
+            "COLOR_DODGE" => BlendMode::ColorDodge,
+            "COLOR_BURN" => BlendMode::ColorBurn,
+            "HARD_LIGHT" => BlendMode::HardLight,
+            "SOFT_LIGHT" => BlendMode::SoftLight,
+            "DIFFERENCE" => BlendMode::Difference,
+            "EXCLUSION" => BlendMode::Exclusion,
+            "HUE" => BlendMode::Hue,
+            "SATURATION" => BlendMode::Saturation,
+            "COLOR" => BlendMode::Color,
+            "LUMINOSITY" => BlendMode::Luminosity,
+            _ => BlendMode::Normal,
+        }
+    }
+}
+
+impl BlendMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::PassThrough => "PASS_THROUGH",
+            BlendMode::Normal => "NORMAL",
+            BlendMode::LinearBurn => "LINEAR_BURN",
+            BlendMode::LinearDodge => "LINEAR_DODGE",
+            BlendMode::Multiply => "MULTIPLY",
+            BlendMode::Screen => "SCREEN",
+            BlendMode::Overlay => "OVERLAY",
+            BlendMode::Darken => "DARKEN",
+            BlendMode::Lighten => "LIGHTEN",
+            BlendMode::ColorDodge => "COLOR_DODGE",
+            BlendMode::ColorBurn => "COLOR_BURN",
+            BlendMode::HardLight => "HARD_LIGHT",
+            BlendMode::SoftLight => "SOFT_LIGHT",
+            BlendMode::Difference => "DIFFERENCE",
+            BlendMode::Exclusion => "EXCLUSION",
+            BlendMode::Hue => "HUE",
+            BlendMode::Saturation => "SATURATION",
+            BlendMode::Color => "COLOR",
+            BlendMode::Luminosity => "LUMINOSITY",
+        }
+    }
+}
+
+impl ScaleMode {
+    /// Parse a schema `ImageScaleMode` name
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "FIT" => ScaleMode::Fit,
+            "TILE" => ScaleMode::Tile,
+            "STRETCH" => ScaleMode::Stretch,
+            _ => ScaleMode::Fill,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScaleMode::Fill => "FILL",
+            ScaleMode::Fit => "FIT",
+            ScaleMode::Tile => "TILE",
+            ScaleMode::Stretch => "STRETCH",
+        }
+    }
+}
+
+impl Paint {
+    /// Build a paint from a decoded `Paint` message. Paint types the renderer
+    /// can't draw (emoji, video, pattern, noise) return None.
+    pub fn from_kiwi(paint: &KiwiMessage) -> Option<Paint> {
+        let visible = paint.bool("visible").unwrap_or(true);
+        let opacity = paint.float("opacity").unwrap_or(1.0);
+        let blend_mode = BlendMode::from_name(paint.str("blendMode").unwrap_or("NORMAL"));
+
+        let gradient = || GradientPaint {
+            stops: paint.array("stops").iter()
+                .filter_map(KiwiValue::as_message)
+                .filter_map(|stop| Some(GradientStopInfo {
+                    position: stop.float("position")?,
+                    color: color_from_kiwi(stop.message("color")?),
+                }))
+                .collect(),
+            transform: paint.matrix("transform").unwrap_or_default(),
+            visible,
+            opacity,
+            blend_mode,
+        };
+
+        match paint.str("type").unwrap_or("SOLID") {
+            "SOLID" => Some(Paint::Solid(SolidPaint {
+                color: paint.message("color").map(color_from_kiwi)
+                    .unwrap_or(ColorInfo { r: 0, g: 0, b: 0, a: 255 }),
+                visible,
+                opacity,
+                blend_mode,
+                color_variable: paint.message("colorVar").and_then(VariableRef::from_alias),
+            })),
+            "GRADIENT_LINEAR" => Some(Paint::GradientLinear(gradient())),
+            "GRADIENT_RADIAL" => Some(Paint::GradientRadial(gradient())),
+            "GRADIENT_ANGULAR" => Some(Paint::GradientAngular(gradient())),
+            "GRADIENT_DIAMOND" => Some(Paint::GradientDiamond(gradient())),
+            "IMAGE" => Some(Paint::Image(ImagePaint {
+                image_ref: paint.message("image")
+                    .and_then(|image| image.bytes("hash"))
+                    .map(hex)
+                    .unwrap_or_default(),
+                scale_mode: ScaleMode::from_name(paint.str("imageScaleMode").unwrap_or("FILL")),
+                transform: paint.matrix("transform").unwrap_or_default(),
+                rotation: paint.float("rotation").unwrap_or(0.0),
+                scale: paint.float("scale").filter(|&scale| scale > 0.0).unwrap_or(1.0),
+                original_size: paint.uint("originalImageWidth")
+                    .zip(paint.uint("originalImageHeight"))
+                    .filter(|&(w, h)| w > 0 && h > 0)
+                    .map(|(w, h)| (w as u32, h as u32)),
+                filters: paint.message("paintFilter")
+                    .or_else(|| paint.message("filterColorAdjust"))
+                    .map(ImageFilters::from_kiwi)
+                    .unwrap_or_default(),
+                visible,
+                opacity,
+                blend_mode,
+            })),
+            _ => None,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        match self {
+            Paint::Solid(p) => p.visible,
+            Paint::GradientLinear(p) | Paint::GradientRadial(p)
+            | Paint::GradientAngular(p) | Paint::GradientDiamond(p) => p.visible,
+            Paint::Image(p) => p.visible,
+        }
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        match self {
+            Paint::Solid(p) => p.blend_mode,
+            Paint::GradientLinear(p) | Paint::GradientRadial(p)
+            | Paint::GradientAngular(p) | Paint::GradientDiamond(p) => p.blend_mode,
+            Paint::Image(p) => p.blend_mode,
+        }
+    }
+
+    /// Gradient handle positions in the node's unit square, as in Figma's
+    /// REST API: the start (or center), the end of the gradient axis, and
+    /// the end of the perpendicular width axis
+    pub fn gradient_handles(&self) -> Option<[(f64, f64); 3]> {
+        match self {
+            Paint::GradientLinear(p) => Some(p.handles([(0.0, 0.5), (1.0, 0.5), (0.0, 1.0)])),
+            Paint::GradientRadial(p) | Paint::GradientAngular(p) | Paint::GradientDiamond(p) => {
+                Some(p.handles([(0.5, 0.5), (1.0, 0.5), (0.5, 1.0)]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert for draw commands on a node of the given size. Sweep and
+    /// diamond geometry is in pixels relative to the node's top-left.
+    pub fn to_paint_info(&self, width: f64, height: f64) -> PaintInfo {
+        let (paint_type, color, stops, opacity, blend_mode) = match self {
+            Paint::Solid(p) => ("solid", Some(p.color.clone()), vec![], p.opacity, p.blend_mode),
+            Paint::GradientLinear(p) => ("gradient_linear", None, p.stops.clone(), p.opacity, p.blend_mode),
+            Paint::GradientRadial(p) => ("gradient_radial", None, p.stops.clone(), p.opacity, p.blend_mode),
+            Paint::GradientAngular(p) => ("gradient_angular", None, p.stops.clone(), p.opacity, p.blend_mode),
+            Paint::GradientDiamond(p) => ("gradient_diamond", None, p.stops.clone(), p.opacity, p.blend_mode),
+            Paint::Image(p) => ("image", None, vec![], p.opacity, p.blend_mode),
+        };
+        let gradient_transform = match self {
+            Paint::GradientLinear(p) | Paint::GradientRadial(p)
+            | Paint::GradientAngular(p) | Paint::GradientDiamond(p) => Some(p.transform.clone()),
+            _ => None,
+        };
+        PaintInfo {
+            paint_type: paint_type.to_string(),
+            visible: self.visible(),
+            color,
+            gradient_stops: stops,
+            gradient_transform,
+            gradient_handles: self.gradient_handles()
+                .map(|handles| handles.iter().map(|&(x, y)| PointInfo { x, y }).collect())
+                .unwrap_or_default(),
+            sweep: match self {
+                Paint::GradientAngular(p) => p.sweep(width, height),
+                _ => None,
+            },
+            diamond_segments: match self {
+                Paint::GradientDiamond(p) => p.diamond_segments(width, height),
+                _ => Vec::new(),
+            },
+            image: match self {
+                Paint::Image(p) => Some(p.to_image_paint_info()),
+                _ => None,
+            },
+            opacity,
+            blend_mode: blend_mode.name().to_string(),
+        }
+    }
+
+    /// Build paints from a decoded `Paint[]` field
+    pub fn list_from_kiwi(paints: &KiwiValue) -> Vec<Paint> {
+        paints.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter_map(Paint::from_kiwi)
+            .collect()
+    }
+
+    /// The `Paint[]` to write for paints built from `decoded`, or None if
+    /// they're unchanged. Edited paints are written over the messages they
+    /// were built from, in order, and paint types the renderer skips are
+    /// kept where they were.
+    pub fn list_to_kiwi(paints: &[Paint], decoded: &[KiwiValue]) -> Option<Vec<KiwiValue>> {
+        let mut paints = paints.iter();
+        let mut edited = false;
+        let mut list = Vec::new();
+        for value in decoded {
+            let Some(original) = value.as_message().and_then(Paint::from_kiwi) else {
+                list.push(value.clone());
+                continue;
+            };
+            match paints.next() {
+                Some(paint) if *paint == original => list.push(value.clone()),
+                Some(paint) => {
+                    edited = true;
+                    list.push(KiwiValue::Message(paint.to_kiwi(value.as_message().cloned().unwrap_or_default())));
+                }
+                None => edited = true,
+            }
+        }
+        for paint in paints {
+            edited = true;
+            list.push(KiwiValue::Message(paint.to_kiwi(KiwiMessage::default())));
+        }
+        edited.then_some(list)
+    }
+
+    /// Write this paint over a decoded `Paint` message, keeping the fields
+    /// the model doesn't cover
+    pub fn to_kiwi(&self, mut paint: KiwiMessage) -> KiwiMessage {
+        let (paint_type, opacity) = match self {
+            Paint::Solid(p) => ("SOLID", p.opacity),
+            Paint::GradientLinear(p) => ("GRADIENT_LINEAR", p.opacity),
+            Paint::GradientRadial(p) => ("GRADIENT_RADIAL", p.opacity),
+            Paint::GradientAngular(p) => ("GRADIENT_ANGULAR", p.opacity),
+            Paint::GradientDiamond(p) => ("GRADIENT_DIAMOND", p.opacity),
+            Paint::Image(p) => ("IMAGE", p.opacity),
+        };
+        paint.set("type", KiwiValue::Enum(paint_type.into()));
+        paint.set("visible", KiwiValue::Bool(self.visible()));
+        paint.set("opacity", KiwiValue::Float(opacity));
+        paint.set("blendMode", KiwiValue::Enum(self.blend_mode().name().into()));
+
+        match self {
+            Paint::Solid(p) => {
+                paint.set("color", color_to_kiwi(&p.color));
+                let bound = paint.message("colorVar").and_then(VariableRef::from_alias);
+                match &p.color_variable {
+                    None => paint.remove("colorVar"),
+                    Some(variable) if bound.as_ref() != Some(variable) => paint.set("colorVar", variable.to_alias("COLOR")),
+                    Some(_) => {}
+                }
+            }
+            Paint::GradientLinear(p) | Paint::GradientRadial(p)
+            | Paint::GradientAngular(p) | Paint::GradientDiamond(p) => {
+                let stops = p.stops.iter()
+                    .map(|stop| KiwiValue::message(vec![("color", color_to_kiwi(&stop.color)), ("position", KiwiValue::Float(stop.position))]))
+                    .collect();
+                paint.set("stops", KiwiValue::Array(stops));
+                // Variables bound to the old stops would override the new ones
+                paint.remove("stopsVar");
+                paint.set("transform", KiwiValue::matrix(&p.transform));
+            }
+            Paint::Image(p) => {
+                let hash = paint.message("image").and_then(|image| image.bytes("hash")).map(hex);
+                if hash.as_deref() != Some(p.image_ref.as_str()) {
+                    paint.set("image", KiwiValue::message(vec![("hash", KiwiValue::Bytes(unhex(&p.image_ref)))]));
+                    for name in ["imageThumbnail", "thumbHash", "thumbHashBase64"] {
+                        paint.remove(name);
+                    }
+                }
+                paint.set("imageScaleMode", KiwiValue::Enum(p.scale_mode.name().into()));
+                paint.set("transform", KiwiValue::matrix(&p.transform));
+                paint.set("rotation", KiwiValue::Float(p.rotation));
+                paint.set("scale", KiwiValue::Float(p.scale));
+                match p.original_size {
+                    Some((width, height)) => {
+                        paint.set("originalImageWidth", KiwiValue::Uint(width as u64));
+                        paint.set("originalImageHeight", KiwiValue::Uint(height as u64));
+                    }
+                    None => {
+                        paint.remove("originalImageWidth");
+                        paint.remove("originalImageHeight");
+                    }
+                }
+                let filter = paint.message("paintFilter").or_else(|| paint.message("filterColorAdjust"));
+                if filter.map(ImageFilters::from_kiwi).unwrap_or_default() != p.filters {
+                    let mut filter = paint.message("paintFilter").cloned().unwrap_or_default();
+                    p.filters.write_kiwi(&mut filter);
+                    paint.set("paintFilter", KiwiValue::Message(filter));
+                    paint.remove("filterColorAdjust");
+                }
+            }
+        }
+        paint
+    }
+}
+
+impl GradientPaint {
+    /// Map points from gradient space back to the node's unit square. A
+    /// degenerate transform leaves them unmapped.
+    fn handles(&self, points: [(f64, f64); 3]) -> [(f64, f64); 3] {
+        points.map(|point| self.to_unit(point).unwrap_or(point))
+    }
+
+    /// Map a point from gradient space to the node's unit square, if the
+    /// transform can be inverted
+    fn to_unit(&self, (x, y): (f64, f64)) -> Option<(f64, f64)> {
+        let t = &self.transform;
+        let det = t.m00 * t.m11 - t.m01 * t.m10;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (dx, dy) = (x - t.m02, y - t.m12);
+        Some(((t.m11 * dx - t.m01 * dy) / det, (t.m00 * dy - t.m10 * dx) / det))
+    }
+
+    /// Map a point from gradient space to node pixels
+    fn to_node(&self, point: (f64, f64), width: f64, height: f64) -> Option<(f64, f64)> {
+        self.to_unit(point).map(|(x, y)| (x * width, y * height))
+    }
+
+    /// Sweep parameters for an angular gradient. Angle zero points from the
+    /// center toward gradient space (1, 0.5); a mirrored transform turns the
+    /// sweep counterclockwise.
+    fn sweep(&self, width: f64, height: f64) -> Option<SweepGradientInfo> {
+        let center = self.to_node((0.5, 0.5), width, height)?;
+        let axis = self.to_node((1.0, 0.5), width, height)?;
+        let normal = self.to_node((0.5, 1.0), width, height)?;
+        let (ax, ay) = (axis.0 - center.0, axis.1 - center.1);
+        let (nx, ny) = (normal.0 - center.0, normal.1 - center.1);
+        Some(SweepGradientInfo {
+            center: PointInfo { x: center.0, y: center.1 },
+            start_angle: ay.atan2(ax),
+            clockwise: ax * ny - ay * nx >= 0.0,
+        })
+    }
+
+    /// Split a diamond gradient into its four quadrants. Within a quadrant
+    /// the gradient position, 2 * (|dx| + |dy|) from the center in gradient
+    /// space, is affine, so each one is a linear gradient clipped to a wedge.
+    fn diamond_segments(&self, width: f64, height: f64) -> Vec<GradientSegmentInfo> {
+        let t = &self.transform;
+        let Some(center) = self.to_node((0.5, 0.5), width, height) else {
+            return Vec::new();
+        };
+        if width <= 0.0 || height <= 0.0 {
+            return Vec::new();
+        }
+
+        // Wedges must reach every corner of the node
+        let reach = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].iter()
+            .map(|&(x, y)| {
+                let gx = t.m00 * x + t.m01 * y + t.m02 - 0.5;
+                let gy = t.m10 * x + t.m11 * y + t.m12 - 0.5;
+                gx.abs().max(gy.abs())
+            })
+            .fold(0.0, f64::max);
+
+        let mut segments = Vec::new();
+        for (sx, sy) in [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)] {
+            // Gradient of the position with respect to node pixels
+            let kx = 2.0 * (sx * t.m00 + sy * t.m10) / width;
+            let ky = 2.0 * (sx * t.m01 + sy * t.m11) / height;
+            let length = kx * kx + ky * ky;
+            if length < 1e-12 {
+                continue;
+            }
+            let corners = [(0.0, 0.0), (sx * reach, 0.0), (sx * reach, sy * reach), (0.0, sy * reach)]
+                .map(|(dx, dy)| self.to_node((0.5 + dx, 0.5 + dy), width, height).unwrap_or(center));
+            let commands = format!(
+                "M {} {} L {} {} L {} {} L {} {} Z",
+                corners[0].0, corners[0].1, corners[1].0, corners[1].1,
+                corners[2].0, corners[2].1, corners[3].0, corners[3].1,
+            );
+            segments.push(GradientSegmentInfo {
+                clip: PathData { commands, fill_rule: "nonzero".to_string() },
+                start: PointInfo { x: center.0, y: center.1 },
+                end: PointInfo { x: center.0 + kx / length, y: center.1 + ky / length },
+            });
+        }
+        segments
+    }
+}
+
+impl ImagePaint {
+    fn to_image_paint_info(&self) -> ImagePaintInfo {
+        ImagePaintInfo {
+            image_hash: self.image_ref.clone(),
+            scale_mode: self.scale_mode.name().to_string(),
+            transform: self.transform.clone(),
+            rotation: self.rotation,
+            scale: self.scale,
+            original_width: self.original_size.map(|(w, _)| w),
+            original_height: self.original_size.map(|(_, h)| h),
+            exposure: self.filters.exposure,
+            contrast: self.filters.contrast,
+            saturation: self.filters.saturation,
+            temperature: self.filters.temperature,
+            tint: self.filters.tint,
+            highlights: self.filters.highlights,
+            shadows: self.filters.shadows,
+        }
+    }
+}
+
+impl ImageFilters {
+    /// Read a `PaintFilterMessage` or `FilterColorAdjust`. Figma shows
+    /// saturation but stores it as `vibrance`.
+    fn from_kiwi(filter: &KiwiMessage) -> Self {
+        let value = |name: &str| filter.float(name).unwrap_or(0.0);
+        ImageFilters {
+            exposure: value("exposure"),
+            contrast: value("contrast"),
+            saturation: value("vibrance"),
+            temperature: value("temperature"),
+            tint: value("tint"),
+            highlights: value("highlights"),
+            shadows: value("shadows"),
+        }
+    }
+
+    fn write_kiwi(&self, filter: &mut KiwiMessage) {
+        for (name, value) in [
+            ("exposure", self.exposure),
+            ("contrast", self.contrast),
+            ("vibrance", self.saturation),
+            ("temperature", self.temperature),
+            ("tint", self.tint),
+            ("highlights", self.highlights),
+            ("shadows", self.shadows),
+        ] {
+            filter.set(name, KiwiValue::Float(value));
+        }
+    }
+}
+
+/// Convert a schema `Color` (channels in 0..1) to 8-bit RGBA
+pub fn color_from_kiwi(color: &KiwiMessage) -> ColorInfo {
+    let channel = |name: &str| (color.float(name).unwrap_or(0.0).clamp(0.0, 1.0) * 255.0).round() as u8;
+    ColorInfo {
+        r: channel("r"),
+        g: channel("g"),
+        b: channel("b"),
+        a: channel("a"),
+    }
+}
+
+/// Convert 8-bit RGBA to a schema `Color`
+pub fn color_to_kiwi(color: &ColorInfo) -> KiwiValue {
+    let channel = |value: u8| KiwiValue::Float(value as f64 / 255.0);
+    KiwiValue::message(vec![("r", channel(color.r)), ("g", channel(color.g)), ("b", channel(color.b)), ("a", channel(color.a))])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+    use crate::render::RenderContext;
+
+    fn gradient(transform: TransformInfo) -> GradientPaint {
+        GradientPaint {
+            stops: vec![],
+            transform,
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        let sweep = gradient(TransformInfo::default()).sweep(200.0, 100.0).unwrap();
+        assert_eq!((sweep.center.x, sweep.center.y), (100.0, 50.0));
+        assert_eq!(sweep.start_angle, 0.0);
+        assert!(sweep.clockwise);
+
+        let rotated = TransformInfo { m00: 0.0, m01: 1.0, m02: 0.0, m10: -1.0, m11: 0.0, m12: 1.0 };
+        let sweep = gradient(rotated).sweep(200.0, 100.0).unwrap();
+        assert_eq!((sweep.center.x, sweep.center.y), (100.0, 50.0));
+        assert!((sweep.start_angle - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!(sweep.clockwise);
+
+        let mirrored = TransformInfo { m00: -1.0, m02: 1.0, ..TransformInfo::default() };
+        let sweep = gradient(mirrored).sweep(200.0, 100.0).unwrap();
+        assert!((sweep.start_angle - std::f64::consts::PI).abs() < 1e-9);
+        assert!(!sweep.clockwise);
+
+        let degenerate = TransformInfo { m00: 0.0, m11: 0.0, ..TransformInfo::default() };
+        assert!(gradient(degenerate).sweep(200.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn test_diamond_segments() {
+        let segments = gradient(TransformInfo::default()).diamond_segments(200.0, 100.0);
+        assert_eq!(segments.len(), 4);
+        let first = &segments[0];
+        assert_eq!(first.clip.commands, "M 100 50 L 200 50 L 200 100 L 100 100 Z");
+        assert_eq!((first.start.x, first.start.y), (100.0, 50.0));
+        assert!((first.end.x - 120.0).abs() < 1e-9 && (first.end.y - 90.0).abs() < 1e-9);
+
+        // Quadrants mirror each other around the center
+        let third = &segments[2];
+        assert!((third.end.x - 80.0).abs() < 1e-9 && (third.end.y - 10.0).abs() < 1e-9);
+
+        let info = Paint::GradientDiamond(gradient(TransformInfo::default())).to_paint_info(200.0, 100.0);
+        assert_eq!(info.diamond_segments.len(), 4);
+        assert!(info.sweep.is_none());
+    }
+
+    #[test]
+    fn test_decode_paints() {
+        let file = test_fixture::file();
+
+        let gradient = &file.get_node("478:97565").unwrap().fill_paints[0];
+        let Paint::GradientLinear(gradient) = gradient else { panic!("{:?}", gradient) };
+        assert_eq!(gradient.stops.len(), 3);
+        assert_eq!(gradient.stops[2].position, 1.0);
+
+        // Rotated 90°, so the gradient runs from the top edge to the bottom
+        let rotated = &file.get_node("349:44491").unwrap().fill_paints[0];
+        let handles = rotated.gradient_handles().unwrap();
+        let expected = [(0.5, 0.0), (0.5, 1.0), (0.0, 0.0)];
+        for (handle, expected) in handles.iter().zip(expected) {
+            assert!((handle.0 - expected.0).abs() < 1e-9 && (handle.1 - expected.1).abs() < 1e-9, "{:?}", handles);
+        }
+        let info = rotated.to_paint_info(1.0, 1.0);
+        assert_eq!(info.gradient_handles.len(), 3);
+        assert!(info.gradient_transform.is_some());
+
+        let paints: Vec<&Paint> = file.nodes.values().flat_map(|node| &node.fill_paints).collect();
+        assert!(paints.iter().any(|paint| !paint.visible()));
+        assert!(paints.iter().any(|paint| paint.to_paint_info(1.0, 1.0).blend_mode == "LINEAR_DODGE"));
+
+        let node = file.get_node("390:38468").unwrap();
+        let Some(Paint::Image(image)) = node.fill_paints.first() else { panic!("{:?}", node.fill_paints) };
+        assert_eq!(image.image_ref, "9d232edd63b6b316e8031cb7709593438cd6f0fd");
+        assert_eq!(image.scale_mode, ScaleMode::Fill);
+        assert_eq!(image.original_size, Some((1164, 771)));
+        assert_eq!(file.decode_paints(&node.fill_paints_data).unwrap().len(), node.fill_paints.len());
+        let info = node.fill_paints[0].to_paint_info(node.width, node.height).image.unwrap();
+        assert_eq!(info.image_hash, image.image_ref);
+        assert_eq!(info.original_width, Some(1164));
+
+        let ctx = RenderContext::empty();
+        let commands = node.draw_commands(&ctx);
+        let image_command = commands.iter().find(|cmd| cmd.command_type == "image").unwrap();
+        let info = image_command.image.as_ref().unwrap();
+        assert_eq!(info.image_hash, image.image_ref);
+        assert!(!info.available);
+        assert!(info.source.is_some());
+        assert!(image_command.clip_path.is_some());
+    }
+}
@@ -0,0 +1,896 @@
+//! Prototype interactions and flows
+//!
+//! A node reacts to triggers (`PrototypeEvent`) with a list of actions
+//! (`PrototypeAction`) in its `prototypeInteractions`. Actions that lead to
+//! another node carry the transition used to get there. Top-level frames
+//! with a `prototypeStartingPoint` start a flow. Files saved before
+//! interactions existed keep a single link in the node's own `transition*`
+//! and `interaction*` fields, named like those of the messages. A frame
+//! opened as an overlay says where it appears in its `overlay*` fields.
+
+use crate::api::{ColorInfo, FlowInfo, InteractionInfo, OverlayInfo, PointInfo, PrototypeActionInfo, PrototypeTransitionInfo, SpringInfo, TriggerInfo};
+use crate::kiwi::{FigFile, KiwiMessage, KiwiValue};
+use crate::nodes::FigmaNode;
+use crate::paints::color_from_kiwi;
+use crate::{FigmaError, Result};
+use std::collections::{HashSet, VecDeque};
+
+/// NodeChange fields holding a link in files without `prototypeInteractions`
+pub(crate) const LEGACY_LINK_FIELDS: &[&str] = &[
+    "transitionNodeID",
+    "transitionType",
+    "transitionDuration",
+    "transitionPreserveScroll",
+    "transitionShouldSmartAnimate",
+    "transitionTimeout",
+    "interactionType",
+    "interactionMaintained",
+    "interactionDuration",
+    "destinationIsOverlay",
+];
+
+/// NodeChange fields describing how a frame is shown as an overlay
+pub(crate) const OVERLAY_FIELDS: &[&str] = &[
+    "overlayPositionType",
+    "overlayRelativePosition",
+    "overlayBackgroundInteraction",
+    "overlayBackgroundAppearance",
+];
+
+/// What sets off an interaction (schema `InteractionType`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TriggerType {
+    #[default]
+    None,
+    OnClick,
+    OnHover,
+    OnPress,
+    OnDrag,
+    AfterTimeout,
+    MouseEnter,
+    MouseLeave,
+    MouseDown,
+    MouseUp,
+    OnKeyDown,
+    OnVoice,
+    OnMediaHit,
+    OnMediaEnd,
+}
+
+impl TriggerType {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "ON_CLICK" => TriggerType::OnClick,
+            "ON_HOVER" => TriggerType::OnHover,
+            "ON_PRESS" => TriggerType::OnPress,
+            "DRAG" => TriggerType::OnDrag,
+            "AFTER_TIMEOUT" => TriggerType::AfterTimeout,
+            "MOUSE_ENTER" | "MOUSE_IN" => TriggerType::MouseEnter,
+            "MOUSE_LEAVE" | "MOUSE_OUT" => TriggerType::MouseLeave,
+            "MOUSE_DOWN" => TriggerType::MouseDown,
+            "MOUSE_UP" => TriggerType::MouseUp,
+            "ON_KEY_DOWN" => TriggerType::OnKeyDown,
+            "ON_VOICE" => TriggerType::OnVoice,
+            "ON_MEDIA_HIT" => TriggerType::OnMediaHit,
+            "ON_MEDIA_END" => TriggerType::OnMediaEnd,
+            _ => TriggerType::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TriggerType::None => "NONE",
+            TriggerType::OnClick => "ON_CLICK",
+            TriggerType::OnHover => "ON_HOVER",
+            TriggerType::OnPress => "ON_PRESS",
+            TriggerType::OnDrag => "DRAG",
+            TriggerType::AfterTimeout => "AFTER_TIMEOUT",
+            TriggerType::MouseEnter => "MOUSE_ENTER",
+            TriggerType::MouseLeave => "MOUSE_LEAVE",
+            TriggerType::MouseDown => "MOUSE_DOWN",
+            TriggerType::MouseUp => "MOUSE_UP",
+            TriggerType::OnKeyDown => "ON_KEY_DOWN",
+            TriggerType::OnVoice => "ON_VOICE",
+            TriggerType::OnMediaHit => "ON_MEDIA_HIT",
+            TriggerType::OnMediaEnd => "ON_MEDIA_END",
+        }
+    }
+}
+
+/// The edge a transition enters from or leaves to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Direction {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "LEFT" => Direction::Left,
+            "RIGHT" => Direction::Right,
+            "TOP" => Direction::Top,
+            "BOTTOM" => Direction::Bottom,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Left => "LEFT",
+            Direction::Right => "RIGHT",
+            Direction::Top => "TOP",
+            Direction::Bottom => "BOTTOM",
+        }
+    }
+}
+
+/// How the destination replaces the current frame (schema `TransitionType`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransitionType {
+    #[default]
+    Instant,
+    Dissolve,
+    /// The destination slides in over the current frame
+    SlideFrom(Direction),
+    /// The current frame slides away, uncovering the destination
+    SlideOutTo(Direction),
+    /// The destination pushes the current frame out
+    PushFrom(Direction),
+    /// The destination moves in over the current frame, without the parallax
+    /// of a slide
+    MoveFrom(Direction),
+    MoveOutTo(Direction),
+    /// Layers with matching names animate between the two frames
+    SmartAnimate,
+    ScrollAnimate,
+}
+
+impl TransitionType {
+    pub fn from_name(name: &str) -> Self {
+        let directed = |prefix: &str| name.strip_prefix(prefix).and_then(Direction::from_name);
+        if let Some(direction) = directed("SLIDE_FROM_") {
+            return TransitionType::SlideFrom(direction);
+        }
+        if let Some(direction) = directed("SLIDE_OUT_TO_") {
+            return TransitionType::SlideOutTo(direction);
+        }
+        if let Some(direction) = directed("PUSH_FROM_") {
+            return TransitionType::PushFrom(direction);
+        }
+        if let Some(direction) = directed("MOVE_FROM_") {
+            return TransitionType::MoveFrom(direction);
+        }
+        if let Some(direction) = directed("MOVE_OUT_TO_") {
+            return TransitionType::MoveOutTo(direction);
+        }
+        match name {
+            "DISSOLVE" | "FADE" => TransitionType::Dissolve,
+            "SMART_ANIMATE" | "MAGIC_MOVE" => TransitionType::SmartAnimate,
+            "SCROLL_ANIMATE" => TransitionType::ScrollAnimate,
+            _ => TransitionType::Instant,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            TransitionType::Instant => "INSTANT_TRANSITION".into(),
+            TransitionType::Dissolve => "DISSOLVE".into(),
+            TransitionType::SlideFrom(direction) => format!("SLIDE_FROM_{}", direction.name()),
+            TransitionType::SlideOutTo(direction) => format!("SLIDE_OUT_TO_{}", direction.name()),
+            TransitionType::PushFrom(direction) => format!("PUSH_FROM_{}", direction.name()),
+            TransitionType::MoveFrom(direction) => format!("MOVE_FROM_{}", direction.name()),
+            TransitionType::MoveOutTo(direction) => format!("MOVE_OUT_TO_{}", direction.name()),
+            TransitionType::SmartAnimate => "SMART_ANIMATE".into(),
+            TransitionType::ScrollAnimate => "SCROLL_ANIMATE".into(),
+        }
+    }
+}
+
+/// Timing of a transition, either a cubic bezier or a spring simulation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EasingCurve {
+    /// Control points x1, y1, x2, y2, like CSS `cubic-bezier()`
+    Bezier([f64; 4]),
+    Spring { mass: f64, stiffness: f64, damping: f64 },
+}
+
+impl EasingCurve {
+    pub const LINEAR: EasingCurve = EasingCurve::Bezier([0.0, 0.0, 1.0, 1.0]);
+
+    /// The curve for a schema `EasingType`, with `easingFunction` holding
+    /// the parameters of custom curves
+    pub fn from_kiwi(easing_type: &str, function: &[f64]) -> Self {
+        let spring = |mass, stiffness, damping| EasingCurve::Spring { mass, stiffness, damping };
+        match (easing_type, function) {
+            (name, &[x1, y1, x2, y2]) if !name.contains("SPRING") => EasingCurve::Bezier([x1, y1, x2, y2]),
+            (name, &[mass, stiffness, damping, ..]) if name.contains("SPRING") => spring(mass, stiffness, damping),
+            ("IN_CUBIC", _) => EasingCurve::Bezier([0.42, 0.0, 1.0, 1.0]),
+            ("OUT_CUBIC", _) => EasingCurve::Bezier([0.0, 0.0, 0.58, 1.0]),
+            ("INOUT_CUBIC", _) => EasingCurve::Bezier([0.42, 0.0, 0.58, 1.0]),
+            ("IN_BACK_CUBIC", _) => EasingCurve::Bezier([0.3, -0.05, 0.7, -0.5]),
+            ("OUT_BACK_CUBIC", _) => EasingCurve::Bezier([0.45, 1.45, 0.8, 1.0]),
+            ("INOUT_BACK_CUBIC", _) => EasingCurve::Bezier([0.7, -0.4, 0.4, 1.4]),
+            // Figma's presets: gentle, quick, bouncy and slow
+            ("SPRING" | "GENTLE_SPRING" | "CUSTOM_SPRING", _) => spring(1.0, 100.0, 15.0),
+            ("SPRING_PRESET_ONE", _) => spring(1.0, 300.0, 20.0),
+            ("SPRING_PRESET_TWO", _) => spring(1.0, 600.0, 15.0),
+            ("SPRING_PRESET_THREE", _) => spring(1.0, 80.0, 20.0),
+            _ => EasingCurve::LINEAR,
+        }
+    }
+
+    /// Eased progress at `progress`, a fraction of the transition's time
+    /// from 0 to 1. Springs run for the time they take to settle, and
+    /// overshoot 1 when they bounce, as some bezier curves do.
+    pub fn ease(&self, progress: f64) -> f64 {
+        if progress <= 0.0 || progress >= 1.0 {
+            return progress.clamp(0.0, 1.0);
+        }
+        match *self {
+            EasingCurve::Bezier([x1, y1, x2, y2]) if x1 == y1 && x2 == y2 => progress,
+            EasingCurve::Bezier([x1, y1, x2, y2]) => {
+                // x grows with s for control points in 0..=1, so bisect for
+                // the s where x reaches progress
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..48 {
+                    let s = (low + high) / 2.0;
+                    if cubic(x1, x2, s) < progress {
+                        low = s;
+                    } else {
+                        high = s;
+                    }
+                }
+                cubic(y1, y2, (low + high) / 2.0)
+            }
+            EasingCurve::Spring { .. } => self.spring_position(progress * self.settle_time()),
+        }
+    }
+
+    /// Position of a spring released from 0 towards 1, `time` seconds in
+    fn spring_position(&self, time: f64) -> f64 {
+        let EasingCurve::Spring { mass, stiffness, damping } = *self else { return 1.0 };
+        let natural = (stiffness / mass).sqrt();
+        let ratio = damping / (2.0 * (stiffness * mass).sqrt());
+        if (ratio - 1.0).abs() < 1e-6 {
+            1.0 - (-natural * time).exp() * (1.0 + natural * time)
+        } else if ratio < 1.0 {
+            let damped = natural * (1.0 - ratio * ratio).sqrt();
+            1.0 - (-ratio * natural * time).exp()
+                * ((damped * time).cos() + ratio * natural / damped * (damped * time).sin())
+        } else {
+            let root = (ratio * ratio - 1.0).sqrt();
+            let (r1, r2) = (-natural * (ratio - root), -natural * (ratio + root));
+            1.0 - (r2 * (r1 * time).exp() - r1 * (r2 * time).exp()) / (r2 - r1)
+        }
+    }
+
+    /// Seconds until a spring stays within a thousandth of its target
+    fn settle_time(&self) -> f64 {
+        const STEP: f64 = 1.0 / 120.0;
+        let mut settled = STEP;
+        for step in 1..1200 {
+            let time = step as f64 * STEP;
+            if (1.0 - self.spring_position(time)).abs() >= 0.001 {
+                settled = time + STEP;
+            }
+        }
+        settled
+    }
+}
+
+/// A 1D cubic bezier from 0 to 1 with control values `a` and `b`
+fn cubic(a: f64, b: f64, s: f64) -> f64 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+}
+
+/// How an action animates to its destination
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub transition_type: TransitionType,
+    /// Seconds
+    pub duration: f64,
+    /// Schema `EasingType` name, e.g. "OUT_CUBIC"
+    pub easing_type: String,
+    pub curve: EasingCurve,
+    /// Layers with matching names animate too, as with smart animate
+    pub smart_animate: bool,
+}
+
+impl Transition {
+    /// Read from a `PrototypeAction`, or a node's legacy link fields.
+    /// Instant transitions are left out.
+    pub fn from_kiwi(action: &KiwiMessage) -> Option<Self> {
+        let smart_animate = action.bool("transitionShouldSmartAnimate").unwrap_or(false);
+        let mut transition_type = TransitionType::from_name(action.str("transitionType").unwrap_or_default());
+        if smart_animate && transition_type == TransitionType::Instant {
+            transition_type = TransitionType::SmartAnimate;
+        }
+        if transition_type == TransitionType::Instant {
+            return None;
+        }
+        let easing_type = action.str("easingType").unwrap_or("OUT_CUBIC").to_string();
+        let function: Vec<f64> = action.array("easingFunction").iter().filter_map(KiwiValue::as_f64).collect();
+        Some(Transition {
+            transition_type,
+            duration: action.float("transitionDuration").unwrap_or(0.3),
+            curve: EasingCurve::from_kiwi(&easing_type, &function),
+            easing_type,
+            smart_animate: smart_animate || transition_type == TransitionType::SmartAnimate,
+        })
+    }
+
+    /// Rebuild a transition passed back from Flutter
+    pub fn from_info(info: &PrototypeTransitionInfo) -> Self {
+        let curve = match (&info.bezier, info.spring) {
+            (Some(bezier), _) if bezier.len() == 4 => EasingCurve::Bezier([bezier[0], bezier[1], bezier[2], bezier[3]]),
+            (_, Some(spring)) => EasingCurve::Spring { mass: spring.mass, stiffness: spring.stiffness, damping: spring.damping },
+            _ => EasingCurve::from_kiwi(&info.easing_type, &[]),
+        };
+        Transition {
+            transition_type: TransitionType::from_name(&info.transition_type),
+            duration: info.duration,
+            easing_type: info.easing_type.clone(),
+            curve,
+            smart_animate: info.smart_animate,
+        }
+    }
+
+    pub fn to_transition_info(&self) -> PrototypeTransitionInfo {
+        PrototypeTransitionInfo {
+            transition_type: self.transition_type.name(),
+            duration: self.duration,
+            easing_type: self.easing_type.clone(),
+            bezier: match self.curve {
+                EasingCurve::Bezier(points) => Some(points.to_vec()),
+                EasingCurve::Spring { .. } => None,
+            },
+            spring: match self.curve {
+                EasingCurve::Spring { mass, stiffness, damping } => Some(SpringInfo { mass, stiffness, damping }),
+                EasingCurve::Bezier(_) => None,
+            },
+            smart_animate: self.smart_animate,
+        }
+    }
+}
+
+/// Where an action that leads to a node goes (schema `NavigationType`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Navigation {
+    #[default]
+    Navigate,
+    /// Open the destination as an overlay
+    Overlay,
+    /// Replace the open overlay with the destination
+    Swap,
+    /// Change an instance to another variant of its component
+    SwapState,
+    /// Scroll to a node in the current frame
+    ScrollTo,
+}
+
+impl Navigation {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "OVERLAY" => Navigation::Overlay,
+            "SWAP" => Navigation::Swap,
+            "SWAP_STATE" => Navigation::SwapState,
+            "SCROLL_TO" => Navigation::ScrollTo,
+            _ => Navigation::Navigate,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Navigation::Navigate => "NAVIGATE",
+            Navigation::Overlay => "OVERLAY",
+            Navigation::Swap => "SWAP",
+            Navigation::SwapState => "SWAP_STATE",
+            Navigation::ScrollTo => "SCROLL_TO",
+        }
+    }
+
+    /// Whether the destination is a frame shown in place of, or over, the
+    /// current one
+    pub fn opens_frame(self) -> bool {
+        matches!(self, Navigation::Navigate | Navigation::Overlay | Navigation::Swap)
+    }
+}
+
+/// A `PrototypeAction`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Node {
+        navigation: Navigation,
+        /// None when the link's destination was deleted
+        destination: Option<String>,
+        transition: Option<Transition>,
+        preserve_scroll: bool,
+        /// For overlays placed manually, the overlay's position relative to
+        /// the hotspot; when scrolling to a node, an extra offset
+        offset: Option<(f64, f64)>,
+    },
+    Back,
+    Close,
+    Url { url: String, new_tab: bool },
+    /// Branches of a conditional action, each with the actions it runs.
+    /// The conditions themselves are expressions on variables and are not
+    /// evaluated.
+    Conditional(Vec<Vec<Action>>),
+    /// Any other `ConnectionType`, such as setting a variable
+    Other(String),
+}
+
+impl Action {
+    pub fn from_kiwi(action: &KiwiMessage) -> Self {
+        let destination = action.guid("transitionNodeID");
+        let connection = action.str("connectionType")
+            .unwrap_or(if destination.is_some() { "INTERNAL_NODE" } else { "NONE" });
+        match connection {
+            "INTERNAL_NODE" => Action::Node {
+                navigation: match action.str("navigationType") {
+                    Some(name) => Navigation::from_name(name),
+                    None if action.bool("destinationIsOverlay").unwrap_or(false) => Navigation::Overlay,
+                    None => Navigation::Navigate,
+                },
+                // Unset links point at the largest session id
+                destination: destination.filter(|id| !id.starts_with(&format!("{}:", u32::MAX))),
+                transition: Transition::from_kiwi(action),
+                preserve_scroll: action.bool("transitionPreserveScroll").unwrap_or(false),
+                offset: action.message("overlayRelativePosition")
+                    .or_else(|| action.message("extraScrollOffset"))
+                    .and_then(|offset| Some((offset.float("x")?, offset.float("y")?))),
+            },
+            "BACK" => Action::Back,
+            "CLOSE" => Action::Close,
+            "URL" => Action::Url {
+                url: action.str("connectionURL").unwrap_or_default().to_string(),
+                new_tab: action.bool("openUrlInNewTab").unwrap_or(true),
+            },
+            "CONDITIONAL" => Action::Conditional(action.array("conditionalActions").iter()
+                .filter_map(KiwiValue::as_message)
+                .map(|branch| Action::list_from_kiwi(branch.array("actions")))
+                .collect()),
+            other => Action::Other(other.to_string()),
+        }
+    }
+
+    pub fn list_from_kiwi(actions: &[KiwiValue]) -> Vec<Action> {
+        actions.iter().filter_map(KiwiValue::as_message).map(Action::from_kiwi).collect()
+    }
+
+    /// Frames this action can open, including from conditional branches
+    fn destinations<'a>(&'a self, found: &mut Vec<&'a str>) {
+        match self {
+            Action::Node { navigation, destination: Some(destination), .. } if navigation.opens_frame() => {
+                found.push(destination);
+            }
+            Action::Conditional(branches) => {
+                for action in branches.iter().flatten() {
+                    action.destinations(found);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn to_action_info(&self, file: &FigFile) -> PrototypeActionInfo {
+        let mut info = PrototypeActionInfo {
+            action_type: String::new(),
+            destination_id: None,
+            transition: None,
+            preserve_scroll: false,
+            offset: None,
+            overlay: None,
+            url: None,
+            open_in_new_tab: false,
+            branches: Vec::new(),
+        };
+        match self {
+            Action::Node { navigation, destination, transition, preserve_scroll, offset } => {
+                info.action_type = navigation.name().to_string();
+                info.destination_id = destination.clone();
+                info.transition = transition.as_ref().map(Transition::to_transition_info);
+                info.preserve_scroll = *preserve_scroll;
+                info.offset = offset.map(|(x, y)| PointInfo { x, y });
+                if matches!(navigation, Navigation::Overlay | Navigation::Swap) {
+                    info.overlay = destination.as_deref()
+                        .and_then(|id| file.get_node(id))
+                        .map(|node| node.overlay.clone().unwrap_or_default().to_overlay_info());
+                }
+            }
+            Action::Back => info.action_type = "BACK".into(),
+            Action::Close => info.action_type = "CLOSE".into(),
+            Action::Url { url, new_tab } => {
+                info.action_type = "URL".into();
+                info.url = Some(url.clone());
+                info.open_in_new_tab = *new_tab;
+            }
+            Action::Conditional(branches) => {
+                info.action_type = "CONDITIONAL".into();
+                info.branches = branches.iter()
+                    .map(|actions| actions.iter().map(|action| action.to_action_info(file)).collect())
+                    .collect();
+            }
+            Action::Other(connection) => info.action_type = connection.clone(),
+        }
+        info
+    }
+}
+
+/// What sets off an interaction (schema `PrototypeEvent`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trigger {
+    pub trigger_type: TriggerType,
+    /// For `AfterTimeout`, seconds before the actions run
+    pub timeout: f64,
+    /// For mouse enter and leave, seconds the pointer must stay
+    pub delay: f64,
+    /// For `OnKeyDown`, the key codes pressed together
+    pub key_codes: Vec<i64>,
+}
+
+impl Trigger {
+    pub fn from_kiwi(event: &KiwiMessage) -> Self {
+        Trigger {
+            trigger_type: TriggerType::from_name(event.str("interactionType").unwrap_or_default()),
+            timeout: event.float("transitionTimeout").unwrap_or(0.0),
+            delay: event.float("interactionDuration").unwrap_or(0.0),
+            key_codes: event.message("keyTrigger").into_iter()
+                .flat_map(|trigger| trigger.array("keyCodes"))
+                .filter_map(|code| code.as_f64().map(|code| code as i64))
+                .collect(),
+        }
+    }
+}
+
+/// A `PrototypeInteraction`: a trigger and the actions it runs in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub trigger: Trigger,
+    pub actions: Vec<Action>,
+}
+
+impl Interaction {
+    /// Build interactions from a decoded `PrototypeInteraction[]` field,
+    /// leaving out deleted ones
+    pub fn list_from_kiwi(interactions: &KiwiValue) -> Vec<Interaction> {
+        interactions.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter(|interaction| !interaction.bool("isDeleted").unwrap_or(false))
+            .map(|interaction| Interaction {
+                trigger: interaction.message("event").map(Trigger::from_kiwi).unwrap_or_default(),
+                actions: Action::list_from_kiwi(interaction.array("actions")),
+            })
+            .collect()
+    }
+
+    /// The link in a node's legacy fields, if it has one
+    pub fn from_legacy(fields: &KiwiMessage) -> Option<Interaction> {
+        fields.get("transitionNodeID")?;
+        let mut trigger = Trigger::from_kiwi(fields);
+        if trigger.trigger_type == TriggerType::None {
+            trigger.trigger_type = TriggerType::OnClick;
+        }
+        Some(Interaction { trigger, actions: vec![Action::from_kiwi(fields)] })
+    }
+
+    pub fn to_interaction_info(&self, node_id: &str, file: &FigFile) -> InteractionInfo {
+        InteractionInfo {
+            node_id: node_id.to_string(),
+            trigger: TriggerInfo {
+                trigger_type: self.trigger.trigger_type.name().to_string(),
+                timeout: self.trigger.timeout,
+                delay: self.trigger.delay,
+                key_codes: self.trigger.key_codes.clone(),
+            },
+            actions: self.actions.iter().map(|action| action.to_action_info(file)).collect(),
+        }
+    }
+}
+
+/// Where a frame appears when opened as an overlay
+#[derive(Debug, Clone)]
+pub struct OverlaySettings {
+    /// Schema `OverlayPositionType`, e.g. "CENTER", "BOTTOM_LEFT", "MANUAL"
+    pub position: String,
+    /// For manually placed overlays, the offset from the hotspot
+    pub offset: (f64, f64),
+    pub close_on_click_outside: bool,
+    /// Color drawn over the frame beneath, if any
+    pub background: Option<ColorInfo>,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        OverlaySettings { position: "CENTER".into(), offset: (0.0, 0.0), close_on_click_outside: false, background: None }
+    }
+}
+
+impl OverlaySettings {
+    /// Read from a frame's overlay fields
+    pub fn from_kiwi(fields: &KiwiMessage) -> Self {
+        OverlaySettings {
+            position: fields.str("overlayPositionType").unwrap_or("CENTER").to_string(),
+            offset: fields.message("overlayRelativePosition")
+                .map(|offset| (offset.float("x").unwrap_or(0.0), offset.float("y").unwrap_or(0.0)))
+                .unwrap_or_default(),
+            close_on_click_outside: fields.str("overlayBackgroundInteraction") == Some("CLOSE_ON_CLICK_OUTSIDE"),
+            background: fields.message("overlayBackgroundAppearance")
+                .filter(|appearance| appearance.str("backgroundType") == Some("SOLID_COLOR"))
+                .and_then(|appearance| appearance.message("backgroundColor"))
+                .map(color_from_kiwi),
+        }
+    }
+
+    pub fn to_overlay_info(&self) -> OverlayInfo {
+        OverlayInfo {
+            position: self.position.clone(),
+            offset: PointInfo { x: self.offset.0, y: self.offset.1 },
+            close_on_click_outside: self.close_on_click_outside,
+            background: self.background.clone(),
+        }
+    }
+}
+
+/// A `PrototypeStartingPoint`, set on the frame a flow starts from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlowStart {
+    pub name: String,
+    pub description: String,
+    /// Fractional index ordering the flows of a page
+    pub position: String,
+}
+
+impl FlowStart {
+    pub fn from_kiwi(start: &KiwiMessage) -> Self {
+        FlowStart {
+            name: start.str("name").unwrap_or_default().to_string(),
+            description: start.str("description").unwrap_or_default().to_string(),
+            position: start.str("position").unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// The flows of each page, in page order and then in the order Figma lists
+/// them
+pub fn flows(file: &FigFile) -> Vec<FlowInfo> {
+    let mut flows = Vec::new();
+    for page in file.get_pages() {
+        let mut starts = Vec::new();
+        let mut pending = vec![page];
+        while let Some(node) = pending.pop() {
+            if let Some(start) = &node.flow_start {
+                starts.push((node, start));
+            }
+            // Flows start from top-level frames, which may sit in sections
+            if node.id == page.id || node.node_type == "SECTION" {
+                pending.extend(node.children.iter().rev().filter_map(|id| file.get_node(id)));
+            }
+        }
+        starts.sort_by(|(_, a), (_, b)| a.position.cmp(&b.position));
+        flows.extend(starts.into_iter().map(|(node, start)| FlowInfo {
+            node_id: node.id.clone(),
+            page_id: page.id.clone(),
+            name: start.name.clone(),
+            description: start.description.clone(),
+        }));
+    }
+    flows
+}
+
+/// The top-level frame a node is in, which is what navigating to it shows
+pub fn screen_of<'a>(file: &'a FigFile, id: &str) -> Option<&'a FigmaNode> {
+    let mut node = file.get_node(id)?;
+    loop {
+        let parent = file.get_node(node.parent_id.as_deref()?)?;
+        if matches!(parent.node_type.as_str(), "CANVAS" | "SECTION") {
+            return (!matches!(node.node_type.as_str(), "SECTION")).then_some(node);
+        }
+        node = parent;
+    }
+}
+
+/// A node and its visible descendants that react to triggers, with their
+/// interactions, parents first
+pub fn hotspots<'a>(file: &'a FigFile, root_id: &str) -> Result<Vec<(&'a FigmaNode, &'a Interaction)>> {
+    let root = file.get_node(root_id).ok_or_else(|| FigmaError::NodeNotFound(root_id.to_string()))?;
+    let mut hotspots = Vec::new();
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        hotspots.extend(node.interactions.iter().map(|interaction| (node, interaction)));
+        pending.extend(node.children.iter().rev().filter_map(|id| file.get_node(id)).filter(|child| child.visible));
+    }
+    Ok(hotspots)
+}
+
+/// The frames a prototype can get to from `start_id`, following navigate,
+/// overlay and swap actions breadth first. `start_id` comes first.
+pub fn reachable_frames(file: &FigFile, start_id: &str) -> Result<Vec<String>> {
+    let start = file.get_node(start_id).ok_or_else(|| FigmaError::NodeNotFound(start_id.to_string()))?;
+    let mut seen = HashSet::from([start.id.as_str()]);
+    let mut frames = vec![start.id.clone()];
+    let mut pending = VecDeque::from([start]);
+    while let Some(frame) = pending.pop_front() {
+        let mut destinations = Vec::new();
+        for (_, interaction) in hotspots(file, &frame.id)? {
+            for action in &interaction.actions {
+                action.destinations(&mut destinations);
+            }
+        }
+        for destination in destinations {
+            let Some(screen) = screen_of(file, destination) else { continue };
+            if seen.insert(screen.id.as_str()) {
+                frames.push(screen.id.clone());
+                pending.push_back(screen);
+            }
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_transition_names() {
+        for name in ["INSTANT_TRANSITION", "DISSOLVE", "SLIDE_FROM_LEFT", "SLIDE_OUT_TO_BOTTOM", "PUSH_FROM_RIGHT", "MOVE_FROM_TOP", "MOVE_OUT_TO_LEFT", "SMART_ANIMATE", "SCROLL_ANIMATE"] {
+            assert_eq!(TransitionType::from_name(name).name(), name);
+        }
+        assert_eq!(TransitionType::from_name("MAGIC_MOVE"), TransitionType::SmartAnimate);
+        assert_eq!(TransitionType::from_name("FADE"), TransitionType::Dissolve);
+    }
+
+    #[test]
+    fn test_easing_curves() {
+        assert_eq!(EasingCurve::from_kiwi("INOUT_CUBIC", &[]), EasingCurve::Bezier([0.42, 0.0, 0.58, 1.0]));
+        assert_eq!(EasingCurve::from_kiwi("CUSTOM_CUBIC", &[0.1, 0.2, 0.3, 0.4]), EasingCurve::Bezier([0.1, 0.2, 0.3, 0.4]));
+        assert_eq!(
+            EasingCurve::from_kiwi("CUSTOM_SPRING", &[1.0, 200.0, 10.0]),
+            EasingCurve::Spring { mass: 1.0, stiffness: 200.0, damping: 10.0 },
+        );
+        assert_eq!(EasingCurve::from_kiwi("SPRING_PRESET_TWO", &[]), EasingCurve::Spring { mass: 1.0, stiffness: 600.0, damping: 15.0 });
+        assert_eq!(EasingCurve::from_kiwi("LINEAR", &[]), EasingCurve::LINEAR);
+    }
+
+    #[test]
+    fn test_ease() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-3;
+        assert!(close(EasingCurve::LINEAR.ease(0.3), 0.3));
+        // CSS ease-in-out is symmetric, and ease-out is ahead of linear
+        let in_out = EasingCurve::from_kiwi("INOUT_CUBIC", &[]);
+        assert!(close(in_out.ease(0.5), 0.5));
+        assert!(close(in_out.ease(0.25) + in_out.ease(0.75), 1.0));
+        assert!(EasingCurve::from_kiwi("OUT_CUBIC", &[]).ease(0.25) > 0.35);
+        // Back curves overshoot
+        assert!(EasingCurve::from_kiwi("OUT_BACK_CUBIC", &[]).ease(0.7) > 1.0);
+
+        for curve in [EasingCurve::from_kiwi("GENTLE_SPRING", &[]), EasingCurve::Spring { mass: 1.0, stiffness: 100.0, damping: 40.0 }] {
+            assert_eq!(curve.ease(0.0), 0.0);
+            assert_eq!(curve.ease(1.0), 1.0);
+            assert!((curve.ease(0.999) - 1.0).abs() < 0.002);
+        }
+        // A bouncy spring overshoots, a heavily damped one doesn't
+        let bouncy = EasingCurve::from_kiwi("SPRING_PRESET_TWO", &[]);
+        assert!((1..100).any(|i| bouncy.ease(i as f64 / 100.0) > 1.01));
+        let damped = EasingCurve::Spring { mass: 1.0, stiffness: 100.0, damping: 40.0 };
+        assert!((1..100).all(|i| damped.ease(i as f64 / 100.0) <= 1.0));
+    }
+
+    #[test]
+    fn test_prototype() {
+        let mut file = test_fixture::expanded().clone();
+
+        // Toggles swap to their other variant with a smart animate
+        let toggle = file.get_node("128:47038").unwrap();
+        assert_eq!(toggle.interactions.len(), 1);
+        assert_eq!(toggle.interactions[0].trigger.trigger_type, TriggerType::OnClick);
+        let Action::Node { navigation, destination, transition: Some(transition), .. } = &toggle.interactions[0].actions[0] else {
+            panic!("expected a transition to a node");
+        };
+        assert_eq!((*navigation, destination.as_deref()), (Navigation::SwapState, Some("128:47044")));
+        assert_eq!(transition.transition_type, TransitionType::SmartAnimate);
+        assert!((transition.duration - 0.2).abs() < 1e-6);
+        assert_eq!(transition.easing_type, "OUT_CUBIC");
+        let EasingCurve::Bezier(points) = transition.curve else { panic!("expected a bezier") };
+        assert!((points[2] - 0.58).abs() < 1e-6);
+        // Swapping variants doesn't leave the frame
+        assert_eq!(reachable_frames(&file, "128:47034").unwrap(), ["128:47034"]);
+
+        // A flow from Home to Details, which opens Sheet as an overlay after
+        // a second; Sheet leads back to Home. Unreached is never shown.
+        let page = file.page_ids[0].clone();
+        let frame = |id: &str, name: &str, children: &[&str]| FigmaNode {
+            id: id.into(),
+            parent_id: Some(page.clone()),
+            name: name.into(),
+            node_type: "FRAME".into(),
+            visible: true,
+            children: children.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        };
+        let link = |navigation, destination: &str, transition| Action::Node {
+            navigation,
+            destination: Some(destination.into()),
+            transition,
+            preserve_scroll: false,
+            offset: None,
+        };
+        let trigger = |trigger_type, timeout| Trigger { trigger_type, timeout, ..Default::default() };
+
+        let mut home = frame("1:900001", "Home", &["1:900002"]);
+        home.flow_start = Some(FlowStart { name: "Onboarding".into(), description: "First run".into(), position: "!".into() });
+        let mut button = frame("1:900002", "Button", &[]);
+        button.parent_id = Some(home.id.clone());
+        let push = Transition {
+            transition_type: TransitionType::from_name("PUSH_FROM_RIGHT"),
+            duration: 0.3,
+            easing_type: "GENTLE_SPRING".into(),
+            curve: EasingCurve::from_kiwi("GENTLE_SPRING", &[]),
+            smart_animate: false,
+        };
+        button.interactions.push(Interaction {
+            trigger: trigger(TriggerType::OnClick, 0.0),
+            // Linking to a layer shows the frame it's in
+            actions: vec![link(Navigation::Navigate, "1:900004", Some(push))],
+        });
+        let mut details = frame("1:900003", "Details", &["1:900004"]);
+        details.interactions.push(Interaction {
+            trigger: trigger(TriggerType::AfterTimeout, 1.0),
+            actions: vec![link(Navigation::Overlay, "1:900005", None)],
+        });
+        let mut title = frame("1:900004", "Title", &[]);
+        title.parent_id = Some(details.id.clone());
+        let mut sheet = frame("1:900005", "Sheet", &[]);
+        sheet.overlay = Some(OverlaySettings { position: "BOTTOM_CENTER".into(), close_on_click_outside: true, ..Default::default() });
+        sheet.interactions.push(Interaction {
+            trigger: trigger(TriggerType::OnClick, 0.0),
+            actions: vec![
+                Action::Back,
+                Action::Conditional(vec![vec![link(Navigation::Navigate, "1:900001", None)], vec![Action::Close]]),
+            ],
+        });
+        let unreached = frame("1:900006", "Unreached", &[]);
+        for node in [home, button, details, title, sheet, unreached] {
+            if node.parent_id.as_deref() == Some(page.as_str()) {
+                file.get_node_mut(&page).unwrap().children.push(node.id.clone());
+            }
+            file.nodes.insert(node.id.clone(), node);
+        }
+
+        let flows = flows(&file);
+        assert_eq!(flows.len(), 1);
+        assert_eq!((flows[0].node_id.as_str(), flows[0].page_id.as_str(), flows[0].name.as_str()), ("1:900001", page.as_str(), "Onboarding"));
+        assert_eq!(reachable_frames(&file, "1:900001").unwrap(), ["1:900001", "1:900003", "1:900005"]);
+        assert!(matches!(reachable_frames(&file, "1:1234567"), Err(FigmaError::NodeNotFound(_))));
+
+        // Hotspots are the frame's descendants
+        let hotspots = hotspots(&file, "1:900001").unwrap();
+        assert_eq!(hotspots.len(), 1);
+        assert_eq!(hotspots[0].0.id, "1:900002");
+        let info = hotspots[0].1.to_interaction_info("1:900002", &file);
+        assert_eq!(info.trigger.trigger_type, "ON_CLICK");
+        let transition = info.actions[0].transition.as_ref().unwrap();
+        assert_eq!(transition.transition_type, "PUSH_FROM_RIGHT");
+        assert!(transition.bezier.is_none());
+        assert_eq!(transition.spring.map(|spring| spring.stiffness), Some(100.0));
+
+        // Overlays say where they appear
+        let info = file.get_node("1:900003").unwrap().interactions[0].to_interaction_info("1:900003", &file);
+        assert_eq!((info.trigger.trigger_type.as_str(), info.trigger.timeout), ("AFTER_TIMEOUT", 1.0));
+        assert_eq!(info.actions[0].action_type, "OVERLAY");
+        let overlay = info.actions[0].overlay.as_ref().unwrap();
+        assert_eq!(overlay.position, "BOTTOM_CENTER");
+        assert!(overlay.close_on_click_outside);
+
+        let info = file.get_node("1:900005").unwrap().interactions[0].to_interaction_info("1:900005", &file);
+        let types: Vec<_> = info.actions.iter().map(|action| action.action_type.as_str()).collect();
+        assert_eq!(types, ["BACK", "CONDITIONAL"]);
+        assert_eq!(info.actions[1].branches[1][0].action_type, "CLOSE");
+    }
+}
@@ -1,9 +1,39 @@
 //! Render tree and draw commands
 
-use crate::api::DrawCommand;
+use crate::api::{DrawCommand, LayerInfo, PathData};
+use crate::fonts::FontRegistry;
+use crate::images::ImageStore;
 use crate::nodes::FigmaNode;
+use crate::scroll::ScrollOffsets;
+use crate::variables::VariableResolver;
 use std::collections::HashMap;
 
+/// Document resources that draw commands depend on besides the nodes
+#[derive(Clone, Copy)]
+pub struct RenderContext<'a> {
+    pub images: &'a ImageStore,
+    pub fonts: &'a FontRegistry,
+    /// Binds variables to node properties in the modes picked
+    pub variables: Option<VariableResolver<'a>>,
+    /// Scroll positions of scrolling frames
+    pub scroll_offsets: Option<&'a ScrollOffsets>,
+}
+
+#[cfg(test)]
+impl RenderContext<'static> {
+    /// No images, fonts, variables or scrolling
+    pub fn empty() -> Self {
+        static IMAGES: std::sync::OnceLock<ImageStore> = std::sync::OnceLock::new();
+        static FONTS: std::sync::OnceLock<FontRegistry> = std::sync::OnceLock::new();
+        RenderContext {
+            images: IMAGES.get_or_init(ImageStore::new),
+            fonts: FONTS.get_or_init(FontRegistry::new),
+            variables: None,
+            scroll_offsets: None,
+        }
+    }
+}
+
 /// Render tree built from node hierarchy
 pub struct RenderTree {
     pub root_id: String,
@@ -17,10 +47,14 @@ pub struct RenderNode {
     pub absolute_y: f64,
     pub width: f64,
     pub height: f64,
+    /// The node's own opacity; a layer applies it to the whole subtree
     pub opacity: f64,
-    pub clip: bool,
+    /// For frames that clip their children, the area they're clipped to
+    pub clip: Option<PathData>,
     pub children: Vec<String>,
     pub draw_command: Option<DrawCommand>,
+    /// Set when the node and its children are drawn into a layer
+    pub layer: Option<LayerInfo>,
 }
 
 impl RenderTree {
@@ -32,7 +66,7 @@ impl RenderTree {
         let mut render_nodes = HashMap::new();
         
         if let Some(root) = nodes.get(root_id) {
-            build_render_node(root, nodes, 0.0, 0.0, 1.0, &mut render_nodes);
+            build_render_node(root, nodes, 0.0, 0.0, &mut render_nodes);
         }
         
         RenderTree {
@@ -50,13 +84,28 @@ impl RenderTree {
     
     fn collect_commands(&self, node_id: &str, commands: &mut Vec<DrawCommand>) {
         if let Some(node) = self.nodes.get(node_id) {
+            if let Some(layer) = &node.layer {
+                commands.push(layer_command("begin_layer", Some(layer.clone())));
+            }
             if let Some(cmd) = &node.draw_command {
                 commands.push(cmd.clone());
             }
-            
+            let clip = node.clip.as_ref().filter(|_| !node.children.is_empty());
+            if let Some(clip) = clip {
+                let mut begin = layer_command("begin_clip", None);
+                begin.clip_path = Some(clip.clone());
+                commands.push(begin);
+            }
+
             for child_id in &node.children {
                 self.collect_commands(child_id, commands);
             }
+            if clip.is_some() {
+                commands.push(layer_command("end_clip", None));
+            }
+            if node.layer.is_some() {
+                commands.push(layer_command("end_layer", None));
+            }
         }
     }
 }
@@ -66,12 +115,10 @@ fn build_render_node(
     all_nodes: &HashMap<String, FigmaNode>,
     parent_x: f64,
     parent_y: f64,
-    parent_opacity: f64,
     render_nodes: &mut HashMap<String, RenderNode>,
 ) {
     let absolute_x = parent_x + node.x;
     let absolute_y = parent_y + node.y;
-    let opacity = parent_opacity * node.opacity;
     
     let render_node = RenderNode {
         id: node.id.clone(),
@@ -79,10 +126,11 @@ fn build_render_node(
         absolute_y,
         width: node.width,
         height: node.height,
-        opacity,
-        clip: false, // TODO: Check clip property
+        opacity: node.opacity,
+        clip: node.clips_content().then(|| node.clip_path()),
         children: node.children.clone(),
         draw_command: node.to_draw_command(),
+        layer: node.layer(node.blends_with_backdrop(all_nodes)).filter(|_| node.visible),
     };
     
     render_nodes.insert(node.id.clone(), render_node);
@@ -90,7 +138,25 @@ fn build_render_node(
     // Build children
     for child_id in &node.children {
         if let Some(child) = all_nodes.get(child_id) {
-            build_render_node(child, all_nodes, absolute_x, absolute_y, opacity, render_nodes);
+            build_render_node(child, all_nodes, absolute_x, absolute_y, render_nodes);
         }
     }
 }
+
+fn layer_command(command_type: &str, layer: Option<LayerInfo>) -> DrawCommand {
+    DrawCommand {
+        command_type: command_type.to_string(),
+        path: None,
+        rect: None,
+        fills: vec![],
+        strokes: vec![],
+        stroke_weight: 0.0,
+        effects: vec![],
+        transform: Default::default(),
+        clip_path: None,
+        image: None,
+        text: None,
+        stroke: None,
+        layer,
+    }
+}
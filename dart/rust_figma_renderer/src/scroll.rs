@@ -0,0 +1,246 @@
+//! Scrolling frames
+//!
+//! In a prototype, a frame with a `scrollDirection` scrolls when its content
+//! overflows it. Each child says how it behaves as the frame scrolls
+//! (`scrollBehavior`): it moves with the content, stays fixed, or moves
+//! until it reaches the frame's top edge and sticks there.
+
+use crate::api::RectInfo;
+use crate::kiwi::{node_matrix, FigFile};
+use crate::nodes::FigmaNode;
+use crate::{FigmaError, Result};
+use std::collections::HashMap;
+
+/// Scroll positions by frame id, as x and y offsets of the content
+pub type ScrollOffsets = HashMap<String, (f64, f64)>;
+
+/// Axes a frame scrolls along (schema `ScrollDirection`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScrollDirection {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl ScrollDirection {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "HORIZONTAL" => ScrollDirection::Horizontal,
+            "VERTICAL" => ScrollDirection::Vertical,
+            "BOTH" => ScrollDirection::Both,
+            _ => ScrollDirection::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScrollDirection::None => "NONE",
+            ScrollDirection::Horizontal => "HORIZONTAL",
+            ScrollDirection::Vertical => "VERTICAL",
+            ScrollDirection::Both => "BOTH",
+        }
+    }
+
+    pub fn horizontal(self) -> bool {
+        matches!(self, ScrollDirection::Horizontal | ScrollDirection::Both)
+    }
+
+    pub fn vertical(self) -> bool {
+        matches!(self, ScrollDirection::Vertical | ScrollDirection::Both)
+    }
+}
+
+/// How a child moves when its frame scrolls (schema `ScrollBehavior`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScrollBehavior {
+    #[default]
+    Scrolls,
+    /// Stays where it is, like a tab bar
+    Fixed,
+    /// Scrolls until it reaches the frame's top edge, like a list header
+    Sticky,
+}
+
+impl ScrollBehavior {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "FIXED_WHEN_CHILD_OF_SCROLLING_FRAME" => ScrollBehavior::Fixed,
+            "STICKY_SCROLLS" => ScrollBehavior::Sticky,
+            _ => ScrollBehavior::Scrolls,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScrollBehavior::Scrolls => "SCROLLS",
+            ScrollBehavior::Fixed => "FIXED_WHEN_CHILD_OF_SCROLLING_FRAME",
+            ScrollBehavior::Sticky => "STICKY_SCROLLS",
+        }
+    }
+}
+
+/// The box holding a frame's scrolling content in the frame's coordinates:
+/// the frame itself and its visible children that aren't fixed, with auto
+/// layout padding after the last child
+pub fn content_bounds(file: &FigFile, frame: &FigmaNode) -> RectInfo {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0_f64, 0.0_f64, frame.width, frame.height);
+    let (padding_right, padding_bottom) = match frame.layout_mode.as_str() {
+        "HORIZONTAL" | "VERTICAL" => (frame.padding[2], frame.padding[3]),
+        _ => (0.0, 0.0),
+    };
+    let children = frame.children.iter()
+        .filter_map(|id| file.get_node(id))
+        .filter(|child| child.visible && child.scroll_behavior != ScrollBehavior::Fixed);
+    for child in children {
+        let t = node_matrix(child);
+        for (x, y) in [(0.0, 0.0), (child.width, 0.0), (0.0, child.height), (child.width, child.height)] {
+            let (x, y) = (t.m00 * x + t.m01 * y + t.m02, t.m10 * x + t.m11 * y + t.m12);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x + padding_right);
+            max_y = max_y.max(y + padding_bottom);
+        }
+    }
+    RectInfo { x: min_x, y: min_y, width: max_x - min_x, height: max_y - min_y, corner_radii: [0.0; 4] }
+}
+
+/// The largest offsets a frame scrolls to, zero along axes it doesn't
+/// scroll
+pub fn max_offset(file: &FigFile, frame: &FigmaNode) -> (f64, f64) {
+    let content = content_bounds(file, frame);
+    let range = |scrolls: bool, end: f64, size: f64| if scrolls { (end - size).max(0.0) } else { 0.0 };
+    (
+        range(frame.scroll_direction.horizontal(), content.x + content.width, frame.width),
+        range(frame.scroll_direction.vertical(), content.y + content.height, frame.height),
+    )
+}
+
+/// An offset limited to the range the frame scrolls over
+pub fn clamp_offset(file: &FigFile, frame: &FigmaNode, (x, y): (f64, f64)) -> (f64, f64) {
+    let (max_x, max_y) = max_offset(file, frame);
+    (x.clamp(0.0, max_x), y.clamp(0.0, max_y))
+}
+
+/// A copy of a scrolling frame's child moved by the frame's offset, or None
+/// if it stays where it is
+pub fn scroll_child(child: &FigmaNode, (dx, dy): (f64, f64)) -> Option<FigmaNode> {
+    if child.scroll_behavior == ScrollBehavior::Fixed || (dx == 0.0 && dy == 0.0) {
+        return None;
+    }
+    let mut moved = child.clone();
+    moved.x -= dx;
+    moved.y -= dy;
+    if child.scroll_behavior == ScrollBehavior::Sticky {
+        moved.y = moved.y.max(child.y.min(0.0));
+    }
+    moved.transform = node_matrix(&moved);
+    Some(moved)
+}
+
+/// A copy of a node moved with every scrolled frame it's in, or None if
+/// none of them move it. A child that stays fixed in its frame stops that
+/// frame's offset for its whole subtree.
+pub fn scroll_descendant(nodes: &HashMap<String, FigmaNode>, offsets: &ScrollOffsets, node: &FigmaNode) -> Option<FigmaNode> {
+    let (mut dx, mut dy) = (0.0, 0.0);
+    let mut child = node;
+    while let Some(parent) = child.parent_id.as_ref().and_then(|id| nodes.get(id)) {
+        if let Some(moved) = offsets.get(&parent.id).and_then(|offset| scroll_child(child, *offset)) {
+            dx += child.x - moved.x;
+            dy += child.y - moved.y;
+        }
+        child = parent;
+    }
+    if dx == 0.0 && dy == 0.0 {
+        return None;
+    }
+    let mut moved = node.clone();
+    moved.x -= dx;
+    moved.y -= dy;
+    moved.transform = node_matrix(&moved);
+    Some(moved)
+}
+
+/// A node and its visible descendants that scroll, parents first
+pub fn scrolling_frames<'a>(file: &'a FigFile, root_id: &str) -> Result<Vec<&'a FigmaNode>> {
+    let root = file.get_node(root_id).ok_or_else(|| FigmaError::NodeNotFound(root_id.to_string()))?;
+    let mut frames = Vec::new();
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        if node.scroll_direction != ScrollDirection::None {
+            frames.push(node);
+        }
+        pending.extend(node.children.iter().rev().filter_map(|id| file.get_node(id)).filter(|child| child.visible));
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{get_scroll_frames, load_figma_file, render_node, set_scroll_offset};
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_scroll_child() {
+        // Fixed children stay, sticky ones stop at the top edge
+        let child = |scroll_behavior, y| FigmaNode { x: 10.0, y, scroll_behavior, ..Default::default() };
+        let moved = scroll_child(&child(ScrollBehavior::Scrolls, 40.0), (5.0, 30.0)).unwrap();
+        assert_eq!((moved.x, moved.y, moved.transform.m02, moved.transform.m12), (5.0, 10.0, 5.0, 10.0));
+        assert!(scroll_child(&child(ScrollBehavior::Fixed, 40.0), (5.0, 30.0)).is_none());
+        assert_eq!(scroll_child(&child(ScrollBehavior::Sticky, 40.0), (0.0, 30.0)).unwrap().y, 10.0);
+        assert_eq!(scroll_child(&child(ScrollBehavior::Sticky, 40.0), (0.0, 60.0)).unwrap().y, 0.0);
+        assert!(scroll_child(&child(ScrollBehavior::Scrolls, 40.0), (0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_scroll_descendant() {
+        // A scrolling list in a scrolling page, and a fixed bar on the page
+        let node = |id: &str, parent: &str, scroll_behavior| {
+            (id.to_string(), FigmaNode { id: id.into(), parent_id: Some(parent.into()), x: 10.0, y: 40.0, scroll_behavior, ..Default::default() })
+        };
+        let nodes = HashMap::from([
+            ("page".to_string(), FigmaNode { id: "page".into(), ..Default::default() }),
+            node("list", "page", ScrollBehavior::Scrolls),
+            node("row", "list", ScrollBehavior::Scrolls),
+            node("header", "list", ScrollBehavior::Fixed),
+            node("bar", "page", ScrollBehavior::Fixed),
+            node("button", "bar", ScrollBehavior::Scrolls),
+        ]);
+        let offsets = ScrollOffsets::from([("page".to_string(), (0.0, 30.0)), ("list".to_string(), (5.0, 0.0))]);
+        let moved = |id: &str| scroll_descendant(&nodes, &offsets, &nodes[id]).map(|node| (node.x, node.y, node.transform.m02, node.transform.m12));
+        assert_eq!(moved("row"), Some((5.0, 10.0, 5.0, 10.0)));
+        assert_eq!(moved("header"), Some((10.0, 10.0, 10.0, 10.0)));
+        assert_eq!(moved("list"), Some((10.0, 10.0, 10.0, 10.0)));
+        assert_eq!(moved("button"), None);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let file = test_fixture::expanded();
+        // A row of tools wider than the area showing it
+        let frame = file.get_node("418:23858").unwrap();
+        assert_eq!(frame.scroll_direction, ScrollDirection::Horizontal);
+        assert!(frame.clips_content());
+        assert!(!file.get_node("478:102203").unwrap().clips_content());
+        let bounds = content_bounds(file, frame);
+        assert_eq!((bounds.width, bounds.height), (360.0, 76.0));
+        assert_eq!(max_offset(file, frame), (52.0, 0.0));
+        assert_eq!(clamp_offset(file, frame, (80.0, 10.0)), (52.0, 0.0));
+        assert_eq!(clamp_offset(file, frame, (-5.0, 0.0)), (0.0, 0.0));
+
+        let doc = load_figma_file(test_fixture::data().to_vec()).unwrap();
+        set_scroll_offset(&doc, "418:23858".into(), 80.0, 0.0).unwrap();
+        let commands = render_node(&doc, "418:23858".into(), true).unwrap();
+        let types: Vec<&str> = commands.iter().map(|command| command.command_type.as_str()).collect();
+        let begin = types.iter().position(|&t| t == "begin_clip").unwrap();
+        assert_eq!(types.last(), Some(&"end_clip"));
+        assert!(commands[begin].clip_path.is_some());
+        assert!(commands[begin + 1..].iter().any(|command| command.transform.m02 < 0.0));
+
+        let frames = get_scroll_frames(&doc, "418:23858".into()).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].direction.as_str(), frames[0].offset.x, frames[0].max_offset.x), ("HORIZONTAL", 52.0, 52.0));
+    }
+}
@@ -0,0 +1,530 @@
+//! Stroke styles and stroke outlines
+//!
+//! Figma strokes can sit inside, outside or centered on a shape's outline,
+//! which most canvases can't draw directly. `stroke_outline` turns a stroke
+//! into geometry to fill: the stroke is tessellated at twice its weight and
+//! clipped to the inside or outside of the shape, leaving the requested half.
+
+use crate::kiwi::{KiwiMessage, KiwiValue};
+use lyon::algorithms::aabb::bounding_box;
+use lyon::math::{point, Box2D, Point};
+use lyon::path::iterator::PathIterator;
+use lyon::path::{Path, PathEvent, Winding};
+use lyon::tessellation::{
+    BuffersBuilder, LineCap, LineJoin, StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+
+/// NodeChange fields that hold stroke styling, decoded into `StrokeStyle`
+pub const STROKE_FIELDS: &[&str] = &[
+    "strokeAlign", "strokeCap", "strokeJoin", "miterLimit", "dashPattern",
+    "borderStrokeWeightsIndependent", "borderTopWeight", "borderRightWeight",
+    "borderBottomWeight", "borderLeftWeight",
+];
+
+/// Curve flattening tolerance in pixels
+const TOLERANCE: f32 = 0.05;
+
+/// Most dashes and gaps cut from one path, beyond which it's drawn solid
+const MAX_DASHES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrokeAlign {
+    #[default]
+    Center,
+    Inside,
+    Outside,
+}
+
+/// Schema `StrokeCap`. Decorative caps (highlight, washi tape) are read as `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrokeCap {
+    #[default]
+    None,
+    Round,
+    Square,
+    ArrowLines,
+    ArrowEquilateral,
+    DiamondFilled,
+    TriangleFilled,
+    CircleFilled,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrokeJoin {
+    #[default]
+    Miter,
+    Bevel,
+    Round,
+}
+
+/// How a node's strokes are drawn. The weight itself is `FigmaNode::stroke_weight`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub align: StrokeAlign,
+    pub cap: StrokeCap,
+    pub join: StrokeJoin,
+    /// Ratio of miter length to stroke weight, as in SVG
+    pub miter_limit: f64,
+    /// Alternating dash and gap lengths; empty for a solid stroke
+    pub dash_pattern: Vec<f64>,
+    /// Weights of the top, right, bottom and left sides when set
+    /// independently (rectangles and frames only)
+    pub side_weights: Option<[f64; 4]>,
+}
+
+/// Geometry to fill in place of a stroke, in the shape's coordinates
+#[derive(Debug, Clone)]
+pub struct StrokeOutline {
+    pub path: Path,
+    /// Clip to apply while filling `path`, with whether it's even-odd
+    pub clip: Option<(Path, bool)>,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            align: StrokeAlign::Center,
+            cap: StrokeCap::None,
+            join: StrokeJoin::Miter,
+            miter_limit: 4.0,
+            dash_pattern: Vec::new(),
+            side_weights: None,
+        }
+    }
+}
+
+impl StrokeAlign {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "INSIDE" => StrokeAlign::Inside,
+            "OUTSIDE" => StrokeAlign::Outside,
+            _ => StrokeAlign::Center,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrokeAlign::Center => "CENTER",
+            StrokeAlign::Inside => "INSIDE",
+            StrokeAlign::Outside => "OUTSIDE",
+        }
+    }
+}
+
+impl StrokeCap {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "ROUND" => StrokeCap::Round,
+            "SQUARE" => StrokeCap::Square,
+            "ARROW_LINES" => StrokeCap::ArrowLines,
+            "ARROW_EQUILATERAL" => StrokeCap::ArrowEquilateral,
+            "DIAMOND_FILLED" => StrokeCap::DiamondFilled,
+            "TRIANGLE_FILLED" => StrokeCap::TriangleFilled,
+            "CIRCLE_FILLED" => StrokeCap::CircleFilled,
+            _ => StrokeCap::None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrokeCap::None => "NONE",
+            StrokeCap::Round => "ROUND",
+            StrokeCap::Square => "SQUARE",
+            StrokeCap::ArrowLines => "ARROW_LINES",
+            StrokeCap::ArrowEquilateral => "ARROW_EQUILATERAL",
+            StrokeCap::DiamondFilled => "DIAMOND_FILLED",
+            StrokeCap::TriangleFilled => "TRIANGLE_FILLED",
+            StrokeCap::CircleFilled => "CIRCLE_FILLED",
+        }
+    }
+
+    /// The line cap to tessellate with. Arrow and marker caps end the line
+    /// flat; the marker itself is left to the client.
+    fn line_cap(&self) -> LineCap {
+        match self {
+            StrokeCap::Round => LineCap::Round,
+            StrokeCap::Square => LineCap::Square,
+            _ => LineCap::Butt,
+        }
+    }
+}
+
+impl StrokeJoin {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "BEVEL" => StrokeJoin::Bevel,
+            "ROUND" => StrokeJoin::Round,
+            _ => StrokeJoin::Miter,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrokeJoin::Miter => "MITER",
+            StrokeJoin::Bevel => "BEVEL",
+            StrokeJoin::Round => "ROUND",
+        }
+    }
+}
+
+impl StrokeStyle {
+    /// Read stroke fields collected from a NodeChange
+    pub fn from_kiwi(fields: &KiwiMessage) -> Self {
+        let mut style = StrokeStyle::default();
+        if let Some(align) = fields.str("strokeAlign") {
+            style.align = StrokeAlign::from_name(align);
+        }
+        if let Some(cap) = fields.str("strokeCap") {
+            style.cap = StrokeCap::from_name(cap);
+        }
+        if let Some(join) = fields.str("strokeJoin") {
+            style.join = StrokeJoin::from_name(join);
+        }
+        if let Some(limit) = fields.float("miterLimit").filter(|&limit| limit >= 1.0) {
+            style.miter_limit = limit;
+        }
+        style.dash_pattern = fields.array("dashPattern").iter()
+            .filter_map(KiwiValue::as_f64)
+            .filter(|&length| length >= 0.0)
+            .collect();
+        if fields.bool("borderStrokeWeightsIndependent") == Some(true) {
+            let weight = |name: &str| fields.float(name).unwrap_or(0.0).max(0.0);
+            style.side_weights = Some([
+                weight("borderTopWeight"),
+                weight("borderRightWeight"),
+                weight("borderBottomWeight"),
+                weight("borderLeftWeight"),
+            ]);
+        }
+        style
+    }
+
+    /// Fraction of the weight that lies outside the shape
+    fn outside_fraction(&self) -> f64 {
+        match self.align {
+            StrokeAlign::Inside => 0.0,
+            StrokeAlign::Center => 0.5,
+            StrokeAlign::Outside => 1.0,
+        }
+    }
+}
+
+/// Fill geometry for a stroke of `weight` along `shape`. Centered strokes
+/// need no clip; inside and outside strokes are drawn at double width and
+/// clipped to the shape or to everything around it.
+pub fn stroke_outline(shape: &Path, weight: f64, style: &StrokeStyle) -> Option<StrokeOutline> {
+    if weight <= 0.0 {
+        return None;
+    }
+    let dashed = is_dashed(&style.dash_pattern).then(|| dash_path(shape, &style.dash_pattern)).flatten();
+    let path = dashed.as_ref().unwrap_or(shape);
+
+    let width = match style.align {
+        StrokeAlign::Center => weight,
+        StrokeAlign::Inside | StrokeAlign::Outside => weight * 2.0,
+    };
+    let join = match style.join {
+        StrokeJoin::Miter => LineJoin::Miter,
+        StrokeJoin::Bevel => LineJoin::Bevel,
+        StrokeJoin::Round => LineJoin::Round,
+    };
+    let options = StrokeOptions::tolerance(TOLERANCE)
+        .with_line_width(width as f32)
+        .with_line_cap(style.cap.line_cap())
+        .with_line_join(join)
+        .with_miter_limit((style.miter_limit as f32).max(StrokeOptions::MINIMUM_MITER_LIMIT));
+
+    let mut geometry: VertexBuffers<Point, u32> = VertexBuffers::new();
+    StrokeTessellator::new()
+        .tessellate_path(path, &options, &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| vertex.position()))
+        .ok()?;
+
+    let clip = match style.align {
+        StrokeAlign::Center => None,
+        StrokeAlign::Inside => Some((shape.clone(), false)),
+        StrokeAlign::Outside => {
+            // Everything around the shape: a margin wide enough for miters,
+            // with the shape cut out
+            let bounds = bounding_box(shape.iter());
+            let margin = (width * style.miter_limit.max(1.0)) as f32 + 1.0;
+            let mut builder = Path::builder();
+            builder.add_rectangle(&bounds.inflate(margin, margin), Winding::Positive);
+            for event in shape.iter() {
+                builder.path_event(event);
+            }
+            Some((builder.build(), true))
+        }
+    };
+    Some(StrokeOutline { path: triangles_path(&geometry), clip })
+}
+
+/// Fill geometry for a rectangle's border with independent side weights:
+/// the ring between the outer and inner edges, as with CSS borders.
+/// Corner radii are not followed.
+pub fn border_outline(width: f64, height: f64, weights: [f64; 4], style: &StrokeStyle) -> Option<StrokeOutline> {
+    if weights.iter().all(|&weight| weight <= 0.0) {
+        return None;
+    }
+    let [top, right, bottom, left] = weights;
+    let outside = style.outside_fraction();
+    let inside = 1.0 - outside;
+    let rect = |x0: f64, y0: f64, x1: f64, y1: f64| Box2D::new(point(x0 as f32, y0 as f32), point(x1 as f32, y1 as f32));
+
+    let mut builder = Path::builder();
+    builder.add_rectangle(
+        &rect(-left * outside, -top * outside, width + right * outside, height + bottom * outside),
+        Winding::Positive,
+    );
+    let inner = rect(left * inside, top * inside, width - right * inside, height - bottom * inside);
+    if inner.width() > 0.0 && inner.height() > 0.0 {
+        builder.add_rectangle(&inner, Winding::Negative);
+    }
+    Some(StrokeOutline { path: builder.build(), clip: None })
+}
+
+/// Whether a pattern from the file can be used to cut dashes. Patterns
+/// with negative or non-finite entries are drawn solid.
+fn is_dashed(pattern: &[f64]) -> bool {
+    pattern.iter().all(|entry| entry.is_finite() && *entry >= 0.0) && pattern.iter().sum::<f64>() > 0.0
+}
+
+/// Cut a path into dashes. Curves are flattened first, and each subpath
+/// starts at the beginning of the pattern, as in SVG. An odd-length pattern
+/// is repeated so dashes and gaps alternate.
+///
+/// Returns None if the path would take more than `MAX_DASHES` dashes and
+/// gaps, to be stroked solid instead.
+fn dash_path(path: &Path, pattern: &[f64]) -> Option<Path> {
+    let length: f64 = path.iter().flattened(TOLERANCE)
+        .map(|event| match event {
+            PathEvent::Line { from, to } | PathEvent::End { last: from, first: to, close: true } => (to - from).length() as f64,
+            _ => 0.0,
+        })
+        .sum();
+    let period: f64 = pattern.iter().sum();
+    if length / period * pattern.len() as f64 > MAX_DASHES as f64 {
+        return None;
+    }
+
+    let mut pattern = pattern.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let mut dasher = Dasher { builder: Path::builder(), pattern, index: 0, remaining: 0.0, on: true };
+    for event in path.iter().flattened(TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => dasher.begin(at),
+            PathEvent::Line { from, to } => dasher.line(from, to),
+            PathEvent::End { last, first, close } => {
+                if close {
+                    dasher.line(last, first);
+                }
+                if dasher.on {
+                    dasher.builder.end(false);
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(dasher.builder.build())
+}
+
+/// Position within a dash pattern while walking a path
+struct Dasher {
+    builder: lyon::path::path::Builder,
+    pattern: Vec<f64>,
+    index: usize,
+    /// Length left in the current dash or gap
+    remaining: f64,
+    /// Whether the current entry is a dash rather than a gap
+    on: bool,
+}
+
+impl Dasher {
+    fn begin(&mut self, at: Point) {
+        self.index = 0;
+        self.remaining = self.pattern[0];
+        self.on = true;
+        self.builder.begin(at);
+    }
+
+    fn line(&mut self, from: Point, to: Point) {
+        let length = (to - from).length() as f64;
+        let mut position = 0.0;
+        while length - position > self.remaining {
+            position += self.remaining;
+            let at = from.lerp(to, (position / length) as f32);
+            if self.on {
+                self.builder.line_to(at);
+                self.builder.end(false);
+            } else {
+                self.builder.begin(at);
+            }
+            self.on = !self.on;
+            self.index = (self.index + 1) % self.pattern.len();
+            self.remaining = self.pattern[self.index];
+        }
+        self.remaining -= length - position;
+        if self.on {
+            self.builder.line_to(to);
+        }
+    }
+}
+
+/// Turn tessellated triangles into a path of closed triangles, all wound
+/// the same way so overlaps stay filled under the nonzero rule
+fn triangles_path(geometry: &VertexBuffers<Point, u32>) -> Path {
+    let mut builder = Path::builder();
+    for triangle in geometry.indices.chunks_exact(3) {
+        let [a, mut b, mut c] = [0, 1, 2].map(|i| geometry.vertices[triangle[i] as usize]);
+        let area = (b - a).cross(c - a);
+        if area.abs() < 1e-9 {
+            continue;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+        builder.begin(a);
+        builder.line_to(b);
+        builder.line_to(c);
+        builder.close();
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::{test_fixture, FigFile};
+
+    fn square(size: f32) -> Path {
+        let mut builder = Path::builder();
+        builder.add_rectangle(&Box2D::new(point(0.0, 0.0), point(size, size)), Winding::Positive);
+        builder.build()
+    }
+
+    fn bounds(path: &Path) -> (f32, f32, f32, f32) {
+        let b = bounding_box(path.iter());
+        (b.min.x, b.min.y, b.max.x, b.max.y)
+    }
+
+    fn near(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3 && (a.2 - b.2).abs() < 1e-3 && (a.3 - b.3).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_from_kiwi() {
+        let fields = KiwiMessage {
+            fields: vec![
+                ("strokeAlign".into(), KiwiValue::Enum("INSIDE".into())),
+                ("strokeCap".into(), KiwiValue::Enum("ARROW_LINES".into())),
+                ("strokeJoin".into(), KiwiValue::Enum("ROUND".into())),
+                ("dashPattern".into(), KiwiValue::Array(vec![KiwiValue::Float(10.0), KiwiValue::Float(5.0)])),
+                ("borderStrokeWeightsIndependent".into(), KiwiValue::Bool(true)),
+                ("borderTopWeight".into(), KiwiValue::Float(2.0)),
+                ("borderLeftWeight".into(), KiwiValue::Float(1.0)),
+            ],
+        };
+        let style = StrokeStyle::from_kiwi(&fields);
+        assert_eq!(style.align, StrokeAlign::Inside);
+        assert_eq!(style.cap, StrokeCap::ArrowLines);
+        assert_eq!(style.join, StrokeJoin::Round);
+        assert_eq!(style.miter_limit, 4.0);
+        assert_eq!(style.dash_pattern, vec![10.0, 5.0]);
+        assert_eq!(style.side_weights, Some([2.0, 0.0, 0.0, 1.0]));
+        assert_eq!(StrokeStyle::from_kiwi(&KiwiMessage::default()), StrokeStyle::default());
+    }
+
+    #[test]
+    fn test_stroke_outline_align() {
+        let shape = square(100.0);
+        let mut style = StrokeStyle::default();
+
+        let center = stroke_outline(&shape, 2.0, &style).unwrap();
+        assert!(center.clip.is_none());
+        assert!(near(bounds(&center.path), (-1.0, -1.0, 101.0, 101.0)));
+
+        style.align = StrokeAlign::Inside;
+        let inside = stroke_outline(&shape, 2.0, &style).unwrap();
+        assert!(near(bounds(&inside.path), (-2.0, -2.0, 102.0, 102.0)));
+        let (clip, even_odd) = inside.clip.unwrap();
+        assert!(!even_odd);
+        assert!(near(bounds(&clip), (0.0, 0.0, 100.0, 100.0)));
+
+        style.align = StrokeAlign::Outside;
+        let (clip, even_odd) = stroke_outline(&shape, 2.0, &style).unwrap().clip.unwrap();
+        assert!(even_odd);
+        assert!(bounds(&clip).0 < -2.0);
+
+        assert!(stroke_outline(&shape, 0.0, &style).is_none());
+    }
+
+    #[test]
+    fn test_dashes() {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.line_to(point(30.0, 0.0));
+        builder.end(false);
+        let dashed = dash_path(&builder.build(), &[10.0, 5.0]).unwrap();
+        let dashes: Vec<(f32, f32)> = dashed.iter().filter_map(|event| match event {
+            PathEvent::End { last, first, .. } => Some((first.x, last.x)),
+            _ => None,
+        }).collect();
+        assert_eq!(dashes, vec![(0.0, 10.0), (15.0, 25.0)]);
+
+        // An odd pattern repeats, so dashes alternate with gaps of the same lengths
+        let dashed = dash_path(&square(10.0), &[5.0]).unwrap();
+        assert_eq!(dashed.iter().filter(|event| matches!(event, PathEvent::Begin { .. })).count(), 4);
+
+        // Degenerate patterns from a file are drawn solid
+        assert!(!is_dashed(&[5.0, -1.0]));
+        assert!(!is_dashed(&[f64::NAN, 5.0]));
+        assert!(!is_dashed(&[f64::INFINITY, 5.0]));
+        assert!(!is_dashed(&[0.0, 0.0]));
+        assert!(is_dashed(&[1e-9, 1e-9]));
+        assert!(dash_path(&square(10.0), &[1e-9, 1e-9]).is_none());
+        let style = StrokeStyle { dash_pattern: vec![1e-9, 1e-9], ..Default::default() };
+        assert!(stroke_outline(&square(10.0), 1.0, &style).is_some());
+    }
+
+    #[test]
+    fn test_border_outline() {
+        let style = StrokeStyle { align: StrokeAlign::Inside, ..Default::default() };
+        let outline = border_outline(100.0, 50.0, [1.0, 2.0, 3.0, 4.0], &style).unwrap();
+        assert!(near(bounds(&outline.path), (0.0, 0.0, 100.0, 50.0)));
+        // The inner edge is cut out
+        let svg = crate::vector::path_to_svg(&outline.path);
+        assert!(svg.contains("M 4 1"), "{}", svg);
+
+        let style = StrokeStyle { align: StrokeAlign::Outside, ..Default::default() };
+        let outline = border_outline(100.0, 50.0, [1.0, 2.0, 3.0, 4.0], &style).unwrap();
+        assert!(near(bounds(&outline.path), (-4.0, -1.0, 102.0, 53.0)));
+        assert!(border_outline(100.0, 50.0, [0.0; 4], &style).is_none());
+    }
+
+    #[test]
+    fn test_decode_strokes() {
+        let file = test_fixture::file();
+        let nodes = file.ordered_nodes();
+        assert!(nodes.iter().any(|node| node.stroke.dash_pattern == [10.0, 5.0]));
+
+        let bordered = nodes.iter()
+            .find(|node| node.stroke.side_weights.is_some() && node.node_type == "FRAME")
+            .unwrap();
+        assert!(bordered.stroke_outline().unwrap().clip.is_none());
+
+        let inside = nodes.iter()
+            .find(|node| node.stroke.align == StrokeAlign::Inside && node.node_type == "ROUNDED_RECTANGLE"
+                && !node.stroke_paints.is_empty() && node.stroke_weight > 0.0 && node.stroke.side_weights.is_none())
+            .unwrap();
+        let command = inside.to_draw_command().unwrap();
+        assert_eq!(command.stroke.unwrap().align, "INSIDE");
+        let outline = inside.stroke_outline().unwrap();
+        assert!(outline.clip.is_some_and(|(_, even_odd)| !even_odd));
+
+        // Stroke fields are still written back unchanged
+        let data = file.write().unwrap();
+        let reparsed = FigFile::parse(&data).unwrap();
+        assert_eq!(reparsed.get_node(&inside.id).unwrap().stroke, inside.stroke);
+    }
+}
@@ -0,0 +1,1171 @@
+//! Text styles and layout for TEXT nodes
+//!
+//! A TEXT node keeps its characters in `textData` together with a style id
+//! per UTF-16 code unit. Style 0 is the node's own style; any other id refers
+//! to an entry of `styleOverrideTable`, a partial NodeChange whose fields
+//! override the node's. Layout breaks the styled characters into lines and
+//! positions glyphs with the fonts registered by the app.
+
+use crate::fonts::{kerning, Font, FontRegistry, FontRequest};
+use crate::kiwi::{KiwiMessage, KiwiValue};
+use crate::nodes::FigmaNode;
+use crate::paints::Paint;
+use lyon::math::point;
+use lyon::path::Path;
+use ttf_parser::{Face, GlyphId, OutlineBuilder, Tag};
+
+/// NodeChange fields that hold text styling, decoded into `TextContent`
+pub const TEXT_STYLE_FIELDS: &[&str] = &[
+    "lineHeight", "letterSpacing", "paragraphSpacing", "paragraphIndent",
+    "textCase", "textDecoration", "textAlignHorizontal", "textAlignVertical",
+    "textAutoResize", "textTruncation", "maxLines", "fontVariations",
+];
+
+/// Metrics used when no registered font matches, roughly those of Inter
+const FALLBACK_ASCENT: f64 = 0.97;
+const FALLBACK_DESCENT: f64 = 0.25;
+const FALLBACK_ADVANCE: f64 = 0.55;
+const FALLBACK_SPACE_ADVANCE: f64 = 0.25;
+
+const ELLIPSIS: char = '\u{2026}';
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineHeight {
+    /// The font's natural line height
+    #[default]
+    Auto,
+    Pixels(f64),
+    /// Percentage of the font's natural line height
+    Percent(f64),
+    /// Multiple of the font size
+    Multiple(f64),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LetterSpacing {
+    #[default]
+    None,
+    Pixels(f64),
+    /// Percentage of the font size
+    Percent(f64),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextCase {
+    #[default]
+    Original,
+    Upper,
+    Lower,
+    Title,
+    /// Drawn as capitals, since true small caps need OpenType shaping
+    SmallCaps,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextDecoration {
+    #[default]
+    None,
+    Underline,
+    Strikethrough,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlignHorizontal {
+    #[default]
+    Left,
+    Center,
+    Right,
+    Justified,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlignVertical {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAutoResize {
+    /// Fixed size
+    #[default]
+    None,
+    /// Fixed width, height grows with the text
+    Height,
+    /// Lines are never wrapped and the box fits the text
+    WidthAndHeight,
+}
+
+/// Styling of a run of characters
+#[derive(Debug, Clone, Default)]
+pub struct TextStyle {
+    pub font_family: String,
+    pub font_style: String,
+    pub font_postscript_name: String,
+    pub font_size: f64,
+    /// Variable font axis values as (OpenType tag, value)
+    pub font_variations: Vec<(u32, f64)>,
+    pub line_height: LineHeight,
+    pub letter_spacing: LetterSpacing,
+    pub paragraph_spacing: f64,
+    pub paragraph_indent: f64,
+    pub text_case: TextCase,
+    pub decoration: TextDecoration,
+    pub fills: Vec<Paint>,
+}
+
+impl TextStyle {
+    /// Apply the style fields present in a NodeChange, such as an entry of
+    /// `styleOverrideTable`
+    pub fn apply(&mut self, fields: &KiwiMessage) {
+        if let Some(font) = fields.message("fontName") {
+            self.font_family = font.str("family").unwrap_or_default().to_string();
+            self.font_style = font.str("style").unwrap_or_default().to_string();
+            self.font_postscript_name = font.str("postscript").unwrap_or_default().to_string();
+        }
+        if let Some(size) = fields.float("fontSize") {
+            self.font_size = size;
+        }
+        if let Some(variations) = fields.get("fontVariations") {
+            self.font_variations = variations.as_array().iter()
+                .filter_map(KiwiValue::as_message)
+                .filter_map(|variation| Some((variation.uint("axisTag")? as u32, variation.float("value")?)))
+                .collect();
+        }
+        if let Some(number) = fields.message("lineHeight") {
+            let value = number.float("value").unwrap_or(100.0);
+            self.line_height = match number.str("units") {
+                Some("PIXELS") => LineHeight::Pixels(value),
+                Some("RAW") => LineHeight::Multiple(value),
+                _ if value == 100.0 => LineHeight::Auto,
+                _ => LineHeight::Percent(value),
+            };
+        }
+        if let Some(number) = fields.message("letterSpacing") {
+            let value = number.float("value").unwrap_or(0.0);
+            self.letter_spacing = match number.str("units") {
+                _ if value == 0.0 => LetterSpacing::None,
+                Some("PIXELS") => LetterSpacing::Pixels(value),
+                _ => LetterSpacing::Percent(value),
+            };
+        }
+        if let Some(spacing) = fields.float("paragraphSpacing") {
+            self.paragraph_spacing = spacing;
+        }
+        if let Some(indent) = fields.float("paragraphIndent") {
+            self.paragraph_indent = indent;
+        }
+        if let Some(case) = fields.str("textCase") {
+            self.text_case = match case {
+                "UPPER" => TextCase::Upper,
+                "LOWER" => TextCase::Lower,
+                "TITLE" => TextCase::Title,
+                "SMALL_CAPS" | "SMALL_CAPS_FORCED" => TextCase::SmallCaps,
+                _ => TextCase::Original,
+            };
+        }
+        if let Some(decoration) = fields.str("textDecoration") {
+            self.decoration = match decoration {
+                "UNDERLINE" => TextDecoration::Underline,
+                "STRIKETHROUGH" => TextDecoration::Strikethrough,
+                _ => TextDecoration::None,
+            };
+        }
+        if let Some(fills) = fields.get("fillPaints") {
+            self.fills = Paint::list_from_kiwi(fills);
+        }
+    }
+
+    fn letter_spacing_px(&self) -> f64 {
+        match self.letter_spacing {
+            LetterSpacing::None => 0.0,
+            LetterSpacing::Pixels(px) => px,
+            LetterSpacing::Percent(percent) => percent / 100.0 * self.font_size,
+        }
+    }
+}
+
+/// The decoded `textData` of a TEXT node with its paragraph settings
+#[derive(Debug, Clone, Default)]
+pub struct TextContent {
+    pub characters: String,
+    /// Style id per UTF-16 code unit. The list may be shorter than the
+    /// text, the remaining characters use style 0.
+    pub character_style_ids: Vec<u32>,
+    /// The node's own style, id 0
+    pub style: TextStyle,
+    /// `styleOverrideTable` entries by style id
+    pub style_overrides: Vec<(u32, KiwiMessage)>,
+    pub align_horizontal: TextAlignHorizontal,
+    pub align_vertical: TextAlignVertical,
+    pub auto_resize: TextAutoResize,
+    /// Cut off overflowing lines with an ellipsis
+    pub truncate: bool,
+    /// Line limit when truncating
+    pub max_lines: Option<u32>,
+}
+
+impl TextContent {
+    /// Build from a decoded `TextData` and the node's text fields, given the
+    /// style from the node's typed properties
+    pub fn from_kiwi(text_data: &KiwiMessage, node: &KiwiMessage, mut style: TextStyle) -> Self {
+        style.apply(node);
+        TextContent {
+            characters: text_data.str("characters").unwrap_or_default().to_string(),
+            character_style_ids: text_data.array("characterStyleIDs").iter()
+                .filter_map(KiwiValue::as_u64)
+                .map(|id| id as u32)
+                .collect(),
+            style,
+            style_overrides: text_data.array("styleOverrideTable").iter()
+                .filter_map(KiwiValue::as_message)
+                .filter_map(|entry| Some((entry.uint("styleID")? as u32, entry.clone())))
+                .collect(),
+            align_horizontal: match node.str("textAlignHorizontal") {
+                Some("CENTER") => TextAlignHorizontal::Center,
+                Some("RIGHT") => TextAlignHorizontal::Right,
+                Some("JUSTIFIED") => TextAlignHorizontal::Justified,
+                _ => TextAlignHorizontal::Left,
+            },
+            align_vertical: match node.str("textAlignVertical") {
+                Some("CENTER") => TextAlignVertical::Center,
+                Some("BOTTOM") => TextAlignVertical::Bottom,
+                _ => TextAlignVertical::Top,
+            },
+            auto_resize: match node.str("textAutoResize") {
+                Some("HEIGHT") => TextAutoResize::Height,
+                Some("WIDTH_AND_HEIGHT") => TextAutoResize::WidthAndHeight,
+                _ => TextAutoResize::None,
+            },
+            truncate: node.str("textTruncation") == Some("ENDING"),
+            max_lines: node.uint("maxLines").filter(|&lines| lines > 0).map(|lines| lines as u32),
+        }
+    }
+
+    /// The effective style for a style id
+    pub fn style_for(&self, style_id: u32) -> TextStyle {
+        let mut style = self.style.clone();
+        if let Some((_, fields)) = self.style_overrides.iter().find(|(id, _)| *id == style_id) {
+            style.apply(fields);
+        }
+        style
+    }
+
+    /// The characters with their style ids
+    pub fn styled_chars(&self) -> impl Iterator<Item = (char, u32)> + '_ {
+        let mut utf16_index = 0;
+        self.characters.chars().map(move |c| {
+            let style_id = self.character_style_ids.get(utf16_index).copied().unwrap_or(0);
+            utf16_index += c.len_utf16();
+            (c, style_id)
+        })
+    }
+
+    /// The styles applied to at least one character, style 0 first
+    pub fn used_styles(&self) -> Vec<TextStyle> {
+        let mut ids: Vec<u32> = self.styled_chars().map(|(_, id)| id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter().map(|id| self.style_for(id)).collect()
+    }
+
+    /// Lay out the text in a box of the given size
+    pub fn layout(&self, width: f64, height: f64, fonts: &FontRegistry) -> TextLayout {
+        Layouter::new(self, fonts).run(width, height)
+    }
+}
+
+/// A font referenced by text, with the TEXT nodes that use it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontUsage {
+    pub family: String,
+    pub style: String,
+    pub postscript_name: String,
+    /// `fontVariations` of the first use
+    pub variations: Vec<(u32, f64)>,
+    pub node_ids: Vec<String>,
+}
+
+impl FontUsage {
+    pub fn request(&self) -> FontRequest<'_> {
+        FontRequest {
+            family: &self.family,
+            style: &self.style,
+            postscript_name: &self.postscript_name,
+            variations: &self.variations,
+        }
+    }
+}
+
+/// The fonts used by the characters of TEXT nodes, in order of first use
+pub fn collect_font_usage<'a>(nodes: impl IntoIterator<Item = &'a FigmaNode>) -> Vec<FontUsage> {
+    let mut usages: Vec<FontUsage> = Vec::new();
+    for node in nodes {
+        let Some(text) = &node.text else {
+            continue;
+        };
+        for style in text.used_styles() {
+            let existing = usages.iter_mut().find(|usage| {
+                usage.family == style.font_family && usage.style == style.font_style
+            });
+            match existing {
+                Some(usage) => {
+                    if usage.node_ids.last() != Some(&node.id) {
+                        usage.node_ids.push(node.id.clone());
+                    }
+                }
+                None => usages.push(FontUsage {
+                    family: style.font_family,
+                    style: style.font_style,
+                    postscript_name: style.font_postscript_name,
+                    variations: style.font_variations,
+                    node_ids: vec![node.id.clone()],
+                }),
+            }
+        }
+    }
+    usages
+}
+
+/// Positioned text, in the coordinates of the text box
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    /// Size of the box after auto-resize
+    pub width: f64,
+    pub height: f64,
+    pub lines: Vec<LineBox>,
+    pub runs: Vec<GlyphRun>,
+    pub decorations: Vec<DecorationRect>,
+    /// Whether lines were cut off and the last one ends with an ellipsis
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineBox {
+    pub top: f64,
+    pub height: f64,
+    pub baseline: f64,
+    pub x: f64,
+    pub width: f64,
+}
+
+/// Glyphs on one line that share a style
+#[derive(Debug, Clone)]
+pub struct GlyphRun {
+    pub style_id: u32,
+    pub text: String,
+    /// The registered face used, None when the fallback metrics were used
+    pub font: Option<Font>,
+    /// Variation coordinates the glyphs were measured with
+    pub variations: Vec<(Tag, f32)>,
+    pub style: TextStyle,
+    pub glyphs: Vec<PositionedGlyph>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub glyph_id: u16,
+    /// Position of the glyph origin on the baseline
+    pub x: f64,
+    pub y: f64,
+    /// Index of the source character in `characters`, counted in chars
+    pub char_index: usize,
+}
+
+/// An underline or strikethrough
+#[derive(Debug, Clone)]
+pub struct DecorationRect {
+    /// Index of the run it belongs to
+    pub run: usize,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub fills: Vec<Paint>,
+}
+
+/// A face used by a style, with the scale from font units to pixels
+struct StyleFace<'a> {
+    font: &'a Font,
+    face: Face<'a>,
+    variations: Vec<(Tag, f32)>,
+    scale: f64,
+}
+
+/// A style resolved against the registered fonts, with sizes in pixels
+struct ResolvedStyle<'a> {
+    id: u32,
+    style: TextStyle,
+    /// The matched face followed by the fallback faces, tried in order for
+    /// each character. Empty if no registered font matched.
+    faces: Vec<StyleFace<'a>>,
+    ascent: f64,
+    descent: f64,
+    line_height: f64,
+    letter_spacing: f64,
+    /// Offset below the baseline and thickness
+    underline: (f64, f64),
+    strikeout: (f64, f64),
+}
+
+impl<'a> ResolvedStyle<'a> {
+    fn new(id: u32, style: TextStyle, fonts: &'a FontRegistry) -> Self {
+        let size = style.font_size;
+        let request = FontRequest {
+            family: &style.font_family,
+            style: &style.font_style,
+            postscript_name: &style.font_postscript_name,
+            variations: &style.font_variations,
+        };
+        let mut faces: Vec<StyleFace> = Vec::new();
+        for found in fonts.resolve(&request).into_iter().chain(fonts.fallback_fonts(&request)) {
+            if faces.iter().any(|f| std::ptr::eq(f.font, found.font)) {
+                continue;
+            }
+            if let Some(face) = found.face() {
+                let scale = size / found.font.metrics.units_per_em;
+                faces.push(StyleFace { font: found.font, face, variations: found.variations, scale });
+            }
+        }
+
+        let (ascent, descent, gap, underline, strikeout) = match faces.first() {
+            Some(primary) => {
+                let m = &primary.font.metrics;
+                let scale = primary.scale;
+                let underline = m.underline.map(|(pos, thickness)| (-pos * scale, thickness * scale));
+                let strikeout = m.strikeout.map(|(pos, thickness)| (-pos * scale, thickness * scale));
+                (m.ascender * scale, -m.descender * scale, m.line_gap * scale, underline, strikeout)
+            }
+            None => (FALLBACK_ASCENT * size, FALLBACK_DESCENT * size, 0.0, None, None),
+        };
+        let natural = ascent + descent + gap;
+        let thickness = (size / 14.0).max(1.0);
+
+        ResolvedStyle {
+            id,
+            faces,
+            ascent,
+            descent,
+            line_height: match style.line_height {
+                LineHeight::Auto => natural,
+                LineHeight::Pixels(px) => px,
+                LineHeight::Percent(percent) => natural * percent / 100.0,
+                LineHeight::Multiple(multiple) => size * multiple,
+            },
+            letter_spacing: style.letter_spacing_px(),
+            underline: underline.unwrap_or((size * 0.1, thickness)),
+            strikeout: strikeout.unwrap_or((-size * 0.3, thickness)),
+            style,
+        }
+    }
+
+    /// The face, glyph and advance for a character, from the first face
+    /// that has it. Characters no face has use the primary face's .notdef.
+    fn glyph(&self, c: char) -> (Option<usize>, u16, f64) {
+        let found = self.faces.iter().enumerate()
+            .find_map(|(index, f)| Some((index, f.face.glyph_index(c)?)))
+            .or_else(|| self.faces.first().map(|_| (0, GlyphId(0))));
+        let size = self.style.font_size;
+        match found {
+            Some((index, glyph)) => {
+                let face = &self.faces[index];
+                let advance = face.face.glyph_hor_advance(glyph).unwrap_or(0) as f64 * face.scale;
+                (Some(index), glyph.0, advance)
+            }
+            None if c.is_whitespace() => (None, 0, FALLBACK_SPACE_ADVANCE * size),
+            None if is_cjk(c) => (None, 0, size),
+            None => (None, 0, FALLBACK_ADVANCE * size),
+        }
+    }
+
+    fn kerning(&self, face: Option<usize>, left: u16, right: u16) -> f64 {
+        match face.map(|index| &self.faces[index]) {
+            Some(face) => kerning(&face.face, GlyphId(left), GlyphId(right)) * face.scale,
+            None => 0.0,
+        }
+    }
+}
+
+/// A character ready for line breaking
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    c: char,
+    char_index: usize,
+    style: usize,
+    /// Index into the style's faces, None without a registered font
+    face: Option<usize>,
+    glyph: u16,
+    /// Including kerning with the next character and letter spacing
+    advance: f64,
+}
+
+/// Outline of the text in one style, in text box coordinates
+#[derive(Debug, Clone)]
+pub struct TextOutline {
+    pub style_id: u32,
+    pub path: Path,
+    pub fills: Vec<Paint>,
+}
+
+impl TextLayout {
+    /// Glyph outlines and decorations as paths, one per style since a
+    /// style's runs share fills. Glyphs laid out without a registered font
+    /// have no outline and are left out.
+    pub fn outlines(&self) -> Vec<TextOutline> {
+        let mut builders: Vec<(u32, Vec<Paint>, lyon::path::path::Builder)> = Vec::new();
+        let mut run_builders = Vec::with_capacity(self.runs.len());
+        for run in &self.runs {
+            let index = match builders.iter().position(|(id, _, _)| *id == run.style_id) {
+                Some(index) => index,
+                None => {
+                    builders.push((run.style_id, run.style.fills.clone(), Path::builder()));
+                    builders.len() - 1
+                }
+            };
+            run_builders.push(index);
+            let Some(font) = &run.font else {
+                continue;
+            };
+            let Some(face) = font.face_with_variations(&run.variations) else {
+                continue;
+            };
+            let scale = (run.style.font_size / font.metrics.units_per_em) as f32;
+            for glyph in &run.glyphs {
+                let mut outline = GlyphOutline {
+                    builder: &mut builders[index].2,
+                    x: glyph.x as f32,
+                    y: glyph.y as f32,
+                    scale,
+                    open: false,
+                };
+                face.outline_glyph(GlyphId(glyph.glyph_id), &mut outline);
+                outline.finish();
+            }
+        }
+
+        for rect in &self.decorations {
+            let Some(&index) = run_builders.get(rect.run) else {
+                continue;
+            };
+            let builder = &mut builders[index].2;
+            let (x0, y0) = (rect.x as f32, rect.y as f32);
+            let (x1, y1) = ((rect.x + rect.width) as f32, (rect.y + rect.height) as f32);
+            builder.begin(point(x0, y0));
+            builder.line_to(point(x1, y0));
+            builder.line_to(point(x1, y1));
+            builder.line_to(point(x0, y1));
+            builder.close();
+        }
+
+        builders.into_iter()
+            .map(|(style_id, fills, builder)| TextOutline { style_id, path: builder.build(), fills })
+            .collect()
+    }
+}
+
+/// Feeds a glyph outline in font units into a path at a pen position.
+/// Font units point up, so y is flipped.
+struct GlyphOutline<'a> {
+    builder: &'a mut lyon::path::path::Builder,
+    x: f32,
+    y: f32,
+    scale: f32,
+    open: bool,
+}
+
+impl GlyphOutline<'_> {
+    fn point(&self, x: f32, y: f32) -> lyon::math::Point {
+        point(self.x + x * self.scale, self.y - y * self.scale)
+    }
+
+    fn finish(&mut self) {
+        if self.open {
+            self.builder.end(false);
+            self.open = false;
+        }
+    }
+}
+
+impl OutlineBuilder for GlyphOutline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish();
+        let at = self.point(x, y);
+        self.builder.begin(at);
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.builder.line_to(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (ctrl, to) = (self.point(x1, y1), self.point(x, y));
+        self.builder.quadratic_bezier_to(ctrl, to);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (ctrl1, ctrl2, to) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.builder.close();
+            self.open = false;
+        }
+    }
+}
+
+/// A line of items before positioning
+struct Line {
+    start: usize,
+    end: usize,
+    /// Style of an empty line, from the line break that created it
+    style: usize,
+    first_in_paragraph: bool,
+    last_in_paragraph: bool,
+}
+
+struct Layouter<'a> {
+    content: &'a TextContent,
+    fonts: &'a FontRegistry,
+    styles: Vec<ResolvedStyle<'a>>,
+}
+
+impl<'a> Layouter<'a> {
+    fn new(content: &'a TextContent, fonts: &'a FontRegistry) -> Self {
+        Layouter { content, fonts, styles: Vec::new() }
+    }
+
+    fn style_index(&mut self, id: u32) -> usize {
+        if let Some(index) = self.styles.iter().position(|style| style.id == id) {
+            return index;
+        }
+        self.styles.push(ResolvedStyle::new(id, self.content.style_for(id), self.fonts));
+        self.styles.len() - 1
+    }
+
+    fn run(mut self, width: f64, height: f64) -> TextLayout {
+        let (items, paragraph_breaks) = self.items();
+        let base = self.style_index(0);
+        let content = self.content;
+
+        let max_width = match content.auto_resize {
+            TextAutoResize::WidthAndHeight => f64::INFINITY,
+            _ => width,
+        };
+        let indent = self.styles[base].style.paragraph_indent;
+
+        // Break each paragraph into lines
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (end, style) in paragraph_breaks.into_iter().chain([(items.len(), base)]) {
+            self.break_paragraph(&items, start, end, style, max_width, indent, &mut lines);
+            start = end + 1;
+        }
+
+        // Drop lines over the limit when truncating
+        let mut truncated = false;
+        if content.truncate {
+            let mut limit = content.max_lines.map_or(lines.len(), |max| max as usize);
+            if content.auto_resize == TextAutoResize::None {
+                let mut bottom = 0.0;
+                let fitting = lines.iter().take_while(|line| {
+                    bottom += self.line_metrics(&items, line).2;
+                    bottom <= height + 0.01
+                }).count();
+                limit = limit.min(fitting.max(1));
+            }
+            if limit < lines.len() {
+                lines.truncate(limit);
+                truncated = true;
+            }
+        }
+
+        let mut items = items;
+        if truncated {
+            if let Some(line) = lines.last_mut() {
+                let available = if line.first_in_paragraph { max_width - indent } else { max_width };
+                self.ellipsize(&mut items, line, available);
+            }
+        }
+
+        // Stack the lines
+        let paragraph_spacing = self.styles[base].style.paragraph_spacing;
+        let mut boxes = Vec::with_capacity(lines.len());
+        let mut y = 0.0;
+        for (index, line) in lines.iter().enumerate() {
+            let (ascent, descent, line_height) = self.line_metrics(&items, line);
+            let line_width = line_width(&items[line.start..line.end]);
+            boxes.push(LineBox {
+                top: y,
+                height: line_height,
+                baseline: y + (line_height - ascent - descent) / 2.0 + ascent,
+                x: if line.first_in_paragraph { indent } else { 0.0 },
+                width: line_width,
+            });
+            y += line_height;
+            if line.last_in_paragraph && index + 1 < lines.len() {
+                y += paragraph_spacing;
+            }
+        }
+        let content_height = y;
+
+        let (box_width, box_height) = match content.auto_resize {
+            TextAutoResize::WidthAndHeight => {
+                let widest = boxes.iter().map(|b| b.x + b.width).fold(0.0, f64::max);
+                (widest, content_height)
+            }
+            TextAutoResize::Height => (width, content_height),
+            TextAutoResize::None => (width, height),
+        };
+
+        // Align
+        let dy = match content.align_vertical {
+            TextAlignVertical::Top => 0.0,
+            TextAlignVertical::Center => (box_height - content_height) / 2.0,
+            TextAlignVertical::Bottom => box_height - content_height,
+        };
+        let mut layout = TextLayout { width: box_width, height: box_height, truncated, ..Default::default() };
+        for (line, line_box) in lines.iter().zip(&mut boxes) {
+            line_box.top += dy;
+            line_box.baseline += dy;
+            let free = box_width - line_box.x - line_box.width;
+            let mut space_extra = 0.0;
+            match content.align_horizontal {
+                TextAlignHorizontal::Left => {}
+                TextAlignHorizontal::Center => line_box.x += free / 2.0,
+                TextAlignHorizontal::Right => line_box.x += free,
+                TextAlignHorizontal::Justified => {
+                    let visible = trim_trailing_whitespace(&items, line.start, line.end);
+                    let spaces = items[line.start..visible].iter().filter(|item| item.c == ' ').count();
+                    if !line.last_in_paragraph && !truncated && spaces > 0 && free > 0.0 {
+                        space_extra = free / spaces as f64;
+                        line_box.width += free;
+                    }
+                }
+            }
+            self.emit_line(&items[line.start..line.end], line_box, space_extra, &mut layout);
+        }
+        layout.lines = boxes;
+        layout
+    }
+
+    /// Styled characters after the text case transform, and the positions
+    /// of paragraph breaks with the style of the break
+    fn items(&mut self) -> (Vec<Item>, Vec<(usize, usize)>) {
+        let mut items: Vec<Item> = Vec::new();
+        let mut breaks = Vec::new();
+        let mut word_start = true;
+
+        for (char_index, (c, style_id)) in self.content.styled_chars().enumerate() {
+            let style = self.style_index(style_id);
+            if c == '\n' {
+                breaks.push((items.len(), style));
+                items.push(Item { c, char_index, style, face: None, glyph: 0, advance: 0.0 });
+                word_start = true;
+                continue;
+            }
+
+            let cased: Vec<char> = match self.styles[style].style.text_case {
+                TextCase::Original => vec![c],
+                TextCase::Upper | TextCase::SmallCaps => c.to_uppercase().collect(),
+                TextCase::Lower => c.to_lowercase().collect(),
+                TextCase::Title if word_start => c.to_uppercase().collect(),
+                TextCase::Title => vec![c],
+            };
+            word_start = c.is_whitespace();
+
+            for c in cased {
+                let resolved = &self.styles[style];
+                let (face, glyph, advance) = resolved.glyph(c);
+                // Kerning only applies within a run of the same face and style
+                if let Some(prev) = items.last_mut().filter(|prev| prev.style == style && prev.face == face && prev.c != '\n') {
+                    prev.advance += resolved.kerning(face, prev.glyph, glyph);
+                }
+                items.push(Item { c, char_index, style, face, glyph, advance: advance + resolved.letter_spacing });
+            }
+        }
+
+        // The breaks are items too, so lines index past them
+        (items, breaks)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn break_paragraph(
+        &self,
+        items: &[Item],
+        start: usize,
+        end: usize,
+        break_style: usize,
+        max_width: f64,
+        indent: f64,
+        lines: &mut Vec<Line>,
+    ) {
+        let first_line = lines.len();
+        let style = items.get(start).filter(|_| start < end).map_or(break_style, |item| item.style);
+        let mut line_start = start;
+        let mut x = 0.0;
+        let mut last_break = None;
+
+        for i in start..end {
+            let item = &items[i];
+            let available = if lines.len() == first_line { max_width - indent } else { max_width };
+            // Whitespace hangs past the edge instead of wrapping
+            if !item.c.is_whitespace() && i > line_start && x + item.advance > available + 0.01 {
+                let line_end = last_break.filter(|&b| b > line_start).unwrap_or(i);
+                lines.push(Line {
+                    start: line_start,
+                    end: line_end,
+                    style,
+                    first_in_paragraph: lines.len() == first_line,
+                    last_in_paragraph: false,
+                });
+                line_start = line_end;
+                x = items[line_start..i].iter().map(|item| item.advance).sum();
+                last_break = None;
+            }
+            x += item.advance;
+            if can_break_after(item.c, items.get(i + 1).filter(|_| i + 1 < end).map(|next| next.c)) {
+                last_break = Some(i + 1);
+            }
+        }
+
+        lines.push(Line {
+            start: line_start,
+            end,
+            style,
+            first_in_paragraph: lines.len() == first_line,
+            last_in_paragraph: true,
+        });
+    }
+
+    /// Ascent, descent and height of a line
+    fn line_metrics(&self, items: &[Item], line: &Line) -> (f64, f64, f64) {
+        let mut styles: Vec<usize> = items[line.start..line.end].iter().map(|item| item.style).collect();
+        if styles.is_empty() {
+            styles.push(line.style);
+        }
+        styles.iter().fold((0.0, 0.0, 0.0), |(ascent, descent, height), &index| {
+            let style = &self.styles[index];
+            (f64::max(ascent, style.ascent), f64::max(descent, style.descent), f64::max(height, style.line_height))
+        })
+    }
+
+    /// Replace the end of a line with an ellipsis so that it fits
+    fn ellipsize(&self, items: &mut Vec<Item>, line: &mut Line, available: f64) {
+        let style = items[line.start..line.end].last().map_or(line.style, |item| item.style);
+        let resolved = &self.styles[style];
+        let (face, glyph, advance) = resolved.glyph(ELLIPSIS);
+        let ellipsis = Item {
+            c: ELLIPSIS,
+            char_index: items.get(line.end.saturating_sub(1)).map_or(0, |item| item.char_index),
+            style,
+            face,
+            glyph,
+            advance: advance + resolved.letter_spacing,
+        };
+
+        let mut end = line.end;
+        while end > line.start && line_width(&items[line.start..end]) + ellipsis.advance > available {
+            end -= 1;
+        }
+        end = trim_trailing_whitespace(items, line.start, end);
+
+        // Keep the items after the line so indices of earlier lines stay valid
+        items.insert(end, ellipsis);
+        line.end = end + 1;
+    }
+
+    fn emit_line(&self, items: &[Item], line: &LineBox, space_extra: f64, layout: &mut TextLayout) {
+        let mut x = line.x;
+        let mut start = 0;
+        while start < items.len() {
+            let (style, face) = (items[start].style, items[start].face);
+            let len = items[start..].iter().take_while(|item| item.style == style && item.face == face).count();
+            let run_items = &items[start..start + len];
+            let resolved = &self.styles[style];
+            let face = face.map(|index| &resolved.faces[index]);
+
+            let run_x = x;
+            let mut glyphs = Vec::new();
+            let mut text = String::new();
+            let mut visible_end = x;
+            for item in run_items {
+                text.push(item.c);
+                if !item.c.is_whitespace() {
+                    glyphs.push(PositionedGlyph { glyph_id: item.glyph, x, y: line.baseline, char_index: item.char_index });
+                    visible_end = x + item.advance - resolved.letter_spacing;
+                }
+                x += item.advance;
+                if item.c == ' ' {
+                    x += space_extra;
+                }
+            }
+
+            let decoration = match resolved.style.decoration {
+                TextDecoration::None => None,
+                TextDecoration::Underline => Some(resolved.underline),
+                TextDecoration::Strikethrough => Some(resolved.strikeout),
+            };
+            if let Some((offset, thickness)) = decoration.filter(|_| visible_end > run_x) {
+                layout.decorations.push(DecorationRect {
+                    run: layout.runs.len(),
+                    x: run_x,
+                    y: line.baseline + offset,
+                    width: visible_end - run_x,
+                    height: thickness,
+                    fills: resolved.style.fills.clone(),
+                });
+            }
+
+            if !glyphs.is_empty() {
+                layout.runs.push(GlyphRun {
+                    style_id: resolved.id,
+                    text,
+                    font: face.map(|face| face.font.clone()),
+                    variations: face.map(|face| face.variations.clone()).unwrap_or_default(),
+                    style: resolved.style.clone(),
+                    glyphs,
+                });
+            }
+            start += len;
+        }
+    }
+}
+
+/// Width of a line without its trailing whitespace
+fn line_width(items: &[Item]) -> f64 {
+    let visible = trim_trailing_whitespace(items, 0, items.len());
+    items[..visible].iter().map(|item| item.advance).sum()
+}
+
+fn trim_trailing_whitespace(items: &[Item], start: usize, mut end: usize) -> usize {
+    while end > start && items[end - 1].c.is_whitespace() {
+        end -= 1;
+    }
+    end
+}
+
+/// Whether a line may wrap between `c` and `next`: after spaces and
+/// hyphens, and around ideographs which wrap per character
+fn can_break_after(c: char, next: Option<char>) -> bool {
+    let Some(next) = next else {
+        return false;
+    };
+    if next.is_whitespace() || c == '\u{a0}' || next == '\u{a0}' {
+        return false;
+    }
+    c.is_whitespace() || matches!(c, '-' | '\u{2010}' | '\u{2013}' | '\u{2014}' | '/') || is_cjk(c) || is_cjk(next)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x2e80..=0x9fff | 0xac00..=0xd7af | 0xf900..=0xfaff | 0xff00..=0xffef | 0x20000..=0x2fa1f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fonts::test_font;
+    use crate::kiwi::test_fixture;
+    use crate::render::RenderContext;
+
+    /// Every ASCII letter is 500 units wide, space 250
+    fn fonts() -> FontRegistry {
+        let mut glyphs: Vec<(char, u16)> = (' '..='~').map(|c| (c, if c == ' ' { 250 } else { 500 })).collect();
+        glyphs.push((ELLIPSIS, 1000));
+        let mut fonts = FontRegistry::new();
+        fonts.register(test_font::build("Test Sans", "Regular", 400, false, &glyphs)).unwrap();
+        fonts
+    }
+
+    fn content(characters: &str) -> TextContent {
+        TextContent {
+            characters: characters.to_string(),
+            style: TextStyle {
+                font_family: "Test Sans".into(),
+                font_style: "Regular".into(),
+                font_size: 10.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn line_texts(layout: &TextLayout) -> Vec<String> {
+        let mut lines: Vec<String> = vec![String::new(); layout.lines.len()];
+        for run in &layout.runs {
+            let line = layout.lines.iter().position(|line| line.baseline == run.glyphs[0].y).unwrap();
+            lines[line].push_str(&run.text);
+        }
+        lines.iter().map(|line| line.trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn test_wraps_at_spaces() {
+        let layout = content("aaa bbb ccc").layout(35.0, 100.0, &fonts());
+        assert_eq!(line_texts(&layout), vec!["aaa bbb", "ccc"]);
+        // Auto line height is ascender - descender
+        assert_eq!(layout.lines[1].top, 10.0);
+        assert_eq!(layout.lines[0].baseline, 8.0);
+        assert_eq!(layout.lines[0].width, 32.5);
+    }
+
+    #[test]
+    fn test_breaks_long_words_and_paragraphs() {
+        let mut text = content("aaaaaa\n\nb");
+        text.style.paragraph_spacing = 4.0;
+        let layout = text.layout(20.0, 100.0, &fonts());
+        assert_eq!(line_texts(&layout), vec!["aaaa", "aa", "", "b"]);
+        // Spacing follows each paragraph but not the wrapped line
+        let tops: Vec<f64> = layout.lines.iter().map(|line| line.top).collect();
+        assert_eq!(tops, vec![0.0, 10.0, 24.0, 38.0]);
+    }
+
+    #[test]
+    fn test_alignment_and_auto_resize() {
+        let mut text = content("ab");
+        text.align_horizontal = TextAlignHorizontal::Right;
+        text.align_vertical = TextAlignVertical::Bottom;
+        let layout = text.layout(100.0, 50.0, &fonts());
+        assert_eq!(layout.runs[0].glyphs[0].x, 90.0);
+        assert_eq!(layout.lines[0].top, 40.0);
+
+        text.auto_resize = TextAutoResize::WidthAndHeight;
+        let layout = text.layout(1.0, 1.0, &fonts());
+        assert_eq!((layout.width, layout.height), (10.0, 10.0));
+    }
+
+    #[test]
+    fn test_justify() {
+        let mut text = content("a b c d");
+        text.align_horizontal = TextAlignHorizontal::Justified;
+        let layout = text.layout(22.0, 100.0, &fonts());
+        assert_eq!(line_texts(&layout), vec!["a b c", "d"]);
+        // The line is stretched to the full width, the last line isn't
+        let glyphs = &layout.runs[0].glyphs;
+        assert_eq!(glyphs[2].x, 17.0);
+        assert_eq!(layout.runs[1].glyphs[0].x, 0.0);
+    }
+
+    #[test]
+    fn test_style_runs_and_spacing() {
+        let mut text = content("abcd");
+        text.character_style_ids = vec![0, 0, 7];
+        let mut big = KiwiMessage::default();
+        big.fields.push(("fontSize".into(), KiwiValue::Float(20.0)));
+        big.fields.push(("textCase".into(), KiwiValue::Enum("UPPER".into())));
+        text.style_overrides.push((7, big));
+        text.style.letter_spacing = LetterSpacing::Percent(10.0);
+
+        let layout = text.layout(100.0, 100.0, &fonts());
+        // Style ids past the end of the list fall back to style 0
+        let runs: Vec<(u32, &str)> = layout.runs.iter().map(|run| (run.style_id, run.text.as_str())).collect();
+        assert_eq!(runs, vec![(0, "ab"), (7, "C"), (0, "d")]);
+        assert_eq!(layout.runs[1].glyphs[0].x, 12.0);
+        assert_eq!(layout.runs[2].glyphs[0].x, 24.0);
+        // The line is as tall as its tallest style
+        assert_eq!(layout.lines[0].height, 20.0);
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut text = content("aaa bbb ccc ddd eee");
+        text.truncate = true;
+        text.max_lines = Some(1);
+        let layout = text.layout(40.0, 100.0, &fonts());
+        assert!(layout.truncated);
+        assert_eq!(line_texts(&layout), vec!["aaa bb\u{2026}"]);
+
+        // Without a line limit, lines are cut at the bottom of the box
+        text.max_lines = None;
+        let layout = text.layout(40.0, 25.0, &fonts());
+        assert_eq!(layout.lines.len(), 2);
+    }
+
+    #[test]
+    fn test_fallback_glyphs() {
+        let mut fonts = fonts();
+        fonts.register(test_font::build("Symbols", "Bold", 700, false, &[('\u{2713}', 800)])).unwrap();
+        fonts.set_fallbacks(vec!["Symbols".into()]);
+
+        let layout = content("a\u{2713}b").layout(100.0, 100.0, &fonts);
+        let runs: Vec<(&str, &str)> = layout.runs.iter()
+            .map(|run| (run.text.as_str(), run.font.as_ref().unwrap().family.as_str()))
+            .collect();
+        assert_eq!(runs, vec![("a", "Test Sans"), ("\u{2713}", "Symbols"), ("b", "Test Sans")]);
+        assert_eq!(layout.runs[2].glyphs[0].x, 13.0);
+
+        assert_eq!(text_fonts(&content("\u{2713}"), &fonts), vec!["Test Sans Regular"]);
+    }
+
+    fn text_fonts(text: &TextContent, fonts: &FontRegistry) -> Vec<String> {
+        text.used_styles().iter()
+            .filter_map(|style| fonts.find(&style.font_family, &style.font_style, ""))
+            .map(Font::display_name)
+            .collect()
+    }
+
+    #[test]
+    fn test_decorations_and_missing_fonts() {
+        let mut text = content("ab cd");
+        text.style.decoration = TextDecoration::Underline;
+        text.style.font_family = "Not Registered".into();
+        let layout = text.layout(100.0, 100.0, &FontRegistry::new());
+        assert!(layout.runs[0].font.is_none());
+        assert_eq!(layout.decorations.len(), 1);
+        let underline = &layout.decorations[0];
+        assert_eq!(underline.x, 0.0);
+        assert!((underline.width - 24.5).abs() < 1e-9);
+        assert!(underline.y > layout.lines[0].baseline);
+    }
+
+    #[test]
+    fn test_outlines() {
+        let mut text = content("ab");
+        let layout = text.layout(100.0, 100.0, &fonts());
+        let outlines = layout.outlines();
+        assert_eq!(outlines.len(), 1);
+        let svg = crate::vector::path_to_svg(&outlines[0].path);
+        let baseline = layout.lines[0].baseline;
+        assert!(svg.starts_with(&format!("M 0.5 {}", baseline as f32)), "{}", svg);
+        assert_eq!(svg.matches('M').count(), 2);
+        assert_eq!(svg.matches('Z').count(), 2);
+
+        text.style.decoration = TextDecoration::Underline;
+        let outlines = text.layout(100.0, 100.0, &fonts()).outlines();
+        assert_eq!(crate::vector::path_to_svg(&outlines[0].path).matches('M').count(), 3);
+
+        text.style.font_family = "Not Registered".into();
+        let outlines = text.layout(100.0, 100.0, &FontRegistry::new()).outlines();
+        assert_eq!(crate::vector::path_to_svg(&outlines[0].path).matches('M').count(), 1);
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let file = test_fixture::file();
+        let node = file.get_node("43:16620").unwrap();
+        let text = node.text.as_ref().unwrap();
+        assert_eq!(text.characters, "5:00\u{2013}5:30PM");
+        assert_eq!(text.style.font_family, "SF Pro");
+        assert_eq!(text.style.line_height, LineHeight::Pixels(20.0));
+        assert_eq!(text.auto_resize, TextAutoResize::WidthAndHeight);
+        assert!(!text.style_for(5).fills.is_empty());
+
+        let ctx = RenderContext::empty();
+        let commands = node.draw_commands(&ctx);
+        let info = commands[0].text.as_ref().unwrap();
+        let runs: Vec<&str> = info.runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(runs, vec!["5:00\u{2013}5:30", "PM"]);
+        assert!(info.runs[0].font_postscript_name.is_empty());
+        assert_eq!(info.runs[0].glyphs[0].x, node.x);
+
+        let usages = collect_font_usage(file.ordered_nodes());
+        let semibold = usages.iter().find(|usage| usage.family == "SF Pro" && usage.style == "Semibold").unwrap();
+        assert!(semibold.node_ids.contains(&node.id));
+        assert!(FontRegistry::new().resolve(&semibold.request()).is_none());
+    }
+}
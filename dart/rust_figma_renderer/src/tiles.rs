@@ -4,6 +4,8 @@
 //! Each tile caches draw commands for nodes intersecting its bounds.
 
 use crate::api::{DrawCommand, RectInfo};
+use crate::render::RenderContext;
+use crate::scroll;
 use crate::spatial::{NodeBounds, SpatialIndex};
 use crate::nodes::FigmaNode;
 use std::collections::HashMap;
@@ -138,6 +140,7 @@ impl TileGrid {
         &mut self,
         coord: TileCoord,
         nodes: &HashMap<String, FigmaNode>,
+        ctx: &RenderContext,
         spatial_index: &SpatialIndex,
     ) -> &Tile {
         self.access_counter += 1;
@@ -156,7 +159,7 @@ impl TileGrid {
         }
 
         // Generate new tile
-        let tile = self.generate_tile(coord, nodes, spatial_index);
+        let tile = self.generate_tile(coord, nodes, ctx, spatial_index);
         self.tiles.insert(coord, tile);
         self.tiles.get(&coord).unwrap()
     }
@@ -166,16 +169,34 @@ impl TileGrid {
         &self,
         coord: TileCoord,
         nodes: &HashMap<String, FigmaNode>,
+        ctx: &RenderContext,
         spatial_index: &SpatialIndex,
     ) -> Tile {
         let bounds = coord.bounds();
-        let node_ids = spatial_index.query_rect(
+        let mut node_ids = spatial_index.query_rect(
             bounds.x,
             bounds.y,
             bounds.x + bounds.width,
             bounds.y + bounds.height,
         );
 
+        // Content scrolled into a frame showing in this tile is indexed
+        // where it was before scrolling
+        let tile_bounds = NodeBounds::new(String::new(), bounds.x, bounds.y, bounds.x + bounds.width, bounds.y + bounds.height);
+        for frame_id in ctx.scroll_offsets.into_iter().flat_map(|offsets| offsets.keys()) {
+            let shown = spatial_index.get_node_bounds(frame_id).is_some_and(|frame| frame.intersects(&tile_bounds));
+            if !shown {
+                continue;
+            }
+            let mut pending: Vec<&String> = nodes.get(frame_id).map(|frame| frame.children.iter().collect()).unwrap_or_default();
+            while let Some(id) = pending.pop() {
+                if !node_ids.contains(id) {
+                    node_ids.push(id.clone());
+                }
+                pending.extend(nodes.get(id).into_iter().flat_map(|node| &node.children));
+            }
+        }
+
         let lod = coord.zoom_level;
         let simplification = match lod {
             0 => 1.0,   // Full detail
@@ -194,9 +215,8 @@ impl TileGrid {
                     continue;
                 }
 
-                if let Some(cmd) = node.to_draw_command() {
-                    commands.push(cmd);
-                }
+                let scrolled = ctx.scroll_offsets.and_then(|offsets| scroll::scroll_descendant(nodes, offsets, node));
+                commands.extend(scrolled.as_ref().unwrap_or(node).draw_commands(ctx));
             }
         }
 
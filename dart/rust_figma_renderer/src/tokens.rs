@@ -0,0 +1,684 @@
+//! Design token export
+//!
+//! Variables and the document's color, text and effect styles are written
+//! as W3C Design Tokens JSON, or as a Dart file of Flutter constants. Each
+//! variable collection becomes a group named after it, holding its variables
+//! by their slash separated names, and styles are grouped by their own
+//! names. Aliases stay references wherever the format can express them.
+//!
+//! Library copies of a collection or style share its name, and the first in
+//! file order is exported. Deleted styles and variables are left out.
+
+use crate::api::ColorInfo;
+use crate::effects::{Effect, ShadowEffect};
+use crate::fonts::style_traits;
+use crate::kiwi::FigFile;
+use crate::nodes::FigmaNode;
+use crate::paints::Paint;
+use crate::text::{LetterSpacing, LineHeight, TextDecoration, TextStyle};
+use crate::variables::{Variable, VariableMode, VariableRef, VariableResolver, VariableValue};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Output format of `export_design_tokens`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    /// W3C Design Tokens JSON
+    W3c,
+    /// A Dart file of Flutter constants, a class per collection and mode
+    Dart,
+}
+
+impl TokenFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "w3c" | "json" => Some(TokenFormat::W3c),
+            "dart" => Some(TokenFormat::Dart),
+            _ => None,
+        }
+    }
+}
+
+/// Export the document's variables and styles as design tokens
+pub fn export_design_tokens(file: &FigFile, format: TokenFormat) -> String {
+    let modes = HashMap::new();
+    let tokens = Tokens::collect(file, VariableResolver::new(file, &modes));
+    match format {
+        TokenFormat::W3c => tokens.to_w3c(),
+        TokenFormat::Dart => tokens.to_dart(),
+    }
+}
+
+/// A variable collection, with the variables of its library copies
+struct Collection<'a> {
+    name: &'a str,
+    modes: &'a [VariableMode],
+    variables: Vec<TokenVariable<'a>>,
+}
+
+struct TokenVariable<'a> {
+    node: &'a FigmaNode,
+    variable: &'a Variable,
+    /// The variable's own collection, which may be a library copy
+    set: &'a FigmaNode,
+    /// Value by mode name
+    values: Vec<(&'a str, &'a VariableValue)>,
+}
+
+struct Tokens<'a> {
+    file: &'a FigFile,
+    resolver: VariableResolver<'a>,
+    collections: Vec<Collection<'a>>,
+    /// Color, text and effect styles in file order
+    styles: Vec<&'a FigmaNode>,
+}
+
+impl<'a> Tokens<'a> {
+    fn collect(file: &'a FigFile, resolver: VariableResolver<'a>) -> Self {
+        let nodes = file.ordered_nodes();
+        let mut collections: Vec<Collection> = Vec::new();
+        for set in nodes.iter().filter(|node| node.node_type == "VARIABLE_SET" && !node.soft_deleted) {
+            if !collections.iter().any(|collection| collection.name == set.name) {
+                collections.push(Collection { name: &set.name, modes: &set.modes, variables: Vec::new() });
+            }
+        }
+        for &node in &nodes {
+            let Some(variable) = node.variable.as_ref().filter(|_| !node.soft_deleted) else { continue };
+            let Some(set) = resolver.lookup(&variable.collection) else { continue };
+            let Some(collection) = collections.iter_mut().find(|collection| collection.name == set.name) else { continue };
+            if collection.variables.iter().any(|other| other.node.name == node.name) {
+                continue;
+            }
+            let values = set.modes.iter()
+                .filter_map(|mode| {
+                    let (_, value) = variable.values.iter().find(|(id, _)| *id == mode.id)?;
+                    Some((mode.name.as_str(), value))
+                })
+                .collect();
+            collection.variables.push(TokenVariable { node, variable, set, values });
+        }
+        collections.retain(|collection| !collection.variables.is_empty());
+
+        let mut seen = HashSet::new();
+        let styles = nodes.into_iter()
+            .filter(|node| matches!(node.style_type.as_deref(), Some("FILL" | "TEXT" | "EFFECT")) && !node.soft_deleted)
+            .filter(|node| seen.insert((node.style_type.as_deref(), node.name.as_str())))
+            .collect();
+
+        Tokens { file, resolver, collections, styles }
+    }
+
+    /// The collection and variable an alias points to
+    fn alias_target(&self, variable: &VariableRef) -> Option<(&Collection<'a>, &'a FigmaNode)> {
+        let node = self.resolver.lookup(variable)?;
+        let set = self.resolver.lookup(&node.variable.as_ref()?.collection)?;
+        let collection = self.collections.iter().find(|collection| collection.name == set.name)?;
+        Some((collection, node))
+    }
+
+    fn to_w3c(&self) -> String {
+        let mut root = Map::new();
+        for collection in &self.collections {
+            for variable in &collection.variables {
+                let values: Vec<(&str, Value)> = variable.values.iter()
+                    .filter_map(|&(mode, value)| Some((mode, self.w3c_value(value)?)))
+                    .collect();
+                let Some((_, value)) = values.first() else { continue };
+                let mut token = Map::new();
+                token.insert("$type".into(), json!(w3c_type(&variable.variable.resolved_type)));
+                token.insert("$value".into(), value.clone());
+                if !variable.node.description.is_empty() {
+                    token.insert("$description".into(), json!(variable.node.description));
+                }
+                if collection.modes.len() > 1 {
+                    let modes: Map<String, Value> = values.into_iter().map(|(mode, value)| (mode.to_string(), value)).collect();
+                    token.insert("$extensions".into(), json!({ "com.figma": { "modes": modes } }));
+                }
+                insert_token(&mut root, path(collection.name).chain(path(&variable.node.name)), token);
+            }
+        }
+
+        for style in &self.styles {
+            let token = match style.style_type.as_deref() {
+                Some("FILL") => self.w3c_fill(style),
+                Some("TEXT") => style.text.as_ref().map(|text| w3c_typography(&text.style)),
+                _ => w3c_shadows(&style.effects),
+            };
+            if let Some(mut token) = token {
+                if !style.description.is_empty() {
+                    token.insert("$description".into(), json!(style.description));
+                }
+                insert_token(&mut root, path(&style.name), token);
+            }
+        }
+
+        let mut json = serde_json::to_string_pretty(&Value::Object(root)).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+
+    fn w3c_value(&self, value: &VariableValue) -> Option<Value> {
+        Some(match value {
+            VariableValue::Bool(value) => json!(value),
+            VariableValue::Float(value) => json!(round(*value)),
+            VariableValue::String(value) => json!(value),
+            VariableValue::Color(color) => json!(hex(color)),
+            VariableValue::Alias(variable) => json!(self.w3c_alias(variable)?),
+        })
+    }
+
+    /// A reference to a variable's token, such as `{Themes.system.red}`
+    fn w3c_alias(&self, variable: &VariableRef) -> Option<String> {
+        let (collection, node) = self.alias_target(variable)?;
+        let names: Vec<String> = path(collection.name).chain(path(&node.name)).map(w3c_name).collect();
+        Some(format!("{{{}}}", names.join(".")))
+    }
+
+    /// A fill style's topmost color or gradient
+    fn w3c_fill(&self, style: &FigmaNode) -> Option<Map<String, Value>> {
+        let (token_type, value) = match fill_paint(style)? {
+            Paint::Solid(solid) => {
+                let alias = solid.color_variable.as_ref().and_then(|variable| self.w3c_alias(variable));
+                ("color", alias.map_or_else(|| json!(hex(&paint_color(&solid.color, solid.opacity))), |alias| json!(alias)))
+            }
+            Paint::GradientLinear(gradient) | Paint::GradientRadial(gradient)
+            | Paint::GradientAngular(gradient) | Paint::GradientDiamond(gradient) => {
+                let stops: Vec<Value> = gradient.stops.iter()
+                    .map(|stop| json!({ "color": hex(&paint_color(&stop.color, gradient.opacity)), "position": round(stop.position) }))
+                    .collect();
+                ("gradient", json!(stops))
+            }
+            Paint::Image(_) => return None,
+        };
+        let mut token = Map::new();
+        token.insert("$type".into(), json!(token_type));
+        token.insert("$value".into(), value);
+        Some(token)
+    }
+
+    fn to_dart(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("// Design tokens exported from {}. Regenerate rather than editing.\n", self.file.name));
+        out.push('\n');
+        out.push_str("import 'package:flutter/painting.dart';\n");
+
+        let names = DartNames::new(self);
+        for collection in &self.collections {
+            for mode in collection.modes {
+                let class = &names.classes[&(collection.name, mode.name.as_str())];
+                out.push('\n');
+                out.push_str(&format!("/// {} ({})\n", doc_line(collection.name), doc_line(&mode.name)));
+                out.push_str(&format!("abstract final class {class} {{\n"));
+                for variable in &collection.variables {
+                    let member = &names.members[&(collection.name, variable.node.name.as_str())];
+                    let value = variable.values.iter()
+                        .find(|(name, _)| *name == mode.name)
+                        .filter(|_| self.resolves(variable, &mode.name))
+                        .and_then(|(_, value)| self.dart_value(value, collection, &names));
+                    let Some(value) = value else {
+                        out.push_str(&format!("  // {member}: no value in this mode\n"));
+                        continue;
+                    };
+                    let dart_type = dart_type(&variable.variable.resolved_type);
+                    write_dart_description(&mut out, &variable.node.description);
+                    out.push_str(&format!("  static const {dart_type} {member} = {value};\n"));
+                }
+                out.push_str("}\n");
+            }
+        }
+
+        for (style_type, class) in [("FILL", "ColorStyles"), ("TEXT", "TextStyles"), ("EFFECT", "EffectStyles")] {
+            let mut members = Vec::new();
+            let mut used = HashSet::new();
+            for style in self.styles.iter().filter(|style| style.style_type.as_deref() == Some(style_type)) {
+                let value = match style_type {
+                    "FILL" => self.dart_fill(style, &names),
+                    "TEXT" => style.text.as_ref().map(|text| ("TextStyle", dart_text_style(&text.style))),
+                    _ => dart_shadows(&style.effects),
+                };
+                if let Some((dart_type, value)) = value {
+                    members.push((style, dart_type, unique(&mut used, dart_identifier(&style.name, false)), value));
+                }
+            }
+            if members.is_empty() {
+                continue;
+            }
+            out.push('\n');
+            out.push_str(&format!("abstract final class {class} {{\n"));
+            for (style, dart_type, member, value) in members {
+                write_dart_description(&mut out, &style.description);
+                out.push_str(&format!("  static const {dart_type} {member} = {value};\n"));
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    /// Whether a variable's value in a mode resolves, rather than running
+    /// into an alias cycle or a missing variable
+    fn resolves(&self, variable: &TokenVariable, mode_name: &str) -> bool {
+        let Some(mode) = variable.set.modes.iter().find(|mode| mode.name == mode_name) else { return false };
+        let active = HashMap::from([(variable.set.id.clone(), mode.id.clone())]);
+        VariableResolver::new(self.file, &active)
+            .resolve(&VariableRef::Id(variable.node.id.clone()), None)
+            .is_ok()
+    }
+
+    /// A Dart expression for a value in `collection`. Aliases refer to the
+    /// constant for the same mode in the same collection, or to the only
+    /// mode of another; otherwise they take the value in the first mode.
+    fn dart_value(&self, value: &VariableValue, collection: &Collection, names: &DartNames) -> Option<String> {
+        Some(match value {
+            VariableValue::Bool(value) => value.to_string(),
+            VariableValue::Float(value) => number(*value),
+            VariableValue::String(value) => dart_string(value),
+            VariableValue::Color(color) => dart_color(color),
+            VariableValue::Alias(variable) => {
+                if let Some(reference) = self.dart_reference(variable, Some(collection.name), names) {
+                    return Some(reference);
+                }
+                let resolved = self.resolver.resolve(variable, None).ok()?;
+                return self.dart_value(&resolved, collection, names);
+            }
+        })
+    }
+
+    /// A reference to a variable's constant, from inside `collection`'s
+    /// classes or from elsewhere
+    fn dart_reference(&self, variable: &VariableRef, from: Option<&str>, names: &DartNames) -> Option<String> {
+        let (collection, node) = self.alias_target(variable)?;
+        let member = names.members.get(&(collection.name, node.name.as_str()))?;
+        if from == Some(collection.name) {
+            return Some(member.clone());
+        }
+        match collection.modes {
+            [mode] => Some(format!("{}.{member}", names.classes[&(collection.name, mode.name.as_str())])),
+            _ => None,
+        }
+    }
+
+    fn dart_fill(&self, style: &FigmaNode, names: &DartNames) -> Option<(&'static str, String)> {
+        match fill_paint(style)? {
+            Paint::Solid(solid) => {
+                let reference = solid.color_variable.as_ref()
+                    .and_then(|variable| self.dart_reference(variable, None, names));
+                Some(("Color", reference.unwrap_or_else(|| dart_color(&paint_color(&solid.color, solid.opacity)))))
+            }
+            paint @ (Paint::GradientLinear(gradient) | Paint::GradientRadial(gradient)
+            | Paint::GradientAngular(gradient) | Paint::GradientDiamond(gradient)) => {
+                let [start, end, _] = paint.gradient_handles()?;
+                let colors: Vec<String> = gradient.stops.iter().map(|stop| dart_color(&paint_color(&stop.color, gradient.opacity))).collect();
+                let stops: Vec<String> = gradient.stops.iter().map(|stop| number(stop.position)).collect();
+                let colors = format!("colors: [{}], stops: [{}]", colors.join(", "), stops.join(", "));
+                Some(match paint {
+                    Paint::GradientLinear(_) => (
+                        "LinearGradient",
+                        format!("LinearGradient(begin: {}, end: {}, {colors})", dart_alignment(start), dart_alignment(end)),
+                    ),
+                    Paint::GradientAngular(_) => ("SweepGradient", format!("SweepGradient(center: {}, {colors})", dart_alignment(start))),
+                    _ => {
+                        let radius = (end.0 - start.0).hypot(end.1 - start.1);
+                        ("RadialGradient", format!("RadialGradient(center: {}, radius: {}, {colors})", dart_alignment(start), number(radius)))
+                    }
+                })
+            }
+            Paint::Image(_) => None,
+        }
+    }
+}
+
+/// Dart identifiers for collections, modes and variables, unique within
+/// their scope
+struct DartNames<'a> {
+    /// Class name by collection and mode name
+    classes: HashMap<(&'a str, &'a str), String>,
+    /// Constant name by collection and variable name
+    members: HashMap<(&'a str, &'a str), String>,
+}
+
+impl<'a> DartNames<'a> {
+    fn new(tokens: &Tokens<'a>) -> Self {
+        let mut classes = HashMap::new();
+        let mut members = HashMap::new();
+        let mut used_classes: HashSet<String> = ["ColorStyles", "TextStyles", "EffectStyles"].map(String::from).into();
+        for collection in &tokens.collections {
+            for mode in collection.modes {
+                let name = match collection.modes.len() {
+                    1 => collection.name.to_string(),
+                    _ => format!("{} {}", collection.name, mode.name),
+                };
+                classes.insert((collection.name, mode.name.as_str()), unique(&mut used_classes, dart_identifier(&name, true)));
+            }
+            let mut used = HashSet::new();
+            for variable in &collection.variables {
+                members.insert((collection.name, variable.node.name.as_str()), unique(&mut used, dart_identifier(&variable.node.name, false)));
+            }
+        }
+        DartNames { classes, members }
+    }
+}
+
+/// The non-empty parts of a slash separated name
+fn path(name: &str) -> impl Iterator<Item = &str> {
+    name.split('/').map(str::trim).filter(|part| !part.is_empty())
+}
+
+/// A token or group name without the characters the format reserves
+fn w3c_name(name: &str) -> String {
+    let name = name.trim_start_matches('$').replace(['.', '{', '}'], "_");
+    if name.is_empty() { "_".to_string() } else { name }
+}
+
+/// Add a token to its groups. The first token at a path wins, and tokens
+/// whose path runs through another token are dropped.
+fn insert_token<'p>(root: &mut Map<String, Value>, path: impl Iterator<Item = &'p str>, token: Map<String, Value>) {
+    let path: Vec<String> = path.map(w3c_name).collect();
+    let Some((name, groups)) = path.split_last() else { return };
+    let mut group = root;
+    for name in groups {
+        let entry = group.entry(name.clone()).or_insert_with(|| Value::Object(Map::new()));
+        match entry.as_object_mut() {
+            Some(next) if !next.contains_key("$value") => group = next,
+            _ => return,
+        }
+    }
+    group.entry(name.clone()).or_insert(Value::Object(token));
+}
+
+fn w3c_type(resolved_type: &str) -> &'static str {
+    match resolved_type {
+        "COLOR" => "color",
+        "FLOAT" => "number",
+        "BOOLEAN" => "boolean",
+        _ => "string",
+    }
+}
+
+fn w3c_typography(style: &TextStyle) -> Map<String, Value> {
+    let mut value = Map::new();
+    value.insert("fontFamily".into(), json!(style.font_family));
+    value.insert("fontWeight".into(), json!(style_traits(&style.font_style).0));
+    value.insert("fontSize".into(), json!(format!("{}px", number(style.font_size))));
+    if let Some(height) = line_height(style) {
+        value.insert("lineHeight".into(), json!(round(height)));
+    }
+    value.insert("letterSpacing".into(), json!(format!("{}px", number(letter_spacing(style)))));
+    let mut token = Map::new();
+    token.insert("$type".into(), json!("typography"));
+    token.insert("$value".into(), Value::Object(value));
+    token
+}
+
+/// An effect style's visible shadows, as one shadow or a list
+fn w3c_shadows(effects: &[Effect]) -> Option<Map<String, Value>> {
+    let mut shadows: Vec<Value> = effects.iter()
+        .filter_map(|effect| match effect {
+            Effect::DropShadow(shadow) => Some((shadow, false)),
+            Effect::InnerShadow(shadow) => Some((shadow, true)),
+            _ => None,
+        })
+        .filter(|(shadow, _)| shadow.visible)
+        .map(|(shadow, inset)| json!({
+            "color": hex(&shadow.color),
+            "offsetX": format!("{}px", number(shadow.offset.0)),
+            "offsetY": format!("{}px", number(shadow.offset.1)),
+            "blur": format!("{}px", number(shadow.radius)),
+            "spread": format!("{}px", number(shadow.spread)),
+            "inset": inset,
+        }))
+        .collect();
+    let value = match shadows.len() {
+        0 => return None,
+        1 => shadows.remove(0),
+        _ => Value::Array(shadows),
+    };
+    let mut token = Map::new();
+    token.insert("$type".into(), json!("shadow"));
+    token.insert("$value".into(), value);
+    Some(token)
+}
+
+/// The topmost visible color or gradient of a fill style. Fill styles
+/// often put a texture image under their color, which has no token form.
+fn fill_paint(style: &FigmaNode) -> Option<&Paint> {
+    style.fill_paints.iter().rev().find(|paint| paint.visible() && !matches!(paint, Paint::Image(_)))
+}
+
+pub(crate) fn paint_color(color: &ColorInfo, opacity: f64) -> ColorInfo {
+    ColorInfo { a: (color.a as f64 * opacity.clamp(0.0, 1.0)).round() as u8, ..color.clone() }
+}
+
+/// Line height as a multiple of the font size, unless it's the font's own
+fn line_height(style: &TextStyle) -> Option<f64> {
+    match style.line_height {
+        LineHeight::Auto => None,
+        LineHeight::Pixels(pixels) if style.font_size > 0.0 => Some(pixels / style.font_size),
+        LineHeight::Pixels(_) => None,
+        LineHeight::Percent(percent) => Some(percent / 100.0),
+        LineHeight::Multiple(multiple) => Some(multiple),
+    }
+}
+
+/// Letter spacing in pixels
+fn letter_spacing(style: &TextStyle) -> f64 {
+    match style.letter_spacing {
+        LetterSpacing::None => 0.0,
+        LetterSpacing::Pixels(pixels) => pixels,
+        LetterSpacing::Percent(percent) => percent / 100.0 * style.font_size,
+    }
+}
+
+/// Round away the float noise of values Figma stores as f32
+pub(crate) fn round(value: f64) -> f64 {
+    // Adding zero turns -0 into 0
+    (value * 10_000.0).round() / 10_000.0 + 0.0
+}
+
+pub(crate) fn number(value: f64) -> String {
+    round(value).to_string()
+}
+
+fn hex(color: &ColorInfo) -> String {
+    match color.a {
+        255 => format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+        a => format!("#{:02x}{:02x}{:02x}{a:02x}", color.r, color.g, color.b),
+    }
+}
+
+fn dart_type(resolved_type: &str) -> &'static str {
+    match resolved_type {
+        "COLOR" => "Color",
+        "FLOAT" => "double",
+        "BOOLEAN" => "bool",
+        _ => "String",
+    }
+}
+
+fn dart_color(color: &ColorInfo) -> String {
+    format!("Color(0x{:02X}{:02X}{:02X}{:02X})", color.a, color.r, color.g, color.b)
+}
+
+pub(crate) fn dart_string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('\'', "\\'").replace('$', "\\$")
+        .replace('\n', "\\n").replace('\r', "\\r");
+    format!("'{escaped}'")
+}
+
+/// A point in the unit square as a Flutter `Alignment`
+fn dart_alignment((x, y): (f64, f64)) -> String {
+    format!("Alignment({}, {})", number(x * 2.0 - 1.0), number(y * 2.0 - 1.0))
+}
+
+fn dart_text_style(style: &TextStyle) -> String {
+    let args: Vec<String> = text_style_fields(style).into_iter().map(|(name, value)| format!("{name}: {value}")).collect();
+    format!("TextStyle({})", args.join(", "))
+}
+
+/// The `TextStyle` arguments for a style, other than its color
+pub(crate) fn text_style_fields(style: &TextStyle) -> Vec<(&'static str, String)> {
+    let (weight, italic) = style_traits(&style.font_style);
+    let mut fields = vec![
+        ("fontFamily", dart_string(&style.font_family)),
+        ("fontSize", number(style.font_size)),
+        ("fontWeight", format!("FontWeight.w{}", weight.clamp(100, 900) / 100 * 100)),
+    ];
+    if italic {
+        fields.push(("fontStyle", "FontStyle.italic".into()));
+    }
+    if let Some(height) = line_height(style) {
+        fields.push(("height", number(height)));
+    }
+    if letter_spacing(style) != 0.0 {
+        fields.push(("letterSpacing", number(letter_spacing(style))));
+    }
+    match style.decoration {
+        TextDecoration::None => {}
+        TextDecoration::Underline => fields.push(("decoration", "TextDecoration.underline".into())),
+        TextDecoration::Strikethrough => fields.push(("decoration", "TextDecoration.lineThrough".into())),
+    }
+    fields
+}
+
+/// An effect style's visible drop shadows. Flutter's `BoxShadow` has no
+/// inner shadows, and blurs have no constant form.
+fn dart_shadows(effects: &[Effect]) -> Option<(&'static str, String)> {
+    let shadows: Vec<String> = effects.iter()
+        .filter_map(|effect| match effect {
+            Effect::DropShadow(shadow) if shadow.visible => Some(dart_shadow(shadow)),
+            _ => None,
+        })
+        .collect();
+    (!shadows.is_empty()).then(|| ("List<BoxShadow>", format!("[{}]", shadows.join(", "))))
+}
+
+fn dart_shadow(shadow: &ShadowEffect) -> String {
+    format!(
+        "BoxShadow(color: {}, offset: Offset({}, {}), blurRadius: {}, spreadRadius: {})",
+        dart_color(&shadow.color), number(shadow.offset.0), number(shadow.offset.1), number(shadow.radius), number(shadow.spread),
+    )
+}
+
+fn write_dart_description(out: &mut String, description: &str) {
+    if !description.trim().is_empty() {
+        out.push_str(&format!("  /// {}\n", doc_line(description)));
+    }
+}
+
+fn doc_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+const DART_RESERVED: &[&str] = &[
+    "abstract", "as", "assert", "async", "await", "break", "case", "catch", "class", "const", "continue",
+    "default", "do", "else", "enum", "extends", "false", "final", "finally", "for", "if", "in", "is",
+    "new", "null", "rethrow", "return", "super", "switch", "this", "throw", "true", "try", "var", "void",
+    "while", "with",
+];
+
+/// A camel case Dart identifier from a name such as `1. Themes` or
+/// `system/red`. Leading numbers that only order a name are dropped.
+pub(crate) fn dart_identifier(name: &str, upper: bool) -> String {
+    let words: Vec<&str> = name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    let numeric = |word: &&str| word.chars().all(|c| c.is_ascii_digit());
+    let words = &words[words.iter().position(|word| !numeric(word)).unwrap_or(0)..];
+
+    let mut identifier = String::new();
+    for (i, word) in words.iter().enumerate() {
+        // Shouted words are treated as words, not acronyms
+        let word = if word.chars().all(|c| !c.is_ascii_lowercase()) { word.to_ascii_lowercase() } else { word.to_string() };
+        let mut chars = word.chars();
+        let first = chars.next().unwrap_or_default();
+        identifier.push(if i == 0 && !upper { first.to_ascii_lowercase() } else { first.to_ascii_uppercase() });
+        identifier.extend(chars);
+    }
+    if identifier.is_empty() {
+        identifier.push_str(if upper { "Tokens" } else { "token" });
+    }
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '$');
+    }
+    if DART_RESERVED.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+    identifier
+}
+
+/// An identifier not yet in `used`, numbered if it was
+pub(crate) fn unique(used: &mut HashSet<String>, identifier: String) -> String {
+    let mut candidate = identifier.clone();
+    let mut n = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{identifier}{n}");
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_dart_identifiers() {
+        assert_eq!(dart_identifier("1. Themes", true), "Themes");
+        assert_eq!(dart_identifier("1. Themes 🌘 Dark", true), "ThemesDark");
+        assert_eq!(dart_identifier("system/red", false), "systemRed");
+        assert_eq!(dart_identifier("Regular/34pt", false), "regular34pt");
+        assert_eq!(dart_identifier("🗑️ DEPRECATED/🌈/black", false), "deprecatedBlack");
+        assert_eq!(dart_identifier("spacing/100", false), "spacing100");
+        assert_eq!(dart_identifier("100", false), "$100");
+        assert_eq!(dart_identifier("default", false), "default_");
+        assert_eq!(dart_identifier("🌈", false), "token");
+
+        let mut used = HashSet::new();
+        assert_eq!(unique(&mut used, "red".into()), "red");
+        assert_eq!(unique(&mut used, "red".into()), "red2");
+    }
+
+    #[test]
+    fn test_w3c_paths() {
+        let mut root = Map::new();
+        insert_token(&mut root, path("1. Themes/system/red"), Map::from_iter([("$value".to_string(), json!("#ff0000"))]));
+        insert_token(&mut root, path("1. Themes/system/red/dark"), Map::from_iter([("$value".to_string(), json!("#000000"))]));
+        insert_token(&mut root, path("$private/{x}"), Map::from_iter([("$value".to_string(), json!(1))]));
+        assert_eq!(root["1_ Themes"]["system"]["red"]["$value"], "#ff0000");
+        assert!(root["1_ Themes"]["system"]["red"].get("dark").is_none());
+        assert_eq!(root["private"]["_x_"]["$value"], 1);
+        assert_eq!(hex(&ColorInfo { r: 255, g: 59, b: 48, a: 255 }), "#ff3b30");
+        assert_eq!(hex(&ColorInfo { r: 0, g: 0, b: 0, a: 64 }), "#00000040");
+    }
+
+    #[test]
+    fn test_design_tokens() {
+        let file = test_fixture::file();
+        assert!(file.nodes.values().any(|node| node.style_type.is_some() && node.soft_deleted));
+
+        // Variables keep their aliases per mode, under their collection
+        let json: serde_json::Value = serde_json::from_str(&export_design_tokens(file, TokenFormat::W3c)).unwrap();
+        let themes = &json["1_ Themes"];
+        assert_eq!(themes["system"]["grey5"]["$type"], "color");
+        assert_eq!(themes["system"]["grey5"]["$value"], "#e6e6eb");
+        let background = &themes["bg"]["primary-base"];
+        assert_eq!(background["$value"], "{1_ Themes.system.white}");
+        assert_eq!(background["$extensions"]["com.figma"]["modes"]["🌘 Dark"], "{1_ Themes.system.black}");
+        assert_eq!(json["2_ Responsive"]["app-calculator"]["btn"]["$type"], "number");
+
+        // Styles, by their own names
+        let text = &json["SemiBold"]["18pt"];
+        assert_eq!(text["$type"], "typography");
+        assert_eq!(text["$value"]["fontWeight"], 600);
+        assert_eq!(text["$value"]["fontSize"], "18px");
+        let shadow = &json["(to define)"]["Big Shadow"];
+        assert_eq!(shadow["$type"], "shadow");
+        assert_eq!(shadow["$value"][0]["offsetY"], "4px");
+
+        // Dart has a class per mode, referring to constants in the same class
+        let dart = export_design_tokens(file, TokenFormat::Dart);
+        assert!(dart.contains("abstract final class ThemesDark {"));
+        assert!(dart.contains("  static const Color bgPrimaryBase = systemBlack;"));
+        assert!(dart.contains("  static const TextStyle semiBold18pt = TextStyle(fontFamily: 'SF Pro', fontSize: 18, fontWeight: FontWeight.w600"));
+        assert!(dart.contains("  static const List<BoxShadow> toDefineBigShadow = [BoxShadow("));
+        assert_eq!(TokenFormat::from_name("xml"), None);
+    }
+}
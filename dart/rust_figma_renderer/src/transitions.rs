@@ -0,0 +1,318 @@
+//! Prototype transition animation
+//!
+//! A transition is sampled by building the nodes drawn at that moment,
+//! which are then rendered like any other nodes. Smart animate matches the
+//! layers of the two frames by name, among siblings whose parents matched,
+//! the nth layer of a name with the nth of the same name. Matched layers
+//! move, resize, rotate and fade from one frame's values to the other's,
+//! while the others fade out or in. Other transitions move or fade the
+//! frames as a whole.
+
+use crate::api::ColorInfo;
+use crate::kiwi::{node_matrix, FigFile};
+use crate::nodes::FigmaNode;
+use crate::paints::{Paint, SolidPaint};
+use crate::prototype::{Direction, Transition, TransitionType};
+use crate::variables::VariableResolver;
+use crate::{FigmaError, Result};
+use std::collections::HashMap;
+
+/// How far a frame slid over moves the other way, as a fraction of its size
+const PARALLAX: f64 = 0.3;
+
+/// The nodes drawn at a moment of a transition
+#[derive(Debug, Default)]
+pub struct TransitionFrame {
+    pub nodes: HashMap<String, FigmaNode>,
+    /// Roots to draw, back to front. Each is placed relative to the screen.
+    pub roots: Vec<String>,
+}
+
+/// Sample the transition from one frame to another at `t`, the fraction of
+/// the transition's duration elapsed. Both frames are placed with their
+/// top-left corner at the origin when at rest.
+pub fn sample_transition(
+    file: &FigFile,
+    variables: Option<VariableResolver>,
+    from_id: &str,
+    to_id: &str,
+    transition: &Transition,
+    t: f64,
+) -> Result<TransitionFrame> {
+    let from = file.get_node(from_id).ok_or_else(|| FigmaError::NodeNotFound(from_id.to_string()))?;
+    let to = file.get_node(to_id).ok_or_else(|| FigmaError::NodeNotFound(to_id.to_string()))?;
+    let progress = transition.curve.ease(t);
+    let mut sampler = Sampler { file, variables, progress, frame: TransitionFrame::default() };
+
+    // Offsets of each frame as fractions of its size along the direction,
+    // and whether the destination is drawn on top
+    let (from_shift, to_shift, to_on_top) = match transition.transition_type {
+        TransitionType::SmartAnimate => {
+            let root = sampler.blend(from, to);
+            sampler.place(&root, (0.0, 0.0), 1.0);
+            sampler.frame.roots.push(root);
+            return Ok(sampler.frame);
+        }
+        TransitionType::Instant | TransitionType::ScrollAnimate => {
+            let root = sampler.copy(to);
+            sampler.place(&root, (0.0, 0.0), 1.0);
+            sampler.frame.roots.push(root);
+            return Ok(sampler.frame);
+        }
+        TransitionType::Dissolve => {
+            let (from_root, to_root) = (sampler.copy(from), sampler.copy(to));
+            sampler.place(&from_root, (0.0, 0.0), 1.0);
+            sampler.place(&to_root, (0.0, 0.0), progress.clamp(0.0, 1.0));
+            sampler.frame.roots = vec![from_root, to_root];
+            return Ok(sampler.frame);
+        }
+        TransitionType::SlideFrom(direction) => (shift(direction, -PARALLAX * progress), shift(direction, 1.0 - progress), true),
+        TransitionType::MoveFrom(direction) => ((0.0, 0.0), shift(direction, 1.0 - progress), true),
+        TransitionType::PushFrom(direction) => (shift(direction, -progress), shift(direction, 1.0 - progress), true),
+        TransitionType::SlideOutTo(direction) => (shift(direction, progress), shift(direction, -PARALLAX * (1.0 - progress)), false),
+        TransitionType::MoveOutTo(direction) => (shift(direction, progress), (0.0, 0.0), false),
+    };
+
+    let (from_root, to_root) = (sampler.copy(from), sampler.copy(to));
+    sampler.place(&from_root, (from_shift.0 * from.width, from_shift.1 * from.height), 1.0);
+    sampler.place(&to_root, (to_shift.0 * to.width, to_shift.1 * to.height), 1.0);
+    sampler.frame.roots = if to_on_top { vec![from_root, to_root] } else { vec![to_root, from_root] };
+    Ok(sampler.frame)
+}
+
+/// `amount` of a frame's size towards the edge `direction` names
+fn shift(direction: Direction, amount: f64) -> (f64, f64) {
+    match direction {
+        Direction::Left => (-amount, 0.0),
+        Direction::Right => (amount, 0.0),
+        Direction::Top => (0.0, -amount),
+        Direction::Bottom => (0.0, amount),
+    }
+}
+
+struct Sampler<'a> {
+    file: &'a FigFile,
+    variables: Option<VariableResolver<'a>>,
+    /// Eased progress, which may overshoot 0 and 1
+    progress: f64,
+    frame: TransitionFrame,
+}
+
+impl<'a> Sampler<'a> {
+    /// A copy of a node with variables bound, since blended values must not
+    /// be bound again when rendering
+    fn bound(&self, node: &FigmaNode) -> FigmaNode {
+        self.variables.and_then(|variables| variables.bind(node)).unwrap_or_else(|| node.clone())
+    }
+
+    fn children(&self, node: &FigmaNode) -> Vec<&'a FigmaNode> {
+        node.children.iter().filter_map(|id| self.file.get_node(id)).collect()
+    }
+
+    /// Add a node and its descendants unchanged
+    fn copy(&mut self, node: &FigmaNode) -> String {
+        for child in self.children(node) {
+            self.copy(child);
+        }
+        self.insert(self.bound(node))
+    }
+
+    fn insert(&mut self, node: FigmaNode) -> String {
+        let id = node.id.clone();
+        self.frame.nodes.insert(id.clone(), node);
+        id
+    }
+
+    /// Move a root to `offset` and fade it by `opacity`
+    fn place(&mut self, id: &str, offset: (f64, f64), opacity: f64) {
+        if let Some(node) = self.frame.nodes.get_mut(id) {
+            node.x = offset.0;
+            node.y = offset.1;
+            node.transform = node_matrix(node);
+            node.opacity *= opacity;
+        }
+    }
+
+    /// Add a matched pair as the destination node with blended values, its
+    /// children matched in turn. Unmatched layers of the source are drawn
+    /// beneath those of the destination.
+    fn blend(&mut self, from: &FigmaNode, to: &FigmaNode) -> String {
+        let p = self.progress;
+        let source = self.bound(from);
+        let mut node = self.bound(to);
+
+        // Hidden layers fade rather than pop
+        let opacity = |node: &FigmaNode| if node.visible { node.opacity } else { 0.0 };
+        node.opacity = lerp(opacity(&source), opacity(&node), p.clamp(0.0, 1.0));
+        node.visible = source.visible || node.visible;
+        node.x = lerp(source.x, node.x, p);
+        node.y = lerp(source.y, node.y, p);
+        node.width = lerp(source.width, node.width, p).max(0.0);
+        node.height = lerp(source.height, node.height, p).max(0.0);
+        let turn = (node.rotation - source.rotation + 540.0).rem_euclid(360.0) - 180.0;
+        node.rotation = source.rotation + turn * p;
+        node.transform = node_matrix(&node);
+        node.corner_radius = lerp(source.corner_radius, node.corner_radius, p).max(0.0);
+        let (from_radii, to_radii) = (source.effective_corner_radii(), node.effective_corner_radii());
+        if from_radii != to_radii {
+            node.corner_radii = std::array::from_fn(|i| lerp(from_radii[i], to_radii[i], p).max(0.0));
+        }
+        node.stroke_weight = lerp(source.stroke_weight, node.stroke_weight, p);
+        node.fill_paints = blend_paints(&source.fill_paints, &node.fill_paints, p);
+        node.stroke_paints = blend_paints(&source.stroke_paints, &node.stroke_paints, p);
+        if let Some(text) = &mut node.text {
+            text.style.fills = node.fill_paints.clone();
+        }
+
+        let from_children = self.children(from);
+        let mut matched = vec![false; from_children.len()];
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        let mut children = Vec::new();
+        for child in self.children(to) {
+            let occurrence = occurrences.entry(child.name.as_str()).or_default();
+            let found = from_children.iter().enumerate()
+                .filter(|(_, other)| other.name == child.name)
+                .nth(*occurrence)
+                .map(|(i, _)| i);
+            *occurrence += 1;
+            let id = match found {
+                Some(i) => {
+                    matched[i] = true;
+                    self.blend(from_children[i], child)
+                }
+                None => {
+                    let id = self.copy(child);
+                    self.fade(&id, p);
+                    id
+                }
+            };
+            children.push(id);
+        }
+
+        let mut faded = Vec::new();
+        for (child, _) in from_children.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+            let id = self.copy(child);
+            self.fade(&id, 1.0 - p);
+            faded.push(id);
+        }
+        faded.extend(children);
+        node.children = faded;
+        self.insert(node)
+    }
+
+    fn fade(&mut self, id: &str, opacity: f64) {
+        if let Some(node) = self.frame.nodes.get_mut(id) {
+            node.opacity *= opacity.clamp(0.0, 1.0);
+        }
+    }
+}
+
+fn lerp(from: f64, to: f64, p: f64) -> f64 {
+    from + (to - from) * p
+}
+
+/// Solid paints at the same index blend their colors; other paints switch
+/// to the destination's halfway through
+fn blend_paints(from: &[Paint], to: &[Paint], p: f64) -> Vec<Paint> {
+    if from.len() != to.len() {
+        return if p < 0.5 { from } else { to }.to_vec();
+    }
+    from.iter().zip(to)
+        .map(|pair| match pair {
+            (Paint::Solid(a), Paint::Solid(b)) => {
+                let mut paint = b.clone();
+                let p = p.clamp(0.0, 1.0);
+                let channel = |a: u8, b: u8| lerp(a as f64, b as f64, p).round() as u8;
+                paint.color = ColorInfo {
+                    r: channel(a.color.r, b.color.r),
+                    g: channel(a.color.g, b.color.g),
+                    b: channel(a.color.b, b.color.b),
+                    a: channel(a.color.a, b.color.a),
+                };
+                let visibility = |paint: &SolidPaint| if paint.visible { paint.opacity } else { 0.0 };
+                paint.opacity = lerp(visibility(a), visibility(b), p);
+                paint.visible = a.visible || b.visible;
+                Paint::Solid(paint)
+            }
+            (a, b) => if p < 0.5 { a } else { b }.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+    use crate::prototype::{Action, EasingCurve};
+
+    #[test]
+    fn test_sample_transition() {
+        let mut file = test_fixture::expanded().clone();
+        // A layer only the destination has fades in
+        let badge = FigmaNode {
+            id: "1:900000".into(),
+            parent_id: Some("128:47039".into()),
+            name: "Badge".into(),
+            node_type: "ELLIPSE".into(),
+            visible: true,
+            opacity: 1.0,
+            width: 8.0,
+            height: 8.0,
+            ..Default::default()
+        };
+        file.nodes.insert(badge.id.clone(), badge);
+        file.get_node_mut("128:47039").unwrap().children.push("1:900000".into());
+
+        // Switching a toggle on moves its knob and changes its color
+        let toggle = file.get_node("128:47044").unwrap();
+        let Some(Action::Node { transition: Some(transition), .. }) = toggle.interactions[0].actions.first() else {
+            panic!("expected a smart animate");
+        };
+        let transition = transition.clone();
+        let color = |frame: &TransitionFrame| match &frame.nodes["128:47039"].fill_paints[0] {
+            Paint::Solid(solid) => solid.color.clone(),
+            _ => panic!("expected a solid fill"),
+        };
+
+        let start = sample_transition(&file, None, "128:47044", "128:47038", &transition, 0.0).unwrap();
+        assert_eq!(start.roots, ["128:47038"]);
+        assert_eq!((start.nodes["128:47038"].x, start.nodes["128:47038"].y), (0.0, 0.0));
+        assert_eq!(start.nodes["128:47040"].x, 2.0);
+        assert_eq!(color(&start).g, 118);
+        assert_eq!(start.nodes["1:900000"].opacity, 0.0);
+
+        let middle = sample_transition(&file, None, "128:47044", "128:47038", &transition, 0.5).unwrap();
+        let eased = transition.curve.ease(0.5);
+        assert!(eased > 0.5);
+        assert!((middle.nodes["128:47040"].x - (2.0 + 20.0 * eased)).abs() < 1e-9);
+        assert!((118..200).contains(&color(&middle).g));
+        assert!((middle.nodes["1:900000"].opacity - eased).abs() < 1e-9);
+        // Matched layers take the destination's ids, the others keep theirs
+        assert_eq!(middle.nodes["128:47039"].children, ["128:47040", "1:900000"]);
+        assert!(!middle.nodes.contains_key("128:47046"));
+
+        let end = sample_transition(&file, None, "128:47044", "128:47038", &transition, 1.0).unwrap();
+        assert_eq!(end.nodes["128:47040"].x, 22.0);
+        assert_eq!(color(&end).g, 200);
+
+        // Pushing moves both frames, the destination on top
+        let push = Transition {
+            transition_type: TransitionType::from_name("PUSH_FROM_RIGHT"),
+            duration: 0.3,
+            easing_type: "LINEAR".into(),
+            curve: EasingCurve::LINEAR,
+            smart_animate: false,
+        };
+        let frame = sample_transition(&file, None, "128:47044", "128:47038", &push, 0.25).unwrap();
+        assert_eq!(frame.roots, ["128:47044", "128:47038"]);
+        assert_eq!(frame.nodes["128:47044"].x, -0.25 * 51.0);
+        assert_eq!(frame.nodes["128:47038"].x, 0.75 * 51.0);
+        assert_eq!(frame.nodes["128:47040"].x, 22.0);
+
+        let dissolve = Transition { transition_type: TransitionType::Dissolve, ..push };
+        let frame = sample_transition(&file, None, "128:47044", "128:47038", &dissolve, 0.25).unwrap();
+        assert_eq!((frame.nodes["128:47044"].opacity, frame.nodes["128:47038"].opacity), (1.0, 0.25));
+
+        assert!(matches!(sample_transition(&file, None, "128:47044", "1:1234567", &dissolve, 0.5), Err(FigmaError::NodeNotFound(_))));
+    }
+}
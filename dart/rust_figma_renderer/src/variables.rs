@@ -0,0 +1,422 @@
+//! Variables and modes
+//!
+//! Variables are `VARIABLE` nodes holding a value per mode of their
+//! collection, a `VARIABLE_SET` node. A value may alias another variable,
+//! possibly in another collection. Nodes bind properties to variables, and
+//! frames can pick the mode their content uses for a collection; elsewhere
+//! the mode chosen by the app applies, or else the collection's first mode.
+//!
+//! Figma stores each bound property's value for the modes in effect when
+//! the file was saved, so binding only changes anything in other modes.
+
+use crate::api::ColorInfo;
+use crate::kiwi::{FigFile, KiwiMessage, KiwiValue};
+use crate::nodes::FigmaNode;
+use crate::paints::{color_from_kiwi, Paint};
+use crate::{FigmaError, Result};
+use std::collections::{HashMap, HashSet};
+
+/// A reference to a variable or collection: a node id, or the key of one
+/// published from a library
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VariableRef {
+    Id(String),
+    Key(String),
+}
+
+impl VariableRef {
+    /// Read a `VariableID` or `VariableSetID`
+    pub fn from_kiwi(id: &KiwiMessage) -> Option<Self> {
+        id.guid("guid").map(VariableRef::Id)
+            .or_else(|| Some(VariableRef::Key(id.message("assetRef")?.str("key")?.to_string())))
+    }
+
+    /// The variable a `VariableData` aliases, such as a paint's `colorVar`
+    pub fn from_alias(data: &KiwiMessage) -> Option<Self> {
+        VariableRef::from_kiwi(data.message("value")?.message("alias")?)
+    }
+
+    /// A `VariableData` aliasing this variable, whose value is of the
+    /// given `VariableResolvedDataType`
+    pub fn to_alias(&self, resolved_type: &str) -> KiwiValue {
+        let id = match self {
+            VariableRef::Id(id) => {
+                let (session, local) = id.split_once(':').unwrap_or(("0", id));
+                let number = |part: &str| KiwiValue::Uint(part.parse().unwrap_or(0));
+                ("guid", KiwiValue::message(vec![("sessionID", number(session)), ("localID", number(local))]))
+            }
+            VariableRef::Key(key) => (
+                "assetRef",
+                KiwiValue::message(vec![("key", KiwiValue::String(key.clone())), ("version", KiwiValue::String(String::new()))]),
+            ),
+        };
+        KiwiValue::message(vec![
+            ("value", KiwiValue::message(vec![("alias", KiwiValue::message(vec![id]))])),
+            ("dataType", KiwiValue::Enum("ALIAS".into())),
+            ("resolvedDataType", KiwiValue::Enum(resolved_type.into())),
+        ])
+    }
+}
+
+/// A variable's value in one mode
+#[derive(Debug, Clone)]
+pub enum VariableValue {
+    Bool(bool),
+    Float(f64),
+    String(String),
+    Color(ColorInfo),
+    Alias(VariableRef),
+}
+
+impl VariableValue {
+    /// Build from a decoded `VariableData`. Expressions and the other data
+    /// types return None.
+    pub fn from_kiwi(data: &KiwiMessage) -> Option<Self> {
+        let value = data.message("value")?;
+        match data.str("dataType")? {
+            "BOOLEAN" => Some(VariableValue::Bool(value.bool("boolValue").unwrap_or(false))),
+            "FLOAT" => Some(VariableValue::Float(value.float("floatValue").unwrap_or(0.0))),
+            "STRING" => Some(VariableValue::String(value.str("textValue").unwrap_or_default().to_string())),
+            "COLOR" => Some(VariableValue::Color(color_from_kiwi(value.message("colorValue")?))),
+            "ALIAS" => VariableRef::from_kiwi(value.message("alias")?).map(VariableValue::Alias),
+            _ => None,
+        }
+    }
+
+    /// Schema name of the value's type
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            VariableValue::Bool(_) => "BOOLEAN",
+            VariableValue::Float(_) => "FLOAT",
+            VariableValue::String(_) => "STRING",
+            VariableValue::Color(_) => "COLOR",
+            VariableValue::Alias(_) => "ALIAS",
+        }
+    }
+}
+
+/// What a `VARIABLE` node holds
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub collection: VariableRef,
+    /// Type of the value after following aliases, such as `COLOR`
+    pub resolved_type: String,
+    /// Values by mode id
+    pub values: Vec<(String, VariableValue)>,
+}
+
+impl Variable {
+    /// Build from a decoded `VariableDataValues`
+    pub fn from_kiwi(collection: VariableRef, resolved_type: String, values: &KiwiMessage) -> Self {
+        Variable {
+            collection,
+            resolved_type,
+            values: values.array("entries").iter()
+                .filter_map(KiwiValue::as_message)
+                .filter_map(|entry| Some((
+                    entry.guid("modeID")?,
+                    VariableValue::from_kiwi(entry.message("variableData")?)?,
+                )))
+                .collect(),
+        }
+    }
+}
+
+/// A mode of a `VARIABLE_SET`, such as light or dark
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableMode {
+    pub id: String,
+    pub name: String,
+}
+
+impl VariableMode {
+    /// Build a collection's modes from a decoded `VariableSetMode[]` field,
+    /// in Figma's order
+    pub fn list_from_kiwi(modes: &KiwiValue) -> Vec<VariableMode> {
+        let mut modes: Vec<(&str, VariableMode)> = modes.as_array().iter()
+            .filter_map(KiwiValue::as_message)
+            .filter_map(|mode| Some((
+                mode.str("sortPosition").unwrap_or_default(),
+                VariableMode { id: mode.guid("id")?, name: mode.str("name").unwrap_or_default().to_string() },
+            )))
+            .collect();
+        modes.sort_by(|a, b| a.0.cmp(b.0));
+        modes.into_iter().map(|(_, mode)| mode).collect()
+    }
+}
+
+/// A node property bound to a variable (`variableConsumptionMap`)
+#[derive(Debug, Clone)]
+pub struct VariableBinding {
+    /// The `VariableField` bound, such as `CORNER_RADIUS`
+    pub field: String,
+    pub variable: VariableRef,
+}
+
+impl VariableBinding {
+    /// Build from a decoded `VariableDataMap`, keeping aliases only
+    pub fn list_from_kiwi(map: &KiwiMessage) -> Vec<VariableBinding> {
+        map.array("entries").iter()
+            .filter_map(KiwiValue::as_message)
+            .filter_map(|entry| Some(VariableBinding {
+                field: entry.str("variableField")?.to_string(),
+                variable: VariableRef::from_alias(entry.message("variableData")?)?,
+            }))
+            .collect()
+    }
+}
+
+/// Resolves variables for rendering, given the modes the app picked
+#[derive(Clone, Copy)]
+pub struct VariableResolver<'a> {
+    file: &'a FigFile,
+    /// Mode ids by collection id
+    active_modes: &'a HashMap<String, String>,
+}
+
+impl<'a> VariableResolver<'a> {
+    pub fn new(file: &'a FigFile, active_modes: &'a HashMap<String, String>) -> Self {
+        VariableResolver { file, active_modes }
+    }
+
+    /// The variable or collection node referred to
+    pub fn lookup(&self, reference: &VariableRef) -> Option<&'a FigmaNode> {
+        match reference {
+            VariableRef::Id(id) => self.file.get_node(id),
+            VariableRef::Key(key) => self.file.get_node_by_key(key),
+        }
+    }
+
+    /// The mode of a collection a node's content uses: the one picked by
+    /// the node or its nearest ancestor that picks one, else the app's,
+    /// else the collection's first
+    pub fn mode(&self, collection: &FigmaNode, node: Option<&FigmaNode>) -> Option<&'a str> {
+        let mut current = node;
+        while let Some(node) = current {
+            let explicit = node.explicit_modes.iter()
+                .find(|(set, _)| self.lookup(set).is_some_and(|set| set.id == collection.id));
+            if let Some((_, mode)) = explicit {
+                // Borrow the id from the collection, which outlives the node
+                if let Some(mode) = collection_mode(self.file, &collection.id, mode) {
+                    return Some(mode);
+                }
+            }
+            current = node.parent_id.as_deref().and_then(|id| self.file.get_node(id));
+        }
+        self.active_modes.get(&collection.id)
+            .and_then(|mode| collection_mode(self.file, &collection.id, mode))
+            .or_else(|| self.file.get_node(&collection.id)?.modes.first().map(|mode| mode.id.as_str()))
+    }
+
+    /// A variable's value for a node, following aliases
+    pub fn resolve(&self, variable: &VariableRef, node: Option<&FigmaNode>) -> Result<VariableValue> {
+        let mut seen = HashSet::new();
+        let mut current = variable.clone();
+        loop {
+            let variable_node = self.lookup(&current)
+                .ok_or_else(|| FigmaError::VariableError(format!("{current:?} not found")))?;
+            if !seen.insert(variable_node.id.as_str()) {
+                return Err(FigmaError::VariableError(format!("alias cycle through {}", variable_node.name)));
+            }
+            let variable = variable_node.variable.as_ref()
+                .ok_or_else(|| FigmaError::VariableError(format!("{} is not a variable", variable_node.id)))?;
+            let mode = self.lookup(&variable.collection).and_then(|collection| self.mode(collection, node));
+            let value = variable.values.iter()
+                .find(|(id, _)| Some(id.as_str()) == mode)
+                .or(variable.values.first())
+                .map(|(_, value)| value)
+                .ok_or_else(|| FigmaError::VariableError(format!("{} has no value", variable_node.name)))?;
+            match value {
+                VariableValue::Alias(next) => current = next.clone(),
+                value => return Ok(value.clone()),
+            }
+        }
+    }
+
+    /// A copy of the node with its bound properties set to their variables'
+    /// values, or None if nothing is bound. Variables that don't resolve
+    /// leave the value stored in the file.
+    pub fn bind(&self, node: &FigmaNode) -> Option<FigmaNode> {
+        let paint_bound = |paints: &[Paint]| paints.iter()
+            .any(|paint| matches!(paint, Paint::Solid(solid) if solid.color_variable.is_some()));
+        if node.variable_bindings.is_empty() && !paint_bound(&node.fill_paints) && !paint_bound(&node.stroke_paints) {
+            return None;
+        }
+
+        let mut bound = node.clone();
+        for binding in &node.variable_bindings {
+            if let Ok(value) = self.resolve(&binding.variable, Some(node)) {
+                apply_binding(&mut bound, &binding.field, value);
+            }
+        }
+        for paint in bound.fill_paints.iter_mut().chain(bound.stroke_paints.iter_mut()) {
+            if let Paint::Solid(solid) = paint {
+                if let Some(Ok(VariableValue::Color(color))) = solid.color_variable.as_ref().map(|v| self.resolve(v, Some(node))) {
+                    solid.color = color;
+                }
+            }
+        }
+        if let Some(text) = &mut bound.text {
+            text.style.fills = bound.fill_paints.clone();
+        }
+        Some(bound)
+    }
+
+    /// Ids of the nodes whose bound values can change with a collection's
+    /// mode, directly or through aliases
+    pub fn affected_nodes(&self, collection_id: &str) -> Vec<String> {
+        let variables: Vec<&FigmaNode> = self.file.nodes.values().filter(|node| node.variable.is_some()).collect();
+        let mut dependent: HashSet<&str> = variables.iter()
+            .filter(|node| node.variable.as_ref()
+                .and_then(|variable| self.lookup(&variable.collection))
+                .is_some_and(|collection| collection.id == collection_id))
+            .map(|node| node.id.as_str())
+            .collect();
+        // Variables aliasing a dependent one in any mode depend on it too
+        loop {
+            let before = dependent.len();
+            for node in &variables {
+                let aliases_dependent = node.variable.iter().flat_map(|variable| &variable.values).any(|(_, value)| {
+                    matches!(value, VariableValue::Alias(alias) if self.lookup(alias).is_some_and(|n| dependent.contains(n.id.as_str())))
+                });
+                if aliases_dependent {
+                    dependent.insert(&node.id);
+                }
+            }
+            if dependent.len() == before {
+                break;
+            }
+        }
+
+        let depends = |reference: &VariableRef| self.lookup(reference).is_some_and(|n| dependent.contains(n.id.as_str()));
+        let paint_depends = |paints: &[Paint]| paints.iter().any(|paint| {
+            matches!(paint, Paint::Solid(solid) if solid.color_variable.as_ref().is_some_and(depends))
+        });
+        self.file.nodes.values()
+            .filter(|node| node.variable_bindings.iter().any(|binding| depends(&binding.variable))
+                || paint_depends(&node.fill_paints)
+                || paint_depends(&node.stroke_paints))
+            .map(|node| node.id.clone())
+            .collect()
+    }
+}
+
+/// A mode id of a collection, if it has that mode
+fn collection_mode<'a>(file: &'a FigFile, collection_id: &str, mode_id: &str) -> Option<&'a str> {
+    file.get_node(collection_id)?.modes.iter()
+        .find(|mode| mode.id == mode_id)
+        .map(|mode| mode.id.as_str())
+}
+
+/// Set a node property from a variable's value
+fn apply_binding(node: &mut FigmaNode, field: &str, value: VariableValue) {
+    match (field, value) {
+        ("VISIBLE", VariableValue::Bool(visible)) => node.visible = visible,
+        ("TEXT_DATA", VariableValue::String(characters)) => {
+            if let Some(text) = &mut node.text {
+                text.characters = characters;
+                text.character_style_ids.clear();
+            }
+        }
+        ("FONT_SIZE", VariableValue::Float(size)) => {
+            node.font_size = size;
+            if let Some(text) = &mut node.text {
+                text.style.font_size = size;
+            }
+        }
+        (field, VariableValue::Float(value)) => {
+            let target = match field {
+                "WIDTH" => &mut node.width,
+                "HEIGHT" => &mut node.height,
+                "CORNER_RADIUS" => &mut node.corner_radius,
+                "RECTANGLE_TOP_LEFT_CORNER_RADIUS" => &mut node.corner_radii[0],
+                "RECTANGLE_TOP_RIGHT_CORNER_RADIUS" => &mut node.corner_radii[1],
+                "RECTANGLE_BOTTOM_RIGHT_CORNER_RADIUS" => &mut node.corner_radii[2],
+                "RECTANGLE_BOTTOM_LEFT_CORNER_RADIUS" => &mut node.corner_radii[3],
+                "STROKE_WEIGHT" => &mut node.stroke_weight,
+                "STACK_SPACING" => &mut node.item_spacing,
+                "STACK_PADDING_LEFT" => &mut node.padding[0],
+                "STACK_PADDING_TOP" => &mut node.padding[1],
+                "STACK_PADDING_RIGHT" => &mut node.padding[2],
+                "STACK_PADDING_BOTTOM" => &mut node.padding[3],
+                _ => {
+                    let side = ["BORDER_TOP_WEIGHT", "BORDER_RIGHT_WEIGHT", "BORDER_BOTTOM_WEIGHT", "BORDER_LEFT_WEIGHT"]
+                        .iter()
+                        .position(|name| *name == field);
+                    match (side, &mut node.stroke.side_weights) {
+                        (Some(side), Some(weights)) => &mut weights[side],
+                        _ => return,
+                    }
+                }
+            };
+            *target = value;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiwi::test_fixture;
+
+    #[test]
+    fn test_variables() {
+        let file = test_fixture::expanded();
+        let color = |value: VariableValue| match value {
+            VariableValue::Color(color) => (color.r, color.g, color.b, color.a),
+            other => panic!("expected a color, got {other:?}"),
+        };
+
+        // Themes has light and dark modes, light first
+        let themes = file.get_node("128:42079").unwrap();
+        let modes: Vec<_> = themes.modes.iter().map(|mode| mode.id.as_str()).collect();
+        assert_eq!(modes, ["128:0", "128:4"]);
+        let grey = VariableRef::Id("128:42098".into());
+        let light = HashMap::new();
+        let dark = HashMap::from([("128:42079".to_string(), "128:4".to_string())]);
+        assert_eq!(color(VariableResolver::new(file, &light).resolve(&grey, None).unwrap()), (230, 230, 235, 255));
+        assert_eq!(color(VariableResolver::new(file, &dark).resolve(&grey, None).unwrap()), (40, 40, 42, 255));
+
+        // Aliases resolve per mode
+        let background = VariableRef::Id("128:42271".into());
+        let aliased = file.get_node("128:42271").unwrap().variable.as_ref().unwrap();
+        assert!(aliased.values.iter().all(|(_, value)| matches!(value, VariableValue::Alias(_))));
+        let resolver = VariableResolver::new(file, &dark);
+        assert_eq!(resolver.resolve(&background, None).unwrap().type_name(), "COLOR");
+
+        // An ancestor's explicit mode wins over the active one
+        let cell = file.get_node("I206:57436;205:47785;205:47567").unwrap();
+        let themed = VariableResolver::new(file, &light);
+        assert_eq!(themed.mode(themes, Some(cell)), Some("128:4"));
+        assert_eq!(color(themed.resolve(&background, Some(cell)).unwrap()), color(resolver.resolve(&background, None).unwrap()));
+
+        // Bound paints follow the mode
+        let node = file.ordered_nodes().into_iter()
+            .find(|node| {
+                themed.mode(themes, Some(node)) == Some("128:0")
+                    && node.fill_paints.iter().any(|paint| matches!(paint, Paint::Solid(solid) if solid.color_variable == Some(grey.clone())))
+            })
+            .unwrap();
+        let bound = VariableResolver::new(file, &dark).bind(node).unwrap();
+        let Some(Paint::Solid(solid)) = bound.fill_paints.iter().find(|paint| matches!(paint, Paint::Solid(solid) if solid.color_variable == Some(grey.clone()))) else {
+            unreachable!()
+        };
+        assert_eq!((solid.color.r, solid.color.g, solid.color.b), (40, 40, 42));
+
+        // Switching themes touches nodes bound to it
+        let affected = resolver.affected_nodes("128:42079");
+        assert!(affected.contains(&node.id));
+
+        // Alias cycles are reported instead of looping
+        let mut cyclic = test_fixture::file().clone();
+        for (id, target) in [("128:42080", "128:42081"), ("128:42081", "128:42080")] {
+            let variable = cyclic.get_node_mut(id).unwrap().variable.as_mut().unwrap();
+            for (_, value) in &mut variable.values {
+                *value = VariableValue::Alias(VariableRef::Id(target.into()));
+            }
+        }
+        let err = VariableResolver::new(&cyclic, &light).resolve(&background, None).unwrap_err();
+        assert!(matches!(err, FigmaError::VariableError(_)));
+    }
+}
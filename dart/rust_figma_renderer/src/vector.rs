@@ -1,6 +1,6 @@
 //! Vector path operations
 
-use lyon::path::Path;
+use lyon::path::{Path, PathEvent};
 #[allow(unused_imports)]
 use lyon::path::builder::*; // Provides PathBuilder trait methods
 use lyon::math::point;
@@ -83,3 +83,23 @@ fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
         }
     }
 }
+
+/// Write a lyon Path as SVG path commands
+pub fn path_to_svg(path: &Path) -> String {
+    let mut commands = Vec::new();
+    for event in path.iter() {
+        match event {
+            PathEvent::Begin { at } => commands.push(format!("M {} {}", at.x, at.y)),
+            PathEvent::Line { to, .. } => commands.push(format!("L {} {}", to.x, to.y)),
+            PathEvent::Quadratic { ctrl, to, .. } => {
+                commands.push(format!("Q {} {} {} {}", ctrl.x, ctrl.y, to.x, to.y))
+            }
+            PathEvent::Cubic { ctrl1, ctrl2, to, .. } => {
+                commands.push(format!("C {} {} {} {} {} {}", ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y))
+            }
+            PathEvent::End { close: true, .. } => commands.push("Z".to_string()),
+            PathEvent::End { close: false, .. } => {}
+        }
+    }
+    commands.join(" ")
+}
@@ -0,0 +1,530 @@
+use kiwi_schema::{ByteBuffer, ByteBufferMut};
+use schema::{Definition, Error, Kind, Schema, quote};
+
+/// A minimal JSON document model. Object keys keep their insertion order so
+/// the output lines up with `JSON.stringify` in the JavaScript compiler.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match *self {
+      Json::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+      _ => None,
+    }
+  }
+
+  /// Formats this value like `JSON.stringify(value, null, 2)`.
+  pub fn stringify(&self) -> String {
+    let mut text = String::new();
+    self.write(&mut text, 0);
+    text
+  }
+
+  fn write(&self, text: &mut String, depth: usize) {
+    match *self {
+      Json::Null => text.push_str("null"),
+      Json::Bool(value) => text.push_str(if value { "true" } else { "false" }),
+      Json::Number(value) => text.push_str(&number_to_string(value)),
+      Json::String(ref value) => text.push_str(&quote(value)),
+
+      Json::Array(ref values) => {
+        if values.is_empty() {
+          text.push_str("[]");
+          return;
+        }
+        text.push('[');
+        for (i, value) in values.iter().enumerate() {
+          if i > 0 {
+            text.push(',');
+          }
+          newline(text, depth + 1);
+          value.write(text, depth + 1);
+        }
+        newline(text, depth);
+        text.push(']');
+      },
+
+      Json::Object(ref fields) => {
+        if fields.is_empty() {
+          text.push_str("{}");
+          return;
+        }
+        text.push('{');
+        for (i, (key, value)) in fields.iter().enumerate() {
+          if i > 0 {
+            text.push(',');
+          }
+          newline(text, depth + 1);
+          text.push_str(&quote(key));
+          text.push_str(": ");
+          value.write(text, depth + 1);
+        }
+        newline(text, depth);
+        text.push('}');
+      },
+    }
+  }
+
+  pub fn parse(text: &str) -> Result<Json, Error> {
+    let mut parser = JsonParser {chars: text.chars().collect(), index: 0};
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.index < parser.chars.len() {
+      return Err(parser.error());
+    }
+    Ok(value)
+  }
+}
+
+fn newline(text: &mut String, depth: usize) {
+  text.push('\n');
+  for _ in 0..depth {
+    text.push_str("  ");
+  }
+}
+
+/// Formats a number the way JavaScript's `Number.prototype.toString` does,
+/// which switches to exponential notation outside of [1e-7, 1e21).
+fn number_to_string(value: f64) -> String {
+  if !value.is_finite() {
+    return "null".to_owned();
+  }
+  if value == 0.0 {
+    return "0".to_owned();
+  }
+
+  // Rust's exponential formatting gives the shortest round-tripping digits
+  let formatted = format!("{:e}", value.abs());
+  let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+  let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+  let exponent: i32 = exponent[1..].parse().unwrap();
+  let k = digits.len() as i32;
+  let n = exponent + 1;
+  let sign = if value < 0.0 { "-" } else { "" };
+
+  let body = if k <= n && n <= 21 {
+    format!("{}{}", digits, "0".repeat((n - k) as usize))
+  } else if 0 < n && n <= 21 {
+    format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+  } else if -6 < n && n <= 0 {
+    format!("0.{}{}", "0".repeat(-n as usize), digits)
+  } else {
+    let e = if n - 1 < 0 { format!("e-{}", 1 - n) } else { format!("e+{}", n - 1) };
+    if k == 1 {
+      format!("{}{}", digits, e)
+    } else {
+      format!("{}.{}{}", &digits[..1], &digits[1..], e)
+    }
+  };
+
+  format!("{}{}", sign, body)
+}
+
+struct JsonParser {
+  chars: Vec<char>,
+  index: usize,
+}
+
+impl JsonParser {
+  fn error(&self) -> Error {
+    match self.chars.get(self.index) {
+      Some(c) => Error::new(format!("Unexpected token {} in JSON at position {}", c, self.index)),
+      None => Error::new("Unexpected end of JSON input".to_owned()),
+    }
+  }
+
+  fn skip_whitespace(&mut self) {
+    while self.index < self.chars.len() && " \t\n\r".contains(self.chars[self.index]) {
+      self.index += 1;
+    }
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    self.skip_whitespace();
+    if self.chars.get(self.index) == Some(&c) {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), Error> {
+    if self.eat(c) { Ok(()) } else { Err(self.error()) }
+  }
+
+  fn eat_word(&mut self, word: &str) -> bool {
+    let end = self.index + word.len();
+    if end <= self.chars.len() && self.chars[self.index..end].iter().cloned().eq(word.chars()) {
+      self.index = end;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse_value(&mut self) -> Result<Json, Error> {
+    self.skip_whitespace();
+    match self.chars.get(self.index).cloned() {
+      Some('{') => {
+        self.index += 1;
+        let mut fields = Vec::new();
+        if !self.eat('}') {
+          loop {
+            self.skip_whitespace();
+            if self.chars.get(self.index) != Some(&'"') {
+              return Err(self.error());
+            }
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            fields.retain(|f: &(String, Json)| f.0 != key);
+            fields.push((key, value));
+            if self.eat('}') {
+              break;
+            }
+            self.expect(',')?;
+          }
+        }
+        Ok(Json::Object(fields))
+      },
+
+      Some('[') => {
+        self.index += 1;
+        let mut values = Vec::new();
+        if !self.eat(']') {
+          loop {
+            values.push(self.parse_value()?);
+            if self.eat(']') {
+              break;
+            }
+            self.expect(',')?;
+          }
+        }
+        Ok(Json::Array(values))
+      },
+
+      Some('"') => Ok(Json::String(self.parse_string()?)),
+      Some('t') if self.eat_word("true") => Ok(Json::Bool(true)),
+      Some('f') if self.eat_word("false") => Ok(Json::Bool(false)),
+      Some('n') if self.eat_word("null") => Ok(Json::Null),
+
+      Some(c) if c == '-' || c.is_ascii_digit() => {
+        let start = self.index;
+        while self.index < self.chars.len() && "+-0123456789.eE".contains(self.chars[self.index]) {
+          self.index += 1;
+        }
+        let text: String = self.chars[start..self.index].iter().collect();
+        match text.parse() {
+          Ok(value) => Ok(Json::Number(value)),
+          Err(_) => {
+            self.index = start;
+            Err(self.error())
+          },
+        }
+      },
+
+      _ => Err(self.error()),
+    }
+  }
+
+  fn parse_string(&mut self) -> Result<String, Error> {
+    self.index += 1;
+    let mut result = String::new();
+    let mut pending_surrogate = None;
+
+    loop {
+      let c = match self.chars.get(self.index).cloned() {
+        Some(c) => c,
+        None => return Err(self.error()),
+      };
+      self.index += 1;
+
+      match c {
+        '"' => return Ok(result),
+        '\\' => {
+          let escape = match self.chars.get(self.index).cloned() {
+            Some(escape) => escape,
+            None => return Err(self.error()),
+          };
+          self.index += 1;
+          match escape {
+            '"' => result.push('"'),
+            '\\' => result.push('\\'),
+            '/' => result.push('/'),
+            'b' => result.push('\u{8}'),
+            'f' => result.push('\u{c}'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'u' => {
+              let end = self.index + 4;
+              if end > self.chars.len() {
+                return Err(self.error());
+              }
+              let hex: String = self.chars[self.index..end].iter().collect();
+              let code = match u32::from_str_radix(&hex, 16) {
+                Ok(code) => code,
+                Err(_) => return Err(self.error()),
+              };
+              self.index = end;
+
+              // Combine UTF-16 surrogate pairs
+              if (0xD800..0xDC00).contains(&code) {
+                pending_surrogate = Some(code);
+                continue;
+              }
+              let code = match pending_surrogate.take() {
+                Some(high) if (0xDC00..0xE000).contains(&code) => 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00),
+                _ => code,
+              };
+              result.push(::std::char::from_u32(code).unwrap_or('\u{FFFD}'));
+            },
+            _ => {
+              self.index -= 1;
+              return Err(self.error());
+            },
+          }
+        },
+        c if (c as u32) < 0x20 => {
+          self.index -= 1;
+          return Err(self.error());
+        },
+        c => result.push(c),
+      }
+      pending_surrogate = None;
+    }
+  }
+}
+
+/// Decodes a value of the given type from a binary message, following the
+/// rules of the JavaScript decoder generated by `compileSchema`. Deprecated
+/// fields are read and then dropped.
+pub fn decode(schema: &Schema, type_name: &str, bb: &mut ByteBuffer) -> Result<Json, Error> {
+  let truncated = || Error::new("Index out of bounds".to_owned());
+
+  match type_name {
+    "bool" => Ok(Json::Bool(bb.read_byte().map_err(|_| truncated())? != 0)),
+    "byte" => Ok(Json::Number(bb.read_byte().map_err(|_| truncated())? as f64)),
+    "int" => Ok(Json::Number(bb.read_var_int().map_err(|_| truncated())? as f64)),
+    "uint" => Ok(Json::Number(bb.read_var_uint().map_err(|_| truncated())? as f64)),
+    "float" => Ok(Json::Number(bb.read_var_float().map_err(|_| truncated())? as f64)),
+    "string" => Ok(Json::String(bb.read_string().map_err(|_| truncated())?.into_owned())),
+    _ => {
+      let definition = match schema.definition(type_name) {
+        Some(definition) => definition,
+        None => return Err(Error::new(format!("Invalid type {}", quote(type_name)))),
+      };
+
+      match definition.kind {
+        Kind::Enum => {
+          let value = bb.read_var_uint().map_err(|_| truncated())?;
+          match definition.fields.iter().find(|f| f.value == value) {
+            Some(field) => Ok(Json::String(field.name.clone())),
+            None => Err(Error::new(format!("Invalid value {} for enum {}", value, quote(&definition.name)))),
+          }
+        },
+
+        Kind::Struct => {
+          let mut fields = Vec::new();
+          for field in &definition.fields {
+            fields.push((field.name.clone(), decode_field(schema, field, bb)?));
+          }
+          Ok(Json::Object(fields))
+        },
+
+        Kind::Message => {
+          let mut fields: Vec<(String, Json)> = Vec::new();
+          loop {
+            let value = bb.read_var_uint().map_err(|_| truncated())?;
+            if value == 0 {
+              return Ok(Json::Object(fields));
+            }
+            let field = match definition.fields.iter().find(|f| f.value == value) {
+              Some(field) => field,
+              None => return Err(Error::new("Attempted to parse invalid message".to_owned())),
+            };
+            let decoded = decode_field(schema, field, bb)?;
+            if !field.is_deprecated {
+              fields.retain(|f| f.0 != field.name);
+              fields.push((field.name.clone(), decoded));
+            }
+          }
+        },
+      }
+    },
+  }
+}
+
+fn decode_field(schema: &Schema, field: &::schema::Field, bb: &mut ByteBuffer) -> Result<Json, Error> {
+  let type_name = field.type_name.as_deref().unwrap_or("");
+  if field.is_array {
+    let length = bb.read_var_uint().map_err(|_| Error::new("Index out of bounds".to_owned()))?;
+    let mut values = Vec::new();
+    for _ in 0..length {
+      values.push(decode(schema, type_name, bb)?);
+    }
+    Ok(Json::Array(values))
+  } else {
+    decode(schema, type_name, bb)
+  }
+}
+
+/// Encodes a JSON value as the given type, following the rules of the
+/// JavaScript encoder generated by `compileSchema`. Deprecated fields are
+/// ignored and missing struct fields are an error.
+pub fn encode(schema: &Schema, type_name: &str, value: &Json, bb: &mut ByteBufferMut) -> Result<(), Error> {
+  match type_name {
+    "bool" => bb.write_bool(truthy(value)),
+    "byte" => bb.write_byte(number(value) as i64 as u8),
+    "int" => bb.write_var_int(number(value) as i64 as i32),
+    "uint" => bb.write_var_uint(number(value) as i64 as u32),
+    "float" => bb.write_var_float(number(value) as f32),
+    "string" => match *value {
+      Json::String(ref text) => bb.write_string(text),
+      _ => bb.write_string(&Json::stringify(value)),
+    },
+    _ => {
+      let definition = match schema.definition(type_name) {
+        Some(definition) => definition,
+        None => return Err(Error::new(format!("Invalid type {}", quote(type_name)))),
+      };
+      encode_definition(schema, definition, value, bb)?;
+    },
+  }
+  Ok(())
+}
+
+fn encode_definition(schema: &Schema, definition: &Definition, value: &Json, bb: &mut ByteBufferMut) -> Result<(), Error> {
+  if definition.kind == Kind::Enum {
+    let name = match *value {
+      Json::String(ref name) => name.as_str(),
+      _ => "",
+    };
+    return match definition.fields.iter().find(|f| f.name == name) {
+      Some(field) => {
+        bb.write_var_uint(field.value);
+        Ok(())
+      },
+      None => Err(Error::new(format!("Invalid value {} for enum {}", Json::stringify(value), quote(&definition.name)))),
+    };
+  }
+
+  for field in &definition.fields {
+    if field.is_deprecated {
+      continue;
+    }
+
+    let field_value = match value.get(&field.name) {
+      Some(&Json::Null) | None => {
+        if definition.kind == Kind::Struct {
+          return Err(Error::new(format!("Missing required field {}", quote(&field.name))));
+        }
+        continue;
+      },
+      Some(field_value) => field_value,
+    };
+
+    if definition.kind == Kind::Message {
+      bb.write_var_uint(field.value);
+    }
+
+    let type_name = field.type_name.as_deref().unwrap_or("");
+    if field.is_array {
+      let empty = Vec::new();
+      let values = match *field_value {
+        Json::Array(ref values) => values,
+        _ => &empty,
+      };
+      bb.write_var_uint(values.len() as u32);
+      for item in values {
+        encode(schema, type_name, item, bb)?;
+      }
+    } else {
+      encode(schema, type_name, field_value, bb)?;
+    }
+  }
+
+  if definition.kind == Kind::Message {
+    bb.write_var_uint(0);
+  }
+  Ok(())
+}
+
+fn truthy(value: &Json) -> bool {
+  match *value {
+    Json::Null => false,
+    Json::Bool(value) => value,
+    Json::Number(value) => value != 0.0 && !value.is_nan(),
+    Json::String(ref value) => !value.is_empty(),
+    Json::Array(_) | Json::Object(_) => true,
+  }
+}
+
+fn number(value: &Json) -> f64 {
+  match *value {
+    Json::Bool(true) => 1.0,
+    Json::Bool(false) => 0.0,
+    Json::Number(value) => value,
+    Json::String(ref value) => value.trim().parse().unwrap_or(0.0),
+    _ => 0.0,
+  }
+}
+
+#[test]
+fn number_formatting() {
+  assert_eq!(number_to_string(0.0), "0");
+  assert_eq!(number_to_string(123.0), "123");
+  assert_eq!(number_to_string(-0.5), "-0.5");
+  assert_eq!(number_to_string(0.1f32 as f64), "0.10000000149011612");
+  assert_eq!(number_to_string(1e21), "1e+21");
+  assert_eq!(number_to_string(1e-7), "1e-7");
+  assert_eq!(number_to_string(1.5e-7), "1.5e-7");
+  assert_eq!(number_to_string(0.000001), "0.000001");
+  assert_eq!(number_to_string(4294967295.0), "4294967295");
+}
+
+#[test]
+fn json_round_trip() {
+  let text = "{\n  \"a\": [\n    1,\n    true,\n    null\n  ],\n  \"b\": \"\\u0001\\n\",\n  \"c\": {},\n  \"d\": []\n}";
+  let value = Json::parse(text).unwrap();
+  assert_eq!(value.get("b"), Some(&Json::String("\u{1}\n".to_owned())));
+  assert_eq!(value.stringify(), text);
+  assert!(Json::parse("{\"a\": }").is_err());
+  assert!(Json::parse("[1, 2").is_err());
+}
+
+#[test]
+fn json_message_round_trip() {
+  let schema = ::parser::parse_schema("
+    enum Kind { A = 1; B = 2; }
+    struct Point { float x; float y; }
+    message Shape {
+      Kind kind = 1;
+      Point[] points = 2;
+      string name = 3;
+      uint old = 4 [deprecated];
+    }
+  ").unwrap();
+  let json = Json::parse("{\"kind\": \"B\", \"points\": [{\"x\": 0.5, \"y\": -2}], \"name\": \"tri\", \"old\": 3}").unwrap();
+
+  let mut bb = ByteBufferMut::new();
+  encode(&schema, "Shape", &json, &mut bb).unwrap();
+  let bytes = bb.data();
+  assert_eq!(bytes, [1, 2, 2, 1, 126, 0, 0, 0, 128, 1, 0, 0, 3, 116, 114, 105, 0, 0]);
+
+  let decoded = decode(&schema, "Shape", &mut ByteBuffer::new(&bytes)).unwrap();
+  assert_eq!(decoded.stringify(), "{\n  \"kind\": \"B\",\n  \"points\": [\n    {\n      \"x\": 0.5,\n      \"y\": -2\n    }\n  ],\n  \"name\": \"tri\"\n}");
+
+  let mut bb = ByteBufferMut::new();
+  assert!(encode(&schema, "Point", &Json::parse("{\"x\": 1}").unwrap(), &mut bb).is_err());
+  assert!(decode(&schema, "Shape", &mut ByteBuffer::new(&[1, 3, 0])).is_err());
+}
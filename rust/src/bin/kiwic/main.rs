@@ -0,0 +1,193 @@
+//! A Rust port of the `kiwic` command from the JavaScript package. It converts
+//! schemas between the textual and binary formats, converts messages between
+//! the binary format and JSON, and generates Rust code for a schema.
+
+extern crate kiwi_schema;
+
+mod json;
+mod parser;
+mod rust;
+mod schema;
+
+use json::Json;
+use kiwi_schema::{ByteBuffer, ByteBufferMut};
+use schema::{Error, Kind, Schema, quote};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process;
+
+const USAGE: &str = "
+Usage: kiwic [OPTIONS]
+
+Options:
+
+  --help                Print this message.
+  --schema [PATH]       The schema file to use.
+  --rust [PATH]         Generate Rust code.
+  --text [PATH]         Encode the schema as text.
+  --binary [PATH]       Encode the schema as a binary blob.
+  --root-type [NAME]    Set the root type for JSON.
+  --to-json [PATH]      Convert a binary file to JSON.
+  --from-json [PATH]    Convert a JSON file to binary.
+
+Examples:
+
+  kiwic --schema test.kiwi --rust test.rs
+  kiwic --schema test.kiwi --binary test.bkiwi
+  kiwic --schema test.bkiwi --text test.kiwi
+  kiwic --schema test.kiwi --root-type Test --from-json buffer.json
+  kiwic --schema test.kiwi --root-type Test --to-json buffer.bin
+";
+
+const FLAGS: [&str; 7] = ["--schema", "--rust", "--text", "--binary", "--root-type", "--to-json", "--from-json"];
+
+#[derive(Default)]
+struct Flags {
+  schema: Option<String>,
+  rust: Option<String>,
+  text: Option<String>,
+  binary: Option<String>,
+  root_type: Option<String>,
+  to_json: Option<String>,
+  from_json: Option<String>,
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+  fs::read(path).map_err(|e| format!("Could not read {}: {}", quote(path), e))
+}
+
+/// Avoids unnecessarily modifying files, like the JavaScript version.
+fn write_file(path: &str, contents: &[u8]) -> Result<(), String> {
+  if let Ok(existing) = fs::read(path) {
+    if existing == contents {
+      return Ok(());
+    }
+  }
+  fs::write(path, contents).map_err(|e| format!("Could not write {}: {}", quote(path), e))
+}
+
+/// Formats a schema error with its location and, for textual schemas, the
+/// offending line with a caret under the column.
+fn format_error(path: &str, content: Option<&str>, error: &Error) -> String {
+  if error.line == 0 && error.column == 0 {
+    return error.text.clone();
+  }
+  let mut message = format!("{}:{}:{}: error: {}", path, error.line, error.column, error.text);
+  if let Some(text) = content {
+    let line = text.split('\n').nth(error.line.saturating_sub(1)).unwrap_or("");
+    message.push_str(&format!("\n{}\n{}^", line, " ".repeat(error.column.saturating_sub(1))));
+  }
+  message
+}
+
+fn load_schema(path: &str) -> Result<Schema, String> {
+  let buffer = read_file(path)?;
+
+  // Binary schemas will have null-terminated strings
+  if buffer.contains(&0) {
+    let binary = kiwi_schema::Schema::decode(&buffer).map_err(|_| format!("Invalid binary schema {}", quote(path)))?;
+    return Schema::from_binary(&binary).map_err(|e| format_error(path, None, &e));
+  }
+
+  let text = String::from_utf8_lossy(&buffer).into_owned();
+  parser::parse_schema(&text).map_err(|e| format_error(path, Some(&text), &e))
+}
+
+fn run(args: &[String]) -> Result<i32, String> {
+  let mut flags = Flags::default();
+
+  // Parse flags
+  let mut i = 0;
+  while i < args.len() {
+    let arg = &args[i];
+
+    if arg == "-h" || arg == "--help" || !arg.starts_with('-') {
+      println!("{}", USAGE);
+      return Ok(1);
+    }
+
+    if !FLAGS.contains(&arg.as_str()) {
+      return Err(format!("Unknown flag {} (use \"--help\" for usage)", quote(arg)));
+    }
+    if i + 1 == args.len() {
+      return Err(format!("Missing value for {} (use \"--help\" for usage)", quote(arg)));
+    }
+
+    let value = Some(args[i + 1].clone());
+    match arg.as_str() {
+      "--schema" => flags.schema = value,
+      "--rust" => flags.rust = value,
+      "--text" => flags.text = value,
+      "--binary" => flags.binary = value,
+      "--root-type" => flags.root_type = value,
+      "--to-json" => flags.to_json = value,
+      _ => flags.from_json = value,
+    }
+    i += 2;
+  }
+
+  // Must have a schema
+  let schema_path = match flags.schema {
+    Some(ref path) => path.clone(),
+    None => {
+      println!("{}", USAGE);
+      return Ok(1);
+    }
+  };
+  let schema = load_schema(&schema_path)?;
+
+  // Validate the root type
+  if let Some(ref root_type) = flags.root_type {
+    match schema.definition(root_type) {
+      Some(definition) if definition.kind != Kind::Enum => {},
+      _ => return Err(format!("Invalid root type: {}", quote(root_type))),
+    }
+  }
+
+  // Generate Rust code
+  if let Some(ref path) = flags.rust {
+    write_file(path, rust::compile_schema_rust(&schema).as_bytes())?;
+  }
+
+  // Generate a binary schema file
+  if let Some(ref path) = flags.binary {
+    write_file(path, &schema.to_binary().encode())?;
+  }
+
+  // Generate a textual schema file
+  if let Some(ref path) = flags.text {
+    write_file(path, schema.pretty_print().as_bytes())?;
+  }
+
+  // Convert a binary file to JSON
+  if let Some(ref path) = flags.to_json {
+    let root_type = flags.root_type.as_ref().ok_or("Missing flag --root-type when using --to-json")?;
+    let buffer = read_file(path)?;
+    let value = json::decode(&schema, root_type, &mut ByteBuffer::new(&buffer)).map_err(|e| e.text)?;
+    write_file(&format!("{}.json", path), format!("{}\n", value.stringify()).as_bytes())?;
+  }
+
+  // Convert a JSON file to binary
+  if let Some(ref path) = flags.from_json {
+    let root_type = flags.root_type.as_ref().ok_or("Missing flag --root-type when using --from-json")?;
+    let buffer = read_file(path)?;
+    let value = Json::parse(&String::from_utf8_lossy(&buffer)).map_err(|e| e.text)?;
+    let mut bb = ByteBufferMut::new();
+    json::encode(&schema, root_type, &value, &mut bb).map_err(|e| e.text)?;
+    write_file(&format!("{}.bin", path), &bb.data())?;
+  }
+
+  Ok(0)
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  match run(&args) {
+    Ok(code) => process::exit(code),
+    Err(message) => {
+      let _ = writeln!(std::io::stderr(), "{}", message);
+      process::exit(1);
+    }
+  }
+}
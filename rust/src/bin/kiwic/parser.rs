@@ -0,0 +1,388 @@
+use schema::{Definition, Error, Field, Kind, Schema, NATIVE_TYPES, RESERVED_NAMES, quote};
+use std::collections::HashMap;
+
+struct Token {
+  text: String,
+  line: usize,
+  column: usize,
+}
+
+fn is_word(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_boundary(chars: &[char], i: usize) -> bool {
+  let before = i > 0 && is_word(chars[i - 1]);
+  let after = i < chars.len() && is_word(chars[i]);
+  before != after
+}
+
+fn starts_with(chars: &[char], i: usize, text: &str) -> bool {
+  let text: Vec<char> = text.chars().collect();
+  chars.len() >= i + text.len() && chars[i..i + text.len()] == text[..]
+}
+
+/// Returns the end of the token starting at `i`, if there is one. This mirrors
+/// the alternation in the regular expression used by the JavaScript tokenizer
+/// so both implementations agree on what counts as a syntax error.
+fn match_token(chars: &[char], i: usize) -> Option<usize> {
+  let len = chars.len();
+  let c = chars[i];
+
+  // Integers: (?:-|\b)\d+\b
+  let digits = if c == '-' { i + 1 } else if is_boundary(chars, i) { i } else { len };
+  if digits < len && chars[digits].is_ascii_digit() {
+    let mut end = digits;
+    while end < len && chars[end].is_ascii_digit() {
+      end += 1;
+    }
+    while end > digits && !is_boundary(chars, end) {
+      end -= 1;
+    }
+    if end > digits {
+      return Some(end);
+    }
+  }
+
+  // Punctuation: [=;{}]|\[\]|\[deprecated\]
+  if c == '=' || c == ';' || c == '{' || c == '}' {
+    return Some(i + 1);
+  }
+  if starts_with(chars, i, "[]") {
+    return Some(i + 2);
+  }
+  if starts_with(chars, i, "[deprecated]") {
+    return Some(i + 12);
+  }
+
+  // Identifiers: \b[A-Za-z_][A-Za-z0-9_]*\b
+  if (c.is_ascii_alphabetic() || c == '_') && is_boundary(chars, i) {
+    let mut end = i + 1;
+    while end < len && is_word(chars[end]) {
+      end += 1;
+    }
+    return Some(end);
+  }
+
+  // Comments: \/\/.*
+  if starts_with(chars, i, "//") {
+    let mut end = i + 2;
+    while end < len && !is_line_terminator(chars[end]) {
+      end += 1;
+    }
+    return Some(end);
+  }
+
+  // Whitespace: \s+
+  if c.is_whitespace() {
+    let mut end = i + 1;
+    while end < len && chars[end].is_whitespace() {
+      end += 1;
+    }
+    return Some(end);
+  }
+
+  None
+}
+
+fn is_line_terminator(c: char) -> bool {
+  c == '\n' || c == '\r' || c == '\u{2028}' || c == '\u{2029}'
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut tokens = Vec::new();
+  let mut line = 0;
+  let mut column = 0;
+  let mut i = 0;
+
+  while i < chars.len() {
+    let end = match match_token(&chars, i) {
+      Some(end) => end,
+      None => {
+        let mut end = i + 1;
+        while end < chars.len() && match_token(&chars, end).is_none() {
+          end += 1;
+        }
+        let part: String = chars[i..end].iter().collect();
+        return Err(Error::at(format!("Syntax error {}", quote(&part)), line + 1, column + 1));
+      }
+    };
+    let part: String = chars[i..end].iter().collect();
+
+    // Keep non-whitespace tokens
+    if !part.starts_with("//") && !part.chars().all(char::is_whitespace) {
+      tokens.push(Token {text: part.clone(), line: line + 1, column: column + 1});
+    }
+
+    // Keep track of the line and column counts
+    let lines: Vec<&str> = part.split('\n').collect();
+    if lines.len() > 1 {
+      column = 0;
+    }
+    line += lines.len() - 1;
+    column += lines[lines.len() - 1].chars().count();
+    i = end;
+  }
+
+  // End-of-file token
+  tokens.push(Token {text: String::new(), line, column});
+  Ok(tokens)
+}
+
+fn is_identifier(text: &str) -> bool {
+  let mut chars = text.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(is_word),
+    _ => false,
+  }
+}
+
+fn is_integer(text: &str) -> bool {
+  let digits = text.strip_prefix('-').unwrap_or(text);
+  !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  index: usize,
+}
+
+impl Parser {
+  fn current(&self) -> &Token {
+    &self.tokens[self.index]
+  }
+
+  fn eat<F: Fn(&str) -> bool>(&mut self, test: F) -> bool {
+    if test(&self.current().text) {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn eat_text(&mut self, text: &str) -> bool {
+    self.eat(|t| t == text)
+  }
+
+  fn expect<F: Fn(&str) -> bool>(&mut self, test: F, expected: &str) -> Result<(), Error> {
+    if self.eat(test) {
+      Ok(())
+    } else {
+      let token = self.current();
+      Err(Error::at(format!("Expected {} but found {}", expected, quote(&token.text)), token.line, token.column))
+    }
+  }
+
+  fn expect_text(&mut self, text: &str) -> Result<(), Error> {
+    self.expect(|t| t == text, &quote(text))
+  }
+
+  fn parse(&mut self) -> Result<Schema, Error> {
+    let mut definitions = Vec::new();
+    let mut package = None;
+
+    if self.eat_text("package") {
+      package = Some(self.current().text.clone());
+      self.expect(is_identifier, "identifier")?;
+      self.expect_text(";")?;
+    }
+
+    while self.index < self.tokens.len() && !self.eat(str::is_empty) {
+      let kind = if self.eat_text("enum") {
+        Kind::Enum
+      } else if self.eat_text("struct") {
+        Kind::Struct
+      } else if self.eat_text("message") {
+        Kind::Message
+      } else {
+        let token = self.current();
+        return Err(Error::at(format!("Unexpected token {}", quote(&token.text)), token.line, token.column));
+      };
+
+      // All definitions start off the same
+      let (name, line, column) = {
+        let token = self.current();
+        (token.text.clone(), token.line, token.column)
+      };
+      self.expect(is_identifier, "identifier")?;
+      self.expect_text("{")?;
+
+      // Parse fields
+      let mut fields: Vec<Field> = Vec::new();
+      while !self.eat_text("}") {
+        let mut type_name = None;
+        let mut is_array = false;
+        let mut is_deprecated = false;
+
+        // Enums don't have types
+        if kind != Kind::Enum {
+          type_name = Some(self.current().text.clone());
+          self.expect(is_identifier, "identifier")?;
+          is_array = self.eat_text("[]");
+        }
+
+        let (field_name, field_line, field_column) = {
+          let token = self.current();
+          (token.text.clone(), token.line, token.column)
+        };
+        self.expect(is_identifier, "identifier")?;
+
+        // Structs don't have explicit values
+        let mut value = fields.len() as u32 + 1;
+        if kind != Kind::Struct {
+          self.expect_text("=")?;
+          let (text, line, column) = {
+            let token = self.current();
+            (token.text.clone(), token.line, token.column)
+          };
+          self.expect(is_integer, "integer")?;
+          match text.parse::<i32>() {
+            Ok(parsed) if parsed.to_string() == text => value = parsed as u32,
+            _ => return Err(Error::at(format!("Invalid integer {}", quote(&text)), line, column)),
+          }
+        }
+
+        let (deprecated_line, deprecated_column) = (self.current().line, self.current().column);
+        if self.eat_text("[deprecated]") {
+          if kind != Kind::Message {
+            return Err(Error::at("Cannot deprecate this field".to_owned(), deprecated_line, deprecated_column));
+          }
+          is_deprecated = true;
+        }
+
+        self.expect_text(";")?;
+
+        fields.push(Field {
+          name: field_name,
+          line: field_line,
+          column: field_column,
+          type_name,
+          is_array,
+          is_deprecated,
+          value,
+        });
+      }
+
+      definitions.push(Definition {name, line, column, kind, fields});
+    }
+
+    Ok(Schema {package, definitions})
+  }
+}
+
+fn verify(root: &Schema) -> Result<(), Error> {
+  let mut defined_types: Vec<&str> = NATIVE_TYPES.to_vec();
+  let mut definitions = HashMap::new();
+
+  // Define definitions
+  for definition in &root.definitions {
+    if defined_types.contains(&definition.name.as_str()) {
+      return Err(Error::at(format!("The type {} is defined twice", quote(&definition.name)), definition.line, definition.column));
+    }
+    if RESERVED_NAMES.contains(&definition.name.as_str()) {
+      return Err(Error::at(format!("The type name {} is reserved", quote(&definition.name)), definition.line, definition.column));
+    }
+    defined_types.push(&definition.name);
+    definitions.insert(definition.name.as_str(), definition);
+  }
+
+  // Check fields
+  for definition in &root.definitions {
+    let fields = &definition.fields;
+    if definition.kind == Kind::Enum || fields.is_empty() {
+      continue;
+    }
+
+    // Check types
+    for field in fields {
+      let type_name = field.type_name.as_deref().unwrap_or("");
+      if !defined_types.contains(&type_name) {
+        return Err(Error::at(format!("The type {} is not defined for field {}", quote(type_name), quote(&field.name)), field.line, field.column));
+      }
+    }
+
+    // Check values
+    let mut values = Vec::new();
+    for field in fields {
+      if values.contains(&field.value) {
+        return Err(Error::at(format!("The id for field {} is used twice", quote(&field.name)), field.line, field.column));
+      }
+      if field.value as i32 <= 0 {
+        return Err(Error::at(format!("The id for field {} must be positive", quote(&field.name)), field.line, field.column));
+      }
+      if field.value as usize > fields.len() {
+        return Err(Error::at(format!("The id for field {} cannot be larger than {}", quote(&field.name), fields.len()), field.line, field.column));
+      }
+      values.push(field.value);
+    }
+  }
+
+  // Check that structs don't contain themselves
+  let mut state = HashMap::new();
+  for definition in &root.definitions {
+    check_recursion(&definition.name, &definitions, &mut state)?;
+  }
+
+  Ok(())
+}
+
+fn check_recursion<'a>(name: &'a str, definitions: &HashMap<&'a str, &'a Definition>, state: &mut HashMap<&'a str, u8>) -> Result<(), Error> {
+  if let Some(definition) = definitions.get(name) {
+    if definition.kind == Kind::Struct {
+      if state.get(name) == Some(&1) {
+        return Err(Error::at(format!("Recursive nesting of {} is not allowed", quote(name)), definition.line, definition.column));
+      }
+      if state.get(name) != Some(&2) {
+        state.insert(name, 1);
+        for field in &definition.fields {
+          if !field.is_array {
+            if let Some(ref type_name) = field.type_name {
+              check_recursion(type_name, definitions, state)?;
+            }
+          }
+        }
+        state.insert(name, 2);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Parses and validates a schema in the textual format.
+pub fn parse_schema(text: &str) -> Result<Schema, Error> {
+  let schema = Parser {tokens: tokenize(text)?, index: 0}.parse()?;
+  verify(&schema)?;
+  Ok(schema)
+}
+
+#[test]
+fn parse_matches_js_binary() {
+  let schema = parse_schema(include_str!("../../../../test/test1-schema.kiwi")).unwrap();
+  assert_eq!(schema.package, Some("test1".to_owned()));
+  assert_eq!(schema.to_binary().encode(), include_bytes!("../../../../test/test1-schema.bkiwi").to_vec());
+
+  let schema = parse_schema(include_str!("../../../../test/test2-schema.kiwi")).unwrap();
+  assert_eq!(schema.to_binary().encode(), include_bytes!("../../../../test/test2-schema.bkiwi").to_vec());
+}
+
+#[test]
+fn parse_round_trip() {
+  let schema = parse_schema(include_str!("../../../../test/test-schema.kiwi")).unwrap();
+  let binary = ::schema::Schema::from_binary(&schema.to_binary()).unwrap();
+  assert_eq!(binary.pretty_print(), include_str!("../../../../test/test-schema-round-trip.kiwi"));
+  assert_eq!(parse_schema(&schema.pretty_print()).unwrap().pretty_print(), schema.pretty_print());
+}
+
+#[test]
+fn parse_errors() {
+  let error = |text| parse_schema(text).unwrap_err();
+  assert_eq!(error("message Foo { int x = 1; int y = 1; }"), Error::at("The id for field \"y\" is used twice".to_owned(), 1, 30));
+  assert_eq!(error("struct Foo { Foo x; }"), Error::at("Recursive nesting of \"Foo\" is not allowed".to_owned(), 1, 8));
+  assert_eq!(error("message Foo { Bar x = 1; }"), Error::at("The type \"Bar\" is not defined for field \"x\"".to_owned(), 1, 19));
+  assert_eq!(error("struct Foo { int x [deprecated]; }"), Error::at("Cannot deprecate this field".to_owned(), 1, 20));
+  assert_eq!(error("enum Foo { A = 01; }"), Error::at("Invalid integer \"01\"".to_owned(), 1, 16));
+  assert_eq!(error("enum Foo {\n  A = 1;\n  B = 2$;\n}"), Error::at("Syntax error \"$\"".to_owned(), 3, 8));
+  assert_eq!(error("enum Foo { A = 1 }"), Error::at("Expected \";\" but found \"}\"".to_owned(), 1, 18));
+}
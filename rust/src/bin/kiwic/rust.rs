@@ -0,0 +1,326 @@
+use schema::{Definition, Field, Kind, Schema};
+
+const KEYWORDS: [&str; 34] = [
+  "as", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+  "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+  "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe",
+  "use", "where", "while", "async", "await",
+];
+
+/// These keywords can't be used as raw identifiers either.
+const PATH_KEYWORDS: [&str; 4] = ["crate", "self", "Self", "super"];
+
+fn field_name(name: &str) -> String {
+  if KEYWORDS.contains(&name) {
+    format!("r#{}", name)
+  } else if PATH_KEYWORDS.contains(&name) {
+    format!("{}_", name)
+  } else {
+    name.to_owned()
+  }
+}
+
+fn type_name(field: &Field) -> &str {
+  field.type_name.as_deref().unwrap_or("")
+}
+
+fn element_type(field: &Field) -> String {
+  match type_name(field) {
+    "bool" => "bool".to_owned(),
+    "byte" => "u8".to_owned(),
+    "int" => "i32".to_owned(),
+    "uint" => "u32".to_owned(),
+    "float" => "f32".to_owned(),
+    "string" => "String".to_owned(),
+    other => other.to_owned(),
+  }
+}
+
+fn field_type(schema: &Schema, definition: &Definition, field: &Field) -> String {
+  let mut result = element_type(field);
+  if field.is_array {
+    result = format!("Vec<{}>", result);
+  } else if needs_box(schema, definition, field) {
+    result = format!("Box<{}>", result);
+  }
+  if definition.kind == Kind::Message {
+    result = format!("Option<{}>", result);
+  }
+  result
+}
+
+/// Messages can refer to themselves through other definitions, so non-array
+/// fields that could close such a cycle are boxed. Structs can't contain
+/// themselves, so only messages and struct fields inside messages need this.
+fn needs_box(schema: &Schema, definition: &Definition, field: &Field) -> bool {
+  if field.is_array {
+    return false;
+  }
+  match schema.definition(type_name(field)).map(|d| d.kind) {
+    Some(Kind::Message) => true,
+    Some(Kind::Struct) => definition.kind == Kind::Message,
+    _ => false,
+  }
+}
+
+fn decode_element(field: &Field) -> String {
+  match type_name(field) {
+    "bool" => "bb.read_bool()?".to_owned(),
+    "byte" => "bb.read_byte()?".to_owned(),
+    "int" => "bb.read_var_int()?".to_owned(),
+    "uint" => "bb.read_var_uint()?".to_owned(),
+    "float" => "bb.read_var_float()?".to_owned(),
+    "string" => "bb.read_string()?.into_owned()".to_owned(),
+    other => format!("{}::decode(bb)?", other),
+  }
+}
+
+fn decode_function(field: &Field) -> String {
+  match type_name(field) {
+    "bool" => "ByteBuffer::read_bool".to_owned(),
+    "byte" => "ByteBuffer::read_byte".to_owned(),
+    "int" => "ByteBuffer::read_var_int".to_owned(),
+    "uint" => "ByteBuffer::read_var_uint".to_owned(),
+    "float" => "ByteBuffer::read_var_float".to_owned(),
+    "string" => "|bb| bb.read_string().map(|s| s.into_owned())".to_owned(),
+    other => format!("{}::decode", other),
+  }
+}
+
+fn decode_field(schema: &Schema, definition: &Definition, field: &Field) -> String {
+  if field.is_array {
+    if type_name(field) == "byte" {
+      return "decode_byte_array(bb)?".to_owned();
+    }
+    return format!("decode_array(bb, {})?", decode_function(field));
+  }
+  if needs_box(schema, definition, field) {
+    return format!("Box::new({})", decode_element(field));
+  }
+  decode_element(field)
+}
+
+/// Returns the statement that encodes `value`, which is either a reference
+/// (when `is_ref` is true) or a place expression like `self.x`.
+fn encode_element(field: &Field, value: &str, is_ref: bool) -> String {
+  let copied = if is_ref { format!("*{}", value) } else { value.to_owned() };
+  let borrowed = if is_ref { value.to_owned() } else { format!("&{}", value) };
+  match type_name(field) {
+    "bool" => format!("bb.write_bool({});", copied),
+    "byte" => format!("bb.write_byte({});", copied),
+    "int" => format!("bb.write_var_int({});", copied),
+    "uint" => format!("bb.write_var_uint({});", copied),
+    "float" => format!("bb.write_var_float({});", copied),
+    "string" => format!("bb.write_string({});", borrowed),
+    _ => format!("{}.encode(bb);", value),
+  }
+}
+
+fn encode_field(field: &Field, value: &str, is_ref: bool, indent: &str, lines: &mut Vec<String>) {
+  if field.is_array {
+    lines.push(format!("{}bb.write_var_uint({}.len() as u32);", indent, value));
+    if type_name(field) == "byte" {
+      lines.push(format!("{}bb.write_bytes({}{});", indent, if is_ref { "" } else { "&" }, value));
+    } else {
+      lines.push(format!("{}for value in {}{} {{", indent, if is_ref { "" } else { "&" }, value));
+      lines.push(format!("{}  {}", indent, encode_element(field, "value", true)));
+      lines.push(format!("{}}}", indent));
+    }
+  } else {
+    lines.push(format!("{}{}", indent, encode_element(field, value, is_ref)));
+  }
+}
+
+fn compile_enum(definition: &Definition, lines: &mut Vec<String>) {
+  let name = &definition.name;
+
+  lines.push("#[allow(non_camel_case_types)]".to_owned());
+  lines.push("#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]".to_owned());
+  lines.push(format!("pub enum {} {{", name));
+  for field in &definition.fields {
+    lines.push(format!("  {},", field.name));
+  }
+  lines.push("}".to_owned());
+  lines.push(String::new());
+
+  lines.push(format!("impl {} {{", name));
+  lines.push(format!("  pub fn from_value(value: u32) -> Option<{}> {{", name));
+  lines.push("    match value {".to_owned());
+  for field in &definition.fields {
+    lines.push(format!("      {} => Some({}::{}),", field.value, name, field.name));
+  }
+  lines.push("      _ => None,".to_owned());
+  lines.push("    }".to_owned());
+  lines.push("  }".to_owned());
+  lines.push(String::new());
+  lines.push("  pub fn value(self) -> u32 {".to_owned());
+  lines.push("    match self {".to_owned());
+  for field in &definition.fields {
+    lines.push(format!("      {}::{} => {},", name, field.name, field.value));
+  }
+  lines.push("    }".to_owned());
+  lines.push("  }".to_owned());
+  lines.push(String::new());
+  lines.push(format!("  pub fn decode(bb: &mut ByteBuffer) -> Result<{}, ()> {{", name));
+  lines.push(format!("    {}::from_value(bb.read_var_uint()?).ok_or(())", name));
+  lines.push("  }".to_owned());
+  lines.push(String::new());
+  lines.push("  pub fn encode(&self, bb: &mut ByteBufferMut) {".to_owned());
+  lines.push("    bb.write_var_uint(self.value());".to_owned());
+  lines.push("  }".to_owned());
+  lines.push("}".to_owned());
+}
+
+fn compile_struct(schema: &Schema, definition: &Definition, lines: &mut Vec<String>) {
+  let name = &definition.name;
+  let is_message = definition.kind == Kind::Message;
+  let fields: Vec<&Field> = definition.fields.iter().filter(|f| !f.is_deprecated).collect();
+
+  lines.push("#[allow(non_snake_case)]".to_owned());
+  lines.push(if is_message {
+    "#[derive(Clone, Debug, Default, PartialEq)]".to_owned()
+  } else {
+    "#[derive(Clone, Debug, PartialEq)]".to_owned()
+  });
+  lines.push(format!("pub struct {} {{", name));
+  for field in &fields {
+    lines.push(format!("  pub {}: {},", field_name(&field.name), field_type(schema, definition, field)));
+  }
+  lines.push("}".to_owned());
+  lines.push(String::new());
+
+  lines.push(format!("impl {} {{", name));
+  lines.push(format!("  pub fn decode(bb: &mut ByteBuffer) -> Result<{}, ()> {{", name));
+  if is_message {
+    lines.push(format!("    let mut result = {}::default();", name));
+    lines.push("    loop {".to_owned());
+    lines.push("      match bb.read_var_uint()? {".to_owned());
+    lines.push("        0 => return Ok(result),".to_owned());
+    for field in &definition.fields {
+      if field.is_deprecated {
+        let decoded = if field.is_array { decode_field(schema, definition, field) } else { decode_element(field) };
+        lines.push(format!("        {} => {{ {}; }},", field.value, decoded));
+      } else {
+        lines.push(format!("        {} => result.{} = Some({}),", field.value, field_name(&field.name), decode_field(schema, definition, field)));
+      }
+    }
+    lines.push("        _ => return Err(()),".to_owned());
+    lines.push("      }".to_owned());
+    lines.push("    }".to_owned());
+  } else {
+    for field in &fields {
+      lines.push(format!("    let {} = {};", field_name(&field.name), decode_field(schema, definition, field)));
+    }
+    let names: Vec<String> = fields.iter().map(|f| field_name(&f.name)).collect();
+    lines.push(format!("    Ok({} {{{}}})", name, names.join(", ")));
+  }
+  lines.push("  }".to_owned());
+  lines.push(String::new());
+
+  lines.push("  pub fn encode(&self, bb: &mut ByteBufferMut) {".to_owned());
+  for field in &fields {
+    let value = format!("self.{}", field_name(&field.name));
+    if is_message {
+      lines.push(format!("    if let Some(ref value) = {} {{", value));
+      lines.push(format!("      bb.write_var_uint({});", field.value));
+      encode_field(field, "value", true, "      ", lines);
+      lines.push("    }".to_owned());
+    } else {
+      encode_field(field, &value, false, "    ", lines);
+    }
+  }
+  if is_message {
+    lines.push("    bb.write_var_uint(0);".to_owned());
+  }
+  lines.push("  }".to_owned());
+  lines.push("}".to_owned());
+}
+
+/// Generates Rust types with `decode` and `encode` methods for every
+/// definition in the schema. The generated code only depends on the
+/// `ByteBuffer` and `ByteBufferMut` types from this crate.
+pub fn compile_schema_rust(schema: &Schema) -> String {
+  let mut lines = Vec::new();
+
+  lines.push("// This file was generated by kiwic. Do not edit.".to_owned());
+  if let Some(ref package) = schema.package {
+    lines.push(format!("// Package: {}", package));
+  }
+  lines.push(String::new());
+  lines.push("use kiwi_schema::{ByteBuffer, ByteBufferMut};".to_owned());
+
+  // Only emit the array helpers that are used to avoid dead code warnings
+  let arrays: Vec<&Field> = schema.definitions.iter()
+    .filter(|d| d.kind != Kind::Enum)
+    .flat_map(|d| d.fields.iter())
+    .filter(|f| f.is_array)
+    .collect();
+  if arrays.iter().any(|f| type_name(f) != "byte") {
+    lines.push(String::new());
+    lines.push("fn decode_array<'a, T, F: FnMut(&mut ByteBuffer<'a>) -> Result<T, ()>>(bb: &mut ByteBuffer<'a>, mut decode: F) -> Result<Vec<T>, ()> {".to_owned());
+    lines.push("  let length = bb.read_var_uint()?;".to_owned());
+    lines.push("  let mut values = Vec::new();".to_owned());
+    lines.push("  for _ in 0..length {".to_owned());
+    lines.push("    values.push(decode(bb)?);".to_owned());
+    lines.push("  }".to_owned());
+    lines.push("  Ok(values)".to_owned());
+    lines.push("}".to_owned());
+  }
+  if arrays.iter().any(|f| type_name(f) == "byte") {
+    lines.push(String::new());
+    lines.push("fn decode_byte_array(bb: &mut ByteBuffer) -> Result<Vec<u8>, ()> {".to_owned());
+    lines.push("  let length = bb.read_var_uint()? as usize;".to_owned());
+    lines.push("  Ok(bb.read_bytes(length)?.to_vec())".to_owned());
+    lines.push("}".to_owned());
+  }
+
+  for definition in &schema.definitions {
+    lines.push(String::new());
+    match definition.kind {
+      Kind::Enum => compile_enum(definition, &mut lines),
+      Kind::Struct | Kind::Message => compile_struct(schema, definition, &mut lines),
+    }
+  }
+
+  lines.push(String::new());
+  lines.join("\n")
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod generated {
+  include!("../../../../test/test-schema.rs");
+}
+
+#[test]
+fn generated_code_is_current() {
+  let schema = ::parser::parse_schema(include_str!("../../../../test/test-schema.kiwi")).unwrap();
+  assert_eq!(compile_schema_rust(&schema), include_str!("../../../../test/test-schema.rs"));
+}
+
+#[test]
+fn generated_code_round_trip() {
+  use kiwi_schema::{ByteBuffer, ByteBufferMut};
+  use self::generated::*;
+
+  let message = NestedMessage {a: Some(1), b: Some(Box::new(CompoundMessage {x: Some(2), y: None})), c: None};
+  let mut bb = ByteBufferMut::new();
+  message.encode(&mut bb);
+  let bytes = bb.data();
+  assert_eq!(bytes, [1, 1, 2, 1, 2, 0, 0]);
+  assert_eq!(NestedMessage::decode(&mut ByteBuffer::new(&bytes)), Ok(message));
+
+  let value = EnumStruct {x: Enum::B, y: vec![Enum::A, Enum::B]};
+  let mut bb = ByteBufferMut::new();
+  value.encode(&mut bb);
+  let bytes = bb.data();
+  assert_eq!(bytes, [200, 1, 2, 100, 200, 1]);
+  assert_eq!(EnumStruct::decode(&mut ByteBuffer::new(&bytes)), Ok(value));
+
+  // Deprecated fields are skipped when decoding
+  let mut bb = ByteBufferMut::new();
+  NonDeprecatedMessage {a: Some(1), b: Some(2), c: None, d: Some(vec![3]), e: None, f: None, g: Some(4)}.encode(&mut bb);
+  let decoded = DeprecatedMessage::decode(&mut ByteBuffer::new(&bb.data())).unwrap();
+  assert_eq!(decoded, DeprecatedMessage {a: Some(1), c: None, e: None, g: Some(4)});
+  assert_eq!(EnumStruct::decode(&mut ByteBuffer::new(&[7])), Err(()));
+}
@@ -0,0 +1,242 @@
+use kiwi_schema;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The types that are built into the language. These match the order used by
+/// the JavaScript implementation, where the type id of a built-in type is the
+/// bitwise complement of its index in this list.
+pub const NATIVE_TYPES: [&str; 6] = ["bool", "byte", "int", "uint", "float", "string"];
+
+/// These are special names that the JavaScript code generator reserves.
+pub const RESERVED_NAMES: [&str; 2] = ["ByteBuffer", "package"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+  Enum,
+  Struct,
+  Message,
+}
+
+impl Kind {
+  pub fn keyword(self) -> &'static str {
+    match self {
+      Kind::Enum => "enum",
+      Kind::Struct => "struct",
+      Kind::Message => "message",
+    }
+  }
+}
+
+/// A field from a textual schema. Unlike [kiwi_schema::Field] this remembers
+/// the type by name, whether the field was deprecated, and where it came from
+/// so errors can point back into the source text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+  pub name: String,
+  pub line: usize,
+  pub column: usize,
+  pub type_name: Option<String>,
+  pub is_array: bool,
+  pub is_deprecated: bool,
+  pub value: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+  pub name: String,
+  pub line: usize,
+  pub column: usize,
+  pub kind: Kind,
+  pub fields: Vec<Field>,
+}
+
+/// The compiler's view of a schema. This carries everything the textual
+/// format can express, which is more than the binary format keeps around.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+  pub package: Option<String>,
+  pub definitions: Vec<Definition>,
+}
+
+/// An error with an optional source location. Errors from the binary schema
+/// decoder and the JSON converter don't have a location.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+  pub text: String,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Error {
+  pub fn new(text: String) -> Error {
+    Error {text, line: 0, column: 0}
+  }
+
+  pub fn at(text: String, line: usize, column: usize) -> Error {
+    Error {text, line, column}
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.text.fmt(f)
+  }
+}
+
+/// Quotes a string the same way `JSON.stringify` does so error messages match
+/// the ones from the JavaScript compiler.
+pub fn quote(text: &str) -> String {
+  let mut result = String::with_capacity(text.len() + 2);
+  result.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      '\u{8}' => result.push_str("\\b"),
+      '\u{c}' => result.push_str("\\f"),
+      c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
+
+impl Schema {
+  pub fn definition(&self, name: &str) -> Option<&Definition> {
+    self.definitions.iter().find(|d| d.name == name)
+  }
+
+  /// Converts from the in-memory representation used by the runtime library.
+  /// Binary schemas don't store the package name or deprecation flags.
+  pub fn from_binary(schema: &kiwi_schema::Schema) -> Result<Schema, Error> {
+    let mut definitions = Vec::with_capacity(schema.defs.len());
+
+    for def in &schema.defs {
+      let kind = match def.kind {
+        kiwi_schema::DefKind::Enum => Kind::Enum,
+        kiwi_schema::DefKind::Struct => Kind::Struct,
+        kiwi_schema::DefKind::Message => Kind::Message,
+      };
+      let mut fields = Vec::with_capacity(def.fields.len());
+
+      for field in &def.fields {
+        let type_name = if kind == Kind::Enum {
+          None
+        } else if field.type_id < 0 {
+          let index = !field.type_id as usize;
+          match NATIVE_TYPES.get(index) {
+            Some(name) => Some(name.to_string()),
+            None => return Err(Error::new(format!("Invalid type {}", field.type_id))),
+          }
+        } else {
+          match schema.defs.get(field.type_id as usize) {
+            Some(other) => Some(other.name.clone()),
+            None => return Err(Error::new(format!("Invalid type {}", field.type_id))),
+          }
+        };
+
+        fields.push(Field {
+          name: field.name.clone(),
+          line: 0,
+          column: 0,
+          type_name,
+          is_array: field.is_array,
+          is_deprecated: false,
+          value: field.value,
+        });
+      }
+
+      definitions.push(Definition {name: def.name.clone(), line: 0, column: 0, kind, fields});
+    }
+
+    Ok(Schema {package: None, definitions})
+  }
+
+  /// Converts to the in-memory representation used by the runtime library.
+  /// Encoding the result gives the same bytes as `kiwic --binary` in the
+  /// JavaScript implementation.
+  pub fn to_binary(&self) -> kiwi_schema::Schema {
+    let mut index = HashMap::new();
+    for (i, definition) in self.definitions.iter().enumerate() {
+      index.insert(definition.name.as_str(), i as i32);
+    }
+
+    kiwi_schema::Schema::new(self.definitions.iter().map(|definition| {
+      let kind = match definition.kind {
+        Kind::Enum => kiwi_schema::DefKind::Enum,
+        Kind::Struct => kiwi_schema::DefKind::Struct,
+        Kind::Message => kiwi_schema::DefKind::Message,
+      };
+
+      kiwi_schema::Def::new(definition.name.clone(), kind, definition.fields.iter().map(|field| {
+        let type_id = match field.type_name {
+          None => 0,
+          Some(ref name) => match NATIVE_TYPES.iter().position(|t| t == name) {
+            Some(i) => !(i as i32),
+            None => index.get(name.as_str()).cloned().unwrap_or(0),
+          },
+        };
+        kiwi_schema::Field {name: field.name.clone(), type_id, is_array: field.is_array, value: field.value}
+      }).collect())
+    }).collect())
+  }
+
+  /// Prints the schema in the textual format. This is the inverse of parsing.
+  pub fn pretty_print(&self) -> String {
+    let mut text = String::new();
+
+    if let Some(ref package) = self.package {
+      text.push_str(&format!("package {};\n", package));
+    }
+
+    for (i, definition) in self.definitions.iter().enumerate() {
+      if i > 0 || self.package.is_some() {
+        text.push('\n');
+      }
+      text.push_str(&format!("{} {} {{\n", definition.kind.keyword(), definition.name));
+
+      for field in &definition.fields {
+        text.push_str("  ");
+        if definition.kind != Kind::Enum {
+          text.push_str(field.type_name.as_deref().unwrap_or(""));
+          if field.is_array {
+            text.push_str("[]");
+          }
+          text.push(' ');
+        }
+        text.push_str(&field.name);
+        if definition.kind != Kind::Struct {
+          text.push_str(&format!(" = {}", field.value));
+        }
+        if field.is_deprecated {
+          text.push_str(" [deprecated]");
+        }
+        text.push_str(";\n");
+      }
+
+      text.push_str("}\n");
+    }
+
+    text
+  }
+}
+
+#[test]
+fn binary_round_trip() {
+  let bytes = include_bytes!("../../../../test/test1-schema.bkiwi");
+  let schema = Schema::from_binary(&kiwi_schema::Schema::decode(bytes).unwrap()).unwrap();
+  assert_eq!(schema.definitions.len(), 2);
+  assert_eq!(schema.definitions[1].fields[1].type_name, Some("Struct".to_owned()));
+  assert_eq!(schema.to_binary().encode(), bytes.to_vec());
+}
+
+#[test]
+fn quote_escapes() {
+  assert_eq!(quote("abc"), "\"abc\"");
+  assert_eq!(quote("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+  assert_eq!(quote("\u{1}"), "\"\\u0001\"");
+}
@@ -0,0 +1,1044 @@
+// This file was generated by kiwic. Do not edit.
+// Package: test
+
+use kiwi_schema::{ByteBuffer, ByteBufferMut};
+
+fn decode_array<'a, T, F: FnMut(&mut ByteBuffer<'a>) -> Result<T, ()>>(bb: &mut ByteBuffer<'a>, mut decode: F) -> Result<Vec<T>, ()> {
+  let length = bb.read_var_uint()?;
+  let mut values = Vec::new();
+  for _ in 0..length {
+    values.push(decode(bb)?);
+  }
+  Ok(values)
+}
+
+fn decode_byte_array(bb: &mut ByteBuffer) -> Result<Vec<u8>, ()> {
+  let length = bb.read_var_uint()? as usize;
+  Ok(bb.read_bytes(length)?.to_vec())
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Enum {
+  A,
+  B,
+}
+
+impl Enum {
+  pub fn from_value(value: u32) -> Option<Enum> {
+    match value {
+      100 => Some(Enum::A),
+      200 => Some(Enum::B),
+      _ => None,
+    }
+  }
+
+  pub fn value(self) -> u32 {
+    match self {
+      Enum::A => 100,
+      Enum::B => 200,
+    }
+  }
+
+  pub fn decode(bb: &mut ByteBuffer) -> Result<Enum, ()> {
+    Enum::from_value(bb.read_var_uint()?).ok_or(())
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.value());
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumStruct {
+  pub x: Enum,
+  pub y: Vec<Enum>,
+}
+
+impl EnumStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<EnumStruct, ()> {
+    let x = Enum::decode(bb)?;
+    let y = decode_array(bb, Enum::decode)?;
+    Ok(EnumStruct {x, y})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    self.x.encode(bb);
+    bb.write_var_uint(self.y.len() as u32);
+    for value in &self.y {
+      value.encode(bb);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoolStruct {
+  pub x: bool,
+}
+
+impl BoolStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<BoolStruct, ()> {
+    let x = bb.read_bool()?;
+    Ok(BoolStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_bool(self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByteStruct {
+  pub x: u8,
+}
+
+impl ByteStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<ByteStruct, ()> {
+    let x = bb.read_byte()?;
+    Ok(ByteStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_byte(self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntStruct {
+  pub x: i32,
+}
+
+impl IntStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<IntStruct, ()> {
+    let x = bb.read_var_int()?;
+    Ok(IntStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_int(self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UintStruct {
+  pub x: u32,
+}
+
+impl UintStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<UintStruct, ()> {
+    let x = bb.read_var_uint()?;
+    Ok(UintStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatStruct {
+  pub x: f32,
+}
+
+impl FloatStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<FloatStruct, ()> {
+    let x = bb.read_var_float()?;
+    Ok(FloatStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_float(self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringStruct {
+  pub x: String,
+}
+
+impl StringStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<StringStruct, ()> {
+    let x = bb.read_string()?.into_owned();
+    Ok(StringStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_string(&self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompoundStruct {
+  pub x: u32,
+  pub y: u32,
+}
+
+impl CompoundStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<CompoundStruct, ()> {
+    let x = bb.read_var_uint()?;
+    let y = bb.read_var_uint()?;
+    Ok(CompoundStruct {x, y})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x);
+    bb.write_var_uint(self.y);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NestedStruct {
+  pub a: u32,
+  pub b: CompoundStruct,
+  pub c: u32,
+}
+
+impl NestedStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<NestedStruct, ()> {
+    let a = bb.read_var_uint()?;
+    let b = CompoundStruct::decode(bb)?;
+    let c = bb.read_var_uint()?;
+    Ok(NestedStruct {a, b, c})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.a);
+    self.b.encode(bb);
+    bb.write_var_uint(self.c);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BoolMessage {
+  pub x: Option<bool>,
+}
+
+impl BoolMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<BoolMessage, ()> {
+    let mut result = BoolMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_bool()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_bool(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ByteMessage {
+  pub x: Option<u8>,
+}
+
+impl ByteMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<ByteMessage, ()> {
+    let mut result = ByteMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_byte()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_byte(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntMessage {
+  pub x: Option<i32>,
+}
+
+impl IntMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<IntMessage, ()> {
+    let mut result = IntMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_var_int()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_int(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UintMessage {
+  pub x: Option<u32>,
+}
+
+impl UintMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<UintMessage, ()> {
+    let mut result = UintMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_var_uint()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FloatMessage {
+  pub x: Option<f32>,
+}
+
+impl FloatMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<FloatMessage, ()> {
+    let mut result = FloatMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_var_float()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_float(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StringMessage {
+  pub x: Option<String>,
+}
+
+impl StringMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<StringMessage, ()> {
+    let mut result = StringMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_string()?.into_owned()),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_string(value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompoundMessage {
+  pub x: Option<u32>,
+  pub y: Option<u32>,
+}
+
+impl CompoundMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<CompoundMessage, ()> {
+    let mut result = CompoundMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(bb.read_var_uint()?),
+        2 => result.y = Some(bb.read_var_uint()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(*value);
+    }
+    if let Some(ref value) = self.y {
+      bb.write_var_uint(2);
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NestedMessage {
+  pub a: Option<u32>,
+  pub b: Option<Box<CompoundMessage>>,
+  pub c: Option<u32>,
+}
+
+impl NestedMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<NestedMessage, ()> {
+    let mut result = NestedMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.a = Some(bb.read_var_uint()?),
+        2 => result.b = Some(Box::new(CompoundMessage::decode(bb)?)),
+        3 => result.c = Some(bb.read_var_uint()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.a {
+      bb.write_var_uint(1);
+      bb.write_var_uint(*value);
+    }
+    if let Some(ref value) = self.b {
+      bb.write_var_uint(2);
+      value.encode(bb);
+    }
+    if let Some(ref value) = self.c {
+      bb.write_var_uint(3);
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoolArrayStruct {
+  pub x: Vec<bool>,
+}
+
+impl BoolArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<BoolArrayStruct, ()> {
+    let x = decode_array(bb, ByteBuffer::read_bool)?;
+    Ok(BoolArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_bool(*value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByteArrayStruct {
+  pub x: Vec<u8>,
+}
+
+impl ByteArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<ByteArrayStruct, ()> {
+    let x = decode_byte_array(bb)?;
+    Ok(ByteArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    bb.write_bytes(&self.x);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntArrayStruct {
+  pub x: Vec<i32>,
+}
+
+impl IntArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<IntArrayStruct, ()> {
+    let x = decode_array(bb, ByteBuffer::read_var_int)?;
+    Ok(IntArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_var_int(*value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UintArrayStruct {
+  pub x: Vec<u32>,
+}
+
+impl UintArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<UintArrayStruct, ()> {
+    let x = decode_array(bb, ByteBuffer::read_var_uint)?;
+    Ok(UintArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_var_uint(*value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatArrayStruct {
+  pub x: Vec<f32>,
+}
+
+impl FloatArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<FloatArrayStruct, ()> {
+    let x = decode_array(bb, ByteBuffer::read_var_float)?;
+    Ok(FloatArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_var_float(*value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringArrayStruct {
+  pub x: Vec<String>,
+}
+
+impl StringArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<StringArrayStruct, ()> {
+    let x = decode_array(bb, |bb| bb.read_string().map(|s| s.into_owned()))?;
+    Ok(StringArrayStruct {x})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_string(value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompoundArrayStruct {
+  pub x: Vec<u32>,
+  pub y: Vec<u32>,
+}
+
+impl CompoundArrayStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<CompoundArrayStruct, ()> {
+    let x = decode_array(bb, ByteBuffer::read_var_uint)?;
+    let y = decode_array(bb, ByteBuffer::read_var_uint)?;
+    Ok(CompoundArrayStruct {x, y})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_var_uint(self.x.len() as u32);
+    for value in &self.x {
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(self.y.len() as u32);
+    for value in &self.y {
+      bb.write_var_uint(*value);
+    }
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BoolArrayMessage {
+  pub x: Option<Vec<bool>>,
+}
+
+impl BoolArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<BoolArrayMessage, ()> {
+    let mut result = BoolArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, ByteBuffer::read_bool)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_bool(*value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ByteArrayMessage {
+  pub x: Option<Vec<u8>>,
+}
+
+impl ByteArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<ByteArrayMessage, ()> {
+    let mut result = ByteArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_byte_array(bb)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      bb.write_bytes(value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntArrayMessage {
+  pub x: Option<Vec<i32>>,
+}
+
+impl IntArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<IntArrayMessage, ()> {
+    let mut result = IntArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, ByteBuffer::read_var_int)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_int(*value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UintArrayMessage {
+  pub x: Option<Vec<u32>>,
+}
+
+impl UintArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<UintArrayMessage, ()> {
+    let mut result = UintArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FloatArrayMessage {
+  pub x: Option<Vec<f32>>,
+}
+
+impl FloatArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<FloatArrayMessage, ()> {
+    let mut result = FloatArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, ByteBuffer::read_var_float)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_float(*value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StringArrayMessage {
+  pub x: Option<Vec<String>>,
+}
+
+impl StringArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<StringArrayMessage, ()> {
+    let mut result = StringArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, |bb| bb.read_string().map(|s| s.into_owned()))?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_string(value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompoundArrayMessage {
+  pub x: Option<Vec<u32>>,
+  pub y: Option<Vec<u32>>,
+}
+
+impl CompoundArrayMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<CompoundArrayMessage, ()> {
+    let mut result = CompoundArrayMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        2 => result.y = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    if let Some(ref value) = self.y {
+      bb.write_var_uint(2);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecursiveMessage {
+  pub x: Option<Box<RecursiveMessage>>,
+}
+
+impl RecursiveMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<RecursiveMessage, ()> {
+    let mut result = RecursiveMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.x = Some(Box::new(RecursiveMessage::decode(bb)?)),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.x {
+      bb.write_var_uint(1);
+      value.encode(bb);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NonDeprecatedMessage {
+  pub a: Option<u32>,
+  pub b: Option<u32>,
+  pub c: Option<Vec<u32>>,
+  pub d: Option<Vec<u32>>,
+  pub e: Option<Box<ByteStruct>>,
+  pub f: Option<Box<ByteStruct>>,
+  pub g: Option<u32>,
+}
+
+impl NonDeprecatedMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<NonDeprecatedMessage, ()> {
+    let mut result = NonDeprecatedMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.a = Some(bb.read_var_uint()?),
+        2 => result.b = Some(bb.read_var_uint()?),
+        3 => result.c = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        4 => result.d = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        5 => result.e = Some(Box::new(ByteStruct::decode(bb)?)),
+        6 => result.f = Some(Box::new(ByteStruct::decode(bb)?)),
+        7 => result.g = Some(bb.read_var_uint()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.a {
+      bb.write_var_uint(1);
+      bb.write_var_uint(*value);
+    }
+    if let Some(ref value) = self.b {
+      bb.write_var_uint(2);
+      bb.write_var_uint(*value);
+    }
+    if let Some(ref value) = self.c {
+      bb.write_var_uint(3);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    if let Some(ref value) = self.d {
+      bb.write_var_uint(4);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    if let Some(ref value) = self.e {
+      bb.write_var_uint(5);
+      value.encode(bb);
+    }
+    if let Some(ref value) = self.f {
+      bb.write_var_uint(6);
+      value.encode(bb);
+    }
+    if let Some(ref value) = self.g {
+      bb.write_var_uint(7);
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeprecatedMessage {
+  pub a: Option<u32>,
+  pub c: Option<Vec<u32>>,
+  pub e: Option<Box<ByteStruct>>,
+  pub g: Option<u32>,
+}
+
+impl DeprecatedMessage {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<DeprecatedMessage, ()> {
+    let mut result = DeprecatedMessage::default();
+    loop {
+      match bb.read_var_uint()? {
+        0 => return Ok(result),
+        1 => result.a = Some(bb.read_var_uint()?),
+        2 => { bb.read_var_uint()?; },
+        3 => result.c = Some(decode_array(bb, ByteBuffer::read_var_uint)?),
+        4 => { decode_array(bb, ByteBuffer::read_var_uint)?; },
+        5 => result.e = Some(Box::new(ByteStruct::decode(bb)?)),
+        6 => { ByteStruct::decode(bb)?; },
+        7 => result.g = Some(bb.read_var_uint()?),
+        _ => return Err(()),
+      }
+    }
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    if let Some(ref value) = self.a {
+      bb.write_var_uint(1);
+      bb.write_var_uint(*value);
+    }
+    if let Some(ref value) = self.c {
+      bb.write_var_uint(3);
+      bb.write_var_uint(value.len() as u32);
+      for value in value {
+        bb.write_var_uint(*value);
+      }
+    }
+    if let Some(ref value) = self.e {
+      bb.write_var_uint(5);
+      value.encode(bb);
+    }
+    if let Some(ref value) = self.g {
+      bb.write_var_uint(7);
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(0);
+  }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortedStruct {
+  pub a1: bool,
+  pub b1: u8,
+  pub c1: i32,
+  pub d1: u32,
+  pub e1: f32,
+  pub f1: String,
+  pub a2: bool,
+  pub b2: u8,
+  pub c2: i32,
+  pub d2: u32,
+  pub e2: f32,
+  pub f2: String,
+  pub a3: Vec<bool>,
+  pub b3: Vec<u8>,
+  pub c3: Vec<i32>,
+  pub d3: Vec<u32>,
+  pub e3: Vec<f32>,
+  pub f3: Vec<String>,
+}
+
+impl SortedStruct {
+  pub fn decode(bb: &mut ByteBuffer) -> Result<SortedStruct, ()> {
+    let a1 = bb.read_bool()?;
+    let b1 = bb.read_byte()?;
+    let c1 = bb.read_var_int()?;
+    let d1 = bb.read_var_uint()?;
+    let e1 = bb.read_var_float()?;
+    let f1 = bb.read_string()?.into_owned();
+    let a2 = bb.read_bool()?;
+    let b2 = bb.read_byte()?;
+    let c2 = bb.read_var_int()?;
+    let d2 = bb.read_var_uint()?;
+    let e2 = bb.read_var_float()?;
+    let f2 = bb.read_string()?.into_owned();
+    let a3 = decode_array(bb, ByteBuffer::read_bool)?;
+    let b3 = decode_byte_array(bb)?;
+    let c3 = decode_array(bb, ByteBuffer::read_var_int)?;
+    let d3 = decode_array(bb, ByteBuffer::read_var_uint)?;
+    let e3 = decode_array(bb, ByteBuffer::read_var_float)?;
+    let f3 = decode_array(bb, |bb| bb.read_string().map(|s| s.into_owned()))?;
+    Ok(SortedStruct {a1, b1, c1, d1, e1, f1, a2, b2, c2, d2, e2, f2, a3, b3, c3, d3, e3, f3})
+  }
+
+  pub fn encode(&self, bb: &mut ByteBufferMut) {
+    bb.write_bool(self.a1);
+    bb.write_byte(self.b1);
+    bb.write_var_int(self.c1);
+    bb.write_var_uint(self.d1);
+    bb.write_var_float(self.e1);
+    bb.write_string(&self.f1);
+    bb.write_bool(self.a2);
+    bb.write_byte(self.b2);
+    bb.write_var_int(self.c2);
+    bb.write_var_uint(self.d2);
+    bb.write_var_float(self.e2);
+    bb.write_string(&self.f2);
+    bb.write_var_uint(self.a3.len() as u32);
+    for value in &self.a3 {
+      bb.write_bool(*value);
+    }
+    bb.write_var_uint(self.b3.len() as u32);
+    bb.write_bytes(&self.b3);
+    bb.write_var_uint(self.c3.len() as u32);
+    for value in &self.c3 {
+      bb.write_var_int(*value);
+    }
+    bb.write_var_uint(self.d3.len() as u32);
+    for value in &self.d3 {
+      bb.write_var_uint(*value);
+    }
+    bb.write_var_uint(self.e3.len() as u32);
+    for value in &self.e3 {
+      bb.write_var_float(*value);
+    }
+    bb.write_var_uint(self.f3.len() as u32);
+    for value in &self.f3 {
+      bb.write_string(value);
+    }
+  }
+}
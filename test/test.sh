@@ -34,3 +34,7 @@ node ../js/cli.js --schema ./test-schema.kiwi --skew-types ./test-schema-types.s
 node ../js/cli.js --schema ./test-schema.kiwi --binary ./test-schema.bkiwi
 node ../js/cli.js --schema ./test-schema.bkiwi --text ./test-schema-round-trip.kiwi
 rm ./test-schema.bkiwi
+
+cargo build --manifest-path ../rust/Cargo.toml --bin kiwic
+../rust/target/debug/kiwic --schema ./test-schema.kiwi --rust ./test-schema.rs
+cargo test --manifest-path ../rust/Cargo.toml